@@ -0,0 +1,103 @@
+// Build identity for the `version` command and the boot banner (see
+// src/buildinfo.rs). Everything is passed in as DDOS_* environment variables
+// read with env!(), and falls back to "unknown" when git or rustc cannot be
+// asked, e.g. when building from a source tarball.
+
+use std::env;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(output.stdout).ok()?;
+    Some(text.trim().to_string())
+}
+
+fn git_hash(dir: &str) -> String {
+    let Some(hash) = command_output("git", &["-C", dir, "rev-parse", "--short=12", "HEAD"]) else {
+        return String::from("unknown");
+    };
+
+    match command_output(
+        "git",
+        &["-C", dir, "status", "--porcelain", "--untracked-files=no"],
+    ) {
+        Some(changes) if !changes.is_empty() => format!("{}-dirty", hash),
+        _ => hash,
+    }
+}
+
+// "2024-05-01 12:34:56 UTC", honouring SOURCE_DATE_EPOCH for reproducible builds
+fn build_time() -> String {
+    let seconds = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs())
+        });
+
+    let days = (seconds / 86400) as i64;
+    let (hour, minute, second) = (seconds % 86400 / 3600, seconds % 3600 / 60, seconds % 60);
+
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year, month, day, hour, minute, second
+    )
+}
+
+fn features() -> String {
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(str::to_lowercase))
+        .collect();
+    features.sort();
+
+    if features.is_empty() {
+        String::from("none")
+    } else {
+        features.join(",")
+    }
+}
+
+fn main() {
+    let dir = env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| String::from("."));
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+
+    println!("cargo:rustc-env=DDOS_GIT_HASH={}", git_hash(&dir));
+    println!("cargo:rustc-env=DDOS_BUILD_TIME={}", build_time());
+    println!("cargo:rustc-env=DDOS_FEATURES={}", features());
+    println!(
+        "cargo:rustc-env=DDOS_RUSTC_VERSION={}",
+        command_output(&rustc, &["--version"]).unwrap_or_else(|| String::from("unknown"))
+    );
+    println!(
+        "cargo:rustc-env=DDOS_PROFILE={}",
+        env::var("PROFILE").unwrap_or_else(|_| String::from("unknown"))
+    );
+
+    // A new commit changes the hash without touching any source file
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
@@ -0,0 +1,114 @@
+use super::gpio::{self, Function, Pull};
+use super::timer;
+use crate::locked::SpinLock;
+
+// ============================================================================
+// PUSH BUTTONS
+// Buttons wired between a GPIO pin and ground, with the internal pull-up
+// holding the pin high until pressed. There are no GPIO interrupts yet, so
+// whoever waits for input calls poll() in its loop; a level only counts once
+// it has held for DEBOUNCE_MS, which filters out contact bounce.
+//
+// A press is reported as Long as soon as it has been held for LONG_PRESS_MS
+// (so "hold to reboot" does not wait for the release), otherwise as Short
+// when it is let go.
+// ============================================================================
+
+pub const MAX_BUTTONS: usize = 4;
+const DEBOUNCE_MS: u64 = 20;
+const LONG_PRESS_MS: u64 = 1000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Press {
+    Short,
+    Long,
+}
+
+#[derive(Clone, Copy)]
+struct Button {
+    pin: u32,
+    // Debounced state, true while pressed
+    pressed: bool,
+    // Raw level waiting to be believed, and since when
+    candidate: bool,
+    candidate_since: u64,
+    pressed_at: u64,
+    long_sent: bool,
+}
+
+static BUTTONS: SpinLock<[Option<Button>; MAX_BUTTONS]> = SpinLock::new([None; MAX_BUTTONS]);
+
+// Start watching `pin`. False when all slots are taken.
+pub fn watch(pin: u32) -> bool {
+    let mut buttons = BUTTONS.lock();
+
+    if buttons.iter().flatten().any(|button| button.pin == pin) {
+        return true;
+    }
+    let Some(slot) = buttons.iter_mut().find(|slot| slot.is_none()) else {
+        return false;
+    };
+
+    gpio::set_function(pin, Function::Input);
+    gpio::set_pull(pin, Pull::Up);
+
+    let now = timer::uptime_ms();
+    *slot = Some(Button {
+        pin,
+        pressed: false,
+        candidate: false,
+        candidate_since: now,
+        pressed_at: now,
+        long_sent: false,
+    });
+    true
+}
+
+pub fn unwatch(pin: u32) {
+    for slot in BUTTONS.lock().iter_mut() {
+        if slot.is_some_and(|button| button.pin == pin) {
+            *slot = None;
+        }
+    }
+}
+
+impl Button {
+    fn update(&mut self, now: u64) -> Option<Press> {
+        // Active low
+        let raw = !gpio::read(self.pin);
+
+        if raw != self.candidate {
+            self.candidate = raw;
+            self.candidate_since = now;
+        }
+
+        if self.candidate != self.pressed && now - self.candidate_since >= DEBOUNCE_MS {
+            self.pressed = self.candidate;
+
+            if self.pressed {
+                self.pressed_at = now;
+                self.long_sent = false;
+            } else if !self.long_sent {
+                return Some(Press::Short);
+            }
+        }
+
+        if self.pressed && !self.long_sent && now - self.pressed_at >= LONG_PRESS_MS {
+            self.long_sent = true;
+            return Some(Press::Long);
+        }
+
+        None
+    }
+}
+
+// Sample every watched button, returns the first one with news
+pub fn poll() -> Option<(u32, Press)> {
+    let now = timer::uptime_ms();
+    let mut buttons = BUTTONS.lock();
+
+    buttons
+        .iter_mut()
+        .flatten()
+        .find_map(|button| button.update(now).map(|press| (button.pin, press)))
+}
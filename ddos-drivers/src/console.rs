@@ -0,0 +1,306 @@
+use crate::gpio::{self, Function};
+use crate::locked::SpinLock;
+use crate::miniuart::{MINI_UART, MiniUart};
+use crate::timer::Timeout;
+use crate::uart::{UART, Uart};
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+// ============================================================================
+// SERIAL CONSOLE
+// Which of the two UARTs the console is on. Which one reaches the header
+// pins (GPIO 14/15) depends on the board and config.txt: the PL011 on
+// QEMU, the RPi4 and a RPi3 with dtoverlay=disable-bt, the mini UART on a
+// RPi3 left as it is. Everything that talks to the user (the log, the
+// shell's input, user programs) goes through CONSOLE, and select() moves
+// all of it over at once:
+//
+//     Backend::Pl011     UART0, uart::UART (the default)
+//     Backend::MiniUart  UART1, miniuart::MINI_UART
+//
+// At boot the default backend keeps the pins wherever the firmware put
+// them. select() also switches GPIO 14/15 to the backend it picks.
+//
+// Output does not wait for the wire. Writes go into a TX_BUFFER_BYTES ring
+// and the transmit FIFO gets whatever it has room for right away; the rest
+// goes out with later writes and whenever drain() runs. Without interrupts
+// that is the shell while it waits for a key and deep idle. Only a full
+// ring makes a writer wait. flush() empties it all onto the wire, and runs
+// before anything that would lose what is still queued: the panic handler,
+// a reset, the test exit, a blocking read, switching the backend. Early
+// boot flushes every line, in case the next step hangs.
+// ============================================================================
+
+// Alternate functions on GPIO 14 (TX) and 15 (RX)
+const PINS: [u32; 2] = [14, 15];
+
+// Output waiting for the transmitter, about a third of a second at 115200
+pub const TX_BUFFER_BYTES: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Backend {
+    Pl011,
+    MiniUart,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 2] = [Backend::Pl011, Backend::MiniUart];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Pl011 => "uart",
+            Backend::MiniUart => "miniuart",
+        }
+    }
+
+    // The name() of a backend, or the other name of its UART
+    pub fn parse(text: &str) -> Option<Backend> {
+        match text {
+            "uart" | "uart0" | "pl011" => Some(Backend::Pl011),
+            "miniuart" | "uart1" => Some(Backend::MiniUart),
+            _ => None,
+        }
+    }
+
+    pub fn available(self) -> bool {
+        match self {
+            Backend::Pl011 => true,
+            Backend::MiniUart => MINI_UART.lock().available(),
+        }
+    }
+
+    fn pin_function(self) -> Function {
+        match self {
+            Backend::Pl011 => Function::Alt0,
+            Backend::MiniUart => Function::Alt5,
+        }
+    }
+}
+
+static ACTIVE: AtomicU8 = AtomicU8::new(Backend::Pl011 as u8);
+
+pub fn active() -> Backend {
+    match ACTIVE.load(Ordering::Relaxed) {
+        1 => Backend::MiniUart,
+        _ => Backend::Pl011,
+    }
+}
+
+// Set up `backend`, route the console pins to it and send the console
+// there. False (and nothing changed) when the board does not have it.
+pub fn select(backend: Backend) -> bool {
+    if !backend.available() {
+        return false;
+    }
+
+    // Held throughout so no half-written line ends up on either side
+    let mut console = CONSOLE.lock();
+    let _ = console.flush();
+    console.init_backend(backend);
+    for pin in PINS {
+        gpio::set_function(pin, backend.pin_function());
+    }
+    ACTIVE.store(backend as u8, Ordering::Relaxed);
+    true
+}
+
+// Hand the transmitter whatever queued output it takes right now
+pub fn drain() {
+    CONSOLE.lock().drain();
+}
+
+// Put all queued output on the wire, for the panic path and the like
+pub fn flush() {
+    let _ = CONSOLE.lock().flush();
+}
+
+struct TxBuffer {
+    bytes: [u8; TX_BUFFER_BYTES],
+    // Oldest byte, and how many are queued from there on
+    head: usize,
+    len: usize,
+}
+
+impl TxBuffer {
+    fn front(&self) -> Option<u8> {
+        (self.len > 0).then(|| self.bytes[self.head])
+    }
+
+    fn pop(&mut self) {
+        self.head = (self.head + 1) % TX_BUFFER_BYTES;
+        self.len -= 1;
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.bytes[(self.head + self.len) % TX_BUFFER_BYTES] = byte;
+        self.len += 1;
+    }
+
+    // Pop bytes for as long as `try_send` takes them
+    fn drain_into(&mut self, mut try_send: impl FnMut(u8) -> bool) -> usize {
+        let mut sent = 0;
+        while let Some(byte) = self.front() {
+            if !try_send(byte) {
+                break;
+            }
+            self.pop();
+            sent += 1;
+        }
+        sent
+    }
+}
+
+pub struct Console {
+    tx: TxBuffer,
+}
+
+pub static CONSOLE: SpinLock<Console> = SpinLock::new(Console::new());
+
+impl Console {
+    pub const fn new() -> Console {
+        Console {
+            tx: TxBuffer {
+                bytes: [0; TX_BUFFER_BYTES],
+                head: 0,
+                len: 0,
+            },
+        }
+    }
+
+    // Set up the active backend, pins untouched
+    pub fn init(&self) {
+        self.init_backend(active());
+    }
+
+    fn init_backend(&self, backend: Backend) {
+        match backend {
+            Backend::Pl011 => UART.lock().init(),
+            Backend::MiniUart => MINI_UART.lock().init(),
+        }
+    }
+
+    // Waits for room in the transmit FIFO, up to the driver's timeout
+    fn send_now(byte: u8) -> Result<(), Timeout> {
+        match active() {
+            Backend::Pl011 => UART.lock().send(byte as char),
+            Backend::MiniUart => MINI_UART.lock().send(byte as char),
+        }
+    }
+
+    // Waits only while the ring is full, for its oldest byte to go out
+    fn queue(&mut self, byte: u8) -> Result<(), Timeout> {
+        if self.tx.len == TX_BUFFER_BYTES
+            && let Some(oldest) = self.tx.front()
+        {
+            Self::send_now(oldest)?;
+            self.tx.pop();
+        }
+        self.tx.push(byte);
+        Ok(())
+    }
+
+    // Returns how many bytes the transmitter took
+    pub fn drain(&mut self) -> usize {
+        match active() {
+            Backend::Pl011 => {
+                let uart = UART.lock();
+                self.tx.drain_into(|byte| uart.try_send(byte))
+            }
+            Backend::MiniUart => {
+                let uart = MINI_UART.lock();
+                self.tx.drain_into(|byte| uart.try_send(byte))
+            }
+        }
+    }
+
+    // Err when the transmitter got stuck, with the rest still queued
+    pub fn flush(&mut self) -> Result<(), Timeout> {
+        while let Some(byte) = self.tx.front() {
+            Self::send_now(byte)?;
+            self.tx.pop();
+        }
+        match active() {
+            Backend::Pl011 => UART.lock().flush(),
+            Backend::MiniUart => MINI_UART.lock().flush(),
+        }
+    }
+
+    // Err when the ring is full and the transmitter stuck, the character is
+    // dropped then
+    pub fn send(&mut self, c: char) -> Result<(), Timeout> {
+        self.queue(c as u8)?;
+        self.drain();
+        Ok(())
+    }
+
+    // Blocks, so whatever was queued (a prompt, say) goes out first
+    pub fn read_byte(&mut self) -> u8 {
+        let _ = self.flush();
+        match active() {
+            Backend::Pl011 => UART.lock().read_byte(),
+            Backend::MiniUart => MINI_UART.lock().read_byte(),
+        }
+    }
+
+    pub fn has_input(&self) -> bool {
+        match active() {
+            Backend::Pl011 => UART.lock().has_input(),
+            Backend::MiniUart => MINI_UART.lock().has_input(),
+        }
+    }
+
+    pub fn try_read_byte(&self) -> Option<u8> {
+        match active() {
+            Backend::Pl011 => UART.lock().try_read_byte(),
+            Backend::MiniUart => MINI_UART.lock().try_read_byte(),
+        }
+    }
+}
+
+impl fmt::Write for Console {
+    // Gives up on the rest of the string when the ring is full and the
+    // transmitter stuck, so that costs one timeout per print
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.queue(b'\r').map_err(|_| fmt::Error)?;
+            }
+            self.queue(byte).map_err(|_| fmt::Error)?;
+        }
+        self.drain();
+        Ok(())
+    }
+}
+
+// The active UART without the locks or the ring, for code that may run
+// while whatever it interrupted holds them (the GDB stub). What is queued
+// does not get out first.
+pub struct Raw;
+
+impl Raw {
+    pub fn send(&self, byte: u8) {
+        let _ = match active() {
+            Backend::Pl011 => Uart::new().send(byte as char),
+            Backend::MiniUart => MiniUart::new().send(byte as char),
+        };
+    }
+
+    pub fn try_read_byte(&self) -> Option<u8> {
+        match active() {
+            Backend::Pl011 => Uart::new().try_read_byte(),
+            Backend::MiniUart => MiniUart::new().try_read_byte(),
+        }
+    }
+}
+
+impl fmt::Write for Raw {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
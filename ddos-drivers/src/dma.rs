@@ -0,0 +1,354 @@
+use super::mailbox;
+use crate::hardwareselect::dma_base;
+use crate::locked::SpinLock;
+use crate::spin_until;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ptr::{read_volatile, write_volatile};
+
+// ============================================================================
+// DMA CONTROLLER
+// The BCM283x DMA engines copy memory while the CPU does something else (or
+// polls, until there are interrupts). A transfer is a chain of 32-byte
+// aligned control blocks in RAM: what to copy from where to where, and the
+// bus address of the next block. The channel walks the chain on its own.
+//
+// The engines see RAM through the VideoCore bus, at the ARM address plus
+// BUS_RAM_ALIAS, so only the first gigabyte is reachable. Caches are off,
+// so there is nothing to clean or invalidate around a transfer.
+//
+// The firmware uses some channels itself and reports the rest over the
+// mailbox. Channels 7 and up are "lite" engines that move at most
+// LITE_MAX_LENGTH bytes per control block, so longer transfers are split.
+// The RPi4's channels 11 to 14 are the newer DMA4 engines with a different
+// register layout and are left alone.
+// ============================================================================
+
+// Uncached alias of ARM RAM as the DMA engines address it
+const BUS_RAM_ALIAS: usize = 0xC000_0000;
+const BUS_RAM_SIZE: usize = 0x4000_0000;
+
+#[cfg(feature = "rpi4")]
+const CHANNELS: usize = 11;
+
+#[cfg(not(feature = "rpi4"))]
+const CHANNELS: usize = 15;
+
+// Channels below this are full engines, the rest are lite
+const FIRST_LITE: usize = 7;
+
+const FULL_MAX_LENGTH: usize = (1 << 30) - 4;
+const LITE_MAX_LENGTH: usize = 0xFFFC;
+
+// Channel n's registers are at dma_base() + n * CHANNEL_STRIDE
+const CHANNEL_STRIDE: usize = 0x100;
+const CS: usize = 0x00;
+const CONBLK_AD: usize = 0x04;
+const DEBUG: usize = 0x20;
+
+// One enable bit per channel
+const ENABLE: usize = 0xFF0;
+
+const CS_ACTIVE: u32 = 1 << 0;
+const CS_END: u32 = 1 << 1;
+const CS_ERROR: u32 = 1 << 8;
+const CS_WAIT_FOR_OUTSTANDING_WRITES: u32 = 1 << 28;
+const CS_ABORT: u32 = 1 << 30;
+const CS_RESET: u32 = 1 << 31;
+
+// Transfer information bits in a control block
+const TI_WAIT_RESP: u32 = 1 << 3;
+const TI_DEST_INC: u32 = 1 << 4;
+const TI_SRC_INC: u32 = 1 << 8;
+
+// Read/FIFO/slave errors in DEBUG, written back to clear them
+const DEBUG_ERRORS: u32 = 0b111;
+
+// The mailbox's usable channel mask when the firmware does not answer
+const DEFAULT_CHANNEL_MASK: u32 = 0x7F35;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DmaError {
+    // No DMA controller on this board (RPi5)
+    Unavailable,
+    // Every channel the firmware left us is taken
+    NoFreeChannel,
+    // Addresses and lengths must be whole words
+    Misaligned,
+    // Outside the gigabyte the engines can see
+    Unreachable(usize),
+    // A transfer is still running on the channel
+    Busy,
+    Timeout,
+    // The engine stopped with an error, DEBUG register value
+    Bus(u32),
+}
+
+impl fmt::Display for DmaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DmaError::Unavailable => write!(f, "DMA not available"),
+            DmaError::NoFreeChannel => write!(f, "no free DMA channel"),
+            DmaError::Misaligned => write!(f, "address or length not word aligned"),
+            DmaError::Unreachable(address) => {
+                write!(f, "{:#x} is out of the DMA engines' reach", address)
+            }
+            DmaError::Busy => write!(f, "channel busy"),
+            DmaError::Timeout => write!(f, "transfer timed out"),
+            DmaError::Bus(debug) => write!(f, "transfer failed (debug {:#x})", debug),
+        }
+    }
+}
+
+#[repr(C, align(32))]
+#[derive(Clone, Copy, Default)]
+struct ControlBlock {
+    transfer_info: u32,
+    source: u32,
+    destination: u32,
+    length: u32,
+    stride: u32,
+    next: u32,
+    reserved: [u32; 2],
+}
+
+// Usable channels (from the firmware) and which of them are handed out
+struct Channels {
+    usable: Option<u32>,
+    taken: u32,
+}
+
+static CHANNELS_STATE: SpinLock<Channels> = SpinLock::new(Channels {
+    usable: None,
+    taken: 0,
+});
+
+fn channel_reg(channel: usize, offset: usize) -> *mut u32 {
+    (dma_base() + channel * CHANNEL_STRIDE + offset) as *mut u32
+}
+
+fn bus_address(address: usize) -> Result<u32, DmaError> {
+    if address >= BUS_RAM_SIZE {
+        return Err(DmaError::Unreachable(address));
+    }
+    Ok((address | BUS_RAM_ALIAS) as u32)
+}
+
+// Control blocks (and a fill word) must be in RAM before the engine reads them
+fn barrier() {
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("dsb sy", options(nostack, preserves_flags));
+    }
+}
+
+// A DMA channel, released again when dropped
+pub struct Channel {
+    number: usize,
+    blocks: Vec<ControlBlock>,
+    // Source of fill(), which must not move while the engine reads it
+    pattern: Box<u32>,
+}
+
+// Take the lowest free channel, preferring full engines over lite ones
+pub fn allocate() -> Result<Channel, DmaError> {
+    if cfg!(feature = "rpi5") || dma_base() == 0 {
+        return Err(DmaError::Unavailable);
+    }
+
+    let number = {
+        let mut state = CHANNELS_STATE.lock();
+        let usable = *state
+            .usable
+            .get_or_insert_with(|| mailbox::dma_channels().unwrap_or(DEFAULT_CHANNEL_MASK));
+
+        let free = |channel: &usize| usable & !state.taken & (1 << channel) != 0;
+        let number = (0..CHANNELS).find(free).ok_or(DmaError::NoFreeChannel)?;
+        state.taken |= 1 << number;
+        number
+    };
+
+    unsafe {
+        let enable = (dma_base() + ENABLE) as *mut u32;
+        write_volatile(enable, read_volatile(enable) | 1 << number);
+        write_volatile(channel_reg(number, CS), CS_RESET);
+    }
+
+    Ok(Channel {
+        number,
+        blocks: Vec::new(),
+        pattern: Box::new(0),
+    })
+}
+
+impl Channel {
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    fn max_length(&self) -> usize {
+        if self.number < FIRST_LITE {
+            FULL_MAX_LENGTH
+        } else {
+            LITE_MAX_LENGTH
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        unsafe { read_volatile(channel_reg(self.number, CS)) & CS_ACTIVE != 0 }
+    }
+
+    // Lay out the chain for `length` bytes and start the engine on it. The
+    // source advances only when `source_inc` is set (a fill reads one word).
+    fn start(
+        &mut self,
+        destination: usize,
+        source: usize,
+        length: usize,
+        source_inc: bool,
+    ) -> Result<(), DmaError> {
+        if self.is_busy() {
+            return Err(DmaError::Busy);
+        }
+        if !(destination | source | length).is_multiple_of(4) {
+            return Err(DmaError::Misaligned);
+        }
+        // Both ends of each range must be in reach, not just the start
+        let source_length = if source_inc { length } else { 4 };
+        bus_address(destination + length.saturating_sub(1))?;
+        bus_address(source + source_length - 1)?;
+
+        let transfer_info = TI_WAIT_RESP | TI_DEST_INC | if source_inc { TI_SRC_INC } else { 0 };
+        let chunk = self.max_length();
+
+        self.blocks.clear();
+        for offset in (0..length).step_by(chunk) {
+            let source = if source_inc { source + offset } else { source };
+            self.blocks.push(ControlBlock {
+                transfer_info,
+                source: bus_address(source)?,
+                destination: bus_address(destination + offset)?,
+                length: chunk.min(length - offset) as u32,
+                ..ControlBlock::default()
+            });
+        }
+        if self.blocks.is_empty() {
+            return Ok(());
+        }
+
+        // Chain each block to the one after it, the last one ends the transfer
+        for index in 1..self.blocks.len() {
+            let next = &self.blocks[index] as *const ControlBlock as usize;
+            self.blocks[index - 1].next = bus_address(next)?;
+        }
+
+        let first = bus_address(self.blocks.as_ptr() as usize)?;
+        barrier();
+
+        unsafe {
+            let cs = channel_reg(self.number, CS);
+            write_volatile(cs, CS_END);
+            write_volatile(channel_reg(self.number, CONBLK_AD), first);
+            write_volatile(cs, CS_ACTIVE | CS_WAIT_FOR_OUTSTANDING_WRITES);
+        }
+        Ok(())
+    }
+
+    // Start copying `length` bytes and return at once; wait() for the end
+    //
+    // Safety: both ranges must stay valid, and untouched by anyone else,
+    // until the transfer has finished.
+    pub unsafe fn start_copy(
+        &mut self,
+        destination: *mut u8,
+        source: *const u8,
+        length: usize,
+    ) -> Result<(), DmaError> {
+        self.start(destination as usize, source as usize, length, true)
+    }
+
+    // Start filling `length` bytes with copies of `value`
+    //
+    // Safety: as for start_copy()
+    pub unsafe fn start_fill(
+        &mut self,
+        destination: *mut u8,
+        value: u32,
+        length: usize,
+    ) -> Result<(), DmaError> {
+        *self.pattern = value;
+        let source = &*self.pattern as *const u32 as usize;
+        self.start(destination as usize, source, length, false)
+    }
+
+    // Poll until the engine is done (an interrupt will replace this later)
+    pub fn wait(&mut self, timeout_ms: u64) -> Result<(), DmaError> {
+        let mut status = 0;
+        let finished = spin_until!(
+            {
+                status = unsafe { read_volatile(channel_reg(self.number, CS)) };
+                status & (CS_ERROR | CS_ACTIVE) != CS_ACTIVE
+            },
+            timeout_ms
+        );
+
+        if finished.is_err() {
+            self.abort();
+            return Err(DmaError::Timeout);
+        }
+        if status & CS_ERROR != 0 {
+            let debug = unsafe { read_volatile(channel_reg(self.number, DEBUG)) };
+            self.abort();
+            unsafe { write_volatile(channel_reg(self.number, DEBUG), debug & DEBUG_ERRORS) };
+            return Err(DmaError::Bus(debug));
+        }
+        Ok(())
+    }
+
+    fn abort(&mut self) {
+        unsafe {
+            let cs = channel_reg(self.number, CS);
+            write_volatile(cs, CS_ABORT);
+            write_volatile(cs, CS_RESET);
+        }
+    }
+
+    // Copy and wait for it
+    //
+    // Safety: as for start_copy()
+    pub unsafe fn copy(
+        &mut self,
+        destination: *mut u8,
+        source: *const u8,
+        length: usize,
+        timeout_ms: u64,
+    ) -> Result<(), DmaError> {
+        unsafe { self.start_copy(destination, source, length)? };
+        self.wait(timeout_ms)
+    }
+
+    // Fill and wait for it
+    //
+    // Safety: as for start_copy()
+    pub unsafe fn fill(
+        &mut self,
+        destination: *mut u8,
+        value: u32,
+        length: usize,
+        timeout_ms: u64,
+    ) -> Result<(), DmaError> {
+        unsafe { self.start_fill(destination, value, length)? };
+        self.wait(timeout_ms)
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        // The control blocks go with us, the engine must not read them after
+        if self.is_busy() {
+            self.abort();
+        }
+        CHANNELS_STATE.lock().taken &= !(1 << self.number);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::hardwareselect::gpio_base;
+use core::ptr::{read_volatile, write_volatile};
+
+// Legacy Broadcom GPIO block (RPi3/RPi4/QEMU). The RPi5 pins live on the RP1
+// southbridge with a completely different register layout, so every call is
+// a no-op there for now.
+
+// Register offsets from gpio_base()
+const GPFSEL0: usize = 0x00;
+const GPSET0: usize = 0x1C;
+const GPCLR0: usize = 0x28;
+const GPLEV0: usize = 0x34;
+const GPEDS0: usize = 0x40;
+const GPREN0: usize = 0x4C;
+const GPFEN0: usize = 0x58;
+
+#[cfg(not(feature = "rpi4"))]
+const GPPUD: usize = 0x94;
+#[cfg(not(feature = "rpi4"))]
+const GPPUDCLK0: usize = 0x98;
+
+// The BCM2711 replaced the GPPUD clocking dance with direct 2-bit fields
+#[cfg(feature = "rpi4")]
+const GPIO_PUP_PDN_CNTRL0: usize = 0xE4;
+
+#[derive(Clone, Copy)]
+pub enum Function {
+    Input = 0b000,
+    Output = 0b001,
+    Alt0 = 0b100,
+    Alt1 = 0b101,
+    Alt2 = 0b110,
+    Alt3 = 0b111,
+    Alt4 = 0b011,
+    Alt5 = 0b010,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    None,
+    Rising,
+    Falling,
+    Both,
+}
+
+#[derive(Clone, Copy)]
+pub enum Pull {
+    None,
+    Down,
+    Up,
+}
+
+// 32 pins per register for set/clear/level, 10 pins per register for function select
+fn bank_reg(first: usize, pin: u32) -> *mut u32 {
+    (gpio_base() + first + (pin as usize / 32) * 4) as *mut u32
+}
+
+pub fn set_function(pin: u32, function: Function) {
+    #[cfg(feature = "rpi5")]
+    {
+        let _ = (pin, function);
+        return;
+    }
+
+    #[cfg(not(feature = "rpi5"))]
+    unsafe {
+        let reg = (gpio_base() + GPFSEL0 + (pin as usize / 10) * 4) as *mut u32;
+        let shift = (pin % 10) * 3;
+
+        let mut value = read_volatile(reg);
+        value &= !(0b111 << shift);
+        value |= (function as u32) << shift;
+        write_volatile(reg, value);
+    }
+}
+
+pub fn set_pull(pin: u32, pull: Pull) {
+    #[cfg(feature = "rpi5")]
+    {
+        let _ = (pin, pull);
+        return;
+    }
+
+    #[cfg(feature = "rpi4")]
+    unsafe {
+        let reg = (gpio_base() + GPIO_PUP_PDN_CNTRL0 + (pin as usize / 16) * 4) as *mut u32;
+        let shift = (pin % 16) * 2;
+        let bits = match pull {
+            Pull::None => 0b00,
+            Pull::Up => 0b01,
+            Pull::Down => 0b10,
+        };
+
+        let mut value = read_volatile(reg);
+        value &= !(0b11 << shift);
+        value |= bits << shift;
+        write_volatile(reg, value);
+    }
+
+    // BCM2835 sequence: write the control, wait 150 cycles, clock it into the pin,
+    // wait again, then remove both
+    #[cfg(not(any(feature = "rpi4", feature = "rpi5")))]
+    unsafe {
+        let bits = match pull {
+            Pull::None => 0b00,
+            Pull::Down => 0b01,
+            Pull::Up => 0b10,
+        };
+        let control = (gpio_base() + GPPUD) as *mut u32;
+        let clock = bank_reg(GPPUDCLK0, pin);
+
+        write_volatile(control, bits);
+        wait_cycles(150);
+        write_volatile(clock, 1 << (pin % 32));
+        wait_cycles(150);
+        write_volatile(control, 0);
+        write_volatile(clock, 0);
+    }
+}
+
+pub fn write(pin: u32, high: bool) {
+    #[cfg(feature = "rpi5")]
+    {
+        let _ = (pin, high);
+        return;
+    }
+
+    #[cfg(not(feature = "rpi5"))]
+    unsafe {
+        let first = if high { GPSET0 } else { GPCLR0 };
+        write_volatile(bank_reg(first, pin), 1 << (pin % 32));
+    }
+}
+
+pub fn read(pin: u32) -> bool {
+    #[cfg(feature = "rpi5")]
+    {
+        let _ = pin;
+        return false;
+    }
+
+    #[cfg(not(feature = "rpi5"))]
+    unsafe {
+        (read_volatile(bank_reg(GPLEV0, pin)) & (1 << (pin % 32))) != 0
+    }
+}
+
+// Which edges set the pin's event detect status bit. These are the
+// synchronous detectors, so pulses shorter than a couple of APB clock cycles
+// are missed.
+pub fn set_edge_detect(pin: u32, edge: Edge) {
+    #[cfg(feature = "rpi5")]
+    {
+        let _ = (pin, edge);
+        return;
+    }
+
+    #[cfg(not(feature = "rpi5"))]
+    unsafe {
+        let bit = 1 << (pin % 32);
+        let rising = matches!(edge, Edge::Rising | Edge::Both);
+        let falling = matches!(edge, Edge::Falling | Edge::Both);
+
+        for (first, enabled) in [(GPREN0, rising), (GPFEN0, falling)] {
+            let reg = bank_reg(first, pin);
+            let value = read_volatile(reg);
+            write_volatile(reg, if enabled { value | bit } else { value & !bit });
+        }
+        // Drop anything seen before the switch
+        write_volatile(bank_reg(GPEDS0, pin), bit);
+    }
+}
+
+// True if an enabled edge was seen since the last call. The status is a
+// single sticky bit, so several edges in between still count as one.
+pub fn take_event(pin: u32) -> bool {
+    #[cfg(feature = "rpi5")]
+    {
+        let _ = pin;
+        return false;
+    }
+
+    #[cfg(not(feature = "rpi5"))]
+    unsafe {
+        let bit = 1 << (pin % 32);
+        let reg = bank_reg(GPEDS0, pin);
+
+        if read_volatile(reg) & bit == 0 {
+            return false;
+        }
+        // Write 1 to clear
+        write_volatile(reg, bit);
+        true
+    }
+}
+
+#[cfg(not(any(feature = "rpi4", feature = "rpi5")))]
+fn wait_cycles(count: u32) {
+    for _ in 0..count {
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,374 @@
+/*
+ * hardwareselect.rs - Hardware Abstraction and Configuration
+ *
+ * This module uses conditional compilation (cfg features) to set memory
+ * addresses, clock speeds, and platform names at compile time.
+ *
+ * Build examples:
+ * cargo build --features qemu
+ * cargo build --features rpi3
+ * cargo build --features rpi4
+ * cargo build --features rpi5
+ *
+ * The constants below are what the kernel was built for. When the firmware
+ * hands over a device tree, section 5 moves the peripheral addresses to what
+ * the board actually reports.
+ */
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// ============================================================================
+// 1. PERIPHERAL BASE ADDRESSES
+// ============================================================================
+
+#[cfg(any(feature = "qemu", feature = "rpi3"))]
+pub const PERIPHERAL_BASE: usize = 0x3F00_0000;
+
+#[cfg(feature = "rpi4")]
+pub const PERIPHERAL_BASE: usize = 0xFE00_0000;
+
+// RPi5 firmware maps the RP1 Southbridge peripherals here via PCIe
+#[cfg(feature = "rpi5")]
+pub const PERIPHERAL_BASE: usize = 0x1C_0000_0000;
+
+// How far the peripheral window reaches from PERIPHERAL_BASE
+#[cfg(any(feature = "qemu", feature = "rpi3"))]
+pub const PERIPHERAL_SIZE: usize = 0x0100_0000;
+
+#[cfg(feature = "rpi4")]
+pub const PERIPHERAL_SIZE: usize = 0x0180_0000;
+
+// The RP1's peripherals in its PCIe BAR
+#[cfg(feature = "rpi5")]
+pub const PERIPHERAL_SIZE: usize = 0x0040_0000;
+
+// ============================================================================
+// 2. PERIPHERAL OFFSETS
+// ============================================================================
+
+// --- UART0 BASE ---
+#[cfg(feature = "rpi5")]
+pub const UART0_BASE: usize = PERIPHERAL_BASE + 0x30000; // RP1 UART0 offset
+
+#[cfg(not(feature = "rpi5"))]
+pub const UART0_BASE: usize = PERIPHERAL_BASE + 0x201000; // Legacy Broadcom offset
+
+// --- AUX (MINI UART) BASE ---
+// UART1, the mini UART, shares the AUX block with the two auxiliary SPI masters
+#[cfg(feature = "rpi5")]
+pub const AUX_BASE: usize = 0; // Placeholder to prevent compilation errors, do not use on RPi5
+
+#[cfg(not(feature = "rpi5"))]
+pub const AUX_BASE: usize = PERIPHERAL_BASE + 0x215000;
+
+// --- GPIO BASE ---
+#[cfg(feature = "rpi5")]
+pub const GPIO_BASE: usize = PERIPHERAL_BASE + 0xD0000; // RP1 SYS_RIO (GPIO) offset
+
+#[cfg(not(feature = "rpi5"))]
+pub const GPIO_BASE: usize = PERIPHERAL_BASE + 0x200000; // Legacy Broadcom offset
+
+// --- TIMER BASE ---
+// Note: RPi5 handles timers differently. These are legacy Broadcom System Timer offsets.
+#[cfg(feature = "rpi5")]
+pub const TIMER_BASE: usize = 0; // Placeholder to prevent compilation errors, do not use on RPi5
+
+#[cfg(not(feature = "rpi5"))]
+pub const TIMER_BASE: usize = PERIPHERAL_BASE + 0x003000;
+
+// --- WATCHDOG BASE ---
+#[cfg(feature = "rpi5")]
+pub const WATCHDOG_BASE: usize = 0; // Placeholder to prevent compilation errors, do not use on RPi5
+
+#[cfg(not(feature = "rpi5"))]
+pub const WATCHDOG_BASE: usize = PERIPHERAL_BASE + 0x100000;
+
+// --- MAILBOX BASE ---
+#[cfg(feature = "rpi5")]
+pub const MAILBOX_BASE: usize = 0; // Placeholder to prevent compilation errors, do not use on RPi5
+
+#[cfg(not(feature = "rpi5"))]
+pub const MAILBOX_BASE: usize = PERIPHERAL_BASE + 0xB880;
+
+// --- PWM AND CLOCK MANAGER BASE ---
+// The clock manager feeds the PWM block; its PWM clock sits at 0xA0/0xA4
+#[cfg(feature = "rpi5")]
+pub const PWM_BASE: usize = 0; // Placeholder to prevent compilation errors, do not use on RPi5
+
+#[cfg(not(feature = "rpi5"))]
+pub const PWM_BASE: usize = PERIPHERAL_BASE + 0x20C000;
+
+// The headphone jack (GPIO 40/41): PWM0 on the BCM2837, a second block (PWM1) on the BCM2711
+#[cfg(feature = "rpi4")]
+pub const AUDIO_PWM_BASE: usize = PERIPHERAL_BASE + 0x20C800;
+
+#[cfg(not(feature = "rpi4"))]
+pub const AUDIO_PWM_BASE: usize = PWM_BASE;
+
+#[cfg(feature = "rpi5")]
+pub const CLOCK_MANAGER_BASE: usize = 0; // Placeholder to prevent compilation errors, do not use on RPi5
+
+#[cfg(not(feature = "rpi5"))]
+pub const CLOCK_MANAGER_BASE: usize = PERIPHERAL_BASE + 0x101000;
+
+// --- DMA BASE ---
+// Channels 0-14, 0x100 apart, with the global enable register at +0xFF0
+#[cfg(feature = "rpi5")]
+pub const DMA_BASE: usize = 0; // Placeholder to prevent compilation errors, do not use on RPi5
+
+#[cfg(not(feature = "rpi5"))]
+pub const DMA_BASE: usize = PERIPHERAL_BASE + 0x7000;
+
+// --- HARDWARE RNG BASE ---
+// Same address on both, but the BCM2711 has the newer RNG200 with its own register layout
+#[cfg(feature = "rpi5")]
+pub const RNG_BASE: usize = 0; // Placeholder to prevent compilation errors, do not use on RPi5
+
+#[cfg(not(feature = "rpi5"))]
+pub const RNG_BASE: usize = PERIPHERAL_BASE + 0x104000;
+
+// --- I2C (BSC1) BASE ---
+// BSC1 is the controller on header pins 3 and 5 (GPIO 2/3); BSC0 belongs to the HAT EEPROM
+#[cfg(feature = "rpi5")]
+pub const I2C1_BASE: usize = 0; // Placeholder to prevent compilation errors, do not use on RPi5
+
+#[cfg(not(feature = "rpi5"))]
+pub const I2C1_BASE: usize = PERIPHERAL_BASE + 0x804000;
+
+// --- EMMC (SD CARD) BASE ---
+// RPi4 moved the SD slot to the new EMMC2 controller; the legacy one drives the WiFi chip
+#[cfg(feature = "rpi4")]
+pub const EMMC_BASE: usize = PERIPHERAL_BASE + 0x340000;
+
+#[cfg(feature = "rpi5")]
+pub const EMMC_BASE: usize = 0; // Placeholder to prevent compilation errors, do not use on RPi5
+
+#[cfg(any(feature = "qemu", feature = "rpi3"))]
+pub const EMMC_BASE: usize = PERIPHERAL_BASE + 0x300000;
+
+// --- WIFI SDIO BASE ---
+// Only the RPi4 has a spare SDHCI for the WiFi chip. On the RPi3 (and QEMU) we
+// use the only EMMC for the SD card ourselves, so there is nothing to probe.
+#[cfg(feature = "rpi4")]
+pub const WIFI_SDIO_BASE: usize = PERIPHERAL_BASE + 0x300000;
+
+#[cfg(not(feature = "rpi4"))]
+pub const WIFI_SDIO_BASE: usize = 0; // No free controller
+
+// ============================================================================
+// 3. CLOCK SPEEDS
+// ============================================================================
+
+#[cfg(any(feature = "qemu", feature = "rpi3", feature = "rpi4"))]
+pub const UART_CLOCK_HZ: u32 = 48_000_000;
+
+// RPi5 RP1 PL011 UART reference clock used for divisor programming
+#[cfg(feature = "rpi5")]
+pub const UART_CLOCK_HZ: u32 = 48_000_000;
+
+// VPU core clock the mini UART divides its baud rate from, for when the
+// firmware cannot be asked. It holds the clock still with enable_uart=1.
+#[cfg(any(feature = "qemu", feature = "rpi3"))]
+pub const CORE_CLOCK_HZ: u32 = 250_000_000;
+
+#[cfg(any(feature = "rpi4", feature = "rpi5"))]
+pub const CORE_CLOCK_HZ: u32 = 500_000_000;
+
+// Base clock feeding the SD controller, divided down to 400 kHz / 25 MHz by the driver
+#[cfg(any(feature = "qemu", feature = "rpi3"))]
+pub const EMMC_CLOCK_HZ: u32 = 41_666_666;
+
+#[cfg(any(feature = "rpi4", feature = "rpi5"))]
+pub const EMMC_CLOCK_HZ: u32 = 100_000_000;
+
+// Crystal oscillator the PWM clock is divided down from
+#[cfg(any(feature = "qemu", feature = "rpi3"))]
+pub const OSCILLATOR_HZ: u32 = 19_200_000;
+
+#[cfg(any(feature = "rpi4", feature = "rpi5"))]
+pub const OSCILLATOR_HZ: u32 = 54_000_000;
+
+pub const SYSTEM_CLOCK_HZ: u32 = 1_000_000_000;
+
+// ============================================================================
+// 4. HELPER FUNCTIONS FOR LOGGING
+// ============================================================================
+
+pub fn get_platform_name() -> &'static str {
+    #[cfg(feature = "qemu")]
+    return "QEMU (RPi3 Model)";
+
+    #[cfg(feature = "rpi3")]
+    return "Raspberry Pi 3";
+
+    #[cfg(feature = "rpi4")]
+    return "Raspberry Pi 4";
+
+    #[cfg(feature = "rpi5")]
+    return "Raspberry Pi 5";
+
+    #[cfg(not(any(feature = "qemu", feature = "rpi3", feature = "rpi4", feature = "rpi5")))]
+    return "Unknown Platform";
+}
+
+pub fn get_peripheral_base_display() -> &'static str {
+    #[cfg(any(feature = "qemu", feature = "rpi3"))]
+    return "0x3F00_0000";
+
+    #[cfg(feature = "rpi4")]
+    return "0xFE00_0000";
+
+    #[cfg(feature = "rpi5")]
+    return "0x1C_0000_0000";
+
+    #[cfg(not(any(feature = "qemu", feature = "rpi3", feature = "rpi4", feature = "rpi5")))]
+    return "Unknown";
+}
+
+// ============================================================================
+// 5. DEVICE TREE OVERRIDES
+// Filled in by devicetree::init() before the UART is set up. Zero means "not
+// found", in which case the compiled-in address is used. The RP1 on the RPi5
+// sits behind PCIe with a BAR the firmware programs itself, so its addresses
+// are never overridden.
+// ============================================================================
+
+static PERIPHERAL_BASE_FOUND: AtomicUsize = AtomicUsize::new(0);
+static UART0_BASE_FOUND: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set_discovered(peripheral_base: Option<usize>, uart0_base: Option<usize>) {
+    if cfg!(feature = "rpi5") {
+        return;
+    }
+
+    PERIPHERAL_BASE_FOUND.store(peripheral_base.unwrap_or(0), Ordering::Relaxed);
+    UART0_BASE_FOUND.store(uart0_base.unwrap_or(0), Ordering::Relaxed);
+}
+
+pub fn peripheral_base() -> usize {
+    match PERIPHERAL_BASE_FOUND.load(Ordering::Relaxed) {
+        0 => PERIPHERAL_BASE,
+        base => base,
+    }
+}
+
+// Move a compiled-in peripheral address onto the discovered peripheral base
+fn rebase(address: usize) -> usize {
+    address - PERIPHERAL_BASE + peripheral_base()
+}
+
+pub fn uart0_base() -> usize {
+    match UART0_BASE_FOUND.load(Ordering::Relaxed) {
+        0 => rebase(UART0_BASE),
+        base => base,
+    }
+}
+
+pub fn aux_base() -> usize {
+    match AUX_BASE {
+        0 => 0,
+        base => rebase(base),
+    }
+}
+
+pub fn gpio_base() -> usize {
+    rebase(GPIO_BASE)
+}
+
+pub fn timer_base() -> usize {
+    match TIMER_BASE {
+        0 => 0,
+        base => rebase(base),
+    }
+}
+
+pub fn watchdog_base() -> usize {
+    match WATCHDOG_BASE {
+        0 => 0,
+        base => rebase(base),
+    }
+}
+
+pub fn mailbox_base() -> usize {
+    match MAILBOX_BASE {
+        0 => 0,
+        base => rebase(base),
+    }
+}
+
+pub fn pwm_base() -> usize {
+    match PWM_BASE {
+        0 => 0,
+        base => rebase(base),
+    }
+}
+
+pub fn audio_pwm_base() -> usize {
+    match AUDIO_PWM_BASE {
+        0 => 0,
+        base => rebase(base),
+    }
+}
+
+pub fn clock_manager_base() -> usize {
+    match CLOCK_MANAGER_BASE {
+        0 => 0,
+        base => rebase(base),
+    }
+}
+
+pub fn dma_base() -> usize {
+    match DMA_BASE {
+        0 => 0,
+        base => rebase(base),
+    }
+}
+
+pub fn rng_base() -> usize {
+    match RNG_BASE {
+        0 => 0,
+        base => rebase(base),
+    }
+}
+
+pub fn i2c1_base() -> usize {
+    match I2C1_BASE {
+        0 => 0,
+        base => rebase(base),
+    }
+}
+
+pub fn emmc_base() -> usize {
+    match EMMC_BASE {
+        0 => 0,
+        base => rebase(base),
+    }
+}
+
+pub fn wifi_sdio_base() -> usize {
+    match WIFI_SDIO_BASE {
+        0 => 0,
+        base => rebase(base),
+    }
+}
+
+// ============================================================================
+// 6. FEATURE VALIDATION
+// ============================================================================
+
+#[cfg(not(any(feature = "qemu", feature = "rpi3", feature = "rpi4", feature = "rpi5")))]
+compile_error!(
+    "Must enable exactly one hardware feature: qemu, rpi3, rpi4, or rpi5\n\
+     Example: cargo build --features qemu"
+);
+
+// This compact block ensures the compiler panics if you accidentally pass multiple flags
+#[cfg(any(
+    all(feature = "qemu", feature = "rpi3"),
+    all(feature = "qemu", feature = "rpi4"),
+    all(feature = "qemu", feature = "rpi5"),
+    all(feature = "rpi3", feature = "rpi4"),
+    all(feature = "rpi3", feature = "rpi5"),
+    all(feature = "rpi4", feature = "rpi5")
+))]
+compile_error!("Cannot enable multiple hardware features simultaneously. Pick one!");
@@ -0,0 +1,194 @@
+use super::gpio::{self, Function};
+use super::mailbox::{self, CLOCK_CORE};
+use super::timer::Timeout;
+use crate::hardwareselect::i2c1_base;
+use crate::locked::SpinLock;
+use crate::spin_until;
+use core::fmt;
+use core::ptr::{read_volatile, write_volatile};
+
+// ============================================================================
+// I2C (BSC1)
+// The Broadcom Serial Controller on header pins 3 (SDA, GPIO 2) and 5 (SCL,
+// GPIO 3), which is where RTC modules and most other I2C breakouts go. The
+// controller does whole transfers by itself: we give it the address and a
+// length, press start, and keep its 16-byte FIFO fed (or drained) until it
+// reports DONE. A device that does not answer its address shows up as ERR.
+//
+// write_read() is a write followed by a separate read with a stop between
+// them, not a repeated start. Register-pointer devices like the DS3231 keep
+// the pointer across the stop, which is all they need.
+// ============================================================================
+
+pub const BUS_HZ: u32 = 100_000;
+
+// Register offsets from i2c1_base()
+const C: usize = 0x00;
+const S: usize = 0x04;
+const DLEN: usize = 0x08;
+const A: usize = 0x0C;
+const FIFO: usize = 0x10;
+const DIV: usize = 0x14;
+
+const C_I2CEN: u32 = 1 << 15;
+const C_ST: u32 = 1 << 7;
+const C_CLEAR: u32 = 0b11 << 4;
+const C_READ: u32 = 1 << 0;
+
+const S_DONE: u32 = 1 << 1;
+const S_TXD: u32 = 1 << 4;
+const S_RXD: u32 = 1 << 5;
+const S_ERR: u32 = 1 << 8;
+const S_CLKT: u32 = 1 << 9;
+
+const SDA: u32 = 2;
+const SCL: u32 = 3;
+
+// What the core clock runs at when the firmware cannot be asked
+const DEFAULT_CORE_HZ: u32 = 250_000_000;
+
+// Far longer than a few bytes at BUS_HZ take
+const TIMEOUT_MS: u64 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2cError {
+    // No BSC on this board (RPi5)
+    Unavailable,
+    // Nobody acknowledged the address (or a data byte)
+    Nack(u8),
+    // The device held SCL low for too long
+    ClockStretch(u8),
+    Timeout,
+    // DLEN is 16 bits wide
+    TooLong(usize),
+}
+
+impl fmt::Display for I2cError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            I2cError::Unavailable => write!(f, "I2C not available"),
+            I2cError::Nack(address) => write!(f, "no answer from I2C device {:#04x}", address),
+            I2cError::ClockStretch(address) => {
+                write!(f, "I2C device {:#04x} held the clock too long", address)
+            }
+            I2cError::Timeout => write!(f, "timed out waiting for the I2C controller"),
+            I2cError::TooLong(len) => write!(f, "{} bytes is too long for one I2C transfer", len),
+        }
+    }
+}
+
+impl From<Timeout> for I2cError {
+    fn from(_: Timeout) -> Self {
+        I2cError::Timeout
+    }
+}
+
+// Whether the pins and the divider have been set up; also keeps transfers
+// from interleaving
+static BUS: SpinLock<bool> = SpinLock::new(false);
+
+fn reg(offset: usize) -> *mut u32 {
+    (i2c1_base() + offset) as *mut u32
+}
+
+fn setup() {
+    gpio::set_function(SDA, Function::Alt0);
+    gpio::set_function(SCL, Function::Alt0);
+
+    // SCL is the core clock over DIV, which the controller rounds down to
+    // an even number, so round up to stay at or below BUS_HZ
+    let core_hz = mailbox::clock_rate(CLOCK_CORE).unwrap_or(DEFAULT_CORE_HZ);
+    let divider = core_hz.div_ceil(BUS_HZ).next_multiple_of(2);
+    unsafe { write_volatile(reg(DIV), divider) };
+}
+
+// Start a transfer of `len` bytes and call `service` until the controller
+// reports DONE, then turn ERR/CLKT into errors
+fn transfer(
+    address: u8,
+    len: usize,
+    read: bool,
+    mut service: impl FnMut(u32),
+) -> Result<(), I2cError> {
+    if cfg!(feature = "rpi5") || i2c1_base() == 0 {
+        return Err(I2cError::Unavailable);
+    }
+    if len > u16::MAX as usize {
+        return Err(I2cError::TooLong(len));
+    }
+
+    let mut ready = BUS.lock();
+    if !*ready {
+        setup();
+        *ready = true;
+    }
+
+    let direction = if read { C_READ } else { 0 };
+    unsafe {
+        // Status bits are write 1 to clear
+        write_volatile(reg(S), S_DONE | S_ERR | S_CLKT);
+        write_volatile(reg(C), C_I2CEN | C_CLEAR);
+        write_volatile(reg(A), address as u32);
+        write_volatile(reg(DLEN), len as u32);
+        write_volatile(reg(C), C_I2CEN | C_ST | direction);
+
+        let waited = spin_until!(
+            {
+                let status = read_volatile(reg(S));
+                service(status);
+                status & (S_DONE | S_ERR | S_CLKT) != 0
+            },
+            TIMEOUT_MS
+        );
+        // What arrived between the last pass and DONE
+        service(read_volatile(reg(S)));
+
+        let status = read_volatile(reg(S));
+        write_volatile(reg(S), S_DONE | S_ERR | S_CLKT);
+        write_volatile(reg(C), C_I2CEN | C_CLEAR);
+
+        if status & S_ERR != 0 {
+            return Err(I2cError::Nack(address));
+        }
+        if status & S_CLKT != 0 {
+            return Err(I2cError::ClockStretch(address));
+        }
+        waited?;
+    }
+    Ok(())
+}
+
+// Send `bytes` to the 7-bit `address`
+pub fn write(address: u8, bytes: &[u8]) -> Result<(), I2cError> {
+    let mut next = 0;
+    transfer(address, bytes.len(), false, |mut status| {
+        while status & S_TXD != 0 && next < bytes.len() {
+            unsafe {
+                write_volatile(reg(FIFO), bytes[next] as u32);
+                status = read_volatile(reg(S));
+            }
+            next += 1;
+        }
+    })
+}
+
+// Fill `buffer` from the 7-bit `address`
+pub fn read(address: u8, buffer: &mut [u8]) -> Result<(), I2cError> {
+    let len = buffer.len();
+    let mut next = 0;
+    transfer(address, len, true, |mut status| {
+        while status & S_RXD != 0 && next < len {
+            unsafe {
+                buffer[next] = read_volatile(reg(FIFO)) as u8;
+                status = read_volatile(reg(S));
+            }
+            next += 1;
+        }
+    })
+}
+
+// Write `bytes` (usually a register number), then read `buffer` back
+pub fn write_read(address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), I2cError> {
+    write(address, bytes)?;
+    read(address, buffer)
+}
@@ -0,0 +1,44 @@
+// ============================================================================
+// DDOS DRIVERS
+// The Raspberry Pi device drivers of the DDOS kernel as a no_std library, so
+// other bare-metal Pi projects can use them without the kernel around them.
+// Besides the drivers it holds what they need: the board's addresses and
+// clocks (hardwareselect) and the locks (locked). Nothing here logs or
+// depends on the kernel's allocator setup; the few drivers that allocate
+// only need a #[global_allocator] from whoever links them in.
+//
+// Pick the board with exactly one feature: qemu, rpi3, rpi4 or rpi5.
+//
+// Built for the host (unit tests) the crate links std, and the CPU specific
+// bits (timer counter, barriers, interrupt masking) do nothing, so pure logic
+// can be tested without a Pi.
+// ============================================================================
+
+#![cfg_attr(not(test), no_std)]
+// Same comment style as the kernel: plain comments rather than rustdoc, and
+// const fn new() for statics instead of Default
+#![allow(clippy::missing_safety_doc, clippy::new_without_default)]
+
+extern crate alloc;
+
+pub mod hardwareselect;
+pub mod locked;
+
+pub mod button;
+pub mod console;
+pub mod dma;
+pub mod gpio;
+pub mod i2c;
+pub mod mailbox;
+pub mod miniuart;
+pub mod profile;
+pub mod pulse;
+pub mod pwm;
+pub mod rng;
+pub mod rtc;
+pub mod sdcard;
+pub mod sdhci;
+pub mod sdio;
+pub mod timer;
+pub mod uart;
+pub mod watchdog;
@@ -2,6 +2,28 @@ use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
+// Cores a lock may be contended by
+pub const MAX_CORES: usize = 4;
+
+// Which core this is, 0 to 3. The Pi 3 and 4 number their cores in MPIDR's
+// Aff0, the Pi 5's Cortex-A76 in Aff1; the other field is 0.
+#[inline(always)]
+pub fn core_id() -> usize {
+    #[cfg(target_arch = "aarch64")]
+    {
+        let mpidr: u64;
+        unsafe {
+            core::arch::asm!("mrs {0}, mpidr_el1", out(reg) mpidr, options(nomem, nostack, preserves_flags));
+        }
+        ((mpidr & 0xFF) | ((mpidr >> 8) & 0xFF)) as usize % MAX_CORES
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    {
+        0
+    }
+}
+
 // this assembly code is temporary code we will change this when we have MMU cause atmoicbool,atomicusize needs cached ram which is on qemu but not on
 // rpi so most code here does not work directly on the hardware so we go back to the basic just turn of the interupts so cpu doesnt change the thread
 // BUT THIS WORKS ONLY ON SIGNLE CORE OR SINGLE CPU READ NOTES WHY
@@ -39,11 +61,78 @@ fn restore_irq_state(was_enabled: bool) {
 // ============================================================================
 // 1. TEST-AND-SET LOCK
 // Uses: atomic .swap()
+//
+// On the RPi5 there are no atomics without the MMU, so the lock is Lamport's
+// bakery algorithm instead: plain loads and stores plus barriers, which RAM
+// with the caches off orders for every core alike. A core takes a number one
+// higher than any it sees and waits until it holds the lowest (ties go to
+// the lower core id). Interrupts stay masked while it is held, so a handler
+// on the same core cannot spin on a lock its own core holds.
 // ============================================================================
 
+#[cfg(feature = "rpi5")]
+struct Bakery {
+    choosing: [UnsafeCell<bool>; MAX_CORES],
+    number: [UnsafeCell<u32>; MAX_CORES],
+}
+
+#[cfg(feature = "rpi5")]
+impl Bakery {
+    const fn new() -> Self {
+        Bakery {
+            choosing: [const { UnsafeCell::new(false) }; MAX_CORES],
+            number: [const { UnsafeCell::new(0) }; MAX_CORES],
+        }
+    }
+
+    fn acquire(&self, me: usize) {
+        use core::ptr::{read_volatile, write_volatile};
+        use core::sync::atomic::fence;
+
+        unsafe {
+            write_volatile(self.choosing[me].get(), true);
+            fence(Ordering::SeqCst);
+
+            let highest = self
+                .number
+                .iter()
+                .map(|number| read_volatile(number.get()))
+                .max()
+                .unwrap_or(0);
+            let mine = highest + 1;
+            write_volatile(self.number[me].get(), mine);
+            fence(Ordering::SeqCst);
+
+            write_volatile(self.choosing[me].get(), false);
+            fence(Ordering::SeqCst);
+
+            for other in (0..MAX_CORES).filter(|&other| other != me) {
+                while read_volatile(self.choosing[other].get()) {
+                    core::hint::spin_loop();
+                }
+                fence(Ordering::SeqCst);
+
+                loop {
+                    let theirs = read_volatile(self.number[other].get());
+                    if theirs == 0 || (theirs, other) > (mine, me) {
+                        break;
+                    }
+                    core::hint::spin_loop();
+                }
+            }
+            fence(Ordering::SeqCst);
+        }
+    }
+
+    fn release(&self, me: usize) {
+        core::sync::atomic::fence(Ordering::SeqCst);
+        unsafe { core::ptr::write_volatile(self.number[me].get(), 0) };
+    }
+}
+
 pub struct SpinLock<T> {
     #[cfg(feature = "rpi5")]
-    locked_state: UnsafeCell<bool>,
+    locked_state: Bakery,
 
     #[cfg(not(feature = "rpi5"))]
     locked_state: AtomicBool,
@@ -58,7 +147,7 @@ impl<T> SpinLock<T> {
     pub const fn new(data: T) -> Self {
         Self {
             #[cfg(feature = "rpi5")]
-            locked_state: UnsafeCell::new(false),
+            locked_state: Bakery::new(),
 
             #[cfg(not(feature = "rpi5"))]
             locked_state: AtomicBool::new(false),
@@ -70,21 +159,11 @@ impl<T> SpinLock<T> {
     pub fn lock(&self) -> SpinLockGuard<T> {
         #[cfg(feature = "rpi5")]
         {
-            loop {
-                let irq_was_enabled = disable_irq_and_save_state();
-
-                unsafe {
-                    if !*self.locked_state.get() {
-                        *self.locked_state.get() = true;
-                        return SpinLockGuard {
-                            lock: self,
-                            irq_was_enabled,
-                        };
-                    }
-                }
-
-                restore_irq_state(irq_was_enabled);
-                core::hint::spin_loop();
+            let irq_was_enabled = disable_irq_and_save_state();
+            self.locked_state.acquire(core_id());
+            SpinLockGuard {
+                lock: self,
+                irq_was_enabled,
             }
         }
 
@@ -98,11 +177,10 @@ impl<T> SpinLock<T> {
         }
     }
 
+    // Only for the core that holds the lock
     pub fn unlock(&self) {
         #[cfg(feature = "rpi5")]
-        unsafe {
-            *self.locked_state.get() = false;
-        }
+        self.locked_state.release(core_id());
 
         #[cfg(not(feature = "rpi5"))]
         self.locked_state.store(false, Ordering::Release);
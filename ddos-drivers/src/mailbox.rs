@@ -0,0 +1,332 @@
+use super::timer::Timeout;
+use crate::hardwareselect::mailbox_base;
+use crate::locked::SpinLock;
+use crate::{profile, spin_until};
+use core::fmt;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{Ordering, compiler_fence};
+
+// ============================================================================
+// VIDEOCORE MAILBOX
+// The GPU firmware answers "property" requests through mailbox 0: we write
+// the address of a 16-byte aligned buffer of tags (plus the channel number
+// in the low four bits) and poll until the same address comes back with the
+// buffer filled in. MboxMessageBuilder lays the tags out. Not available on
+// the RPi5, whose firmware interface lives elsewhere.
+// ============================================================================
+
+// Register offsets from mailbox_base()
+const READ: usize = 0x00;
+const STATUS: usize = 0x18;
+const WRITE: usize = 0x20;
+
+const STATUS_FULL: u32 = 0x8000_0000;
+const STATUS_EMPTY: u32 = 0x4000_0000;
+
+const CHANNEL_PROPERTY: u32 = 8;
+
+const REQUEST: u32 = 0x0000_0000;
+const RESPONSE_SUCCESS: u32 = 0x8000_0000;
+const TAG_RESPONSE: u32 = 0x8000_0000;
+const TAG_END: u32 = 0;
+
+const TAG_GET_BOARD_REVISION: u32 = 0x0001_0002;
+const TAG_GET_BOARD_SERIAL: u32 = 0x0001_0004;
+const TAG_GET_ARM_MEMORY: u32 = 0x0001_0005;
+const TAG_SET_POWER_STATE: u32 = 0x0002_8001;
+pub const TAG_GET_CLOCK_RATE: u32 = 0x0003_0002;
+pub const TAG_GET_MAX_CLOCK_RATE: u32 = 0x0003_0004;
+pub const TAG_GET_TEMPERATURE: u32 = 0x0003_0006;
+pub const TAG_GET_MIN_CLOCK_RATE: u32 = 0x0003_0007;
+pub const TAG_SET_CLOCK_RATE: u32 = 0x0003_8002;
+const TAG_GET_RTC_REG: u32 = 0x0003_0087;
+const TAG_SET_RTC_REG: u32 = 0x0003_8087;
+const TAG_GET_DMA_CHANNELS: u32 = 0x0006_0001;
+
+// Clock ids for the clock tags
+pub const CLOCK_UART: u32 = 2;
+pub const CLOCK_CORE: u32 = 4;
+pub const CLOCK_ARM: u32 = 3;
+
+// Device ids for the power state tag
+pub const DEVICE_SD_CARD: u32 = 0;
+pub const DEVICE_USB: u32 = 3;
+
+// Power state request bits
+const POWER_ON: u32 = 1 << 0;
+const POWER_WAIT: u32 = 1 << 1;
+
+// Seconds since 1970 in the firmware's RTC register file
+const RTC_TIME: u32 = 0;
+
+// The only sensor the temperature tag knows
+const SENSOR_SOC: u32 = 0;
+
+const TIMEOUT_MS: u64 = 100;
+
+// Words in a message: the size/code header, the tags and the end tag
+const MESSAGE_WORDS: usize = 64;
+// id, value buffer size in bytes, request/response code
+const TAG_HEADER_WORDS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MailboxError {
+    // No mailbox on this board (RPi5) or no base address for it
+    Unavailable,
+    // The firmware did not take or answer the message within TIMEOUT_MS
+    Timeout,
+    // The buffer address cannot be passed through the mailbox
+    Misaligned,
+    // Response code of a message the firmware rejected as a whole
+    GpuError(u32),
+    // A tag came back unanswered or with more data than its buffer holds
+    TagParseError,
+}
+
+impl fmt::Display for MailboxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MailboxError::Unavailable => write!(f, "mailbox not available"),
+            MailboxError::Timeout => write!(f, "timed out waiting for the firmware"),
+            MailboxError::Misaligned => write!(f, "message buffer not 16-byte aligned below 4 GiB"),
+            MailboxError::GpuError(code) => {
+                write!(f, "firmware rejected the message ({:#010x})", code)
+            }
+            MailboxError::TagParseError => write!(f, "malformed tag in the response"),
+        }
+    }
+}
+
+impl From<Timeout> for MailboxError {
+    fn from(_: Timeout) -> Self {
+        MailboxError::Timeout
+    }
+}
+
+// The firmware needs 16-byte alignment
+#[repr(C, align(16))]
+struct Buffer([u32; MESSAGE_WORDS]);
+
+// One request at a time on the hardware
+static MAILBOX: SpinLock<()> = SpinLock::new(());
+
+fn reg(offset: usize) -> *mut u32 {
+    (mailbox_base() + offset) as *mut u32
+}
+
+// Hand `buffer` to the firmware on `channel` and wait for the answer. The
+// firmware writes the buffer behind the compiler's back, hence the fences and
+// volatile reads of the response.
+//
+// A malformed message is the usual reason for the firmware to stay silent,
+// so both waits give up after TIMEOUT_MS instead of spinning forever.
+// Answers to other messages are read and dropped while waiting for ours.
+fn call(buffer: &mut Buffer, channel: u32) -> Result<(), MailboxError> {
+    let address = buffer.0.as_mut_ptr() as usize;
+    // The register is 32 bits wide and the low four carry the channel
+    if address & 0xF != 0 || address > u32::MAX as usize {
+        return Err(MailboxError::Misaligned);
+    }
+    let message = address as u32 | channel;
+
+    let _hardware = MAILBOX.lock();
+
+    compiler_fence(Ordering::SeqCst);
+
+    unsafe {
+        spin_until!(read_volatile(reg(STATUS)) & STATUS_FULL == 0, TIMEOUT_MS)?;
+        write_volatile(reg(WRITE), message);
+
+        spin_until!(
+            read_volatile(reg(STATUS)) & STATUS_EMPTY == 0 && read_volatile(reg(READ)) == message,
+            TIMEOUT_MS
+        )?;
+
+        compiler_fence(Ordering::SeqCst);
+
+        // Pull in what the firmware wrote so plain slices can be handed out
+        for word in buffer.0.iter_mut() {
+            *word = read_volatile(word);
+        }
+    }
+
+    match buffer.0[1] {
+        RESPONSE_SUCCESS => Ok(()),
+        code => Err(MailboxError::GpuError(code)),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Message builder
+// ----------------------------------------------------------------------------
+
+// Where a tag sits in the message, for reading its response back
+#[derive(Clone, Copy)]
+pub struct TagHandle {
+    offset: usize,
+    words: usize,
+}
+
+// Builds a property message tag by tag, filling in the sizes, codes and the
+// end tag, so nobody has to count words by hand:
+//
+//     let mut message = MboxMessageBuilder::new();
+//     let rate = message.add_tag(TAG_GET_CLOCK_RATE, &[CLOCK_ARM, 0]);
+//     message.send()?;
+//     let hz = message.response(rate)?[1];
+//
+// The request slice doubles as the value buffer, so it must be as long as
+// the longer of request and response (pad with zeros).
+pub struct MboxMessageBuilder {
+    buffer: Buffer,
+    // Next free word, starts after the size and code words
+    len: usize,
+}
+
+impl MboxMessageBuilder {
+    pub fn new() -> Self {
+        MboxMessageBuilder {
+            buffer: Buffer([0; MESSAGE_WORDS]),
+            len: 2,
+        }
+    }
+
+    // Panics when the message is full: that is a bug in the caller's tag list
+    pub fn add_tag(&mut self, tag: u32, request: &[u32]) -> TagHandle {
+        let offset = self.len;
+        let end = offset + TAG_HEADER_WORDS + request.len();
+        assert!(end < MESSAGE_WORDS, "mailbox message too long");
+
+        let words = &mut self.buffer.0;
+        words[offset] = tag;
+        words[offset + 1] = (request.len() * 4) as u32;
+        words[offset + 2] = 0;
+        words[offset + TAG_HEADER_WORDS..end].copy_from_slice(request);
+
+        self.len = end;
+        TagHandle {
+            offset,
+            words: request.len(),
+        }
+    }
+
+    // Send on the property channel (the `mailbox` probe times the round trip)
+    pub fn send(&mut self) -> Result<(), MailboxError> {
+        if cfg!(feature = "rpi5") || mailbox_base() == 0 {
+            return Err(MailboxError::Unavailable);
+        }
+
+        let words = &mut self.buffer.0;
+        words[0] = ((self.len + 1) * 4) as u32;
+        words[1] = REQUEST;
+        words[self.len] = TAG_END;
+
+        profile!("mailbox", call(&mut self.buffer, CHANNEL_PROPERTY))
+    }
+
+    // The value the firmware wrote back for `handle`. Fails if it did not
+    // answer that tag, or wanted more room than add_tag gave it (the value
+    // is cut short then).
+    pub fn response(&self, handle: TagHandle) -> Result<&[u32], MailboxError> {
+        let words = &self.buffer.0;
+        let code = words[handle.offset + 2];
+
+        if code & TAG_RESPONSE == 0 {
+            return Err(MailboxError::TagParseError);
+        }
+
+        let length = ((code & !TAG_RESPONSE) as usize).div_ceil(4);
+        if length > handle.words {
+            return Err(MailboxError::TagParseError);
+        }
+        let start = handle.offset + TAG_HEADER_WORDS;
+        Ok(&words[start..start + length])
+    }
+}
+
+// A request with a single tag whose value is N words, returns the response value
+fn property<const N: usize>(tag: u32, request: [u32; N]) -> Result<[u32; N], MailboxError> {
+    let mut message = MboxMessageBuilder::new();
+    let handle = message.add_tag(tag, &request);
+    message.send()?;
+
+    let response = message.response(handle)?;
+    let mut value = [0u32; N];
+    value[..response.len()].copy_from_slice(response);
+    Ok(value)
+}
+
+// (base, size) of the RAM the GPU leaves to the ARM cores
+pub fn arm_memory() -> Result<(usize, usize), MailboxError> {
+    let [base, size] = property(TAG_GET_ARM_MEMORY, [0, 0])?;
+    Ok((base as usize, size as usize))
+}
+
+// Revision code, e.g. 0xa02082 for a Pi 3 Model B
+pub fn board_revision() -> Result<u32, MailboxError> {
+    let [revision] = property(TAG_GET_BOARD_REVISION, [0])?;
+    Ok(revision)
+}
+
+pub fn board_serial() -> Result<u64, MailboxError> {
+    let [low, high] = property(TAG_GET_BOARD_SERIAL, [0, 0])?;
+    Ok((high as u64) << 32 | low as u64)
+}
+
+// Bit n set: DMA channel n is free for the ARM, the firmware uses the rest
+pub fn dma_channels() -> Result<u32, MailboxError> {
+    let [mask] = property(TAG_GET_DMA_CHANNELS, [0])?;
+    Ok(mask)
+}
+
+// SoC temperature in thousandths of a degree Celsius
+pub fn temperature() -> Result<u32, MailboxError> {
+    let [_, millidegrees] = property(TAG_GET_TEMPERATURE, [SENSOR_SOC, 0])?;
+    Ok(millidegrees)
+}
+
+// Switch a device's power and wait for it to settle. Returns whether the
+// device is on afterwards (false too for ids the firmware does not know).
+pub fn set_power_state(device: u32, on: bool) -> Result<bool, MailboxError> {
+    let request = if on {
+        POWER_ON | POWER_WAIT
+    } else {
+        POWER_WAIT
+    };
+    let [_, state] = property(TAG_SET_POWER_STATE, [device, request])?;
+    Ok(state & POWER_ON != 0)
+}
+
+pub fn clock_rate(clock: u32) -> Result<u32, MailboxError> {
+    let [_, rate] = property(TAG_GET_CLOCK_RATE, [clock, 0])?;
+    Ok(rate)
+}
+
+pub fn max_clock_rate(clock: u32) -> Result<u32, MailboxError> {
+    let [_, rate] = property(TAG_GET_MAX_CLOCK_RATE, [clock, 0])?;
+    Ok(rate)
+}
+
+pub fn min_clock_rate(clock: u32) -> Result<u32, MailboxError> {
+    let [_, rate] = property(TAG_GET_MIN_CLOCK_RATE, [clock, 0])?;
+    Ok(rate)
+}
+
+// Returns the rate the firmware actually settled on. The last word asks it
+// not to apply turbo settings on top.
+pub fn set_clock_rate(clock: u32, hz: u32) -> Result<u32, MailboxError> {
+    let [_, rate, _] = property(TAG_SET_CLOCK_RATE, [clock, hz, 1])?;
+    Ok(rate)
+}
+
+// Seconds since 1970 from the RTC the firmware looks after. Only firmware
+// for a board with an RTC answers; the others reject the tag.
+pub fn rtc_time() -> Result<u32, MailboxError> {
+    let [_, seconds] = property(TAG_GET_RTC_REG, [RTC_TIME, 0])?;
+    Ok(seconds)
+}
+
+pub fn set_rtc_time(seconds: u32) -> Result<(), MailboxError> {
+    property(TAG_SET_RTC_REG, [RTC_TIME, seconds])?;
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use crate::hardwareselect::{CORE_CLOCK_HZ, aux_base};
+use crate::locked::SpinLock;
+use crate::mailbox::{self, CLOCK_CORE};
+use crate::spin_until;
+use crate::timer::Timeout;
+use core::fmt;
+use core::ptr::{read_volatile, write_volatile};
+
+// ============================================================================
+// MINI UART (UART1)
+// The 16550-like UART in the AUX block. On boards with Bluetooth the
+// firmware gives the PL011 to the radio and puts this one on GPIO 14/15,
+// so it is often the serial console a RPi3 comes up with. Compared to the
+// PL011 it has 8-byte FIFOs, no parity and a baud rate divided from the VPU
+// core clock instead of a clock of its own: whatever the firmware reports
+// (core_freq in config.txt), or hardwareselect::CORE_CLOCK_HZ.
+//
+// Same interface as uart::Uart. The RPi5 has no AUX block: init() does
+// nothing there and available() says so.
+// ============================================================================
+
+// Register offsets from aux_base()
+const AUX_ENABLES: usize = 0x04;
+const MU_IO: usize = 0x40;
+const MU_IER: usize = 0x44;
+const MU_IIR: usize = 0x48;
+const MU_LCR: usize = 0x4C;
+const MU_MCR: usize = 0x50;
+const MU_LSR: usize = 0x54;
+const MU_CNTL: usize = 0x60;
+const MU_BAUD: usize = 0x68;
+
+const ENABLES_MINI_UART: u32 = 1 << 0;
+
+// Writing both clear bits empties the FIFOs
+const IIR_CLEAR_FIFOS: u32 = 0b11 << 1;
+// 8 data bits (bit 1 is undocumented but needed for 8 bits)
+const LCR_8BIT: u32 = 0b11;
+const CNTL_RX_ENABLE: u32 = 1 << 0;
+const CNTL_TX_ENABLE: u32 = 1 << 1;
+
+// Line status bits
+const LSR_DATA_READY: u32 = 1 << 0;
+// Room for at least one more byte in the transmit FIFO
+const LSR_TX_EMPTY: u32 = 1 << 5;
+// FIFO empty and the last bit sent
+const LSR_TX_IDLE: u32 = 1 << 6;
+
+// The 8-byte FIFO drains in under 1 ms at 115200 baud
+const TX_TIMEOUT_MS: u64 = 10;
+
+fn reg(offset: usize) -> *mut u32 {
+    (aux_base() + offset) as *mut u32
+}
+
+fn clock_hz() -> u32 {
+    match mailbox::clock_rate(CLOCK_CORE) {
+        Ok(hz) if hz > 0 => hz,
+        _ => CORE_CLOCK_HZ,
+    }
+}
+
+pub struct MiniUart;
+
+pub static MINI_UART: SpinLock<MiniUart> = SpinLock::new(MiniUart::new());
+
+impl MiniUart {
+    const BAUD_RATE: u32 = 115_200;
+
+    pub const fn new() -> MiniUart {
+        MiniUart
+    }
+
+    // False on boards without an AUX block
+    pub fn available(&self) -> bool {
+        aux_base() != 0
+    }
+
+    // The pins are left alone, see console::select() for those
+    pub fn init(&self) {
+        if !self.available() {
+            return;
+        }
+
+        // baud = core clock / (8 * (divisor + 1))
+        let divisor = (clock_hz() + 4 * Self::BAUD_RATE) / (8 * Self::BAUD_RATE) - 1;
+
+        unsafe {
+            let enables = read_volatile(reg(AUX_ENABLES));
+            write_volatile(reg(AUX_ENABLES), enables | ENABLES_MINI_UART);
+            write_volatile(reg(MU_CNTL), 0);
+            write_volatile(reg(MU_IER), 0);
+            write_volatile(reg(MU_LCR), LCR_8BIT);
+            write_volatile(reg(MU_MCR), 0);
+            write_volatile(reg(MU_IIR), IIR_CLEAR_FIFOS);
+            write_volatile(reg(MU_BAUD), divisor);
+            write_volatile(reg(MU_CNTL), CNTL_RX_ENABLE | CNTL_TX_ENABLE);
+        }
+    }
+
+    // Err when the transmit FIFO stays full, the character is dropped then
+    pub fn send(&self, c: char) -> Result<(), Timeout> {
+        if !self.available() {
+            return Ok(());
+        }
+        unsafe {
+            spin_until!(
+                read_volatile(reg(MU_LSR)) & LSR_TX_EMPTY != 0,
+                TX_TIMEOUT_MS
+            )?;
+            write_volatile(reg(MU_IO), c as u32 & 0xFF);
+        }
+        Ok(())
+    }
+
+    // Hand one byte to the transmit FIFO if it has room, never waits
+    pub fn try_send(&self, byte: u8) -> bool {
+        if !self.available() {
+            return true;
+        }
+        unsafe {
+            if read_volatile(reg(MU_LSR)) & LSR_TX_EMPTY == 0 {
+                return false;
+            }
+            write_volatile(reg(MU_IO), byte as u32);
+        }
+        true
+    }
+
+    // Wait until the last byte handed over has left the pins
+    pub fn flush(&self) -> Result<(), Timeout> {
+        if !self.available() {
+            return Ok(());
+        }
+        unsafe { spin_until!(read_volatile(reg(MU_LSR)) & LSR_TX_IDLE != 0, TX_TIMEOUT_MS) }
+    }
+
+    // Waits for a key as long as it takes, like Uart::read_byte()
+    pub fn read_byte(&self) -> u8 {
+        loop {
+            if let Some(byte) = self.try_read_byte() {
+                return byte;
+            }
+        }
+    }
+
+    // Something is waiting in the receive FIFO
+    pub fn has_input(&self) -> bool {
+        self.available() && unsafe { read_volatile(reg(MU_LSR)) & LSR_DATA_READY != 0 }
+    }
+
+    // Non-blocking read, None while the receive FIFO is empty
+    pub fn try_read_byte(&self) -> Option<u8> {
+        if !self.has_input() {
+            return None;
+        }
+        Some(unsafe { (read_volatile(reg(MU_IO)) & 0xFF) as u8 })
+    }
+}
+
+impl fmt::Write for MiniUart {
+    // UTF-8 bytes and one timeout per print, like Uart
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send('\r').map_err(|_| fmt::Error)?;
+            }
+            self.send(byte as char).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
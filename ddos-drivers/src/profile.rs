@@ -0,0 +1,321 @@
+use crate::locked::SpinLock;
+#[cfg(target_arch = "aarch64")]
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+// ============================================================================
+// PROFILING
+// The ARMv8 PMU counts on every core: a 64-bit cycle counter and a few event
+// counters. init() sets up the core it runs on with
+//
+//     cycles        PMCCNTR_EL0, CPU clock cycles at EL0 and EL1
+//     instructions  event counter 0, INST_RETIRED (0x08)
+//     cache misses  event counter 1, L1D_CACHE_REFILL (0x03)
+//
+// Profiler::measure(|| ...) counts one closure. A Probe adds up every call
+// made through it, and a probe that recorded anything shows up in probes()
+// for the `perf` command:
+//
+//     let frame = profile!("render", draw(&scene));
+//
+// declares the probe in place and measures the expression. The counters are
+// per core, so a probe only means something for code that runs where init()
+// ran. Without a PMU (or on the host) every count reads zero.
+// ============================================================================
+
+// Probes that can show up in probes()
+pub const MAX_PROBES: usize = 16;
+
+// PMCR_EL0
+#[cfg(target_arch = "aarch64")]
+const PMCR_E: u64 = 1 << 0;
+#[cfg(target_arch = "aarch64")]
+const PMCR_P: u64 = 1 << 1;
+#[cfg(target_arch = "aarch64")]
+const PMCR_C: u64 = 1 << 2;
+// Cycle counter overflows at 64 bits, not 32
+#[cfg(target_arch = "aarch64")]
+const PMCR_LC: u64 = 1 << 6;
+#[cfg(target_arch = "aarch64")]
+const PMCR_N_SHIFT: u64 = 11;
+
+// PMCNTENSET_EL0
+#[cfg(target_arch = "aarch64")]
+const CNTEN_CYCLES: u64 = 1 << 31;
+
+#[cfg(target_arch = "aarch64")]
+const EVENT_INST_RETIRED: u64 = 0x08;
+#[cfg(target_arch = "aarch64")]
+const EVENT_L1D_CACHE_REFILL: u64 = 0x03;
+
+// ID_AA64DFR0_EL1.PMUVer: 0 is none, 0xF an implementation defined one
+#[cfg(target_arch = "aarch64")]
+const DFR0_PMUVER_SHIFT: u64 = 8;
+
+// Set once init() found a PMU. Probes record while ENABLED (`perf on`).
+static READY: AtomicBool = AtomicBool::new(false);
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static EVENTS: AtomicBool = AtomicBool::new(false);
+
+// Counter values at one moment, or the difference between two
+#[derive(Clone, Copy, Default)]
+pub struct Sample {
+    pub cycles: u64,
+    pub instructions: u64,
+    pub cache_misses: u64,
+}
+
+impl Sample {
+    // Counts since `earlier`. The event counters are 32 bits and wrap.
+    pub fn since(&self, earlier: &Sample) -> Sample {
+        Sample {
+            cycles: self.cycles.wrapping_sub(earlier.cycles),
+            instructions: (self.instructions as u32).wrapping_sub(earlier.instructions as u32)
+                as u64,
+            cache_misses: (self.cache_misses as u32).wrapping_sub(earlier.cache_misses as u32)
+                as u64,
+        }
+    }
+}
+
+fn has_pmu() -> bool {
+    #[cfg(target_arch = "aarch64")]
+    {
+        let dfr0: u64;
+        unsafe {
+            asm!("mrs {0}, id_aa64dfr0_el1", out(reg) dfr0, options(nomem, nostack));
+        }
+        !matches!((dfr0 >> DFR0_PMUVER_SHIFT) & 0xF, 0 | 0xF)
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    {
+        false
+    }
+}
+
+// Reset and start the counters of the calling core. False without a PMU.
+pub fn init() -> bool {
+    if !has_pmu() {
+        return false;
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        let pmcr: u64;
+        asm!("mrs {0}, pmcr_el0", out(reg) pmcr, options(nomem, nostack));
+        let events = ((pmcr >> PMCR_N_SHIFT) & 0x1F) >= 2;
+
+        // Zero filter bits: count at EL0 and EL1
+        asm!("msr pmccfiltr_el0, xzr", options(nomem, nostack));
+        let mut enable = CNTEN_CYCLES;
+        if events {
+            asm!("msr pmevtyper0_el0, {0}", in(reg) EVENT_INST_RETIRED, options(nomem, nostack));
+            asm!("msr pmevtyper1_el0, {0}", in(reg) EVENT_L1D_CACHE_REFILL, options(nomem, nostack));
+            enable |= 0b11;
+        }
+        asm!("msr pmcntenset_el0, {0}", in(reg) enable, options(nomem, nostack));
+        asm!(
+            "msr pmcr_el0, {0}",
+            "isb",
+            in(reg) PMCR_E | PMCR_P | PMCR_C | PMCR_LC,
+            options(nomem, nostack),
+        );
+
+        EVENTS.store(events, Ordering::Relaxed);
+    }
+
+    READY.store(true, Ordering::Relaxed);
+    ENABLED.store(true, Ordering::Relaxed);
+    true
+}
+
+// Whether instructions and cache misses are counted, not just cycles
+pub fn has_events() -> bool {
+    EVENTS.load(Ordering::Relaxed)
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Turns the probes on and off (Profiler::measure keeps counting). False
+// when asked to turn them on without a PMU.
+pub fn set_enabled(on: bool) -> bool {
+    if on && !READY.load(Ordering::Relaxed) {
+        return false;
+    }
+    ENABLED.store(on, Ordering::Relaxed);
+    true
+}
+
+#[inline(always)]
+pub fn read() -> Sample {
+    #[cfg(target_arch = "aarch64")]
+    {
+        let (cycles, instructions, cache_misses): (u64, u64, u64);
+        unsafe {
+            asm!("mrs {0}, pmccntr_el0", out(reg) cycles, options(nomem, nostack, preserves_flags));
+            if has_events() {
+                asm!("mrs {0}, pmevcntr0_el0", out(reg) instructions, options(nomem, nostack, preserves_flags));
+                asm!("mrs {0}, pmevcntr1_el0", out(reg) cache_misses, options(nomem, nostack, preserves_flags));
+            } else {
+                instructions = 0;
+                cache_misses = 0;
+            }
+        }
+        Sample {
+            cycles,
+            instructions,
+            cache_misses,
+        }
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    {
+        Sample::default()
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Measuring
+// ----------------------------------------------------------------------------
+
+pub struct Profiler {
+    start: Sample,
+}
+
+impl Profiler {
+    pub fn start() -> Self {
+        Profiler { start: read() }
+    }
+
+    // Counts since start()
+    pub fn stop(self) -> Sample {
+        read().since(&self.start)
+    }
+
+    pub fn measure<T>(f: impl FnOnce() -> T) -> (T, Sample) {
+        let profiler = Profiler::start();
+        let value = f();
+        (value, profiler.stop())
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Totals {
+    pub calls: u64,
+    pub cycles: u64,
+    pub instructions: u64,
+    pub cache_misses: u64,
+    // Slowest single call
+    pub max_cycles: u64,
+}
+
+impl Totals {
+    const ZERO: Totals = Totals {
+        calls: 0,
+        cycles: 0,
+        instructions: 0,
+        cache_misses: 0,
+        max_cycles: 0,
+    };
+}
+
+// Named running totals for one piece of code, kept in a static
+pub struct Probe {
+    name: &'static str,
+    totals: SpinLock<Totals>,
+    registered: AtomicBool,
+}
+
+static PROBES: SpinLock<[Option<&'static Probe>; MAX_PROBES]> = SpinLock::new([None; MAX_PROBES]);
+
+impl Probe {
+    pub const fn new(name: &'static str) -> Self {
+        Probe {
+            name,
+            totals: SpinLock::new(Totals::ZERO),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn totals(&self) -> Totals {
+        *self.totals.lock()
+    }
+
+    pub fn reset(&self) {
+        *self.totals.lock() = Totals::ZERO;
+    }
+
+    pub fn record(&'static self, sample: &Sample) {
+        {
+            let mut totals = self.totals.lock();
+            totals.calls += 1;
+            totals.cycles += sample.cycles;
+            totals.instructions += sample.instructions;
+            totals.cache_misses += sample.cache_misses;
+            totals.max_cycles = totals.max_cycles.max(sample.cycles);
+        }
+
+        // Checked again under the lock, so two cores racing here still
+        // list the probe once
+        if !self.registered.load(Ordering::Relaxed) {
+            self.registered.store(true, Ordering::Relaxed);
+            let mut probes = PROBES.lock();
+            if !probes
+                .iter()
+                .flatten()
+                .any(|probe| core::ptr::eq(*probe, self))
+                && let Some(slot) = probes.iter_mut().find(|slot| slot.is_none())
+            {
+                *slot = Some(self);
+            }
+        }
+    }
+
+    // Runs `f`, and counts it while probes are enabled
+    #[inline(always)]
+    pub fn measure<T>(&'static self, f: impl FnOnce() -> T) -> T {
+        if !enabled() {
+            return f();
+        }
+        let (value, sample) = Profiler::measure(f);
+        self.record(&sample);
+        value
+    }
+}
+
+// Every probe that recorded a call since boot, in order of first call
+pub fn probes() -> impl Iterator<Item = &'static Probe> {
+    let probes = *PROBES.lock();
+    probes.into_iter().flatten()
+}
+
+pub fn reset() {
+    for probe in probes() {
+        probe.reset();
+    }
+}
+
+// profile!("name", expr): evaluates `expr` through a probe called "name"
+// declared right there, and gives its value back
+#[macro_export]
+macro_rules! profile {
+    ($name:expr, $body:expr $(,)?) => {{
+        static PROBE: $crate::profile::Probe = $crate::profile::Probe::new($name);
+        PROBE.measure(|| $body)
+    }};
+}
+
+// measure!(expr): (value of expr, Sample), whether probes are enabled or not
+#[macro_export]
+macro_rules! measure {
+    ($body:expr $(,)?) => {
+        $crate::profile::Profiler::measure(|| $body)
+    };
+}
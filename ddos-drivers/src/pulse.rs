@@ -0,0 +1,59 @@
+use super::gpio::{self, Edge};
+use super::timer;
+
+// ============================================================================
+// PULSE COUNTING
+// Counts edges on a pin over a gate time: tachometers, flow sensors, or a
+// PWM output wired back to an input to check it. The GPIO event detect
+// hardware catches the edges, but there are no GPIO interrupts yet, so
+// count() polls (and clears) the sticky status bit for the whole gate time.
+// Two edges landing between polls count as one, which caps the usable rate
+// at what the loop can keep up with, far below the CPU clock.
+//
+// The caller sets the pin up first, usually as an input with a pull.
+// ============================================================================
+
+pub const MAX_GATE_MS: u64 = 10_000;
+
+#[derive(Clone, Copy)]
+pub struct Measurement {
+    pub edges: u64,
+    // Actual gate time, which runs a little over what was asked for
+    pub gate_us: u64,
+}
+
+impl Measurement {
+    // Edges per second in thousandths, so slow signals keep their fraction
+    pub fn millihertz(&self) -> u64 {
+        (self.edges as u128 * 1_000_000_000 / self.gate_us.max(1) as u128) as u64
+    }
+}
+
+// Count `edge`s on `pin` for `gate_ms` (clamped to 1..=MAX_GATE_MS)
+pub fn count(pin: u32, edge: Edge, gate_ms: u64) -> Measurement {
+    let gate_ms = gate_ms.clamp(1, MAX_GATE_MS);
+    let ticks = gate_ms * timer::frequency() / 1000;
+    let mut edges = 0;
+
+    gpio::set_edge_detect(pin, edge);
+    let start = timer::counter();
+
+    while timer::counter() - start < ticks {
+        if gpio::take_event(pin) {
+            edges += 1;
+        }
+    }
+
+    let elapsed = timer::counter() - start;
+    gpio::set_edge_detect(pin, Edge::None);
+
+    Measurement {
+        edges,
+        gate_us: timer::ticks_to_us(elapsed),
+    }
+}
+
+// One rising edge per period, so the edge rate is the frequency
+pub fn frequency(pin: u32, gate_ms: u64) -> Measurement {
+    count(pin, Edge::Rising, gate_ms)
+}
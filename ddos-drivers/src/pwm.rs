@@ -0,0 +1,322 @@
+use super::gpio::{self, Function};
+use super::timer;
+use crate::hardwareselect::{OSCILLATOR_HZ, audio_pwm_base, clock_manager_base, pwm_base};
+use crate::locked::{SpinLock, SpinLockGuard};
+use crate::spin_until;
+use core::fmt;
+use core::ptr::{read_volatile, write_volatile};
+
+// ============================================================================
+// PWM: SERVOS, MOTORS, LEDS AND TONES
+// The PWM block has two channels, each reachable from two header pins. Both
+// run in mark/space mode: the output is high for `high` ticks out of every
+// `period` ticks. The clock manager divides the crystal down to PWM_CLOCK_HZ
+// so a tick is one microsecond.
+//
+// servo() turns an angle into the usual 50 Hz hobby servo pulse, motor() a
+// duty cycle into a 1 kHz signal for a motor driver. Both clamp their input
+// to a range that cannot drive the hardware past its end stops. Pins on the
+// same channel (12 and 18, 13 and 19) always carry the same signal.
+//
+// The headphone jack hangs off GPIO 40 (left) and 41 (right) behind a
+// low-pass filter, so a square wave there is an audible tone: beep() plays
+// one on both sides. play() streams samples through the FIFO instead of the
+// data register, one sample per period, which is enough for simple
+// waveforms at low sample rates with a 1 MHz clock.
+// ============================================================================
+
+pub const PWM_CLOCK_HZ: u32 = 1_000_000;
+
+// Most servos want a 20 ms frame and a 1-2 ms pulse. Some take a wider
+// range, but going past the stops stalls (and heats) the motor.
+pub const SERVO_PERIOD_US: u32 = 20_000;
+pub const SERVO_MIN_US: u32 = 1000;
+pub const SERVO_MAX_US: u32 = 2000;
+pub const SERVO_MAX_ANGLE: u32 = 180;
+
+pub const MOTOR_HZ: u32 = 1000;
+
+// Fast enough that a dimmed LED does not flicker
+pub const LED_HZ: u32 = 1000;
+
+// Headphone jack, left and right
+pub const AUDIO_LEFT: u32 = 40;
+pub const AUDIO_RIGHT: u32 = 41;
+
+// PWM register offsets from the block's base, channel 2's are
+// CHANNEL_STRIDE higher
+const CTL: usize = 0x00;
+const STA: usize = 0x04;
+const RNG1: usize = 0x10;
+const DAT1: usize = 0x14;
+const FIF1: usize = 0x18;
+const CHANNEL_STRIDE: usize = 0x10;
+
+// Channel 1 bits in CTL, channel 2's are CTL_CHANNEL_SHIFT higher
+const CTL_PWEN: u32 = 1 << 0;
+const CTL_USEF: u32 = 1 << 5;
+const CTL_MSEN: u32 = 1 << 7;
+const CTL_CHANNEL_SHIFT: usize = 8;
+// Shared by both channels
+const CTL_CLRF: u32 = 1 << 6;
+
+const STA_FULL: u32 = 1 << 0;
+const STA_EMPTY: u32 = 1 << 1;
+
+// Clock manager registers for the PWM clock, from clock_manager_base()
+const CM_PWMCTL: usize = 0xA0;
+const CM_PWMDIV: usize = 0xA4;
+
+// Every clock manager write needs this in the top byte
+const CM_PASSWORD: u32 = 0x5A00_0000;
+const CM_SRC_OSCILLATOR: u32 = 1;
+const CM_ENAB: u32 = 1 << 4;
+const CM_BUSY: u32 = 1 << 7;
+// 1-stage MASH, needed for the fractional part of the divider to count
+const CM_MASH_1: u32 = 1 << 9;
+
+const CLOCK_TIMEOUT_MS: u64 = 10;
+
+// A FIFO slot frees up every period, which is at most 65 ms at 1 MHz
+const FIFO_TIMEOUT_MS: u64 = 100;
+
+#[derive(Clone, Copy, Debug)]
+pub enum PwmError {
+    // No PWM block on this board (RPi5)
+    Unavailable,
+    NotPwmPin(u32),
+    // The PWM clock did not stop to take its new divider
+    ClockTimeout,
+    // The FIFO stopped draining
+    FifoTimeout,
+    // Zero, or too low to fit a period into the range register
+    BadFrequency(u32),
+}
+
+impl fmt::Display for PwmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PwmError::Unavailable => write!(f, "PWM not available"),
+            PwmError::NotPwmPin(pin) => write!(f, "GPIO{} has no PWM (use 12, 13, 18 or 19)", pin),
+            PwmError::ClockTimeout => write!(f, "timed out stopping the PWM clock"),
+            PwmError::FifoTimeout => write!(f, "timed out waiting for the PWM FIFO"),
+            PwmError::BadFrequency(hz) => write!(f, "cannot make {} Hz", hz),
+        }
+    }
+}
+
+// Whether the PWM clock has been set up
+static CLOCK_RUNNING: SpinLock<bool> = SpinLock::new(false);
+
+// Where the signal on a pin comes from
+#[derive(Clone, Copy)]
+struct Route {
+    base: usize,
+    channel: usize,
+    function: Function,
+}
+
+impl Route {
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (self.base + offset) as *mut u32
+    }
+
+    // The channel's copy of a per-channel register
+    fn channel_reg(&self, offset: usize) -> *mut u32 {
+        self.reg(offset + self.channel * CHANNEL_STRIDE)
+    }
+
+    // The channel's copy of a CTL bit
+    fn ctl_bits(&self, bits: u32) -> u32 {
+        bits << (self.channel * CTL_CHANNEL_SHIFT)
+    }
+}
+
+// Block, channel and alternate function that bring the PWM out on `pin`
+fn route(pin: u32) -> Result<Route, PwmError> {
+    if cfg!(feature = "rpi5") || pwm_base() == 0 {
+        return Err(PwmError::Unavailable);
+    }
+
+    let (base, channel, function) = match pin {
+        12 => (pwm_base(), 0, Function::Alt0),
+        13 => (pwm_base(), 1, Function::Alt0),
+        18 => (pwm_base(), 0, Function::Alt5),
+        19 => (pwm_base(), 1, Function::Alt5),
+        AUDIO_LEFT => (audio_pwm_base(), 0, Function::Alt0),
+        AUDIO_RIGHT => (audio_pwm_base(), 1, Function::Alt0),
+        _ => return Err(PwmError::NotPwmPin(pin)),
+    };
+    Ok(Route {
+        base,
+        channel,
+        function,
+    })
+}
+
+fn clock_reg(offset: usize) -> *mut u32 {
+    (clock_manager_base() + offset) as *mut u32
+}
+
+// The divider may only change while the clock is stopped and no longer busy
+fn start_clock() -> Result<(), PwmError> {
+    // 12.12 fixed point, which is exactly the DIVI/DIVF layout of CM_PWMDIV
+    let divisor = (OSCILLATOR_HZ as u64 * 4096 / PWM_CLOCK_HZ as u64) as u32;
+    let mash = if divisor & 0xFFF != 0 { CM_MASH_1 } else { 0 };
+
+    unsafe {
+        write_volatile(clock_reg(CM_PWMCTL), CM_PASSWORD | CM_SRC_OSCILLATOR);
+
+        spin_until!(
+            read_volatile(clock_reg(CM_PWMCTL)) & CM_BUSY == 0,
+            CLOCK_TIMEOUT_MS
+        )
+        .map_err(|_| PwmError::ClockTimeout)?;
+
+        write_volatile(clock_reg(CM_PWMDIV), CM_PASSWORD | divisor);
+        write_volatile(clock_reg(CM_PWMCTL), CM_PASSWORD | mash | CM_SRC_OSCILLATOR);
+        write_volatile(
+            clock_reg(CM_PWMCTL),
+            CM_PASSWORD | mash | CM_SRC_OSCILLATOR | CM_ENAB,
+        );
+    }
+    Ok(())
+}
+
+// Lock the block, starting the clock on first use
+fn lock_running() -> Result<SpinLockGuard<'static, bool>, PwmError> {
+    let mut clock_running = CLOCK_RUNNING.lock();
+    if !*clock_running {
+        start_clock()?;
+        *clock_running = true;
+    }
+    Ok(clock_running)
+}
+
+// Drive `pin` high for `high` out of every `period` ticks of PWM_CLOCK_HZ
+pub fn output(pin: u32, period: u32, high: u32) -> Result<(), PwmError> {
+    let route = route(pin)?;
+    let _lock = lock_running()?;
+
+    unsafe {
+        write_volatile(route.channel_reg(RNG1), period);
+        write_volatile(route.channel_reg(DAT1), high.min(period));
+
+        let control = read_volatile(route.reg(CTL)) & !route.ctl_bits(CTL_USEF);
+        write_volatile(
+            route.reg(CTL),
+            control | route.ctl_bits(CTL_PWEN | CTL_MSEN),
+        );
+    }
+
+    gpio::set_function(pin, route.function);
+    Ok(())
+}
+
+// Stop the channel behind `pin`, which leaves its pins low
+pub fn stop(pin: u32) -> Result<(), PwmError> {
+    let route = route(pin)?;
+
+    let _lock = CLOCK_RUNNING.lock();
+    unsafe {
+        let control = read_volatile(route.reg(CTL));
+        write_volatile(route.reg(CTL), control & !route.ctl_bits(CTL_PWEN));
+    }
+    Ok(())
+}
+
+// Period in ticks for a signal of `hz`
+fn period_for(hz: u32) -> Result<u32, PwmError> {
+    match PWM_CLOCK_HZ.checked_div(hz) {
+        Some(period) if period >= 2 => Ok(period),
+        _ => Err(PwmError::BadFrequency(hz)),
+    }
+}
+
+// `hz` on `pin`, high for `percent` of each period (capped at 100)
+pub fn square(pin: u32, hz: u32, percent: u32) -> Result<(), PwmError> {
+    let period = period_for(hz)?;
+    output(pin, period, period * percent.min(100) / 100)
+}
+
+// Stream `samples` out of `pin`'s channel through the FIFO. Each sample is
+// the high time of one period of `period` ticks, so they play at
+// PWM_CLOCK_HZ / period samples a second. Returns once the last one went
+// out, with the channel stopped. The FIFO is shared, so only one channel
+// can play at a time.
+pub fn play(pin: u32, period: u32, samples: &[u32]) -> Result<(), PwmError> {
+    let route = route(pin)?;
+    let _lock = lock_running()?;
+
+    unsafe {
+        write_volatile(route.channel_reg(RNG1), period);
+
+        let control = read_volatile(route.reg(CTL)) & !route.ctl_bits(CTL_PWEN);
+        write_volatile(route.reg(CTL), control | CTL_CLRF);
+        write_volatile(
+            route.reg(CTL),
+            control | route.ctl_bits(CTL_PWEN | CTL_MSEN | CTL_USEF),
+        );
+        gpio::set_function(pin, route.function);
+
+        let queued = samples.iter().try_for_each(|&sample| {
+            spin_until!(
+                read_volatile(route.reg(STA)) & STA_FULL == 0,
+                FIFO_TIMEOUT_MS
+            )?;
+            write_volatile(route.reg(FIF1), sample.min(period));
+            Ok(())
+        });
+        let drained = queued.and_then(|()| {
+            spin_until!(
+                read_volatile(route.reg(STA)) & STA_EMPTY != 0,
+                FIFO_TIMEOUT_MS
+            )
+        });
+
+        // Stopped either way, a stuck FIFO must not keep the pin busy
+        let control = read_volatile(route.reg(CTL));
+        write_volatile(
+            route.reg(CTL),
+            control & !route.ctl_bits(CTL_PWEN | CTL_USEF),
+        );
+        drained.map_err(|_| PwmError::FifoTimeout)
+    }
+}
+
+// A square wave of `hz` on the headphone jack for `ms` milliseconds, for
+// diagnostic tones. Blocks while it plays.
+pub fn beep(hz: u32, ms: u64) -> Result<(), PwmError> {
+    for pin in [AUDIO_LEFT, AUDIO_RIGHT] {
+        square(pin, hz, 50)?;
+    }
+    timer::delay_ms(ms);
+    for pin in [AUDIO_LEFT, AUDIO_RIGHT] {
+        stop(pin)?;
+    }
+    Ok(())
+}
+
+// Pulse width in microseconds for `angle` degrees, clamped to the servo range
+pub fn servo_pulse_us(angle: u32) -> u32 {
+    let angle = angle.min(SERVO_MAX_ANGLE);
+    SERVO_MIN_US + (SERVO_MAX_US - SERVO_MIN_US) * angle / SERVO_MAX_ANGLE
+}
+
+// Turn the servo on `pin` to `angle` degrees (0 to SERVO_MAX_ANGLE).
+// Returns the pulse width actually sent.
+pub fn servo(pin: u32, angle: u32) -> Result<u32, PwmError> {
+    let pulse = servo_pulse_us(angle);
+    output(pin, SERVO_PERIOD_US, pulse)?;
+    Ok(pulse)
+}
+
+// Run the motor driver on `pin` at `percent` duty cycle (capped at 100)
+pub fn motor(pin: u32, percent: u32) -> Result<(), PwmError> {
+    square(pin, MOTOR_HZ, percent)
+}
+
+// Dim the LED on `pin` to `percent` brightness (capped at 100)
+pub fn led(pin: u32, percent: u32) -> Result<(), PwmError> {
+    square(pin, LED_HZ, percent)
+}
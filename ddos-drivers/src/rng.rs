@@ -0,0 +1,352 @@
+use super::timer;
+use crate::hardwareselect::rng_base;
+use crate::locked::SpinLock;
+use crate::spin_until;
+#[cfg(target_arch = "aarch64")]
+use core::arch::asm;
+use core::ptr::{read_volatile, write_volatile};
+
+// ============================================================================
+// RANDOM NUMBERS
+// rand_u32()/rand_u64()/fill_bytes() take their bits from the first source
+// that works, picked on first use:
+//
+//     rndr      the ARMv8.5 RNDR register, on cores that have it (none of
+//               the Pi's so far, but it costs one ID register read to ask)
+//     bcm2835   the SoC's RNG on the Pi 3 (and QEMU's model of it)
+//     rng200    the BCM2711's newer RNG block on the Pi 4, same address
+//     chacha    ChaCha20 keyed from the generic timer, when there is no
+//               hardware RNG (Pi 5) or it never warmed up
+//
+// The timer-keyed fallback is fine for sequence numbers and shuffling, not
+// for keys: its seed is only as unpredictable as the boot timing.
+// ============================================================================
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Rndr,
+    Bcm2835,
+    Rng200,
+    ChaCha,
+}
+
+impl Source {
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Rndr => "rndr",
+            Source::Bcm2835 => "bcm2835",
+            Source::Rng200 => "rng200",
+            Source::ChaCha => "chacha (timer seeded)",
+        }
+    }
+}
+
+// BCM2835 RNG registers, from rng_base()
+const CTRL: usize = 0x00;
+const STATUS: usize = 0x04;
+const DATA: usize = 0x08;
+
+// Bits both RNGs throw away before the first word
+const WARMUP_BITS: u32 = 0x40000;
+
+const CTRL_RBGEN: u32 = 1 << 0;
+// The top byte of STATUS counts words ready
+const STATUS_WORDS_SHIFT: u32 = 24;
+
+// RNG200 registers, from rng_base()
+const RNG200_CTRL: usize = 0x00;
+const RNG200_TOTAL_BIT_COUNT: usize = 0x0C;
+const RNG200_TOTAL_BIT_COUNT_THRESHOLD: usize = 0x10;
+const RNG200_FIFO_DATA: usize = 0x20;
+const RNG200_FIFO_COUNT: usize = 0x24;
+
+const RNG200_CTRL_RBGEN: u32 = 1 << 0;
+const RNG200_CTRL_DIV_SHIFT: u32 = 13;
+const RNG200_FIFO_THRESHOLD_SHIFT: u32 = 8;
+const RNG200_FIFO_WORDS: u32 = 0xFF;
+// Bits counted once the warm-up is over
+const RNG200_READY_BITS: u32 = 16;
+
+// ID_AA64ISAR0_EL1.RNDR
+#[cfg(target_arch = "aarch64")]
+const ISAR0_RNDR_SHIFT: u64 = 60;
+
+// Warm-up takes a few ms, a word after that far less
+const WARMUP_TIMEOUT_MS: u64 = 100;
+const WORD_TIMEOUT_MS: u64 = 10;
+
+struct State {
+    source: Option<Source>,
+    chacha: ChaCha,
+}
+
+static RNG: SpinLock<State> = SpinLock::new(State {
+    source: None,
+    chacha: ChaCha::new([0; 8], [0; 3]),
+});
+
+fn reg(offset: usize) -> *mut u32 {
+    (rng_base() + offset) as *mut u32
+}
+
+fn has_rndr() -> bool {
+    #[cfg(target_arch = "aarch64")]
+    {
+        let isar0: u64;
+        unsafe {
+            asm!("mrs {0}, id_aa64isar0_el1", out(reg) isar0, options(nomem, nostack));
+        }
+        (isar0 >> ISAR0_RNDR_SHIFT) & 0xF != 0
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    {
+        false
+    }
+}
+
+// None when the hardware could not gather enough entropy just now
+fn rndr() -> Option<u64> {
+    #[cfg(target_arch = "aarch64")]
+    {
+        let (value, ok): (u64, u64);
+        unsafe {
+            // RNDR by its encoding, so the assembler needs no +rng; Z set on failure
+            asm!(
+                "mrs {value}, s3_3_c2_c4_0",
+                "cset {ok}, ne",
+                value = out(reg) value,
+                ok = out(reg) ok,
+                options(nomem, nostack),
+            );
+        }
+        (ok != 0).then_some(value)
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    {
+        None
+    }
+}
+
+// Start the SoC's RNG and wait for it to warm up
+fn start_hardware() -> Option<Source> {
+    if cfg!(feature = "rpi5") || rng_base() == 0 {
+        return None;
+    }
+
+    unsafe {
+        if cfg!(feature = "rpi4") {
+            write_volatile(reg(RNG200_TOTAL_BIT_COUNT_THRESHOLD), WARMUP_BITS);
+            write_volatile(reg(RNG200_FIFO_COUNT), 2 << RNG200_FIFO_THRESHOLD_SHIFT);
+            write_volatile(
+                reg(RNG200_CTRL),
+                (3 << RNG200_CTRL_DIV_SHIFT) | RNG200_CTRL_RBGEN,
+            );
+            spin_until!(
+                read_volatile(reg(RNG200_TOTAL_BIT_COUNT)) > RNG200_READY_BITS,
+                WARMUP_TIMEOUT_MS
+            )
+            .ok()?;
+            Some(Source::Rng200)
+        } else {
+            write_volatile(reg(STATUS), WARMUP_BITS);
+            write_volatile(reg(CTRL), CTRL_RBGEN);
+            spin_until!(
+                read_volatile(reg(STATUS)) >> STATUS_WORDS_SHIFT != 0,
+                WARMUP_TIMEOUT_MS
+            )
+            .ok()?;
+            Some(Source::Bcm2835)
+        }
+    }
+}
+
+fn hardware_word(source: Source) -> Option<u32> {
+    let (count, mask, data) = match source {
+        Source::Bcm2835 => (STATUS, !0 << STATUS_WORDS_SHIFT, DATA),
+        Source::Rng200 => (RNG200_FIFO_COUNT, RNG200_FIFO_WORDS, RNG200_FIFO_DATA),
+        _ => return None,
+    };
+
+    unsafe {
+        spin_until!(read_volatile(reg(count)) & mask != 0, WORD_TIMEOUT_MS).ok()?;
+        Some(read_volatile(reg(data)))
+    }
+}
+
+// Key the ChaCha fallback from the low bits of the counter, read with some
+// work in between so the readings drift apart
+fn timer_seed() -> [u32; 8] {
+    let mut key = [0u32; 8];
+    for (index, word) in key.iter_mut().enumerate() {
+        for _ in 0..32 {
+            *word = word.rotate_left(5) ^ timer::counter() as u32;
+            for _ in 0..(index + 1) * 7 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+    key
+}
+
+fn pick_source(state: &mut State) -> Source {
+    if let Some(source) = state.source {
+        return source;
+    }
+
+    let source = if has_rndr() && rndr().is_some() {
+        Source::Rndr
+    } else {
+        start_hardware().unwrap_or(Source::ChaCha)
+    };
+    // Keyed whatever the source, it also covers for hardware that stops
+    // answering later
+    let nonce = [timer::counter() as u32, (timer::counter() >> 32) as u32, 0];
+    state.chacha = ChaCha::new(timer_seed(), nonce);
+
+    state.source = Some(source);
+    source
+}
+
+// Which source the numbers come from (picks one if nothing asked yet)
+pub fn source() -> Source {
+    pick_source(&mut RNG.lock())
+}
+
+pub fn rand_u64() -> u64 {
+    let mut state = RNG.lock();
+
+    let value = match pick_source(&mut state) {
+        Source::Rndr => rndr(),
+        source => hardware_word(source)
+            .zip(hardware_word(source))
+            .map(|(high, low)| ((high as u64) << 32) | low as u64),
+    };
+
+    // A hardware source that stops answering still gets an answer
+    value.unwrap_or_else(|| {
+        let chacha = &mut state.chacha;
+        ((chacha.next_u32() as u64) << 32) | chacha.next_u32() as u64
+    })
+}
+
+pub fn rand_u32() -> u32 {
+    rand_u64() as u32
+}
+
+pub fn fill_bytes(buffer: &mut [u8]) {
+    for chunk in buffer.chunks_mut(8) {
+        let bytes = rand_u64().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+// ----------------------------------------------------------------------------
+// ChaCha20 (RFC 8439), as a stream of 32-bit words
+// ----------------------------------------------------------------------------
+
+// "expand 32-byte k"
+const CHACHA_CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+pub struct ChaCha {
+    // Constants, key, block counter, nonce
+    input: [u32; 16],
+    block: [u32; 16],
+    // Next unused word of `block`
+    used: usize,
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+impl ChaCha {
+    // The block counter starts at zero
+    pub const fn new(key: [u32; 8], nonce: [u32; 3]) -> Self {
+        let mut input = [0u32; 16];
+        let mut i = 0;
+        while i < 4 {
+            input[i] = CHACHA_CONSTANTS[i];
+            i += 1;
+        }
+        let mut i = 0;
+        while i < 8 {
+            input[4 + i] = key[i];
+            i += 1;
+        }
+        input[13] = nonce[0];
+        input[14] = nonce[1];
+        input[15] = nonce[2];
+
+        ChaCha {
+            input,
+            block: [0; 16],
+            used: 16,
+        }
+    }
+
+    fn refill(&mut self) {
+        let mut state = self.input;
+        for _ in 0..10 {
+            quarter_round(&mut state, 0, 4, 8, 12);
+            quarter_round(&mut state, 1, 5, 9, 13);
+            quarter_round(&mut state, 2, 6, 10, 14);
+            quarter_round(&mut state, 3, 7, 11, 15);
+            quarter_round(&mut state, 0, 5, 10, 15);
+            quarter_round(&mut state, 1, 6, 11, 12);
+            quarter_round(&mut state, 2, 7, 8, 13);
+            quarter_round(&mut state, 3, 4, 9, 14);
+        }
+        for (word, input) in state.iter_mut().zip(self.input) {
+            *word = word.wrapping_add(input);
+        }
+
+        self.block = state;
+        self.used = 0;
+        self.input[12] = self.input[12].wrapping_add(1);
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        if self.used == self.block.len() {
+            self.refill();
+        }
+        self.used += 1;
+        self.block[self.used - 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChaCha;
+
+    // RFC 8439 section 2.3.2: key 00..1f, block counter 1
+    #[test]
+    fn chacha_block() {
+        let key = [
+            0x0302_0100,
+            0x0706_0504,
+            0x0b0a_0908,
+            0x0f0e_0d0c,
+            0x1312_1110,
+            0x1716_1514,
+            0x1b1a_1918,
+            0x1f1e_1d1c,
+        ];
+        let mut chacha = ChaCha::new(key, [0x0900_0000, 0x4a00_0000, 0]);
+
+        // Skip block 0
+        for _ in 0..16 {
+            chacha.next_u32();
+        }
+        let words = [(); 4].map(|()| chacha.next_u32());
+        assert_eq!(words, [0xe4e7_f110, 0x1559_3bd1, 0x1fdd_0f50, 0xc471_20a3]);
+    }
+}
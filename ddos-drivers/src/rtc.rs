@@ -0,0 +1,309 @@
+use super::i2c::{self, I2cError};
+use super::mailbox::{self, MailboxError};
+use core::fmt;
+
+// ============================================================================
+// REAL-TIME CLOCKS
+// The Pi keeps no time while it is off. Two things can:
+//
+//     ds3231     the usual battery-backed RTC module on the I2C header
+//                (address 0x68), time of day and date as BCD registers
+//     firmware   the firmware's RTC register, seconds since 1970, on boards
+//                that have an RTC with a battery connector
+//
+// Both read and write a DateTime, which is always UTC and limited to the
+// years either of them can hold (1970 to 2099).
+// ============================================================================
+
+pub const MIN_YEAR: u16 = 1970;
+pub const MAX_YEAR: u16 = 2099;
+
+const SECONDS_PER_DAY: u64 = 86400;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RtcError {
+    I2c(I2cError),
+    Firmware(MailboxError),
+    // The DS3231's oscillator stopped (flat battery), its time means nothing
+    TimeLost,
+    // The clock returned a date that does not exist
+    Garbage,
+    OutOfRange,
+}
+
+impl fmt::Display for RtcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RtcError::I2c(err) => write!(f, "{}", err),
+            RtcError::Firmware(err) => write!(f, "{}", err),
+            RtcError::TimeLost => write!(f, "RTC lost power, its time is not set"),
+            RtcError::Garbage => write!(f, "RTC returned an invalid date"),
+            RtcError::OutOfRange => {
+                write!(f, "only years {} to {} can be stored", MIN_YEAR, MAX_YEAR)
+            }
+        }
+    }
+}
+
+impl From<I2cError> for RtcError {
+    fn from(err: I2cError) -> Self {
+        RtcError::I2c(err)
+    }
+}
+
+impl From<MailboxError> for RtcError {
+    fn from(err: MailboxError) -> Self {
+        RtcError::Firmware(err)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Dates
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+fn is_leap(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl DateTime {
+    // None for a date that does not exist or is outside MIN_YEAR..=MAX_YEAR
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Option<Self> {
+        let valid = (MIN_YEAR..=MAX_YEAR).contains(&year)
+            && (1..=12).contains(&month)
+            && day >= 1
+            && day <= days_in_month(year, month)
+            && hour < 24
+            && minute < 60
+            && second < 60;
+
+        valid.then_some(DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm, the
+    // same as build.rs uses for the build time)
+    pub fn from_unix(seconds: u64) -> Self {
+        let days = (seconds / SECONDS_PER_DAY) as i64;
+        let time = seconds % SECONDS_PER_DAY;
+
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time % 3600 / 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+
+    // The way back, seconds since 1970-01-01 00:00:00 UTC
+    pub fn to_unix(&self) -> u64 {
+        let month = self.month as i64;
+        let year = self.year as i64 - if month <= 2 { 1 } else { 0 };
+
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year =
+            (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        days as u64 * SECONDS_PER_DAY
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64
+    }
+
+    // 1 for Monday to 7 for Sunday (1970-01-01 was a Thursday)
+    pub fn weekday(&self) -> u8 {
+        ((self.to_unix() / SECONDS_PER_DAY + 3) % 7 + 1) as u8
+    }
+
+    // "YYYY-MM-DD HH:MM:SS", with a T instead of the space also accepted
+    pub fn parse(text: &str) -> Option<Self> {
+        let (date, time) = text.trim().split_once([' ', 'T'])?;
+
+        let mut date = date.split('-').map(|part| part.parse::<u16>().ok());
+        let mut time = time.trim().split(':').map(|part| part.parse::<u8>().ok());
+
+        let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+        let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+        if date.next().is_some() || time.next().is_some() {
+            return None;
+        }
+
+        DateTime::new(
+            year,
+            u8::try_from(month).ok()?,
+            u8::try_from(day).ok()?,
+            hour,
+            minute,
+            second,
+        )
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+// ----------------------------------------------------------------------------
+// DS3231
+// ----------------------------------------------------------------------------
+
+pub const DS3231_ADDRESS: u8 = 0x68;
+
+// Seconds, minutes, hours, weekday, day, month/century, year
+const REG_TIME: u8 = 0x00;
+const REG_STATUS: u8 = 0x0F;
+
+const HOURS_12H: u8 = 1 << 6;
+const HOURS_PM: u8 = 1 << 5;
+const MONTH_CENTURY: u8 = 1 << 7;
+// Oscillator stop flag, set at power-up and when the battery ran flat
+const STATUS_OSF: u8 = 1 << 7;
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0xF)
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+pub fn ds3231_read() -> Result<DateTime, RtcError> {
+    let mut status = [0u8];
+    i2c::write_read(DS3231_ADDRESS, &[REG_STATUS], &mut status)?;
+    if status[0] & STATUS_OSF != 0 {
+        return Err(RtcError::TimeLost);
+    }
+
+    let mut registers = [0u8; 7];
+    i2c::write_read(DS3231_ADDRESS, &[REG_TIME], &mut registers)?;
+    let [seconds, minutes, hours, _weekday, day, month, year] = registers;
+
+    // Whatever set the clock may have left it in 12-hour mode
+    let hour = if hours & HOURS_12H != 0 {
+        let hour = from_bcd(hours & 0x1F) % 12;
+        if hours & HOURS_PM != 0 {
+            hour + 12
+        } else {
+            hour
+        }
+    } else {
+        from_bcd(hours & 0x3F)
+    };
+    let century = if month & MONTH_CENTURY != 0 {
+        2100
+    } else {
+        2000
+    };
+
+    DateTime::new(
+        century + from_bcd(year) as u16,
+        from_bcd(month & 0x1F),
+        from_bcd(day & 0x3F),
+        hour,
+        from_bcd(minutes & 0x7F),
+        from_bcd(seconds & 0x7F),
+    )
+    .ok_or(RtcError::Garbage)
+}
+
+// Set the time (24-hour mode) and clear the oscillator stop flag, which
+// makes the time count as valid again
+pub fn ds3231_write(time: &DateTime) -> Result<(), RtcError> {
+    if time.year < 2000 {
+        return Err(RtcError::OutOfRange);
+    }
+
+    i2c::write(
+        DS3231_ADDRESS,
+        &[
+            REG_TIME,
+            to_bcd(time.second),
+            to_bcd(time.minute),
+            to_bcd(time.hour),
+            time.weekday(),
+            to_bcd(time.day),
+            to_bcd(time.month),
+            to_bcd((time.year - 2000) as u8),
+        ],
+    )?;
+
+    let mut status = [0u8];
+    i2c::write_read(DS3231_ADDRESS, &[REG_STATUS], &mut status)?;
+    i2c::write(DS3231_ADDRESS, &[REG_STATUS, status[0] & !STATUS_OSF])?;
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------
+// Firmware RTC
+// ----------------------------------------------------------------------------
+
+// Zero means the RTC was never set
+pub fn firmware_read() -> Result<DateTime, RtcError> {
+    let seconds = match mailbox::rtc_time()? {
+        0 => return Err(RtcError::TimeLost),
+        seconds => seconds,
+    };
+
+    // 32 bits of seconds go a few years past MAX_YEAR
+    let time = DateTime::from_unix(seconds as u64);
+    if time.year > MAX_YEAR {
+        return Err(RtcError::OutOfRange);
+    }
+    Ok(time)
+}
+
+pub fn firmware_write(time: &DateTime) -> Result<(), RtcError> {
+    let seconds = u32::try_from(time.to_unix()).map_err(|_| RtcError::OutOfRange)?;
+    mailbox::set_rtc_time(seconds)?;
+    Ok(())
+}
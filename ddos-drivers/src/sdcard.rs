@@ -0,0 +1,417 @@
+use super::sdhci::{
+    BLKSIZECNT, C0_HCTL_DWIDTH, C0_HCTL_HS_EN, CONTROL0, DATA, Host, INT_DATA_DONE, INT_READ_RDY,
+    INT_WRITE_RDY, RESP1, RESP2, RESP3, SR_DAT_INHIBIT, SR_READ_AVAILABLE, STATUS,
+};
+use super::timer;
+use crate::hardwareselect::{EMMC_CLOCK_HZ, emmc_base};
+use crate::locked::SpinLock;
+use crate::spin_until;
+
+// ============================================================================
+// EMMC / SDHCI SD CARD DRIVER
+// Polled, single-block transfers. Cards start in 1-bit mode at 25 MHz; after
+// identification we move to a 4-bit bus and 50 MHz high speed when both the
+// card and the host allow it, and stay put on any failure along the way.
+// The host controller side lives in sdhci.rs, this file is the SD protocol.
+// ============================================================================
+
+pub use super::sdhci::SdError;
+
+pub const BLOCK_SIZE: usize = 512;
+
+// --- Commands (index in bits 29:24, response/data flags below) ---
+const CMD_NEED_APP: u32 = 0x8000_0000;
+const CMD_RSPNS_48: u32 = 0x0002_0000;
+const CMD_ERRORS_MASK: u32 = 0xFFF9_C004;
+const CMD_RCA_MASK: u32 = 0xFFFF_0000;
+
+const CMD_GO_IDLE: u32 = 0x0000_0000;
+const CMD_ALL_SEND_CID: u32 = 0x0201_0000;
+const CMD_SEND_REL_ADDR: u32 = 0x0302_0000;
+const CMD_CARD_SELECT: u32 = 0x0703_0000;
+const CMD_SEND_IF_COND: u32 = 0x0802_0000;
+const CMD_READ_SINGLE: u32 = 0x1122_0010;
+const CMD_WRITE_SINGLE: u32 = 0x1822_0000;
+const CMD_APP_CMD: u32 = 0x3700_0000;
+const CMD_SEND_OP_COND: u32 = 0x2902_0000 | CMD_NEED_APP;
+const CMD_SEND_SCR: u32 = 0x3322_0010 | CMD_NEED_APP;
+const CMD_SET_BUS_WIDTH: u32 = 0x0602_0000 | CMD_NEED_APP;
+const CMD_SWITCH_FUNC: u32 = 0x0622_0010;
+
+// R1 card status bit: the card accepted CMD55 and expects an app command
+const SR_APP_CMD: u32 = 0x0000_0020;
+
+// --- ACMD41 (SEND_OP_COND) ---
+const ACMD41_VOLTAGE: u32 = 0x00FF_8000;
+const ACMD41_CMD_COMPLETE: u32 = 0x8000_0000;
+const ACMD41_CMD_CCS: u32 = 0x4000_0000;
+const ACMD41_ARG_HC: u32 = 0x51FF_8000;
+
+// --- SCR (first word as read from DATA, the card sends it MSB first) ---
+const SCR_SD_SPEC: u32 = 0x0000_000F;
+const SCR_BUS_WIDTH_4: u32 = 0x0000_0400;
+
+// --- CMD6 (SWITCH_FUNC): function group 1 is the bus speed ---
+const SWITCH_CHECK: u32 = 0x00FF_FFF1;
+const SWITCH_SET: u32 = 0x80FF_FFF1;
+const SWITCH_STATUS_SIZE: usize = 64;
+const SWITCH_HS_SUPPORTED_BYTE: usize = 13;
+const SWITCH_HS_SUPPORTED_BIT: u8 = 0x02;
+const SWITCH_GROUP1_RESULT_BYTE: usize = 16;
+const SWITCH_FUNCTION_HS: u8 = 1;
+
+const IDENT_CLOCK_HZ: u32 = 400_000;
+const TRANSFER_CLOCK_HZ: u32 = 25_000_000;
+const HIGH_SPEED_CLOCK_HZ: u32 = 50_000_000;
+
+pub struct SdCard {
+    host: Host,
+    rca: u32,
+    scr: [u32; 2],
+    // SDHC/SDXC cards are block addressed, SDSC cards want a byte address
+    high_capacity: bool,
+    bus_width: u8,
+    ready: bool,
+}
+
+pub static SDCARD: SpinLock<SdCard> = SpinLock::new(SdCard::new());
+
+impl SdCard {
+    // The host base is filled in by init(), once the device tree has had a
+    // chance to move the peripherals
+    pub const fn new() -> SdCard {
+        SdCard {
+            host: Host::new(0),
+            rca: 0,
+            scr: [0; 2],
+            high_capacity: false,
+            bus_width: 1,
+            ready: false,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    pub fn is_high_capacity(&self) -> bool {
+        self.high_capacity
+    }
+
+    pub fn bus_width(&self) -> u8 {
+        self.bus_width
+    }
+
+    // SD clock actually produced by the divisor, not the one asked for
+    pub fn clock_hz(&self) -> u32 {
+        self.host.clock_hz()
+    }
+
+    // ------------------------------------------------------------------------
+    // Low level helpers
+    // ------------------------------------------------------------------------
+
+    fn command(&mut self, mut code: u32, arg: u32) -> Result<u32, SdError> {
+        // Application commands must be preceded by CMD55 addressed to our card
+        if (code & CMD_NEED_APP) != 0 {
+            let app_cmd = if self.rca != 0 {
+                CMD_APP_CMD | CMD_RSPNS_48
+            } else {
+                CMD_APP_CMD
+            };
+            self.command(app_cmd, self.rca)?;
+            code &= !CMD_NEED_APP;
+        }
+
+        // Some cards need a moment before they answer these
+        let settle_us = if code == (CMD_SEND_OP_COND & !CMD_NEED_APP) {
+            1000
+        } else if code == CMD_SEND_IF_COND || code == CMD_APP_CMD {
+            100
+        } else {
+            0
+        };
+
+        let response = self.host.send(code, arg, settle_us)?;
+
+        match code {
+            CMD_GO_IDLE | CMD_APP_CMD => Ok(0),
+            c if c == (CMD_APP_CMD | CMD_RSPNS_48) => Ok(response & SR_APP_CMD),
+            c if c == (CMD_SEND_OP_COND & !CMD_NEED_APP) => Ok(response),
+            CMD_SEND_IF_COND => {
+                if response == arg {
+                    Ok(0)
+                } else {
+                    Err(SdError::Unsupported)
+                }
+            }
+            CMD_ALL_SEND_CID => Ok(response
+                | self.host.read_reg(RESP3)
+                | self.host.read_reg(RESP2)
+                | self.host.read_reg(RESP1)),
+            CMD_SEND_REL_ADDR => {
+                // R6 packs a few status bits differently, move them back to R1 positions
+                let status = ((response & 0x1FFF)
+                    | ((response & 0x2000) << 6)
+                    | ((response & 0x4000) << 8)
+                    | ((response & 0x8000) << 8))
+                    & CMD_ERRORS_MASK;
+
+                if status != 0 {
+                    Err(SdError::Card(status))
+                } else {
+                    Ok(response & CMD_RCA_MASK)
+                }
+            }
+            _ => {
+                let status = response & CMD_ERRORS_MASK;
+                if status != 0 {
+                    Err(SdError::Card(status))
+                } else {
+                    Ok(response)
+                }
+            }
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Card bring-up
+    // ------------------------------------------------------------------------
+
+    pub fn init(&mut self) -> Result<(), SdError> {
+        #[cfg(feature = "rpi5")]
+        {
+            return Err(SdError::Unsupported);
+        }
+
+        #[cfg(not(feature = "rpi5"))]
+        {
+            self.ready = false;
+            self.host = Host::new(emmc_base());
+
+            #[cfg(any(feature = "qemu", feature = "rpi3"))]
+            route_pins();
+
+            // Reset the whole host controller and start at identification speed
+            self.host.reset(IDENT_CLOCK_HZ)?;
+
+            self.rca = 0;
+            self.scr = [0; 2];
+            self.bus_width = 1;
+
+            // CMD0 -> idle, CMD8 -> "I speak 2.7-3.6V, echo 0xAA back"
+            self.command(CMD_GO_IDLE, 0)?;
+            self.command(CMD_SEND_IF_COND, 0x0000_01AA)?;
+
+            // ACMD41 until the card reports power-up complete (can take up to
+            // a second). A command that fails ends the wait with its error.
+            let mut op_cond = Ok(0);
+            spin_until!(
+                {
+                    timer::delay_us(400);
+                    op_cond = self.command(CMD_SEND_OP_COND, ACMD41_ARG_HC);
+                    op_cond.map_or(true, |response| response & ACMD41_CMD_COMPLETE != 0)
+                },
+                1000
+            )?;
+            let op_cond = op_cond?;
+
+            if (op_cond & ACMD41_VOLTAGE) == 0 {
+                return Err(SdError::Unsupported);
+            }
+            self.high_capacity = (op_cond & ACMD41_CMD_CCS) != 0;
+
+            self.command(CMD_ALL_SEND_CID, 0)?;
+            self.rca = self.command(CMD_SEND_REL_ADDR, 0)?;
+
+            self.host.set_clock(TRANSFER_CLOCK_HZ)?;
+            self.command(CMD_CARD_SELECT, self.rca)?;
+
+            self.read_scr()?;
+
+            // Both are optional speed-ups: a card that refuses keeps working as is
+            let _ = self.enable_wide_bus();
+            let _ = self.enable_high_speed();
+
+            self.ready = true;
+            Ok(())
+        }
+    }
+
+    // ACMD6 moves the card to 4 data lines, then the host follows
+    fn enable_wide_bus(&mut self) -> Result<(), SdError> {
+        if (self.scr[0] & SCR_BUS_WIDTH_4) == 0 {
+            return Err(SdError::Unsupported);
+        }
+
+        self.command(CMD_SET_BUS_WIDTH, self.rca | 2)?;
+        self.host
+            .write_reg(CONTROL0, self.host.read_reg(CONTROL0) | C0_HCTL_DWIDTH);
+        self.bus_width = 4;
+        Ok(())
+    }
+
+    // CMD6 needs SD spec 1.10 or later. Ask first (mode 0), then switch (mode 1),
+    // and only then raise the host side to 50 MHz.
+    fn enable_high_speed(&mut self) -> Result<(), SdError> {
+        if (self.scr[0] & SCR_SD_SPEC) == 0 {
+            return Err(SdError::Unsupported);
+        }
+
+        // Nothing to gain if the base clock cannot get above 25 MHz anyway
+        if EMMC_CLOCK_HZ < 2 * HIGH_SPEED_CLOCK_HZ {
+            return Err(SdError::Unsupported);
+        }
+
+        let status = self.switch_function(SWITCH_CHECK)?;
+        if (status[SWITCH_HS_SUPPORTED_BYTE] & SWITCH_HS_SUPPORTED_BIT) == 0
+            || (status[SWITCH_GROUP1_RESULT_BYTE] & 0x0F) != SWITCH_FUNCTION_HS
+        {
+            return Err(SdError::Unsupported);
+        }
+
+        let status = self.switch_function(SWITCH_SET)?;
+        if (status[SWITCH_GROUP1_RESULT_BYTE] & 0x0F) != SWITCH_FUNCTION_HS {
+            return Err(SdError::Unsupported);
+        }
+
+        // The card switches within 8 clocks, give it a comfortable margin
+        timer::delay_us(100);
+
+        self.host
+            .write_reg(CONTROL0, self.host.read_reg(CONTROL0) | C0_HCTL_HS_EN);
+        if let Err(err) = self.host.set_clock(HIGH_SPEED_CLOCK_HZ) {
+            // Back to default speed on the host side, the card copes with a slower clock
+            self.host
+                .write_reg(CONTROL0, self.host.read_reg(CONTROL0) & !C0_HCTL_HS_EN);
+            self.host.set_clock(TRANSFER_CLOCK_HZ)?;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    // CMD6 answers with a 512-bit status block on the data lines
+    fn switch_function(&mut self, arg: u32) -> Result<[u8; SWITCH_STATUS_SIZE], SdError> {
+        let mut status = [0u8; SWITCH_STATUS_SIZE];
+
+        self.host.wait_status(SR_DAT_INHIBIT)?;
+        self.host
+            .write_reg(BLKSIZECNT, (1 << 16) | SWITCH_STATUS_SIZE as u32);
+        self.command(CMD_SWITCH_FUNC, arg)?;
+        self.host.read_data(&mut status)?;
+        Ok(status)
+    }
+
+    // SD Configuration Register: bus widths and command support of the card
+    fn read_scr(&mut self) -> Result<(), SdError> {
+        self.host.wait_status(SR_DAT_INHIBIT)?;
+        self.host.write_reg(BLKSIZECNT, (1 << 16) | 8);
+        self.command(CMD_SEND_SCR, 0)?;
+        self.host.wait_interrupt(INT_READ_RDY)?;
+
+        let mut index = 0;
+        spin_until!(
+            {
+                if (self.host.read_reg(STATUS) & SR_READ_AVAILABLE) != 0 {
+                    self.scr[index] = self.host.read_reg(DATA);
+                    index += 1;
+                }
+                index == 2
+            },
+            500
+        )?;
+
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------------
+    // Block I/O
+    // ------------------------------------------------------------------------
+
+    fn block_address(&self, lba: u32) -> u32 {
+        if self.high_capacity {
+            lba
+        } else {
+            lba * BLOCK_SIZE as u32
+        }
+    }
+
+    pub fn read_block(&mut self, lba: u32, buffer: &mut [u8]) -> Result<(), SdError> {
+        if !self.ready {
+            return Err(SdError::NotInitialized);
+        }
+        if buffer.len() < BLOCK_SIZE {
+            return Err(SdError::BadBuffer);
+        }
+
+        self.host.wait_status(SR_DAT_INHIBIT)?;
+        self.host
+            .write_reg(BLKSIZECNT, (1 << 16) | BLOCK_SIZE as u32);
+        self.command(CMD_READ_SINGLE, self.block_address(lba))?;
+        self.host.read_data(&mut buffer[..BLOCK_SIZE])
+    }
+
+    pub fn write_block(&mut self, lba: u32, buffer: &[u8]) -> Result<(), SdError> {
+        if !self.ready {
+            return Err(SdError::NotInitialized);
+        }
+        if buffer.len() < BLOCK_SIZE {
+            return Err(SdError::BadBuffer);
+        }
+
+        self.host.wait_status(SR_DAT_INHIBIT)?;
+        self.host
+            .write_reg(BLKSIZECNT, (1 << 16) | BLOCK_SIZE as u32);
+        self.command(CMD_WRITE_SINGLE, self.block_address(lba))?;
+        self.host.wait_interrupt(INT_WRITE_RDY)?;
+
+        for chunk in buffer[..BLOCK_SIZE].chunks_exact(4) {
+            self.host.write_reg(
+                DATA,
+                u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]),
+            );
+        }
+
+        self.host.wait_interrupt(INT_DATA_DONE)
+    }
+
+    pub fn read_blocks(&mut self, lba: u32, buffer: &mut [u8]) -> Result<(), SdError> {
+        if !buffer.len().is_multiple_of(BLOCK_SIZE) {
+            return Err(SdError::BadBuffer);
+        }
+
+        for (i, block) in buffer.chunks_exact_mut(BLOCK_SIZE).enumerate() {
+            self.read_block(lba + i as u32, block)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_blocks(&mut self, lba: u32, buffer: &[u8]) -> Result<(), SdError> {
+        if !buffer.len().is_multiple_of(BLOCK_SIZE) {
+            return Err(SdError::BadBuffer);
+        }
+
+        for (i, block) in buffer.chunks_exact(BLOCK_SIZE).enumerate() {
+            self.write_block(lba + i as u32, block)?;
+        }
+
+        Ok(())
+    }
+}
+
+// On the BCM2837 the SD slot pins (GPIO 48-53) default to the SDHOST controller,
+// so hand them to EMMC (ALT3) with pull-ups; GPIO 47 is card detect.
+#[cfg(any(feature = "qemu", feature = "rpi3"))]
+fn route_pins() {
+    use super::gpio::{self, Function, Pull};
+
+    gpio::set_function(47, Function::Input);
+    gpio::set_pull(47, Pull::Up);
+
+    for pin in 48..=53 {
+        gpio::set_function(pin, Function::Alt3);
+        gpio::set_pull(pin, Pull::Up);
+    }
+}
@@ -0,0 +1,245 @@
+use super::timer::{self, Timeout};
+use crate::hardwareselect::EMMC_CLOCK_HZ;
+use crate::spin_until;
+use core::fmt;
+use core::ptr::{read_volatile, write_volatile};
+
+// ============================================================================
+// SDHCI HOST CONTROLLER
+// The Arasan/EMMC2 host register plumbing shared by the SD card driver and
+// the SDIO (WiFi) driver: reset, clock, raw command issue and polled waits.
+// What a response means is up to the protocol on top.
+// ============================================================================
+
+// --- Registers (offsets from the controller base) ---
+pub const BLKSIZECNT: usize = 0x04;
+pub const ARG1: usize = 0x08;
+pub const CMDTM: usize = 0x0C;
+pub const RESP0: usize = 0x10;
+pub const RESP1: usize = 0x14;
+pub const RESP2: usize = 0x18;
+pub const RESP3: usize = 0x1C;
+pub const DATA: usize = 0x20;
+pub const STATUS: usize = 0x24;
+pub const CONTROL0: usize = 0x28;
+pub const CONTROL1: usize = 0x2C;
+pub const INTERRUPT: usize = 0x30;
+pub const INT_MASK: usize = 0x34;
+pub const INT_EN: usize = 0x38;
+pub const SLOTISR_VER: usize = 0xFC;
+
+// --- STATUS bits ---
+pub const SR_READ_AVAILABLE: u32 = 0x0000_0800;
+pub const SR_DAT_INHIBIT: u32 = 0x0000_0002;
+pub const SR_CMD_INHIBIT: u32 = 0x0000_0001;
+
+// --- INTERRUPT bits ---
+pub const INT_DATA_TIMEOUT: u32 = 0x0010_0000;
+pub const INT_CMD_TIMEOUT: u32 = 0x0001_0000;
+pub const INT_READ_RDY: u32 = 0x0000_0020;
+pub const INT_WRITE_RDY: u32 = 0x0000_0010;
+pub const INT_DATA_DONE: u32 = 0x0000_0002;
+pub const INT_CMD_DONE: u32 = 0x0000_0001;
+pub const INT_ERROR_MASK: u32 = 0x017E_8000;
+
+// --- CONTROL0 bits ---
+pub const C0_HCTL_DWIDTH: u32 = 0x0000_0002;
+pub const C0_HCTL_HS_EN: u32 = 0x0000_0004;
+
+// --- CONTROL1 bits ---
+const C1_SRST_HC: u32 = 0x0100_0000;
+const C1_TOUNIT_MAX: u32 = 0x000E_0000;
+const C1_CLK_EN: u32 = 0x0000_0004;
+const C1_CLK_STABLE: u32 = 0x0000_0002;
+const C1_CLK_INTLEN: u32 = 0x0000_0001;
+
+// --- Host controller version (SLOTISR_VER) ---
+const HOST_SPEC_NUM: u32 = 0x00FF_0000;
+const HOST_SPEC_NUM_SHIFT: u32 = 16;
+const HOST_SPEC_V2: u32 = 1;
+
+#[derive(Clone, Copy, Debug)]
+pub enum SdError {
+    NotInitialized,
+    Unsupported,
+    Timeout,
+    // Raw INTERRUPT register value for anything the controller flagged
+    Controller(u32),
+    // Card status error bits from an R1 (or R5) response
+    Card(u32),
+    BadBuffer,
+}
+
+impl fmt::Display for SdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SdError::NotInitialized => write!(f, "card not initialized"),
+            SdError::Unsupported => write!(f, "card or controller not supported"),
+            SdError::Timeout => write!(f, "timed out waiting for card"),
+            SdError::Controller(bits) => write!(f, "controller error (INTERRUPT={:#010x})", bits),
+            SdError::Card(bits) => write!(f, "card status error ({:#010x})", bits),
+            SdError::BadBuffer => write!(f, "buffer must be a multiple of 512 bytes"),
+        }
+    }
+}
+
+impl From<Timeout> for SdError {
+    fn from(_: Timeout) -> Self {
+        SdError::Timeout
+    }
+}
+
+pub struct Host {
+    base: usize,
+    version: u32,
+    clock_hz: u32,
+}
+
+impl Host {
+    pub const fn new(base: usize) -> Host {
+        Host {
+            base,
+            version: 0,
+            clock_hz: 0,
+        }
+    }
+
+    pub fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    pub fn write_reg(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    // SD clock actually produced by the divisor, not the one asked for
+    pub fn clock_hz(&self) -> u32 {
+        self.clock_hz
+    }
+
+    // Full controller reset, identification clock, polling-only interrupts
+    pub fn reset(&mut self, ident_clock_hz: u32) -> Result<(), SdError> {
+        self.version = (self.read_reg(SLOTISR_VER) & HOST_SPEC_NUM) >> HOST_SPEC_NUM_SHIFT;
+
+        self.write_reg(CONTROL0, 0);
+        self.write_reg(CONTROL1, self.read_reg(CONTROL1) | C1_SRST_HC);
+
+        spin_until!(self.read_reg(CONTROL1) & C1_SRST_HC == 0, 100)?;
+
+        self.write_reg(
+            CONTROL1,
+            self.read_reg(CONTROL1) | C1_CLK_INTLEN | C1_TOUNIT_MAX,
+        );
+        timer::delay_us(10);
+
+        self.set_clock(ident_clock_hz)?;
+
+        // Polling only: flag everything, route nothing to the IRQ line
+        self.write_reg(INT_EN, 0xFFFF_FFFF);
+        self.write_reg(INT_MASK, 0xFFFF_FFFF);
+        Ok(())
+    }
+
+    // Wait for the given STATUS bits to clear, or for the controller to
+    // flag an error
+    pub fn wait_status(&self, mask: u32) -> Result<(), SdError> {
+        spin_until!(
+            self.read_reg(STATUS) & mask == 0 || self.read_reg(INTERRUPT) & INT_ERROR_MASK != 0,
+            500
+        )?;
+
+        let flags = self.read_reg(INTERRUPT);
+        if (flags & INT_ERROR_MASK) != 0 && (self.read_reg(STATUS) & mask) != 0 {
+            return Err(SdError::Controller(flags));
+        }
+        Ok(())
+    }
+
+    // Wait for an interrupt flag, then acknowledge it
+    pub fn wait_interrupt(&self, mask: u32) -> Result<(), SdError> {
+        let wanted = mask | INT_ERROR_MASK;
+
+        let mut flags = 0;
+        spin_until!(
+            {
+                flags = self.read_reg(INTERRUPT);
+                flags & wanted != 0
+            },
+            1000
+        )?;
+
+        if (flags & (INT_CMD_TIMEOUT | INT_DATA_TIMEOUT)) != 0 {
+            self.write_reg(INTERRUPT, flags);
+            return Err(SdError::Timeout);
+        }
+
+        if (flags & INT_ERROR_MASK) != 0 {
+            self.write_reg(INTERRUPT, flags);
+            return Err(SdError::Controller(flags));
+        }
+
+        self.write_reg(INTERRUPT, mask);
+        Ok(())
+    }
+
+    // Issue one command and wait for it to complete. `settle_us` is a pause
+    // before polling that some cards need for slow commands. Returns RESP0.
+    pub fn send(&self, code: u32, arg: u32, settle_us: u64) -> Result<u32, SdError> {
+        self.wait_status(SR_CMD_INHIBIT)?;
+
+        self.write_reg(INTERRUPT, self.read_reg(INTERRUPT));
+        self.write_reg(ARG1, arg);
+        self.write_reg(CMDTM, code);
+
+        if settle_us > 0 {
+            timer::delay_us(settle_us);
+        }
+
+        self.wait_interrupt(INT_CMD_DONE)?;
+        Ok(self.read_reg(RESP0))
+    }
+
+    // Drain one data block of `buffer.len()` bytes after a read command
+    pub fn read_data(&self, buffer: &mut [u8]) -> Result<(), SdError> {
+        self.wait_interrupt(INT_READ_RDY)?;
+
+        for chunk in buffer.chunks_exact_mut(4) {
+            chunk.copy_from_slice(&self.read_reg(DATA).to_le_bytes());
+        }
+
+        self.wait_interrupt(INT_DATA_DONE)
+    }
+
+    pub fn set_clock(&mut self, freq: u32) -> Result<(), SdError> {
+        spin_until!(
+            self.read_reg(STATUS) & (SR_CMD_INHIBIT | SR_DAT_INHIBIT) == 0,
+            100
+        )?;
+
+        self.write_reg(CONTROL1, self.read_reg(CONTROL1) & !C1_CLK_EN);
+        timer::delay_ms(10);
+
+        // SD clock = base / (2 * divisor). Version 3 hosts take a 10-bit divisor,
+        // older ones only accept powers of two in 8 bits.
+        let mut divisor = EMMC_CLOCK_HZ.div_ceil(2 * freq);
+
+        if self.version <= HOST_SPEC_V2 {
+            divisor = divisor.next_power_of_two().min(0x80);
+        } else {
+            divisor = divisor.min(0x3FF);
+        }
+
+        self.clock_hz = EMMC_CLOCK_HZ / (2 * divisor);
+
+        let bits = ((divisor & 0xFF) << 8) | ((divisor & 0x300) >> 2);
+        self.write_reg(CONTROL1, (self.read_reg(CONTROL1) & 0xFFFF_003F) | bits);
+        timer::delay_ms(10);
+
+        self.write_reg(CONTROL1, self.read_reg(CONTROL1) | C1_CLK_EN);
+        timer::delay_ms(10);
+
+        spin_until!(self.read_reg(CONTROL1) & C1_CLK_STABLE != 0, 1000)?;
+
+        Ok(())
+    }
+}
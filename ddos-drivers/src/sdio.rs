@@ -0,0 +1,349 @@
+use super::sdhci::{BLKSIZECNT, C0_HCTL_DWIDTH, CONTROL0, Host, SR_DAT_INHIBIT, SdError};
+use crate::hardwareselect::wifi_sdio_base;
+use crate::spin_until;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+// ============================================================================
+// SDIO (bring-up only)
+// Enough of the SDIO protocol to find out what sits on the WiFi controller:
+// CMD5 to power up the I/O card, CMD52 for single register bytes, CMD53 for
+// byte-mode block reads, then the CCCR, FBRs and CIS tuples. Nothing here
+// talks to the WiFi firmware itself.
+//
+// The chip must already be powered: on the Pi4 its WL_ON line hangs off the
+// firmware's GPIO expander, which we cannot drive without the mailbox.
+// ============================================================================
+
+// --- Commands (same CMDTM encoding as sdcard.rs) ---
+const CMD_IO_SEND_OP_COND: u32 = 0x0502_0000; // R4, no CRC
+const CMD_SEND_REL_ADDR: u32 = 0x0302_0000; // R6
+const CMD_CARD_SELECT: u32 = 0x0703_0000; // R1b
+const CMD_IO_RW_DIRECT: u32 = 0x3402_0000; // CMD52, R5
+const CMD_IO_RW_EXTENDED_READ: u32 = 0x3522_0010; // CMD53, R5 + data in
+
+// --- R4 (IO_SEND_OP_COND response) ---
+const R4_READY: u32 = 0x8000_0000;
+const R4_FUNCTIONS_SHIFT: u32 = 28;
+const R4_FUNCTIONS_MASK: u32 = 0x7;
+const R4_MEMORY_PRESENT: u32 = 0x0800_0000;
+const R4_OCR_MASK: u32 = 0x00FF_FFFF;
+const OCR_3V3: u32 = 0x0030_0000;
+
+// --- R5 flags (bits 15:8) that mean the command failed ---
+const R5_ERROR_MASK: u32 = 0xCB00;
+
+// --- CCCR (function 0 register space) ---
+const CCCR_REVISION: u32 = 0x00;
+const CCCR_SD_SPEC: u32 = 0x01;
+const CCCR_BUS_INTERFACE: u32 = 0x07;
+const CCCR_CAPABILITY: u32 = 0x08;
+const CCCR_CIS_POINTER: u32 = 0x09;
+const CCCR_BUS_SPEED: u32 = 0x13;
+const BUS_WIDTH_4: u8 = 0x02;
+
+// --- FBR (one 256-byte block per function at 0x100 * n) ---
+const FBR_INTERFACE: u32 = 0x00;
+const FBR_CIS_POINTER: u32 = 0x09;
+
+// --- CIS tuples ---
+const CISTPL_NULL: u8 = 0x00;
+const CISTPL_VERS_1: u8 = 0x15;
+const CISTPL_MANFID: u8 = 0x20;
+const CISTPL_FUNCID: u8 = 0x21;
+const CISTPL_END: u8 = 0xFF;
+const CIS_MAX_TUPLES: usize = 32;
+const CIS_AREA_END: u32 = 0x1_7FFF;
+
+const IDENT_CLOCK_HZ: u32 = 400_000;
+const TRANSFER_CLOCK_HZ: u32 = 25_000_000;
+
+pub struct Tuple {
+    pub code: u8,
+    pub body: Vec<u8>,
+}
+
+impl Tuple {
+    pub fn name(&self) -> &'static str {
+        match self.code {
+            CISTPL_VERS_1 => "VERS_1",
+            CISTPL_MANFID => "MANFID",
+            CISTPL_FUNCID => "FUNCID",
+            0x22 => "FUNCE",
+            0x80..=0x8F => "vendor",
+            _ => "?",
+        }
+    }
+}
+
+pub struct Function {
+    pub number: u8,
+    pub interface: u8,
+    pub cis: Vec<Tuple>,
+}
+
+pub struct CardInfo {
+    pub ocr: u32,
+    pub function_count: u8,
+    pub memory_present: bool,
+    pub cccr_revision: u8,
+    pub sd_spec: u8,
+    pub capability: u8,
+    pub bus_speed: u8,
+    pub bus_width: u8,
+    // Function 0 common CIS
+    pub common_cis: Vec<Tuple>,
+    pub functions: Vec<Function>,
+}
+
+impl CardInfo {
+    // (manufacturer, card) from the common CISTPL_MANFID, e.g. 0x02D0 = Broadcom/Cypress
+    pub fn manufacturer(&self) -> Option<(u16, u16)> {
+        self.common_cis
+            .iter()
+            .find(|tuple| tuple.code == CISTPL_MANFID && tuple.body.len() >= 4)
+            .map(|tuple| {
+                let body = &tuple.body;
+                (
+                    u16::from_le_bytes([body[0], body[1]]),
+                    u16::from_le_bytes([body[2], body[3]]),
+                )
+            })
+    }
+
+    // Product strings from CISTPL_VERS_1 ("Broadcom", "43455", ...)
+    pub fn product(&self) -> Option<String> {
+        let tuple = self
+            .common_cis
+            .iter()
+            .find(|tuple| tuple.code == CISTPL_VERS_1)?;
+        let strings = tuple
+            .body
+            .get(2..)?
+            .split(|&b| b == 0)
+            .take_while(|s| !s.is_empty() && s[0] != CISTPL_END)
+            .map(|s| String::from_utf8_lossy(s).into_owned())
+            .collect::<Vec<_>>();
+
+        Some(strings.join(" "))
+    }
+}
+
+pub struct Sdio {
+    host: Host,
+    rca: u32,
+}
+
+impl Sdio {
+    pub const fn new(base: usize) -> Sdio {
+        Sdio {
+            host: Host::new(base),
+            rca: 0,
+        }
+    }
+
+    // CMD52: one byte from (function, address)
+    pub fn read_byte(&self, function: u8, address: u32) -> Result<u8, SdError> {
+        let arg = ((function as u32 & 0x7) << 28) | ((address & 0x1FFFF) << 9);
+        let response = self.host.send(CMD_IO_RW_DIRECT, arg, 0)?;
+
+        if (response & R5_ERROR_MASK) != 0 {
+            return Err(SdError::Card(response));
+        }
+        Ok(response as u8)
+    }
+
+    // CMD52 write, returns the value read back after the write (RAW flag)
+    pub fn write_byte(&self, function: u8, address: u32, value: u8) -> Result<u8, SdError> {
+        let arg = (1 << 31)
+            | ((function as u32 & 0x7) << 28)
+            | (1 << 27)
+            | ((address & 0x1FFFF) << 9)
+            | value as u32;
+        let response = self.host.send(CMD_IO_RW_DIRECT, arg, 0)?;
+
+        if (response & R5_ERROR_MASK) != 0 {
+            return Err(SdError::Card(response));
+        }
+        Ok(response as u8)
+    }
+
+    // CMD53 in byte mode with an incrementing address. At most 512 bytes, and a
+    // multiple of 4 because the host FIFO is read a word at a time.
+    pub fn read_extended(
+        &self,
+        function: u8,
+        address: u32,
+        buffer: &mut [u8],
+    ) -> Result<(), SdError> {
+        let count = buffer.len();
+        if count == 0 || count > 512 || !count.is_multiple_of(4) {
+            return Err(SdError::BadBuffer);
+        }
+
+        let arg = ((function as u32 & 0x7) << 28)
+            | (1 << 26)
+            | ((address & 0x1FFFF) << 9)
+            | (count as u32 & 0x1FF);
+
+        self.host.wait_status(SR_DAT_INHIBIT)?;
+        self.host.write_reg(BLKSIZECNT, (1 << 16) | count as u32);
+
+        let response = self.host.send(CMD_IO_RW_EXTENDED_READ, arg, 0)?;
+        if (response & R5_ERROR_MASK) != 0 {
+            return Err(SdError::Card(response));
+        }
+
+        self.host.read_data(buffer)
+    }
+
+    fn read_pointer(&self, address: u32) -> Result<u32, SdError> {
+        let mut pointer = 0;
+        for i in 0..3 {
+            pointer |= (self.read_byte(0, address + i)? as u32) << (8 * i);
+        }
+        Ok(pointer)
+    }
+
+    // Walk a CIS chain: headers with CMD52, tuple bodies with CMD53 when they
+    // fit the FIFO word size, so both transfer paths get exercised
+    fn read_cis(&self, mut address: u32) -> Result<Vec<Tuple>, SdError> {
+        let mut tuples = Vec::new();
+
+        while tuples.len() < CIS_MAX_TUPLES && address < CIS_AREA_END {
+            let code = self.read_byte(0, address)?;
+
+            match code {
+                CISTPL_END => break,
+                CISTPL_NULL => {
+                    address += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let link = self.read_byte(0, address + 1)? as usize;
+            let body_address = address + 2;
+            let mut body = vec![0u8; link];
+
+            if link > 0 && link.is_multiple_of(4) {
+                self.read_extended(0, body_address, &mut body)?;
+            } else {
+                for (i, byte) in body.iter_mut().enumerate() {
+                    *byte = self.read_byte(0, body_address + i as u32)?;
+                }
+            }
+
+            tuples.push(Tuple { code, body });
+
+            if link == 0xFF {
+                break;
+            }
+            address = body_address + link as u32;
+        }
+
+        Ok(tuples)
+    }
+
+    // Reset the host, power up the I/O card and read everything it describes
+    // about itself
+    pub fn probe(&mut self) -> Result<CardInfo, SdError> {
+        route_pins();
+        self.host.reset(IDENT_CLOCK_HZ)?;
+        self.rca = 0;
+
+        // CMD5 with no voltage first just asks which voltages the card supports
+        let r4 = self.host.send(CMD_IO_SEND_OP_COND, 0, 1000)?;
+        let ocr = r4 & R4_OCR_MASK;
+        if (ocr & OCR_3V3) == 0 {
+            return Err(SdError::Unsupported);
+        }
+
+        // Again with 3.3V until the card is ready; a failed command ends the
+        // wait with its error
+        let mut r4 = Ok(0);
+        spin_until!(
+            {
+                r4 = self.host.send(CMD_IO_SEND_OP_COND, OCR_3V3, 1000);
+                r4.map_or(true, |r4| r4 & R4_READY != 0)
+            },
+            1000
+        )?;
+        let r4 = r4?;
+
+        self.rca = self.host.send(CMD_SEND_REL_ADDR, 0, 0)? & 0xFFFF_0000;
+        self.host.set_clock(TRANSFER_CLOCK_HZ)?;
+        self.host.send(CMD_CARD_SELECT, self.rca, 0)?;
+
+        let capability = self.read_byte(0, CCCR_CAPABILITY)?;
+
+        // Every full-speed SDIO card supports 4-bit; low-speed ones say so in bit 7
+        let mut bus_width = 1;
+        let low_speed_no_4bit = (capability & 0xC0) == 0x40;
+        if !low_speed_no_4bit {
+            let interface = self.read_byte(0, CCCR_BUS_INTERFACE)?;
+            if self
+                .write_byte(0, CCCR_BUS_INTERFACE, (interface & !0x03) | BUS_WIDTH_4)
+                .is_ok()
+            {
+                self.host
+                    .write_reg(CONTROL0, self.host.read_reg(CONTROL0) | C0_HCTL_DWIDTH);
+                bus_width = 4;
+            }
+        }
+
+        let function_count = ((r4 >> R4_FUNCTIONS_SHIFT) & R4_FUNCTIONS_MASK) as u8;
+        let common_cis = self.read_cis(self.read_pointer(CCCR_CIS_POINTER)?)?;
+
+        let mut functions = Vec::new();
+        for number in 1..=function_count {
+            let fbr = 0x100 * number as u32;
+            let interface = self.read_byte(0, fbr + FBR_INTERFACE)? & 0x0F;
+            let cis = self.read_cis(self.read_pointer(fbr + FBR_CIS_POINTER)?)?;
+
+            functions.push(Function {
+                number,
+                interface,
+                cis,
+            });
+        }
+
+        Ok(CardInfo {
+            ocr,
+            function_count,
+            memory_present: (r4 & R4_MEMORY_PRESENT) != 0,
+            cccr_revision: self.read_byte(0, CCCR_REVISION)?,
+            sd_spec: self.read_byte(0, CCCR_SD_SPEC)?,
+            capability,
+            bus_speed: self.read_byte(0, CCCR_BUS_SPEED)?,
+            bus_width,
+            common_cis,
+            functions,
+        })
+    }
+}
+
+// Probe the WiFi controller of this board, if it has a free one
+pub fn probe_wifi() -> Result<CardInfo, SdError> {
+    let base = wifi_sdio_base();
+    if base == 0 {
+        return Err(SdError::Unsupported);
+    }
+
+    Sdio::new(base).probe()
+}
+
+// On the Pi4 the WiFi chip sits on GPIO 34-39 (CLK, CMD, DAT0-3) in ALT3.
+// CLK is left floating, the other lines want pull-ups.
+fn route_pins() {
+    #[cfg(feature = "rpi4")]
+    {
+        use super::gpio::{self, Function, Pull};
+
+        for pin in 34..=39 {
+            gpio::set_function(pin, Function::Alt3);
+            gpio::set_pull(pin, if pin == 34 { Pull::None } else { Pull::Up });
+        }
+    }
+}
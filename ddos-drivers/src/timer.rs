@@ -0,0 +1,133 @@
+// ARM Generic Timer
+// Every Pi (and QEMU) has the architectural counter, including the RPi5 where
+// the legacy Broadcom system timer at TIMER_BASE is not available. The counter
+// runs at CNTFRQ_EL0 Hz, which the firmware programs before jumping to us.
+
+//
+// Host builds (unit tests of this crate) get a counter that never moves.
+
+use crate::hardwareselect::timer_base;
+#[cfg(target_arch = "aarch64")]
+use core::arch::asm;
+use core::fmt;
+use core::ptr::read_volatile;
+
+// Broadcom system timer: low word of its free-running 1 MHz counter
+const SYSTEM_TIMER_CLO: usize = 0x04;
+
+pub fn counter() -> u64 {
+    #[cfg(target_arch = "aarch64")]
+    {
+        let count: u64;
+        unsafe {
+            // isb so the read is not speculated ahead of the code we are timing
+            asm!("isb", "mrs {0}, cntpct_el0", out(reg) count, options(nomem, nostack));
+        }
+        count
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    {
+        0
+    }
+}
+
+pub fn frequency() -> u64 {
+    #[cfg(target_arch = "aarch64")]
+    {
+        let freq: u64;
+        unsafe {
+            asm!("mrs {0}, cntfrq_el0", out(reg) freq, options(nomem, nostack, preserves_flags));
+        }
+        freq
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    {
+        1_000_000
+    }
+}
+
+pub fn ticks_to_us(ticks: u64) -> u64 {
+    let freq = frequency().max(1);
+    (ticks as u128 * 1_000_000 / freq as u128) as u64
+}
+
+pub fn uptime_us() -> u64 {
+    ticks_to_us(counter())
+}
+
+pub fn uptime_ms() -> u64 {
+    uptime_us() / 1000
+}
+
+pub fn delay_us(us: u64) {
+    let start = counter();
+    let ticks = (us as u128 * frequency() as u128 / 1_000_000) as u64;
+
+    while counter().wrapping_sub(start) < ticks {
+        core::hint::spin_loop();
+    }
+}
+
+pub fn delay_ms(ms: u64) {
+    delay_us(ms * 1000);
+}
+
+// The Broadcom system timer in microseconds (wraps every 71 minutes), None
+// where there is none. Only there to cross-check the generic timer.
+pub fn system_timer_us() -> Option<u32> {
+    if cfg!(feature = "rpi5") || timer_base() == 0 {
+        return None;
+    }
+    Some(unsafe { read_volatile((timer_base() + SYSTEM_TIMER_CLO) as *const u32) })
+}
+
+// ============================================================================
+// BOUNDED POLLING
+// Drivers wait for status bits with spin_until! instead of a bare while
+// loop, so a peripheral that never answers turns into an error the caller
+// can handle rather than a kernel that stops dead:
+//
+//     spin_until!(read_volatile(reg(FR)) & FR_TXFF == 0, TX_TIMEOUT_MS)?;
+//
+// The condition is checked once more after the deadline, so a wait that was
+// held up (by an interrupt, say) only fails if the hardware really is stuck.
+// Each driver error type converts from Timeout, so `?` works directly.
+//
+// On the host the counter never moves, so a condition that stays false
+// there spins forever; host tests must not wait on hardware.
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout;
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "timed out")
+    }
+}
+
+// What spin_until! expands to
+pub fn spin_until(timeout_ms: u64, mut condition: impl FnMut() -> bool) -> Result<(), Timeout> {
+    let start = counter();
+    let ticks = (timeout_ms as u128 * frequency() as u128 / 1000) as u64;
+
+    while !condition() {
+        if counter().wrapping_sub(start) > ticks {
+            return if condition() { Ok(()) } else { Err(Timeout) };
+        }
+        core::hint::spin_loop();
+    }
+    Ok(())
+}
+
+// spin_until!(condition, timeout_ms): Ok once `condition` holds, Err(Timeout)
+// if it still does not after `timeout_ms` milliseconds. The condition is
+// re-evaluated on every pass and may read registers or update variables.
+#[macro_export]
+macro_rules! spin_until {
+    ($condition:expr, $timeout_ms:expr $(,)?) => {
+        $crate::timer::spin_until($timeout_ms, || $condition)
+    };
+}
@@ -0,0 +1,398 @@
+use crate::hardwareselect::{UART_CLOCK_HZ, uart0_base};
+use crate::locked::SpinLock;
+use crate::mailbox::{self, CLOCK_UART};
+use crate::spin_until;
+use crate::timer::Timeout;
+use core::fmt;
+use core::ptr::{read_volatile, write_volatile};
+
+// PL011 register offsets. The base comes from hardwareselect at runtime since
+// the device tree may move it.
+const DR: usize = 0x00;
+const FR: usize = 0x18;
+const IBRD: usize = 0x24;
+const FBRD: usize = 0x28;
+const LCRH: usize = 0x2C;
+const CR: usize = 0x30;
+const IMSC: usize = 0x38;
+const ICR: usize = 0x44;
+
+// Line control register
+const LCRH_PEN: u32 = 1 << 1;
+const LCRH_EPS: u32 = 1 << 2;
+const LCRH_STP2: u32 = 1 << 3;
+const LCRH_FEN: u32 = 1 << 4;
+// Word length, 0 for 5 bits up to 3 for 8
+const LCRH_WLEN_SHIFT: u32 = 5;
+
+// Flag register bits
+const FR_BUSY: u32 = 1 << 3;
+const FR_RXFE: u32 = 1 << 4;
+const FR_TXFF: u32 = 1 << 5;
+
+// Control register
+const CR_UARTEN: u32 = 1 << 0;
+const CR_LBE: u32 = 1 << 7;
+const CR_TXE: u32 = 1 << 8;
+const CR_RXE: u32 = 1 << 9;
+
+const ICR_ALL: u32 = 0x7FF;
+
+// Depth of either FIFO
+pub const FIFO_BYTES: usize = 16;
+
+// The 16-byte FIFO drains in under 2 ms at 115200 baud, so a transmitter
+// that stays full for longer than this is not coming back
+const TX_TIMEOUT_MS: u64 = 10;
+
+fn reg(offset: usize) -> *mut u32 {
+    (uart0_base() + offset) as *mut u32
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+// Speed and frame format, 115200 8N1 unless configured otherwise
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LineSettings {
+    pub baud: u32,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: u8,
+}
+
+impl LineSettings {
+    pub const DEFAULT: LineSettings = LineSettings {
+        baud: 115_200,
+        data_bits: 8,
+        parity: Parity::None,
+        stop_bits: 1,
+    };
+
+    // "9600", "9600n8", "19200e7" or "9600n82": the speed, then parity (n, e
+    // or o), data bits and stop bits as far as given, the rest as DEFAULT.
+    // Same as the options in Linux's console=ttyAMA0,115200n8.
+    pub fn parse(text: &str) -> Option<LineSettings> {
+        let digits = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let (baud, rest) = text.split_at(digits);
+        let mut settings = LineSettings {
+            baud: baud.parse().ok()?,
+            ..LineSettings::DEFAULT
+        };
+
+        let mut rest = rest.chars();
+        if let Some(parity) = rest.next() {
+            settings.parity = match parity {
+                'n' | 'N' => Parity::None,
+                'e' | 'E' => Parity::Even,
+                'o' | 'O' => Parity::Odd,
+                _ => return None,
+            };
+        }
+        if let Some(bits) = rest.next() {
+            settings.data_bits = bits.to_digit(10)? as u8;
+        }
+        if let Some(bits) = rest.next() {
+            settings.stop_bits = bits.to_digit(10)? as u8;
+        }
+        if rest.next().is_some() {
+            return None;
+        }
+        Some(settings)
+    }
+}
+
+// "115200 8N1"
+impl fmt::Display for LineSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Even => 'E',
+            Parity::Odd => 'O',
+        };
+        write!(
+            f,
+            "{} {}{}{}",
+            self.baud, self.data_bits, parity, self.stop_bits
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UartError {
+    // Out of reach of the divisors with the UART clock as it is
+    BaudRate(u32),
+    // 5 to 8
+    DataBits(u8),
+    // 1 or 2
+    StopBits(u8),
+    // The transmitter never finished what it was sending
+    Timeout,
+}
+
+impl fmt::Display for UartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UartError::BaudRate(baud) => {
+                write!(
+                    f,
+                    "{} baud is out of range for a {} Hz clock",
+                    baud,
+                    clock_hz()
+                )
+            }
+            UartError::DataBits(bits) => write!(f, "{} data bits, expected 5 to 8", bits),
+            UartError::StopBits(bits) => write!(f, "{} stop bits, expected 1 or 2", bits),
+            UartError::Timeout => write!(f, "timed out waiting for the transmitter"),
+        }
+    }
+}
+
+impl From<Timeout> for UartError {
+    fn from(_: Timeout) -> Self {
+        UartError::Timeout
+    }
+}
+
+// The UART clock as the firmware reports it (init_uart_clock in config.txt
+// moves it), UART_CLOCK_HZ on boards it cannot be asked on
+fn clock_hz() -> u32 {
+    match mailbox::clock_rate(CLOCK_UART) {
+        Ok(hz) if hz > 0 => hz,
+        _ => UART_CLOCK_HZ,
+    }
+}
+
+// IBRD and FBRD for `baud`: clock / (16 * baud) in 64ths, rounded. None
+// when the integer part does not fit its 16 bits.
+fn divisors(clock: u32, baud: u32) -> Option<(u32, u32)> {
+    if baud == 0 {
+        return None;
+    }
+    let times_64 = (clock as u64 * 4 + baud as u64 / 2) / baud as u64;
+    let integer = times_64 / 64;
+    if !(1..=0xFFFF).contains(&integer) {
+        return None;
+    }
+    Some((integer as u32, (times_64 % 64) as u32))
+}
+
+pub struct Uart {
+    settings: LineSettings,
+}
+
+pub static UART: SpinLock<Uart> = SpinLock::new(Uart::new());
+
+impl Uart {
+    pub const fn new() -> Uart {
+        Uart {
+            settings: LineSettings::DEFAULT,
+        }
+    }
+
+    // Program the current settings. The RPi5's firmware sets up the RP1's
+    // UART itself and is left alone until configure() says otherwise.
+    pub fn init(&mut self) {
+        #[cfg(feature = "rpi5")]
+        {
+            return;
+        }
+
+        #[cfg(not(feature = "rpi5"))]
+        {
+            // Nowhere to report a failure to yet, so fall back to settings
+            // that work with any of the usual clocks
+            if self.set_line_settings(self.settings).is_err() {
+                let _ = self.set_line_settings(LineSettings::DEFAULT);
+            }
+        }
+    }
+
+    pub fn line_settings(&self) -> LineSettings {
+        self.settings
+    }
+
+    pub fn set_line_settings(&mut self, settings: LineSettings) -> Result<(), UartError> {
+        self.configure(
+            settings.baud,
+            settings.data_bits,
+            settings.parity,
+            settings.stop_bits,
+        )
+    }
+
+    // Reprogram speed and frame format. What is still in the transmit FIFO
+    // goes out first, at the old settings; the receive FIFO is emptied.
+    pub fn configure(
+        &mut self,
+        baud: u32,
+        data_bits: u8,
+        parity: Parity,
+        stop_bits: u8,
+    ) -> Result<(), UartError> {
+        if !(5..=8).contains(&data_bits) {
+            return Err(UartError::DataBits(data_bits));
+        }
+        if !(1..=2).contains(&stop_bits) {
+            return Err(UartError::StopBits(stop_bits));
+        }
+        let (integer, fractional) = divisors(clock_hz(), baud).ok_or(UartError::BaudRate(baud))?;
+
+        let mut line = LCRH_FEN | (data_bits as u32 - 5) << LCRH_WLEN_SHIFT;
+        match parity {
+            Parity::None => {}
+            Parity::Even => line |= LCRH_PEN | LCRH_EPS,
+            Parity::Odd => line |= LCRH_PEN,
+        }
+        if stop_bits == 2 {
+            line |= LCRH_STP2;
+        }
+
+        unsafe {
+            spin_until!(read_volatile(reg(FR)) & FR_BUSY == 0, TX_TIMEOUT_MS)?;
+            write_volatile(reg(CR), 0);
+            write_volatile(reg(IMSC), 0);
+            write_volatile(reg(ICR), ICR_ALL);
+            // The divisors only take effect with the LCRH write after them
+            write_volatile(reg(IBRD), integer);
+            write_volatile(reg(FBRD), fractional);
+            write_volatile(reg(LCRH), line);
+            write_volatile(reg(CR), CR_UARTEN | CR_TXE | CR_RXE);
+        }
+
+        self.settings = LineSettings {
+            baud,
+            data_bits,
+            parity,
+            stop_bits,
+        };
+        Ok(())
+    }
+
+    // Err when the transmit FIFO stays full, the character is dropped then
+    pub fn send(&self, c: char) -> Result<(), Timeout> {
+        unsafe {
+            spin_until!(read_volatile(reg(FR)) & FR_TXFF == 0, TX_TIMEOUT_MS)?;
+            write_volatile(reg(DR), c as u32);
+        }
+        Ok(())
+    }
+
+    // Hand one byte to the transmit FIFO if it has room, never waits
+    pub fn try_send(&self, byte: u8) -> bool {
+        unsafe {
+            if read_volatile(reg(FR)) & FR_TXFF != 0 {
+                return false;
+            }
+            write_volatile(reg(DR), byte as u32);
+        }
+        true
+    }
+
+    // Wait until the last byte handed over has left the pins
+    pub fn flush(&self) -> Result<(), Timeout> {
+        unsafe { spin_until!(read_volatile(reg(FR)) & FR_BUSY == 0, TX_TIMEOUT_MS) }
+    }
+
+    // Waits for a key as long as it takes: an empty receive FIFO is someone
+    // not typing, not a stuck UART
+    pub fn read_byte(&self) -> u8 {
+        unsafe {
+            while (read_volatile(reg(FR)) & FR_RXFE) != 0 {}
+            (read_volatile(reg(DR)) & 0xFF) as u8
+        }
+    }
+
+    // Send `bytes` (at most FIFO_BYTES) through the PL011's internal
+    // loopback and return how many came back unchanged. The pins are left
+    // out of it, so it needs no jumper and shows nothing on the console, but
+    // whatever was waiting in the receive FIFO is thrown away. Err when a
+    // byte never comes back.
+    pub fn loopback(&self, bytes: &[u8]) -> Result<usize, Timeout> {
+        let bytes = &bytes[..bytes.len().min(FIFO_BYTES)];
+
+        unsafe {
+            // Console output still going out would loop back too
+            spin_until!(read_volatile(reg(FR)) & FR_BUSY == 0, TX_TIMEOUT_MS)?;
+            while self.try_read_byte().is_some() {}
+
+            let control = read_volatile(reg(CR));
+            write_volatile(reg(CR), control | CR_LBE);
+
+            for &byte in bytes {
+                write_volatile(reg(DR), byte as u32);
+            }
+            let matched = bytes.iter().try_fold(0, |matched, &byte| {
+                spin_until!(read_volatile(reg(FR)) & FR_RXFE == 0, TX_TIMEOUT_MS)?;
+                let same = (read_volatile(reg(DR)) & 0xFF) as u8 == byte;
+                Ok(matched + same as usize)
+            });
+
+            write_volatile(reg(CR), control);
+            matched
+        }
+    }
+
+    // Something is waiting in the receive FIFO
+    pub fn has_input(&self) -> bool {
+        unsafe { (read_volatile(reg(FR)) & FR_RXFE) == 0 }
+    }
+
+    // Non-blocking read, None while the receive FIFO is empty
+    pub fn try_read_byte(&self) -> Option<u8> {
+        unsafe {
+            if (read_volatile(reg(FR)) & FR_RXFE) != 0 {
+                return None;
+            }
+            Some((read_volatile(reg(DR)) & 0xFF) as u8)
+        }
+    }
+}
+
+impl fmt::Write for Uart {
+    // Sends the UTF-8 bytes, which the terminal puts back together. Gives
+    // up on the rest of the string at the first timeout, so a stuck UART
+    // costs one timeout per print rather than one per character
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send('\r').map_err(|_| fmt::Error)?;
+            }
+            self.send(byte as char).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineSettings, Parity};
+
+    #[test]
+    fn line_settings_parse() {
+        let slow = LineSettings {
+            baud: 9600,
+            ..LineSettings::DEFAULT
+        };
+        assert_eq!(LineSettings::parse("9600"), Some(slow));
+        assert_eq!(LineSettings::parse("9600n8"), Some(slow));
+
+        let unusual = LineSettings {
+            baud: 19200,
+            data_bits: 7,
+            parity: Parity::Even,
+            stop_bits: 2,
+        };
+        assert_eq!(LineSettings::parse("19200e72"), Some(unusual));
+
+        assert!(LineSettings::parse("fast").is_none());
+        assert!(LineSettings::parse("9600x8").is_none());
+        assert!(LineSettings::parse("9600n812").is_none());
+    }
+}
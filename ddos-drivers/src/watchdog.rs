@@ -0,0 +1,119 @@
+use crate::hardwareselect::watchdog_base;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+// ============================================================================
+// PM WATCHDOG
+// The power management block's watchdog counts down in ticks of 1/65536 s
+// and resets the chip when it reaches zero. start() arms it, feed() winds it
+// back up; whatever waits for input (the shell loop, deep idle) and anything
+// drawing a progress bar feeds it, so it only fires when the kernel hangs.
+// It keeps counting through a panic, which turns a panic into a reboot.
+//
+// reset() is the same mechanism with a tiny timeout, used by power.rs. The
+// RPi5 has no PM block at WATCHDOG_BASE: start() fails and reset() returns.
+// ============================================================================
+
+// Arm at boot with this timeout, for boards nobody is watching
+pub const START_AT_BOOT_MS: Option<u64> = None;
+
+const TICKS_PER_SECOND: u64 = 65536;
+// The counter is 20 bits wide
+const MAX_TICKS: u32 = 0x000F_FFFF;
+pub const MAX_TIMEOUT_MS: u64 = MAX_TICKS as u64 * 1000 / TICKS_PER_SECOND;
+
+// Register offsets from watchdog_base()
+const PM_RSTC: usize = 0x1C;
+const PM_RSTS: usize = 0x20;
+const PM_WDOG: usize = 0x24;
+
+// Every PM write needs this in the top byte
+const PM_PASSWORD: u32 = 0x5A00_0000;
+const PM_RSTC_WRCFG_MASK: u32 = 0x30;
+const PM_RSTC_WRCFG_FULL_RESET: u32 = 0x20;
+const PM_RSTC_RESET: u32 = 0x102;
+// The boot partition number is spread over every other bit of PM_RSTS
+const PM_RSTS_PARTITION_MASK: u32 = 0x555;
+
+// Ticks before reset() fires
+const RESET_TICKS: u32 = 10;
+
+// Ticks feed() winds the counter back to, 0 while stopped. Plain
+// loads/stores only, fine on RPi5 with the MMU off.
+static TIMEOUT_TICKS: AtomicU32 = AtomicU32::new(0);
+
+fn available() -> bool {
+    !cfg!(feature = "rpi5") && watchdog_base() != 0
+}
+
+fn reg(offset: usize) -> *mut u32 {
+    (watchdog_base() + offset) as *mut u32
+}
+
+fn arm(ticks: u32) {
+    unsafe {
+        write_volatile(reg(PM_WDOG), PM_PASSWORD | (ticks & MAX_TICKS));
+        let rstc = read_volatile(reg(PM_RSTC)) & !PM_RSTC_WRCFG_MASK;
+        write_volatile(reg(PM_RSTC), PM_PASSWORD | rstc | PM_RSTC_WRCFG_FULL_RESET);
+    }
+}
+
+// Reset the board unless fed within `timeout_ms` (clamped to 1 ms up to
+// MAX_TIMEOUT_MS). False if there is no watchdog.
+pub fn start(timeout_ms: u64) -> bool {
+    if !available() {
+        return false;
+    }
+
+    let timeout_ms = timeout_ms.clamp(1, MAX_TIMEOUT_MS);
+    let ticks = (timeout_ms * TICKS_PER_SECOND / 1000).max(1) as u32;
+    TIMEOUT_TICKS.store(ticks, Ordering::Relaxed);
+    arm(ticks);
+    true
+}
+
+// Cheap to call often, does nothing while stopped
+pub fn feed() {
+    match TIMEOUT_TICKS.load(Ordering::Relaxed) {
+        0 => {}
+        ticks => arm(ticks),
+    }
+}
+
+pub fn stop() {
+    if !available() {
+        return;
+    }
+
+    TIMEOUT_TICKS.store(0, Ordering::Relaxed);
+    unsafe { write_volatile(reg(PM_RSTC), PM_PASSWORD | PM_RSTC_RESET) };
+}
+
+// (timeout, time left) in milliseconds, None while stopped
+pub fn status() -> Option<(u64, u64)> {
+    let ticks = TIMEOUT_TICKS.load(Ordering::Relaxed);
+    if ticks == 0 {
+        return None;
+    }
+
+    let left = unsafe { read_volatile(reg(PM_WDOG)) } & MAX_TICKS;
+    Some((
+        ticks as u64 * 1000 / TICKS_PER_SECOND,
+        left as u64 * 1000 / TICKS_PER_SECOND,
+    ))
+}
+
+// Reset the chip right away. The firmware boots from `partition` (in the
+// PM_RSTS encoding) next; 0 is the normal boot.
+pub fn reset(partition: u32) {
+    if !available() {
+        return;
+    }
+
+    TIMEOUT_TICKS.store(0, Ordering::Relaxed);
+    unsafe {
+        let rsts = read_volatile(reg(PM_RSTS)) & !PM_RSTS_PARTITION_MASK;
+        write_volatile(reg(PM_RSTS), PM_PASSWORD | rsts | partition);
+    }
+    arm(RESET_TICKS);
+}
@@ -0,0 +1,1483 @@
+use core::fmt;
+use core::mem::size_of;
+
+// ============================================================================
+// FREE LIST HEAP
+// An explicit free list in address order. Every block, free or allocated,
+// starts with a FreeListNode header and ends with a footer holding its size:
+//
+//     [ size | next ][ payload ... ][ size ]
+//
+// Allocation searches the list with one of four strategies (OSTEP 17.3) and
+// splits off what is left when it can still hold a block. Freeing inserts
+// the block in address order and merges it with free neighbours.
+//
+// Nothing here knows where the memory comes from. The kernel hands it frames
+// and locks it behind the global allocator (src/memory/heap.rs); the host
+// tests below run it over a boxed buffer.
+//
+// deallocate() and reallocate() check the pointer before touching the list:
+// it has to point into one of the regions the heap was given, at a header
+// allocate() could have written, and not into a block that is already free.
+// Anything else is refused with a FreeError and the list is left as it was.
+// ============================================================================
+
+const ALIGN: usize = 16;
+
+// Separate ranges of memory the heap keeps track of; touching ones count once
+pub const MAX_REGIONS: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeapType {
+    BestFit,
+    WorstFit,
+    FirstFit,
+    NextFit,
+}
+
+impl HeapType {
+    pub const ALL: [HeapType; 4] = [
+        HeapType::BestFit,
+        HeapType::WorstFit,
+        HeapType::FirstFit,
+        HeapType::NextFit,
+    ];
+
+    // As typed on the kernel command line and in the shell
+    pub fn name(self) -> &'static str {
+        match self {
+            HeapType::BestFit => "bestfit",
+            HeapType::WorstFit => "worstfit",
+            HeapType::FirstFit => "firstfit",
+            HeapType::NextFit => "nextfit",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<HeapType> {
+        HeapType::ALL.into_iter().find(|kind| kind.name() == text)
+    }
+}
+
+pub struct FreeList {
+    pub head: Option<*mut FreeListNode>,
+    pub start_address: usize,
+    pub capacity: usize,
+    pub used: usize,
+    pub heap_type: HeapType,
+    pub(crate) last_position: Option<*mut FreeListNode>,
+    // Every range of memory the heap was given as (start, end), touching
+    // ones merged, so a pointer from somewhere else or from a gap between
+    // two of them is caught before its "header" is read
+    regions: [(usize, usize); MAX_REGIONS],
+    region_count: usize,
+}
+
+// Why deallocate() or reallocate() refused a pointer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FreeError {
+    // Not inside any memory the heap manages, or not where a payload starts
+    OutOfRange(usize),
+    // The header before it is not one allocate() wrote
+    BadHeader { address: usize, size: usize },
+    // On the free list already, or inside a block that is
+    AlreadyFree(usize),
+}
+
+impl fmt::Display for FreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FreeError::OutOfRange(address) => write!(f, "free of {:#x}: not a heap block", address),
+            FreeError::BadHeader { address, size } => {
+                write!(
+                    f,
+                    "free of {:#x}: bad block header (size {:#x})",
+                    address, size
+                )
+            }
+            FreeError::AlreadyFree(address) => write!(f, "free of {:#x}: already free", address),
+        }
+    }
+}
+
+#[repr(C, align(16))]
+pub struct FreeListNode {
+    size: usize,
+    next: Option<*mut FreeListNode>,
+}
+
+impl FreeListNode {
+    fn new(size: usize, next: Option<*mut FreeListNode>) -> Self {
+        FreeListNode { size, next }
+    }
+}
+
+impl FreeList {
+    // No memory at all until init(), usable in statics
+    pub const fn empty(heap_type: HeapType) -> Self {
+        FreeList {
+            head: None,
+            start_address: 0,
+            capacity: 0,
+            used: 0,
+            heap_type,
+            last_position: None,
+            regions: [(0, 0); MAX_REGIONS],
+            region_count: 0,
+        }
+    }
+
+    pub unsafe fn init(start: usize, capacity: usize, heap_type: HeapType) -> Self {
+        let aligned_start = Self::align_up(start).expect("heap start alignment overflow");
+        let alignment_loss = aligned_start - start;
+        let usable_capacity = capacity.saturating_sub(alignment_loss) & !(ALIGN - 1);
+
+        assert!(usable_capacity >= Self::block_overhead());
+
+        let node_ptr = aligned_start as *mut FreeListNode;
+        unsafe {
+            node_ptr.write(FreeListNode::new(usable_capacity, None));
+
+            let footer = (aligned_start + usable_capacity - size_of::<usize>()) as *mut usize;
+            footer.write(usable_capacity);
+        }
+
+        let mut regions = [(0, 0); MAX_REGIONS];
+        regions[0] = (aligned_start, aligned_start + usable_capacity);
+
+        FreeList {
+            head: Some(node_ptr),
+            start_address: aligned_start,
+            capacity: usable_capacity,
+            used: 0,
+            heap_type,
+            last_position: Some(node_ptr),
+            regions,
+            region_count: 1,
+        }
+    }
+
+    // The same over a buffer the caller owns, e.g. a Box<[u8]> on the host.
+    // The buffer must outlive the FreeList and not be touched any other way
+    // while it is in use.
+    pub unsafe fn from_buffer(buffer: &mut [u8], heap_type: HeapType) -> Self {
+        unsafe { Self::init(buffer.as_mut_ptr() as usize, buffer.len(), heap_type) }
+    }
+
+    // Hand another chunk of memory to the heap. It does not have to touch the
+    // existing ones; if it does, deallocate() merges it with its neighbour.
+    // False, and the memory left alone, when it is too small to hold a block
+    // or the heap already keeps MAX_REGIONS regions it touches none of.
+    pub unsafe fn add_region(&mut self, start: usize, size: usize) -> bool {
+        let Some(aligned_start) = Self::align_up(start) else {
+            return false;
+        };
+        let usable = size.saturating_sub(aligned_start - start) & !(ALIGN - 1);
+        if usable < Self::block_overhead() || !self.track(aligned_start, aligned_start + usable) {
+            return false;
+        }
+
+        unsafe {
+            let node_ptr = aligned_start as *mut FreeListNode;
+            node_ptr.write(FreeListNode::new(usable, None));
+
+            let footer = (aligned_start + usable - size_of::<usize>()) as *mut usize;
+            footer.write(usable);
+
+            // Free it as if it had been allocated, so it lands in address order
+            self.capacity += usable;
+            self.used += usable;
+            self.release(node_ptr);
+        }
+        true
+    }
+
+    // Add [start, end) to the regions, merged with any it touches, so a
+    // block merged across the seam still lies inside one region
+    fn track(&mut self, mut start: usize, mut end: usize) -> bool {
+        let mut index = 0;
+        while index < self.region_count {
+            let (region_start, region_end) = self.regions[index];
+            if region_end == start || region_start == end {
+                start = start.min(region_start);
+                end = end.max(region_end);
+                self.region_count -= 1;
+                self.regions[index] = self.regions[self.region_count];
+                index = 0;
+            } else {
+                index += 1;
+            }
+        }
+
+        if self.region_count == MAX_REGIONS {
+            return false;
+        }
+        self.regions[self.region_count] = (start, end);
+        self.region_count += 1;
+        true
+    }
+
+    // Take [start, end) out of the region holding it. False, with nothing
+    // changed, when that would split a region and there is no room for the
+    // second half.
+    fn untrack(&mut self, start: usize, end: usize) -> bool {
+        let Some(index) = self.regions[..self.region_count]
+            .iter()
+            .position(|&(region_start, region_end)| region_start <= start && end <= region_end)
+        else {
+            return false;
+        };
+        let (region_start, region_end) = self.regions[index];
+
+        match (region_start == start, region_end == end) {
+            (true, true) => {
+                self.region_count -= 1;
+                self.regions[index] = self.regions[self.region_count];
+            }
+            (true, false) => self.regions[index].0 = end,
+            (false, true) => self.regions[index].1 = start,
+            (false, false) => {
+                if self.region_count == MAX_REGIONS {
+                    return false;
+                }
+                self.regions[index].1 = start;
+                self.regions[self.region_count] = (end, region_end);
+                self.region_count += 1;
+            }
+        }
+        true
+    }
+
+    // The region `address` lies in, as (start, end)
+    fn region_of(&self, address: usize) -> Option<(usize, usize)> {
+        self.regions[..self.region_count]
+            .iter()
+            .copied()
+            .find(|&(start, end)| start <= address && address < end)
+    }
+
+    // Merge every pair of free blocks that touch. deallocate() already does
+    // this for its neighbours, so normally there is nothing to do; this is
+    // the last resort before an allocation is declared failed.
+    // Returns how many merges happened.
+    pub fn coalesce(&mut self) -> usize {
+        let mut merges = 0;
+        let mut current = self.head;
+
+        while let Some(node_ptr) = current {
+            unsafe {
+                let node = &mut *node_ptr;
+
+                match node.next {
+                    Some(next_ptr) if node_ptr as usize + node.size == next_ptr as usize => {
+                        node.size += (*next_ptr).size;
+                        node.next = (*next_ptr).next;
+
+                        let footer =
+                            (node_ptr as usize + node.size - size_of::<usize>()) as *mut usize;
+                        footer.write(node.size);
+
+                        if self.last_position == Some(next_ptr) {
+                            self.last_position = Some(node_ptr);
+                        }
+                        merges += 1;
+                        // Stay on this node, it may reach the next one now
+                    }
+                    next => current = next,
+                }
+            }
+        }
+
+        merges
+    }
+
+    // Take whole free pages outside [keep_start, keep_end) out of the heap and
+    // hand each one to `release`, so regions added during a busy spell do not
+    // stay with the heap forever. What is left of a free block on either side
+    // must still hold a block, otherwise that page stays too.
+    // Returns how many bytes went.
+    pub fn trim(
+        &mut self,
+        keep_start: usize,
+        keep_end: usize,
+        page: usize,
+        mut release: impl FnMut(usize),
+    ) -> usize {
+        let mut released = 0;
+        let mut prev: Option<*mut FreeListNode> = None;
+        let mut current = self.head;
+
+        while let Some(node_ptr) = current {
+            let start = node_ptr as usize;
+            let (size, next) = unsafe { ((*node_ptr).size, (*node_ptr).next) };
+            let end = start + size;
+            current = next;
+
+            let mut low = (start + page - 1) & !(page - 1);
+            let mut high = end & !(page - 1);
+
+            // Only one side of the kept range can go, take the upper one
+            if low < keep_end && high > keep_start {
+                if high > keep_end {
+                    low = (keep_end + page - 1) & !(page - 1);
+                } else {
+                    high = keep_start & !(page - 1);
+                }
+            }
+            if low > start && low - start < Self::block_overhead() {
+                low += page;
+            }
+            if high < end && end - high < Self::block_overhead() {
+                high = high.saturating_sub(page);
+            }
+            if low >= high || !self.untrack(low, high) {
+                prev = Some(node_ptr);
+                continue;
+            }
+
+            unsafe {
+                // The rest of the block after the released pages
+                let after = if high < end {
+                    let tail_ptr = high as *mut FreeListNode;
+                    tail_ptr.write(FreeListNode::new(end - high, next));
+                    ((end - size_of::<usize>()) as *mut usize).write(end - high);
+                    Some(tail_ptr)
+                } else {
+                    next
+                };
+
+                if low > start {
+                    (*node_ptr).size = low - start;
+                    (*node_ptr).next = after;
+                    ((low - size_of::<usize>()) as *mut usize).write(low - start);
+                    prev = Some(node_ptr);
+                } else {
+                    match prev {
+                        Some(prev_ptr) => (*prev_ptr).next = after,
+                        None => self.head = after,
+                    }
+                    if self.last_position == Some(node_ptr) {
+                        self.last_position = after;
+                    }
+                }
+
+                if let Some(tail_ptr) = after.filter(|_| high < end) {
+                    prev = Some(tail_ptr);
+                }
+            }
+
+            for address in (low..high).step_by(page) {
+                release(address);
+            }
+            self.capacity -= high - low;
+            released += high - low;
+        }
+
+        if self.last_position.is_none() {
+            self.last_position = self.head;
+        }
+        released
+    }
+
+    // (free blocks, free bytes, largest free block)
+    pub fn free_stats(&self) -> (usize, usize, usize) {
+        let mut current = self.head;
+        let (mut blocks, mut free, mut largest) = (0, 0, 0);
+
+        while let Some(node_ptr) = current {
+            let size = unsafe { (*node_ptr).size };
+            blocks += 1;
+            free += size;
+            largest = largest.max(size);
+            current = unsafe { (*node_ptr).next };
+        }
+
+        (blocks, free, largest)
+    }
+
+    // Walk the free list and panic with the details if it is damaged: nodes
+    // must be aligned, in address order without overlap, carry a matching
+    // footer, and free + used must add up to the capacity.
+    // Returns (free blocks, free bytes).
+    pub fn check(&self) -> (usize, usize) {
+        let mut current = self.head;
+        let mut previous_end = 0;
+        let mut blocks = 0;
+        let mut free = 0;
+
+        while let Some(node_ptr) = current {
+            let address = node_ptr as usize;
+            let size = unsafe { (*node_ptr).size };
+
+            if !address.is_multiple_of(ALIGN)
+                || !size.is_multiple_of(ALIGN)
+                || size < Self::block_overhead()
+            {
+                panic!(
+                    "heap: free block {} at {:#x} has bad address or size {:#x}",
+                    blocks, address, size
+                );
+            }
+            if address < previous_end {
+                panic!(
+                    "heap: free block {} at {:#x} overlaps or precedes the one ending at {:#x}",
+                    blocks, address, previous_end
+                );
+            }
+
+            let footer = unsafe { *((address + size - size_of::<usize>()) as *const usize) };
+            if footer != size {
+                panic!(
+                    "heap: free block {} at {:#x} is {:#x} bytes but its footer says {:#x}",
+                    blocks, address, size, footer
+                );
+            }
+
+            previous_end = address + size;
+            blocks += 1;
+            free += size;
+            current = unsafe { (*node_ptr).next };
+        }
+
+        if free + self.used != self.capacity {
+            panic!(
+                "heap: {} bytes free + {} used != {} capacity ({} free blocks)",
+                free, self.used, self.capacity, blocks
+            );
+        }
+
+        (blocks, free)
+    }
+
+    fn contains_node(&self, target: *mut FreeListNode) -> bool {
+        let mut current = self.head;
+
+        while let Some(node_ptr) = current {
+            if node_ptr == target {
+                return true;
+            }
+
+            unsafe {
+                current = (*node_ptr).next;
+            }
+        }
+
+        false
+    }
+
+    fn find_prev_node(&self, target: *mut FreeListNode) -> Option<*mut FreeListNode> {
+        let mut current = self.head;
+        let mut prev = None;
+
+        while let Some(node_ptr) = current {
+            if node_ptr == target {
+                return prev;
+            }
+
+            unsafe {
+                prev = current;
+                current = (*node_ptr).next;
+            }
+        }
+
+        None
+    }
+
+    fn find_region_best_fit(
+        &mut self,
+        requested_size: usize,
+    ) -> (Option<*mut FreeListNode>, Option<*mut FreeListNode>) {
+        let mut current = self.head;
+        let mut prev: Option<*mut FreeListNode> = None;
+
+        let mut best: Option<*mut FreeListNode> = None;
+        let mut best_prev: Option<*mut FreeListNode> = None;
+
+        if requested_size > self.capacity {
+            return (None, None);
+        }
+
+        while let Some(node_ptr) = current {
+            unsafe {
+                let node = &*node_ptr;
+
+                if node.size >= requested_size
+                    && (best.is_none() || node.size < (*best.unwrap()).size)
+                {
+                    best = Some(node_ptr);
+                    best_prev = prev;
+                }
+
+                prev = current;
+                current = node.next;
+            }
+        }
+
+        (best, best_prev)
+    }
+
+    fn find_region_worst_fit(
+        &mut self,
+        requested_size: usize,
+    ) -> (Option<*mut FreeListNode>, Option<*mut FreeListNode>) {
+        let mut current = self.head;
+        let mut prev: Option<*mut FreeListNode> = None;
+
+        let mut worst: Option<*mut FreeListNode> = None;
+        let mut worst_prev: Option<*mut FreeListNode> = None;
+
+        if requested_size > self.capacity {
+            return (None, None);
+        }
+
+        while let Some(node_ptr) = current {
+            unsafe {
+                let node = &*node_ptr;
+
+                if node.size >= requested_size
+                    && (worst.is_none() || node.size > (*worst.unwrap()).size)
+                {
+                    worst = Some(node_ptr);
+                    worst_prev = prev;
+                }
+
+                prev = current;
+                current = node.next;
+            }
+        }
+
+        (worst, worst_prev)
+    }
+
+    // Next Fit (OSTEP 17.3): start searching where the last allocation left off
+    // instead of always at the head, wrapping around to the head once the end of
+    // the list is reached. `last_position` survives across allocations and frees.
+    fn find_region_next_fit(
+        &mut self,
+        requested_size: usize,
+    ) -> (Option<*mut FreeListNode>, Option<*mut FreeListNode>) {
+        if requested_size > self.capacity {
+            return (None, None);
+        }
+
+        let head = match self.head {
+            Some(head) => head,
+            None => return (None, None),
+        };
+
+        // The roving pointer can go stale if its node was handed out in full or
+        // merged away, so fall back to the head when it is no longer in the list.
+        let start = match self.last_position {
+            Some(cursor) if self.contains_node(cursor) => cursor,
+            _ => head,
+        };
+
+        let mut current = Some(start);
+        let mut prev = self.find_prev_node(start);
+
+        // First pass: roving pointer -> end of list
+        while let Some(node_ptr) = current {
+            unsafe {
+                let node = &*node_ptr;
+
+                if node.size >= requested_size {
+                    return (Some(node_ptr), prev);
+                }
+
+                prev = current;
+                current = node.next;
+            }
+        }
+
+        // Second pass: wrap around, head -> roving pointer
+        current = self.head;
+        prev = None;
+
+        while let Some(node_ptr) = current {
+            if node_ptr == start {
+                break;
+            }
+
+            unsafe {
+                let node = &*node_ptr;
+
+                if node.size >= requested_size {
+                    return (Some(node_ptr), prev);
+                }
+
+                prev = current;
+                current = node.next;
+            }
+        }
+
+        (None, None)
+    }
+
+    fn find_region_first_fit(
+        &mut self,
+        requested_size: usize,
+    ) -> (Option<*mut FreeListNode>, Option<*mut FreeListNode>) {
+        let mut current = self.head;
+        let mut prev = None;
+
+        if requested_size > self.capacity {
+            return (None, None);
+        }
+
+        while let Some(node_ptr) = current {
+            unsafe {
+                let node = &*node_ptr;
+
+                if node.size >= requested_size {
+                    return (Some(node_ptr), prev);
+                }
+
+                prev = current;
+                current = node.next;
+            }
+        }
+
+        (None, None)
+    }
+
+    fn align_up(size: usize) -> Option<usize> {
+        size.checked_add(ALIGN - 1).map(|s| s & !(ALIGN - 1))
+    }
+
+    fn block_overhead() -> usize {
+        size_of::<FreeListNode>() + size_of::<usize>()
+    }
+
+    // Switch the fit used from the next allocation on. Blocks already handed
+    // out stay where they are; next fit starts over from the head.
+    pub fn set_heap_type(&mut self, heap_type: HeapType) {
+        self.heap_type = heap_type;
+        self.last_position = self.head;
+    }
+
+    // Full block size (header + payload + footer) needed to serve a request
+    pub fn block_size_for(requested_size: usize) -> Option<usize> {
+        let request = requested_size.max(1);
+        let aligned_payload = Self::align_up(request)?;
+        let raw_total = aligned_payload.checked_add(Self::block_overhead())?;
+        Self::align_up(raw_total)
+    }
+
+    pub fn allocate(&mut self, requested_size: usize, requested_align: usize) -> Option<*mut u8> {
+        if requested_align > ALIGN {
+            return None;
+        }
+
+        let total_size = Self::block_size_for(requested_size)?;
+
+        let (region, prev) = match self.heap_type {
+            HeapType::FirstFit => self.find_region_first_fit(total_size),
+            HeapType::BestFit => self.find_region_best_fit(total_size),
+            HeapType::WorstFit => self.find_region_worst_fit(total_size),
+            HeapType::NextFit => self.find_region_next_fit(total_size),
+        };
+
+        let node_ptr = region?;
+
+        unsafe {
+            let node = &mut *node_ptr;
+            let next_cursor;
+
+            if node.size >= total_size + Self::block_overhead() {
+                let remaining_size = node.size - total_size;
+
+                let new_node_ptr = (node_ptr as *mut u8).add(total_size) as *mut FreeListNode;
+
+                new_node_ptr.write(FreeListNode::new(remaining_size, node.next));
+
+                let new_footer =
+                    (new_node_ptr as usize + remaining_size - size_of::<usize>()) as *mut usize;
+                new_footer.write(remaining_size);
+
+                if let Some(prev_ptr) = prev {
+                    (*prev_ptr).next = Some(new_node_ptr);
+                } else {
+                    self.head = Some(new_node_ptr);
+                }
+
+                next_cursor = Some(new_node_ptr);
+
+                node.size = total_size;
+            } else {
+                let next_node = node.next;
+
+                if let Some(prev_ptr) = prev {
+                    (*prev_ptr).next = next_node;
+                } else {
+                    self.head = next_node;
+                }
+
+                next_cursor = next_node.or(self.head);
+            }
+
+            self.last_position = next_cursor.or(self.head);
+            self.used += node.size;
+
+            let footer_ptr = (node_ptr as usize + node.size - size_of::<usize>()) as *mut usize;
+            footer_ptr.write(node.size);
+
+            Some((node_ptr as *mut u8).add(size_of::<FreeListNode>()))
+        }
+    }
+
+    // Give back a block allocate() or reallocate() handed out. Refuses (and
+    // changes nothing) when `address` cannot be such a block or is free.
+    pub fn deallocate(&mut self, address: usize) -> Result<(), FreeError> {
+        let node_ptr = self.validate(address)?;
+        unsafe { self.release(node_ptr) };
+        Ok(())
+    }
+
+    // The header of the block at `address`, if freeing it is safe
+    fn validate(&self, address: usize) -> Result<*mut FreeListNode, FreeError> {
+        let header = size_of::<FreeListNode>();
+        let node = address.wrapping_sub(header);
+        let region_end = match self.region_of(node) {
+            Some((_, end)) if address.is_multiple_of(ALIGN) && address < end => end,
+            _ => return Err(FreeError::OutOfRange(address)),
+        };
+
+        // Freed before, either still on its own or merged into a neighbour
+        let mut current = self.head;
+        let mut next_free = region_end;
+        while let Some(free_ptr) = current {
+            let start = free_ptr as usize;
+            if start > node {
+                next_free = next_free.min(start);
+                break;
+            }
+            if node < start + unsafe { (*free_ptr).size } {
+                return Err(FreeError::AlreadyFree(address));
+            }
+            current = unsafe { (*free_ptr).next };
+        }
+
+        // A real block ends before the next free one starts
+        let node_ptr = node as *mut FreeListNode;
+        let size = unsafe { (*node_ptr).size };
+        let sane = size.is_multiple_of(ALIGN)
+            && size >= Self::block_overhead()
+            && size <= next_free - node
+            && unsafe { *((node + size - size_of::<usize>()) as *const usize) } == size;
+        if !sane {
+            return Err(FreeError::BadHeader { address, size });
+        }
+
+        Ok(node_ptr)
+    }
+
+    // Put a block on the free list, merging it with free neighbours. No
+    // checks: the block must be allocated and belong to this heap.
+    unsafe fn release(&mut self, node_ptr: *mut FreeListNode) {
+        unsafe {
+            let node = &mut *node_ptr;
+            self.used -= node.size;
+
+            let mut current = self.head;
+            let mut prev: Option<*mut FreeListNode> = None;
+
+            while let Some(curr_ptr) = current {
+                if curr_ptr as usize > node_ptr as usize {
+                    break;
+                }
+                prev = current;
+                current = (*curr_ptr).next;
+            }
+
+            node.next = current;
+
+            if let Some(prev_ptr) = prev {
+                (*prev_ptr).next = Some(node_ptr);
+            } else {
+                self.head = Some(node_ptr);
+            }
+
+            if let Some(next_ptr) = node.next {
+                let node_end = node_ptr as usize + node.size;
+
+                if node_end == next_ptr as usize {
+                    node.size += (*next_ptr).size;
+                    node.next = (*next_ptr).next;
+
+                    let footer = (node_ptr as usize + node.size - size_of::<usize>()) as *mut usize;
+                    footer.write(node.size);
+
+                    // The roving pointer was sitting on the block we just absorbed
+                    if self.last_position == Some(next_ptr) {
+                        self.last_position = Some(node_ptr);
+                    }
+                }
+            }
+
+            // Only merge backwards into a block that is actually on the free list.
+            // Allocated blocks carry the same header and footer, so the footer alone
+            // cannot tell us whether the neighbour is free.
+            if let Some(prev_node_ptr) = prev {
+                let prev_start = prev_node_ptr as usize;
+                let prev_node = &mut *prev_node_ptr;
+
+                if prev_start + prev_node.size == node_ptr as usize {
+                    prev_node.size += node.size;
+                    prev_node.next = node.next;
+
+                    let footer = (prev_start + prev_node.size - size_of::<usize>()) as *mut usize;
+                    footer.write(prev_node.size);
+
+                    // The freed block no longer exists on its own, so point at its new owner
+                    if self.last_position == Some(node_ptr) {
+                        self.last_position = Some(prev_node_ptr);
+                    }
+                }
+            }
+
+            if self.last_position.is_none() {
+                self.last_position = self.head;
+            }
+        }
+    }
+
+    // Resize a block, growing into the physically next block when it is free so
+    // Vec/String growth does not have to copy. Only falls back to
+    // allocate + copy + deallocate when the neighbour cannot satisfy the request.
+    // Ok(None) when there is no room, the block left as it was; refuses
+    // `address` just like deallocate() does.
+    pub fn reallocate(
+        &mut self,
+        address: usize,
+        new_size: usize,
+        requested_align: usize,
+    ) -> Result<Option<*mut u8>, FreeError> {
+        let node_ptr = self.validate(address)?;
+        if requested_align > ALIGN {
+            return Ok(None);
+        }
+        let Some(total_size) = Self::block_size_for(new_size) else {
+            return Ok(None);
+        };
+
+        unsafe {
+            let current_size = (*node_ptr).size;
+
+            // Shrinking (or already big enough): give the tail back if it can hold a block
+            if total_size <= current_size {
+                if current_size - total_size >= Self::block_overhead() {
+                    self.shrink_block(node_ptr, total_size);
+                }
+                return Ok(Some(address as *mut u8));
+            }
+
+            // Growing: try to swallow the free block right after us. Regions
+            // added by grow() can sit anywhere, so whether that address is
+            // heap at all is only known from the free list itself.
+            let next_ptr = (node_ptr as usize + current_size) as *mut FreeListNode;
+
+            if self.contains_node(next_ptr) {
+                let combined = current_size + (*next_ptr).size;
+
+                if combined >= total_size {
+                    self.grow_into_next(node_ptr, next_ptr, total_size);
+                    return Ok(Some(address as *mut u8));
+                }
+            }
+
+            // No room in place, move the data
+            let Some(new_ptr) = self.allocate(new_size, requested_align) else {
+                return Ok(None);
+            };
+            let old_payload = current_size - Self::block_overhead();
+            core::ptr::copy_nonoverlapping(
+                address as *const u8,
+                new_ptr,
+                old_payload.min(new_size),
+            );
+            self.release(node_ptr);
+
+            Ok(Some(new_ptr))
+        }
+    }
+
+    unsafe fn shrink_block(&mut self, node_ptr: *mut FreeListNode, total_size: usize) {
+        unsafe {
+            let node = &mut *node_ptr;
+            let tail_size = node.size - total_size;
+            let tail_ptr = (node_ptr as *mut u8).add(total_size) as *mut FreeListNode;
+
+            node.size = total_size;
+            let footer = (node_ptr as usize + total_size - size_of::<usize>()) as *mut usize;
+            footer.write(total_size);
+
+            // Make the tail look like an allocated block and free it, so the
+            // normal path handles insertion and coalescing with whatever follows
+            tail_ptr.write(FreeListNode::new(tail_size, None));
+            let tail_footer = (tail_ptr as usize + tail_size - size_of::<usize>()) as *mut usize;
+            tail_footer.write(tail_size);
+
+            self.release(tail_ptr);
+        }
+    }
+
+    unsafe fn grow_into_next(
+        &mut self,
+        node_ptr: *mut FreeListNode,
+        next_ptr: *mut FreeListNode,
+        total_size: usize,
+    ) {
+        unsafe {
+            let node = &mut *node_ptr;
+            let prev = self.find_prev_node(next_ptr);
+            let next_size = (*next_ptr).size;
+            let next_next = (*next_ptr).next;
+            let combined = node.size + next_size;
+            let old_size = node.size;
+
+            // Either leave a smaller free block behind or take the neighbour whole
+            let replacement = if combined - total_size >= Self::block_overhead() {
+                let remaining = combined - total_size;
+                let new_free = (node_ptr as *mut u8).add(total_size) as *mut FreeListNode;
+
+                new_free.write(FreeListNode::new(remaining, next_next));
+                let new_footer = (new_free as usize + remaining - size_of::<usize>()) as *mut usize;
+                new_footer.write(remaining);
+
+                node.size = total_size;
+                Some(new_free)
+            } else {
+                node.size = combined;
+                next_next
+            };
+
+            if let Some(prev_ptr) = prev {
+                (*prev_ptr).next = replacement;
+            } else {
+                self.head = replacement;
+            }
+
+            if self.last_position == Some(next_ptr) {
+                self.last_position = replacement.or(self.head);
+            }
+
+            self.used += node.size - old_size;
+
+            let footer = (node_ptr as usize + node.size - size_of::<usize>()) as *mut usize;
+            footer.write(node.size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A FreeList over a boxed buffer. The list is declared first so it is
+    // dropped before the memory it points into.
+    struct Arena {
+        list: FreeList,
+        _memory: Box<[u8]>,
+    }
+
+    fn arena(size: usize, heap_type: HeapType) -> Arena {
+        let mut memory = vec![0u8; size].into_boxed_slice();
+        let list = unsafe { FreeList::from_buffer(&mut memory, heap_type) };
+        Arena {
+            list,
+            _memory: memory,
+        }
+    }
+
+    fn alloc(list: &mut FreeList, size: usize) -> usize {
+        list.allocate(size, 8).expect("allocation failed") as usize
+    }
+
+    // Block sizes of the free list, in address order
+    fn free_sizes(list: &FreeList) -> Vec<usize> {
+        let mut sizes = Vec::new();
+        let mut current = list.head;
+        while let Some(node_ptr) = current {
+            unsafe {
+                sizes.push((*node_ptr).size);
+                current = (*node_ptr).next;
+            }
+        }
+        sizes
+    }
+
+    // Largest payload whose block is exactly `block` bytes
+    fn payload_for(block: usize) -> usize {
+        (block - FreeList::block_overhead()) & !(ALIGN - 1)
+    }
+
+    // Four free blocks of 128, 512, 256 and 1024 bytes (in address order),
+    // kept apart by allocated blocks, and nothing else free. Returns the
+    // payload addresses of the free blocks in that order.
+    fn holes(list: &mut FreeList) -> [usize; 4] {
+        let sizes = [128, 512, 256, 1024];
+
+        let mut freed = [0; 4];
+        for (slot, &size) in sizes.iter().enumerate() {
+            freed[slot] = alloc(list, payload_for(size));
+            alloc(list, 16);
+        }
+        let (_, rest, _) = list.free_stats();
+        alloc(list, payload_for(rest));
+
+        for address in freed {
+            list.deallocate(address).unwrap();
+        }
+        assert_eq!(free_sizes(list), sizes);
+        freed
+    }
+
+    // 32 byte header, 8 byte footer, all in steps of 16
+    #[test]
+    fn block_sizes_round_up_with_room_for_header_and_footer() {
+        assert_eq!(FreeList::block_overhead(), 40);
+        assert_eq!(FreeList::block_size_for(0), Some(64));
+        assert_eq!(FreeList::block_size_for(16), Some(64));
+        assert_eq!(FreeList::block_size_for(17), Some(80));
+        assert_eq!(FreeList::block_size_for(usize::MAX), None);
+    }
+
+    #[test]
+    fn init_aligns_the_start_and_the_capacity() {
+        let mut memory = vec![0u8; 4096 + 32].into_boxed_slice();
+        let start = (memory.as_ptr() as usize + 15) & !15;
+        let offset = start + 1 - memory.as_ptr() as usize;
+
+        let list =
+            unsafe { FreeList::from_buffer(&mut memory[offset..offset + 4000], HeapType::BestFit) };
+        assert_eq!(list.start_address, start + 16);
+        assert_eq!(list.capacity, (4000 - 15) & !15);
+        assert_eq!(list.check(), (1, list.capacity));
+    }
+
+    #[test]
+    fn allocation_splits_the_block() {
+        let mut arena = arena(4096, HeapType::FirstFit);
+        let list = &mut arena.list;
+        let capacity = list.capacity;
+
+        let address = alloc(list, 32);
+        assert_eq!(address, list.start_address + size_of::<FreeListNode>());
+        assert_eq!(address % ALIGN, 0);
+        assert_eq!(list.used, 80);
+        assert_eq!(free_sizes(list), [capacity - 80]);
+        list.check();
+    }
+
+    #[test]
+    fn small_remainder_is_not_split_off() {
+        let mut arena = arena(4096, HeapType::FirstFit);
+        let list = &mut arena.list;
+        let capacity = list.capacity;
+
+        // Leaves 16 bytes, less than a block needs
+        alloc(list, payload_for(capacity - 16));
+        assert_eq!(list.used, capacity);
+        assert!(list.head.is_none());
+        assert!(list.allocate(1, 8).is_none());
+        list.check();
+    }
+
+    #[test]
+    fn free_merges_with_both_neighbours() {
+        let mut arena = arena(4096, HeapType::FirstFit);
+        let list = &mut arena.list;
+        let capacity = list.capacity;
+
+        let a = alloc(list, 100);
+        let b = alloc(list, 100);
+        let c = alloc(list, 100);
+        let _guard = alloc(list, 100);
+        assert_eq!(free_sizes(list).len(), 1);
+
+        // a and c are not next to each other, b joins them
+        list.deallocate(a).unwrap();
+        list.deallocate(c).unwrap();
+        assert_eq!(free_sizes(list).len(), 3);
+        list.deallocate(b).unwrap();
+        let block = FreeList::block_size_for(100).unwrap();
+        assert_eq!(free_sizes(list), [3 * block, capacity - 4 * block]);
+        list.check();
+    }
+
+    #[test]
+    fn everything_freed_is_one_block_again() {
+        let mut arena = arena(8192, HeapType::BestFit);
+        let list = &mut arena.list;
+        let capacity = list.capacity;
+
+        let addresses: Vec<usize> = (1..20).map(|n| alloc(list, n * 24)).collect();
+        for address in addresses.iter().step_by(2) {
+            list.deallocate(*address).unwrap();
+        }
+        for address in addresses.iter().skip(1).step_by(2) {
+            list.deallocate(*address).unwrap();
+        }
+
+        assert_eq!(list.used, 0);
+        assert_eq!(free_sizes(list), [capacity]);
+        list.check();
+    }
+
+    #[test]
+    fn coalesce_merges_blocks_that_touch() {
+        let mut arena = arena(4096, HeapType::FirstFit);
+        let list = &mut arena.list;
+        let capacity = list.capacity;
+
+        // Split the free space into two touching blocks by hand, as a region
+        // added next to the heap would be before anything merged it
+        let half = capacity / 2;
+        unsafe {
+            let head = list.head.unwrap();
+            let second = (head as usize + half) as *mut FreeListNode;
+            second.write(FreeListNode::new(capacity - half, None));
+            ((head as usize + capacity - size_of::<usize>()) as *mut usize).write(capacity - half);
+            (*head).size = half;
+            (*head).next = Some(second);
+            ((head as usize + half - size_of::<usize>()) as *mut usize).write(half);
+        }
+        list.check();
+
+        assert_eq!(list.coalesce(), 1);
+        assert_eq!(free_sizes(list), [capacity]);
+        assert_eq!(list.coalesce(), 0);
+    }
+
+    #[test]
+    fn first_fit_takes_the_first_block_that_fits() {
+        let mut arena = arena(8192, HeapType::FirstFit);
+        let holes = holes(&mut arena.list);
+        assert_eq!(alloc(&mut arena.list, 200), holes[1]);
+        arena.list.check();
+    }
+
+    #[test]
+    fn best_fit_takes_the_smallest_block_that_fits() {
+        let mut arena = arena(8192, HeapType::BestFit);
+        let holes = holes(&mut arena.list);
+        assert_eq!(alloc(&mut arena.list, 200), holes[2]);
+        arena.list.check();
+    }
+
+    #[test]
+    fn worst_fit_takes_the_largest_block() {
+        let mut arena = arena(8192, HeapType::WorstFit);
+        let holes = holes(&mut arena.list);
+        assert_eq!(alloc(&mut arena.list, 200), holes[3]);
+        arena.list.check();
+    }
+
+    #[test]
+    fn next_fit_carries_on_from_the_last_allocation() {
+        let mut arena = arena(8192, HeapType::NextFit);
+        let list = &mut arena.list;
+        let holes = holes(list);
+
+        // Half of the 512 byte block, then the rest of it where first fit
+        // would go back to the 128 byte one
+        assert_eq!(alloc(list, payload_for(256)), holes[1]);
+        assert_eq!(alloc(list, 64), holes[1] + 256);
+
+        // Past the last block it wraps around to the head
+        assert_eq!(alloc(list, payload_for(1024)), holes[3]);
+        assert_eq!(alloc(list, 64), holes[0]);
+        list.check();
+    }
+
+    #[test]
+    fn next_fit_survives_its_block_being_merged() {
+        let mut arena = arena(8192, HeapType::NextFit);
+        let list = &mut arena.list;
+
+        let a = alloc(list, 100);
+        let b = alloc(list, 100);
+        list.deallocate(b).unwrap();
+        list.deallocate(a).unwrap();
+
+        alloc(list, 100);
+        list.check();
+    }
+
+    #[test]
+    fn strategy_can_change_between_allocations() {
+        let mut arena = arena(8192, HeapType::BestFit);
+        let list = &mut arena.list;
+        let holes = holes(list);
+
+        list.set_heap_type(HeapType::FirstFit);
+        assert_eq!(alloc(list, 64), holes[0]);
+
+        list.set_heap_type(HeapType::WorstFit);
+        assert_eq!(alloc(list, 200), holes[3]);
+        list.check();
+    }
+
+    #[test]
+    fn oversized_and_overaligned_requests_fail() {
+        let mut arena = arena(4096, HeapType::BestFit);
+        let list = &mut arena.list;
+
+        assert!(list.allocate(4096, 8).is_none());
+        assert!(list.allocate(16, 32).is_none());
+        assert_eq!(list.used, 0);
+    }
+
+    #[test]
+    fn reallocate_shrinks_and_grows_in_place() {
+        let mut arena = arena(4096, HeapType::FirstFit);
+        let list = &mut arena.list;
+
+        let address = alloc(list, 256);
+        unsafe { core::ptr::write_bytes(address as *mut u8, 0xAB, 256) };
+
+        assert_eq!(
+            list.reallocate(address, 64, 8),
+            Ok(Some(address as *mut u8))
+        );
+        assert_eq!(list.used, FreeList::block_size_for(64).unwrap());
+        assert_eq!(
+            list.reallocate(address, 512, 8),
+            Ok(Some(address as *mut u8))
+        );
+        assert_eq!(list.used, FreeList::block_size_for(512).unwrap());
+
+        let bytes = unsafe { core::slice::from_raw_parts(address as *const u8, 64) };
+        assert!(bytes.iter().all(|&byte| byte == 0xAB));
+        list.check();
+    }
+
+    #[test]
+    fn reallocate_moves_when_the_neighbour_is_taken() {
+        let mut arena = arena(4096, HeapType::FirstFit);
+        let list = &mut arena.list;
+
+        let address = alloc(list, 64);
+        let _neighbour = alloc(list, 64);
+        unsafe { core::ptr::write_bytes(address as *mut u8, 0xCD, 64) };
+
+        let moved = list.reallocate(address, 256, 8).unwrap().unwrap() as usize;
+        assert_ne!(moved, address);
+        let bytes = unsafe { core::slice::from_raw_parts(moved as *const u8, 64) };
+        assert!(bytes.iter().all(|&byte| byte == 0xCD));
+
+        // The old block is free again and reused
+        assert_eq!(alloc(list, 64), address);
+        list.check();
+    }
+
+    #[test]
+    fn reallocate_refuses_what_deallocate_refuses() {
+        let mut arena = arena(4096, HeapType::FirstFit);
+        let list = &mut arena.list;
+
+        let address = alloc(list, 64);
+        let _neighbour = alloc(list, 64);
+        list.deallocate(address).unwrap();
+        let before = (list.used, free_sizes(list));
+
+        assert_eq!(
+            list.reallocate(address, 256, 8),
+            Err(FreeError::AlreadyFree(address))
+        );
+        assert_eq!(list.reallocate(8, 256, 8), Err(FreeError::OutOfRange(8)));
+        assert_eq!((list.used, free_sizes(list)), before);
+        list.check();
+    }
+
+    #[test]
+    fn added_region_merges_with_a_touching_block() {
+        let mut memory = vec![0u8; 8192 + 16].into_boxed_slice();
+        let start = (memory.as_ptr() as usize + 15) & !15;
+        let offset = start - memory.as_ptr() as usize;
+        let (first, second) = memory[offset..offset + 8192].split_at_mut(4096);
+
+        let mut list = unsafe { FreeList::from_buffer(first, HeapType::BestFit) };
+        assert!(unsafe { list.add_region(second.as_mut_ptr() as usize, second.len()) });
+
+        assert_eq!(list.capacity, 8192);
+        assert_eq!(free_sizes(&list), [8192]);
+        list.check();
+    }
+
+    #[test]
+    fn trim_releases_whole_free_pages_outside_the_kept_range() {
+        const PAGE: usize = 4096;
+        let mut memory = vec![0u8; 5 * PAGE].into_boxed_slice();
+        let start = (memory.as_ptr() as usize + PAGE - 1) & !(PAGE - 1);
+        let offset = start - memory.as_ptr() as usize;
+
+        let mut list = unsafe {
+            FreeList::from_buffer(&mut memory[offset..offset + 4 * PAGE], HeapType::BestFit)
+        };
+        let kept = alloc(&mut list, 64);
+
+        let mut released = Vec::new();
+        let bytes = list.trim(start, start + PAGE, PAGE, |address| released.push(address));
+
+        assert_eq!(bytes, 3 * PAGE);
+        assert_eq!(released, [start + PAGE, start + 2 * PAGE, start + 3 * PAGE]);
+        assert_eq!(list.capacity, PAGE);
+        list.check();
+
+        list.deallocate(kept).unwrap();
+        assert_eq!(free_sizes(&list), [PAGE]);
+    }
+
+    #[test]
+    fn double_free_is_refused() {
+        let mut arena = arena(4096, HeapType::BestFit);
+        let list = &mut arena.list;
+
+        let a = alloc(list, 64);
+        let _b = alloc(list, 64);
+        list.deallocate(a).unwrap();
+        let before = (list.used, free_sizes(list));
+
+        assert_eq!(list.deallocate(a), Err(FreeError::AlreadyFree(a)));
+        assert_eq!((list.used, free_sizes(list)), before);
+        list.check();
+    }
+
+    #[test]
+    fn free_of_a_block_merged_into_a_neighbour_is_refused() {
+        let mut arena = arena(4096, HeapType::BestFit);
+        let list = &mut arena.list;
+
+        let a = alloc(list, 64);
+        let b = alloc(list, 64);
+        let _guard = alloc(list, 64);
+        list.deallocate(a).unwrap();
+        list.deallocate(b).unwrap();
+
+        // b's header now sits inside a's free block
+        assert_eq!(list.deallocate(b), Err(FreeError::AlreadyFree(b)));
+        list.check();
+    }
+
+    #[test]
+    fn pointers_outside_the_heap_are_refused() {
+        let mut arena = arena(4096, HeapType::BestFit);
+        let list = &mut arena.list;
+        let end = list.start_address + list.capacity;
+        alloc(list, 64);
+
+        for address in [0, list.start_address, end, end + 4096, usize::MAX & !15] {
+            assert_eq!(
+                list.deallocate(address),
+                Err(FreeError::OutOfRange(address))
+            );
+        }
+        let unaligned = list.start_address + size_of::<FreeListNode>() + 8;
+        assert_eq!(
+            list.deallocate(unaligned),
+            Err(FreeError::OutOfRange(unaligned))
+        );
+        list.check();
+    }
+
+    #[test]
+    fn pointers_into_the_middle_of_a_block_are_refused() {
+        let mut arena = arena(4096, HeapType::BestFit);
+        let list = &mut arena.list;
+
+        let address = alloc(list, 256);
+        unsafe { core::ptr::write_bytes(address as *mut u8, 0x5A, 256) };
+        let used = list.used;
+
+        let inside = address + 64;
+        assert!(matches!(
+            list.deallocate(inside),
+            Err(FreeError::BadHeader { address, .. }) if address == inside
+        ));
+        assert_eq!(list.used, used);
+
+        // The real block still frees normally
+        list.deallocate(address).unwrap();
+        assert_eq!(list.used, 0);
+        list.check();
+    }
+
+    #[test]
+    fn frees_in_an_added_region_are_accepted() {
+        let mut arena = arena(4096, HeapType::FirstFit);
+        let mut extra = vec![0u8; 4096].into_boxed_slice();
+        let list = &mut arena.list;
+        assert!(unsafe { list.add_region(extra.as_mut_ptr() as usize, extra.len()) });
+
+        // Each fills one region, wherever the second one landed
+        let block = payload_for(4096 - ALIGN);
+        let first = alloc(list, block);
+        let second = alloc(list, block);
+
+        list.deallocate(first).unwrap();
+        list.deallocate(second).unwrap();
+        assert_eq!(list.used, 0);
+        list.check();
+    }
+
+    #[test]
+    fn free_into_the_gap_between_regions_is_refused() {
+        let mut memory = vec![0u8; 3 * 4096 + 16].into_boxed_slice();
+        let start = (memory.as_ptr() as usize + 15) & !15;
+        let offset = start - memory.as_ptr() as usize;
+        let (first, rest) = memory[offset..offset + 3 * 4096].split_at_mut(4096);
+        let (gap, third) = rest.split_at_mut(4096);
+
+        // A block that would pass every other check, in memory the heap
+        // was never given
+        let block = FreeList::block_overhead() + 64;
+        gap[..size_of::<usize>()].copy_from_slice(&block.to_ne_bytes());
+        gap[block - size_of::<usize>()..block].copy_from_slice(&block.to_ne_bytes());
+        let wild = gap.as_ptr() as usize + size_of::<FreeListNode>();
+
+        let mut list = unsafe { FreeList::from_buffer(first, HeapType::FirstFit) };
+        assert!(unsafe { list.add_region(third.as_mut_ptr() as usize, third.len()) });
+        let before = (list.used, free_sizes(&list));
+
+        assert_eq!(list.deallocate(wild), Err(FreeError::OutOfRange(wild)));
+        assert_eq!((list.used, free_sizes(&list)), before);
+        list.check();
+    }
+
+    #[test]
+    fn trimmed_pages_no_longer_count_as_the_heap() {
+        const PAGE: usize = 4096;
+        let mut memory = vec![0u8; 3 * PAGE].into_boxed_slice();
+        let start = (memory.as_ptr() as usize + PAGE - 1) & !(PAGE - 1);
+        let offset = start - memory.as_ptr() as usize;
+
+        let mut list = unsafe {
+            FreeList::from_buffer(&mut memory[offset..offset + 2 * PAGE], HeapType::BestFit)
+        };
+        let _kept = alloc(&mut list, 64);
+        assert_eq!(list.trim(start, start + PAGE, PAGE, |_| {}), PAGE);
+
+        let wild = start + PAGE + size_of::<FreeListNode>();
+        assert_eq!(list.deallocate(wild), Err(FreeError::OutOfRange(wild)));
+    }
+
+    #[test]
+    fn heap_type_names_parse_back() {
+        for kind in HeapType::ALL {
+            assert_eq!(HeapType::parse(kind.name()), Some(kind));
+        }
+        assert_eq!(HeapType::parse("best"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "footer")]
+    fn check_catches_a_smashed_footer() {
+        let mut arena = arena(4096, HeapType::BestFit);
+        let list = &mut arena.list;
+
+        alloc(list, 64);
+        let end = list.start_address + list.capacity;
+        unsafe { ((end - size_of::<usize>()) as *mut usize).write(0) };
+        list.check();
+    }
+}
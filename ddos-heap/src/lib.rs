@@ -0,0 +1,18 @@
+// ============================================================================
+// DDOS HEAP
+// The kernel's FreeList allocator as a no_std library. It only manages a
+// range of memory it is given and never asks for more by itself, so it has no
+// ties to the frame allocator, the locks or the board, and builds for the
+// host as well; scripts/test-host.sh runs the tests in freelist.rs.
+//
+// The kernel wraps it in a lock, grows it with frames and puts it behind the
+// global allocator (src/memory/heap.rs); none of that lives here.
+// ============================================================================
+
+#![cfg_attr(not(test), no_std)]
+// Same comment style as the kernel: plain comments rather than rustdoc
+#![allow(clippy::missing_safety_doc)]
+
+mod freelist;
+
+pub use freelist::{FreeError, FreeList, FreeListNode, HeapType};
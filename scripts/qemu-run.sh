@@ -0,0 +1,27 @@
+#!/bin/bash
+
+# ============================================================================
+# qemu-run.sh - cargo runner for the kernel target
+# ============================================================================
+#
+# PURPOSE:
+# .cargo/config.toml names this as the runner, so cargo hands it the ELF it
+# just built:
+# - cargo run --features qemu   boots the kernel, same as build-qemu.sh
+# - cargo test --features qemu  boots the test build, which runs every
+#                               #[test_case] and exits QEMU with the result
+#
+# -semihosting is what lets the kernel end QEMU with a status code
+# (src/qemu.rs). Exit status: 0 all tests passed, 1 a test failed, 101 panic.
+#
+# REQUIREMENTS:
+# - qemu-system-aarch64
+#
+
+exec qemu-system-aarch64 \
+    -M raspi3b \
+    -display none \
+    -monitor none \
+    -serial stdio \
+    -semihosting \
+    -kernel "$1"
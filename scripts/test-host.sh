@@ -0,0 +1,26 @@
+#!/bin/bash
+
+# ============================================================================
+# test-host.sh - run the library unit tests on the host
+# ============================================================================
+#
+# PURPOSE:
+# The FreeList allocator (ddos-heap/) and the pure logic of the drivers
+# (ddos-drivers/: ChaCha20, UART line settings, ...) have plain #[test]s
+# that run on the build machine, no QEMU needed. The drivers still need a
+# board feature to build, qemu here. The repository's .cargo/config.toml points
+# every build at the kernel target with build-std, which a host test build
+# cannot use, so cargo is started from outside the repository where that
+# config is not picked up. Extra arguments go to cargo test, e.g. a filter:
+#
+#     ./scripts/test-host.sh next_fit
+#
+# REQUIREMENTS:
+# - cargo (nightly, same as the kernel)
+#
+
+root="$(cd "$(dirname "$0")/.." && pwd)"
+
+cd "${TMPDIR:-/tmp}" || exit 1
+cargo test --manifest-path "$root/ddos-heap/Cargo.toml" "$@" || exit 1
+exec cargo test --manifest-path "$root/ddos-drivers/Cargo.toml" --features qemu "$@"
@@ -0,0 +1,123 @@
+use crate::cmdline;
+use crate::memory::{self, config::MEMTEST_AT_BOOT, heap::HeapType};
+use crate::println;
+use crate::utils::tui::{self, Menu};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// ============================================================================
+// BOOT MENU
+// With `bootmenu` on the command line, boot stops once the drivers are up
+// and asks on the console what to do before the shell:
+//
+//     ┌─ Boot menu ────────────┐
+//     │ Continue booting       │
+//     │ Heap strategy: nextfit │
+//     │ Memory test: off       │
+//     │ Self-test: on          │
+//     └────────────────────────┘
+//
+// Enter on a setting changes it (the strategy from a second menu, the
+// tests switch on and off); Enter on the first line or Escape boots on,
+// and the menu makes way for a panel with the settings boot goes on with.
+// Nobody touching a key for TIMEOUT_MS boots with the defaults, so a board
+// left with `bootmenu` in cmdline.txt still comes up on its own. The
+// strategy line is only there on a heap that has one, the self-test line
+// only with the `selftest` feature.
+// ============================================================================
+
+const TIMEOUT_MS: u64 = 5000;
+
+// What the rest of boot runs
+pub struct Choices {
+    pub memtest: bool,
+    #[cfg(feature = "selftest")]
+    pub selftest: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Item {
+    Continue,
+    Strategy,
+    Memtest,
+    #[cfg(feature = "selftest")]
+    Selftest,
+}
+
+// The defaults, or what the menu changed them to
+pub fn run() -> Choices {
+    let mut choices = Choices {
+        memtest: MEMTEST_AT_BOOT,
+        #[cfg(feature = "selftest")]
+        selftest: true,
+    };
+    if !cmdline::has("bootmenu") {
+        return choices;
+    }
+
+    let mut strategy = memory::strategy();
+    let mut items = Vec::from([Item::Continue]);
+    if strategy.is_some() {
+        items.push(Item::Strategy);
+    }
+    items.push(Item::Memtest);
+    #[cfg(feature = "selftest")]
+    items.push(Item::Selftest);
+
+    println!();
+    let mut menu = Menu::new("Boot menu", labels(&items, &choices, strategy));
+    let mut timeout = Some(TIMEOUT_MS);
+    while let Some(index) = menu.choose(timeout.take()) {
+        match items[index] {
+            Item::Continue => break,
+            Item::Strategy => strategy = pick_strategy(strategy).or(strategy),
+            Item::Memtest => choices.memtest = !choices.memtest,
+            #[cfg(feature = "selftest")]
+            Item::Selftest => choices.selftest = !choices.selftest,
+        }
+        menu.set_items(labels(&items, &choices, strategy));
+    }
+    // What boot goes on with stays on the screen
+    menu.erase();
+    tui::panel("Booting with", &labels(&items[1..], &choices, strategy));
+
+    // Only now, its log line would have broken the menu's redraws
+    if let Some(strategy) = strategy
+        && memory::strategy() != Some(strategy)
+    {
+        memory::set_strategy(strategy);
+    }
+    choices
+}
+
+fn labels(items: &[Item], choices: &Choices, strategy: Option<HeapType>) -> Vec<String> {
+    let on_off = |on: bool| if on { "on" } else { "off" };
+
+    items
+        .iter()
+        .map(|item| match item {
+            Item::Continue => String::from("Continue booting"),
+            Item::Strategy => format!("Heap strategy: {}", strategy.map_or("none", HeapType::name)),
+            Item::Memtest => format!("Memory test: {}", on_off(choices.memtest)),
+            #[cfg(feature = "selftest")]
+            Item::Selftest => format!("Self-test: {}", on_off(choices.selftest)),
+        })
+        .collect()
+}
+
+// A second menu under the first, wiped again once something is picked
+fn pick_strategy(current: Option<HeapType>) -> Option<HeapType> {
+    let names = HeapType::ALL
+        .iter()
+        .map(|strategy| String::from(strategy.name()))
+        .collect();
+    let mut menu = Menu::new("Heap strategy", names);
+    if let Some(index) = HeapType::ALL.iter().position(|&s| Some(s) == current) {
+        menu.select(index);
+    }
+
+    let picked = menu.choose(None);
+    menu.erase();
+    picked.map(|index| HeapType::ALL[index])
+}
@@ -0,0 +1,12 @@
+// ============================================================================
+// BUILD IDENTITY
+// Filled in by build.rs, so a crash log or bug report can say exactly which
+// build it came from. Printed in the boot banner and by `version`.
+// ============================================================================
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_HASH: &str = env!("DDOS_GIT_HASH");
+pub const BUILD_TIME: &str = env!("DDOS_BUILD_TIME");
+pub const FEATURES: &str = env!("DDOS_FEATURES");
+pub const RUSTC_VERSION: &str = env!("DDOS_RUSTC_VERSION");
+pub const PROFILE: &str = env!("DDOS_PROFILE");
@@ -0,0 +1,146 @@
+use crate::cpu::gdb;
+use crate::devicetree;
+use crate::drivers::console::{self, Backend};
+use crate::drivers::uart::{LineSettings, UART};
+use crate::memory::heap::HeapType;
+use crate::utils::log;
+
+// ============================================================================
+// KERNEL COMMAND LINE
+// Options from /chosen/bootargs: on the Pi the firmware's own Linux options
+// followed by cmdline.txt from the boot partition, in QEMU whatever -append
+// gave (with -dtb). Each word is `key=value` or a bare `key`; a key given
+// twice takes the last value, so cmdline.txt wins over the firmware.
+//
+//     console=uart,9600n8   the serial console: uart (the PL011, default)
+//                           or miniuart, picked before the first line; the
+//                           PL011 takes line settings after a comma
+//     loglevel=debug        global log level or module=level, comma separated
+//     heap.strategy=nextfit bestfit, worstfit, firstfit or nextfit, picked
+//                           when the heap is set up
+//     gdb                   the GDB stub on the console (cpu/gdb.rs);
+//                           gdb=wait also stops at boot until GDB attaches
+//     noautoexec            skip /boot/autoexec.rc (shell/script.rs)
+//     bootmenu              ask about the heap strategy and the boot-time
+//                           tests before the shell (bootmenu.rs)
+//
+// Words for Linux (root=, console=tty1, ...) are skipped. Reading an option
+// walks the device tree again, which works before the heap, so the heap can
+// take its strategy from here.
+// ============================================================================
+
+// The whole line, empty without a device tree
+pub fn line() -> &'static str {
+    devicetree::bootargs().unwrap_or("")
+}
+
+// (key, value) per word, value None for a bare key
+pub fn options() -> impl Iterator<Item = (&'static str, Option<&'static str>)> {
+    line()
+        .split_whitespace()
+        .map(|word| match word.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (word, None),
+        })
+}
+
+// Value of the last `key=value`
+pub fn get(key: &str) -> Option<&'static str> {
+    options()
+        .filter(|&(name, _)| name == key)
+        .filter_map(|(_, value)| value)
+        .last()
+}
+
+// A bare `key` or any `key=value`
+pub fn has(key: &str) -> bool {
+    options().any(|(name, _)| name == key)
+}
+
+// Last console= that is not one of Linux's (ttyS0, tty1, ...), which the
+// firmware adds on its own
+fn console_value() -> Option<&'static str> {
+    options()
+        .filter(|&(name, _)| name == "console")
+        .filter_map(|(_, value)| value)
+        .filter(|value| !value.starts_with("tty"))
+        .last()
+}
+
+// The backend part of console=, before any comma
+fn console_name() -> Option<&'static str> {
+    console_value().map(|value| value.split_once(',').map_or(value, |(name, _)| name))
+}
+
+pub fn console() -> Option<Backend> {
+    console_name().and_then(Backend::parse)
+}
+
+// Line settings for the PL011 after the comma in console=uart,9600n8
+pub fn line_settings() -> Option<LineSettings> {
+    let (_, settings) = console_value()?.split_once(',')?;
+    match console() {
+        Some(Backend::Pl011) => LineSettings::parse(settings),
+        _ => None,
+    }
+}
+
+pub fn heap_strategy() -> Option<HeapType> {
+    get("heap.strategy").and_then(HeapType::parse)
+}
+
+// gdb=wait: stop at boot for GDB
+pub fn gdb_wait() -> bool {
+    get("gdb") == Some("wait")
+}
+
+// Apply the options that take effect after boot and complain about values
+// that make no sense. Runs once the log is up.
+pub fn apply() {
+    if let Some(levels) = get("loglevel") {
+        for directive in levels.split(',') {
+            if let Err(err) = log::apply_directive(directive) {
+                log::warn!("cmdline: loglevel={}: {}", directive, err);
+            }
+        }
+    }
+
+    if let Some(value) = console_value() {
+        let settings = value.split_once(',').map(|(_, settings)| settings);
+        match (console(), settings) {
+            (None, _) => log::warn!("cmdline: console={}: expected uart or miniuart", value),
+            (Some(backend), _) if backend != console::active() => {
+                log::warn!("cmdline: console={}: not on this board", value)
+            }
+            (Some(Backend::MiniUart), Some(_)) => {
+                log::warn!("cmdline: console={}: only uart takes line settings", value)
+            }
+            (Some(Backend::Pl011), Some(_)) => match line_settings() {
+                None => log::warn!("cmdline: console={}: expected e.g. uart,9600n8", value),
+                Some(settings) if settings != UART.lock().line_settings() => log::warn!(
+                    "cmdline: console={}: not applied, still at {}",
+                    value,
+                    UART.lock().line_settings()
+                ),
+                Some(_) => {}
+            },
+            _ => {}
+        }
+    }
+
+    if has("gdb") {
+        match get("gdb") {
+            None | Some("wait") => gdb::enable(),
+            Some(value) => log::warn!("cmdline: gdb={}: expected gdb or gdb=wait", value),
+        }
+    }
+
+    if let Some(strategy) = get("heap.strategy")
+        && heap_strategy().is_none()
+    {
+        log::warn!(
+            "cmdline: heap.strategy={}: expected bestfit, worstfit, firstfit or nextfit",
+            strategy
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use super::smp;
+use crate::memory::config::KERNEL_START;
+use crate::println;
+use core::arch::asm;
+
+// ============================================================================
+// BACKTRACES
+// The kernel is built with frame pointers (.cargo/config.toml), so every
+// function that calls another keeps a frame record on the stack and points
+// x29 at it:
+//
+//     x29 -> [ caller's x29 ][ return address ]
+//
+// Following the chain from the current x29 gives the return address of every
+// call on the way here. There is no symbol table in the image, so the trace
+// is printed as addresses; run them through addr2line on the host:
+//
+//     addr2line -fipe target/aarch64-unknown-none-softfloat/debug/ddos 0x81234
+//
+// The walk stops at anything that is not a sane frame record on the core's
+// stack or a return address outside the kernel's code, so a smashed stack
+// ends the trace early instead of faulting inside the panic handler.
+// ============================================================================
+
+unsafe extern "C" {
+    static __text_end: u8;
+}
+
+const MAX_FRAMES: usize = 32;
+
+// Callee-saved registers, the frame pointer, link register and stack pointer
+// of the function that called capture()
+#[repr(C)]
+#[derive(Default)]
+pub struct CalleeSaved {
+    pub x19_x28: [u64; 10],
+    pub fp: u64,
+    pub lr: u64,
+    pub sp: u64,
+}
+
+#[inline(always)]
+pub fn capture() -> CalleeSaved {
+    let mut registers = CalleeSaved::default();
+    unsafe {
+        asm!(
+            "stp x19, x20, [{regs}, #0]",
+            "stp x21, x22, [{regs}, #16]",
+            "stp x23, x24, [{regs}, #32]",
+            "stp x25, x26, [{regs}, #48]",
+            "stp x27, x28, [{regs}, #64]",
+            "stp x29, x30, [{regs}, #80]",
+            "mov {sp}, sp",
+            "str {sp}, [{regs}, #96]",
+            regs = in(reg) &mut registers as *mut CalleeSaved,
+            sp = out(reg) _,
+            options(nostack),
+        );
+    }
+    registers
+}
+
+impl CalleeSaved {
+    pub fn print(&self) {
+        for (pair, values) in self.x19_x28.chunks(2).enumerate() {
+            println!(
+                "  x{:<2} {:#018x}  x{:<2} {:#018x}",
+                19 + pair * 2,
+                values[0],
+                20 + pair * 2,
+                values[1]
+            );
+        }
+        println!(
+            "  fp  {:#018x}  lr  {:#018x}  sp  {:#018x}",
+            self.fp, self.lr, self.sp
+        );
+    }
+}
+
+fn in_text(address: usize) -> bool {
+    let text_end = unsafe { &__text_end as *const u8 as usize };
+    (KERNEL_START..text_end).contains(&address)
+}
+
+// Frame records are two words on this core's stack: the boot stack below
+// the kernel image on core 0, its own block on the others
+fn on_stack(fp: usize) -> bool {
+    let (low, high) = smp::stack_bounds();
+    fp.is_multiple_of(8) && fp >= low && fp + 16 <= high
+}
+
+// Fill `out` with the return addresses of the calls that led to the caller
+// of callers(), innermost first, after skipping `skip` of them. Stops where
+// print() would; returns how many it wrote.
+#[inline(never)]
+pub fn callers(skip: usize, out: &mut [usize]) -> usize {
+    let mut fp: usize;
+    unsafe {
+        asm!("mov {0}, x29", out(reg) fp, options(nomem, nostack, preserves_flags));
+    }
+
+    // Our own record first: its return address is in our caller
+    let mut written = 0;
+    for depth in 0..skip + 1 + out.len() {
+        if written == out.len() || !on_stack(fp) {
+            break;
+        }
+        let (caller_fp, return_address) = unsafe {
+            let record = fp as *const usize;
+            (record.read(), record.add(1).read())
+        };
+        if !in_text(return_address) {
+            break;
+        }
+        if depth > skip {
+            out[written] = return_address - 4;
+            written += 1;
+        }
+        if caller_fp <= fp {
+            break;
+        }
+        fp = caller_fp;
+    }
+    written
+}
+
+// Print the call chain starting at the frame record `fp` points to
+pub fn print(fp: usize) {
+    println!("Backtrace (call sites, innermost first):");
+
+    let mut fp = fp;
+    for depth in 0..MAX_FRAMES {
+        if !on_stack(fp) {
+            return;
+        }
+
+        let (caller_fp, return_address) = unsafe {
+            let record = fp as *const usize;
+            (record.read(), record.add(1).read())
+        };
+        if return_address == 0 {
+            return;
+        }
+        if !in_text(return_address) {
+            println!(
+                "  #{:<2} {:#x} (not kernel code, stopping)",
+                depth, return_address
+            );
+            return;
+        }
+
+        // The return address is the instruction after the bl
+        println!("  #{:<2} {:#x}", depth, return_address - 4);
+
+        // Stacks grow down, so callers' records sit higher up
+        if caller_fp <= fp {
+            return;
+        }
+        fp = caller_fp;
+    }
+    println!("  ... (stopped after {} frames)", MAX_FRAMES);
+}
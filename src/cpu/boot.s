@@ -1,13 +1,68 @@
 .section .text._start
 .global _start
 
+// Firmware (and QEMU) start us at EL2. Drop to EL1 so EL0 programs and SVC
+// system calls go through our EL1 vector table; already at EL1, go straight
+// to \el1. Clobbers x1.
+.macro drop_to_el1 el1
+    mrs     x1, CurrentEL
+    lsr     x1, x1, #2
+    cmp     x1, #2
+    b.ne    \el1
+
+    // Let EL1 read the physical counter, no virtual offset
+    mov     x1, #3
+    msr     cnthctl_el2, x1
+    msr     cntvoff_el2, xzr
+
+    // EL1 runs AArch64
+    mov     x1, #(1 << 31)
+    msr     hcr_el2, x1
+
+    // eret into EL1h with DAIF masked
+    mov     x1, #0x3c5
+    msr     spsr_el2, x1
+    adr     x1, \el1
+    msr     elr_el2, x1
+    eret
+.endm
+
+// Known SCTLR_EL1 (RES1 bits only, MMU and caches off) and our vectors
+.macro setup_el1
+    ldr     x1, =0x30D00800
+    msr     sctlr_el1, x1
+
+    ldr     x1, =exception_vectors
+    msr     vbar_el1, x1
+    isb
+.endm
+
 _start:
+    // x0 holds the device tree address from the firmware, keep it in x19
+    // (callee-saved, survives the eret below) until _main
+    mov     x19, x0
+
     mrs     x0, mpidr_el1
     and     x0, x0, #0x3
     cbz     x0, master
-    b       hang
+
+    // Firmware that starts every core here (QEMU with an ELF kernel): wait
+    // until cpu/smp.rs puts an entry point in this core's smp_release slot
+    ldr     x1, =smp_release
+park:
+    ldr     x2, [x1, x0, lsl #3]
+    cbnz    x2, released
+    wfe
+    b       park
+released:
+    br      x2
 
 master:
+    drop_to_el1 el1_entry
+
+el1_entry:
+    setup_el1
+
     ldr     x1, =_start
     mov     sp, x1
 
@@ -23,9 +78,48 @@ loop_bss:
     b.lo    loop_bss
 
 jump_main:
+    mov     x0, x19
     bl      _main
     b       hang
 
+// Cores 1-3 come here once cpu/smp.rs releases them, through the firmware's
+// spin table, PSCI CPU_ON or smp_release
+.global secondary_entry
+secondary_entry:
+    // Core number: Aff0 on the Pi 3/4, Aff1 on the Pi 5 (see core_id())
+    mrs     x0, mpidr_el1
+    and     x1, x0, #0xff
+    ubfx    x2, x0, #8, #8
+    orr     x20, x1, x2
+    and     x20, x20, #0x3
+
+    drop_to_el1 secondary_el1
+
+secondary_el1:
+    setup_el1
+
+    // The stack smp::start() set aside for this core
+    ldr     x1, =smp_stacks
+    ldr     x2, [x1, x20, lsl #3]
+    mov     sp, x2
+
+    mov     x0, x20
+    bl      secondary_main
+    b       hang
+
 hang:
     wfe
-    b       hang
\ No newline at end of file
+    b       hang
+
+// Written by core 0 before it wakes the others, read by them before they
+// have a stack. In .data rather than .bss: parked cores read smp_release
+// while core 0 is still clearing .bss, and on real hardware .bss starts out
+// as whatever was in RAM.
+.section .data
+.balign 8
+.global smp_release
+smp_release:
+    .quad   0, 0, 0, 0
+.global smp_stacks
+smp_stacks:
+    .quad   0, 0, 0, 0
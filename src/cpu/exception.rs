@@ -0,0 +1,114 @@
+use crate::cpu::{gdb, usermode};
+use crate::println;
+use crate::syscall;
+use crate::utils::log;
+use core::arch::{asm, global_asm};
+
+global_asm!(include_str!("vectors.s"));
+
+// ============================================================================
+// EXCEPTIONS
+// vectors.s saves every register into a TrapFrame on the kernel stack and
+// calls exception_handler with the vector index. Whatever the handler leaves
+// in the frame is restored before eret, which is how syscalls return values.
+// ============================================================================
+
+#[repr(C)]
+pub struct TrapFrame {
+    pub x: [u64; 31],
+    pub elr: u64,
+    pub spsr: u64,
+    pub sp_el0: u64,
+}
+
+// What vectors.s takes off the stack for the frame
+pub const FRAME_SIZE: usize = core::mem::size_of::<TrapFrame>();
+
+impl TrapFrame {
+    // Every register as it was when the exception was taken
+    pub fn print(&self) {
+        println!("Registers at the exception:");
+        for (pair, values) in self.x.chunks(2).enumerate() {
+            match values {
+                [low, high] => println!(
+                    "  x{:<2} {:#018x}  x{:<2} {:#018x}",
+                    pair * 2,
+                    low,
+                    pair * 2 + 1,
+                    high
+                ),
+                [last] => println!("  x{:<2} {:#018x}", pair * 2, last),
+                _ => {}
+            }
+        }
+        println!(
+            "  elr {:#018x}  spsr {:#018x}  sp_el0 {:#018x}",
+            self.elr, self.spsr, self.sp_el0
+        );
+    }
+}
+
+// Vector index = source * 4 + kind
+const SOURCE_CURRENT_SP0: u64 = 0;
+const SOURCE_CURRENT_SPX: u64 = 1;
+const SOURCE_LOWER_AARCH64: u64 = 2;
+const KIND_SYNC: u64 = 0;
+const KIND_IRQ: u64 = 1;
+
+// ESR_EL1 exception classes we care about
+const EC_SVC64: u64 = 0x15;
+const EC_SHIFT: u64 = 26;
+
+fn esr() -> u64 {
+    let value: u64;
+    unsafe { asm!("mrs {0}, esr_el1", out(reg) value, options(nomem, nostack)) };
+    value
+}
+
+fn far() -> u64 {
+    let value: u64;
+    unsafe { asm!("mrs {0}, far_el1", out(reg) value, options(nomem, nostack)) };
+    value
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn exception_handler(frame: &mut TrapFrame, index: u64) {
+    let source = index / 4;
+    let kind = index % 4;
+    let esr = esr();
+    let class = esr >> EC_SHIFT;
+
+    match (source, kind) {
+        (SOURCE_LOWER_AARCH64, KIND_SYNC) if class == EC_SVC64 => {
+            // Number in x8, arguments in x0-x5, result back in x0
+            frame.x[0] = syscall::dispatch(frame.x[8], &frame.x[0..6]);
+        }
+        (SOURCE_LOWER_AARCH64, KIND_SYNC) => {
+            log::error!(
+                "user program faulted: ESR={:#x} ELR={:#x} FAR={:#x}",
+                esr,
+                frame.elr,
+                far()
+            );
+            usermode::exit(-1);
+        }
+        (SOURCE_CURRENT_SP0 | SOURCE_CURRENT_SPX, KIND_SYNC) if gdb::enabled() => {
+            // Breakpoints, steps and kernel faults go to GDB instead
+            gdb::handle(frame, source == SOURCE_CURRENT_SP0, esr);
+        }
+        (_, KIND_IRQ) => {
+            // Nothing enables interrupts at the controllers yet
+            log::warn!("unexpected IRQ (vector {})", index);
+        }
+        _ => {
+            frame.print();
+            panic!(
+                "unhandled exception (vector {}): ESR={:#x} ELR={:#x} FAR={:#x}",
+                index,
+                esr,
+                frame.elr,
+                far()
+            )
+        }
+    }
+}
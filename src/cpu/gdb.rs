@@ -0,0 +1,557 @@
+use super::exception::{FRAME_SIZE, TrapFrame};
+use crate::drivers::console::Raw;
+use crate::drivers::watchdog;
+use crate::memory::map;
+use crate::utils::locked::SpinLock;
+use core::arch::asm;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+// ============================================================================
+// GDB STUB
+// The GDB remote serial protocol over the serial console, for debugging on a
+// real board the way `qemu -s -S` allows in the emulator. With the stub on
+// (`gdb` on the command line, or the `gdb` command), a breakpoint, a single
+// step or a fault in the kernel stops in here instead of panicking, and GDB
+// on the other end of the serial line takes over:
+//
+//     aarch64-none-elf-gdb target/aarch64-unknown-none/debug/ddos
+//     (gdb) set serial baud 115200
+//     (gdb) target remote /dev/ttyUSB0
+//
+// `gdb=wait` stops at boot right after the heap is up so GDB can attach
+// before anything interesting runs. Understood:
+//
+//     ?  g  G       stop reason, read and write x0-x30, sp, pc, cpsr
+//     m  M          read and write memory, RAM only
+//     Z0 z0         software breakpoints (BRK), up to MAX_BREAKPOINTS
+//     c  s          continue and single step, both from an optional address
+//     D  k          detach: breakpoints out, carry on
+//
+// Everything else gets the empty reply, which GDB takes as "not supported".
+// Only the core that stopped waits here, the others run on; writing sp does
+// nothing. The stub talks to the UART registers directly, past the console's
+// locks, since whatever it interrupted may hold them, and it allocates
+// nothing for the same reason. Kernel output while GDB is attached shows up
+// between packets, which GDB skips over.
+// ============================================================================
+
+// Largest packet either way, hex encoded
+const PACKET_SIZE: usize = 1024;
+
+pub const MAX_BREAKPOINTS: usize = 16;
+
+const BRK_0: u32 = 0xD420_0000;
+
+// ESR_EL1 exception classes, taken from EL1
+const EC_UNKNOWN: u64 = 0x00;
+const EC_INSTRUCTION_ABORT: u64 = 0x21;
+const EC_PC_ALIGNMENT: u64 = 0x22;
+const EC_DATA_ABORT: u64 = 0x25;
+const EC_SP_ALIGNMENT: u64 = 0x26;
+const EC_SOFTWARE_STEP: u64 = 0x33;
+const EC_BRK: u64 = 0x3C;
+
+// Signals in the stop reply
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// MDSCR_EL1: single step, and debug exceptions at EL1
+const MDSCR_SS: u64 = 1 << 0;
+const MDSCR_KDE: u64 = 1 << 13;
+
+// SPSR: software step pending, debug exceptions masked
+const SPSR_SS: u64 = 1 << 21;
+const SPSR_D: u64 = 1 << 9;
+
+// GDB's AArch64 register numbers
+const REGISTER_SP: usize = 31;
+const REGISTER_PC: usize = 32;
+const REGISTER_CPSR: usize = 33;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// GDB resumed us with c or s and waits for a stop reply
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy)]
+struct Breakpoint {
+    address: usize,
+    // The instruction the BRK replaced
+    original: u32,
+}
+
+static BREAKPOINTS: SpinLock<[Option<Breakpoint>; MAX_BREAKPOINTS]> =
+    SpinLock::new([None; MAX_BREAKPOINTS]);
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Let kernel faults, BRK and single steps stop in the stub
+pub fn enable() {
+    unsafe {
+        // Debug exceptions are held back while the OS lock is set
+        asm!("msr oslar_el1, xzr", options(nomem, nostack));
+        let mdscr: u64;
+        asm!("mrs {0}, mdscr_el1", out(reg) mdscr, options(nomem, nostack));
+        asm!("msr mdscr_el1, {0}", "isb", in(reg) mdscr | MDSCR_KDE, options(nomem, nostack));
+    }
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+// Stop right here and wait for GDB
+#[inline(never)]
+pub fn breakpoint() {
+    unsafe { asm!("brk #0", options(nomem, nostack)) };
+}
+
+// ----------------------------------------------------------------------------
+// Packets
+// ----------------------------------------------------------------------------
+
+fn read_byte() -> u8 {
+    loop {
+        if let Some(byte) = Raw.try_read_byte() {
+            return byte;
+        }
+        // A session can last a while
+        watchdog::feed();
+    }
+}
+
+fn hex_digit(value: u8) -> u8 {
+    b"0123456789abcdef"[(value & 0xF) as usize]
+}
+
+fn from_hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+// The body of the next packet with a good checksum, acknowledged
+fn receive(buffer: &mut [u8; PACKET_SIZE]) -> &[u8] {
+    'packet: loop {
+        while read_byte() != b'$' {}
+
+        let mut len = 0;
+        loop {
+            match read_byte() {
+                b'#' => break,
+                // A new packet started, this one was cut short
+                b'$' => len = 0,
+                _ if len == PACKET_SIZE => {
+                    Raw.send(b'-');
+                    continue 'packet;
+                }
+                byte => {
+                    buffer[len] = byte;
+                    len += 1;
+                }
+            }
+        }
+
+        let sum = [read_byte(), read_byte()].map(from_hex_digit);
+        match sum {
+            [Some(high), Some(low)] if high << 4 | low == checksum(&buffer[..len]) => {
+                Raw.send(b'+');
+                return &buffer[..len];
+            }
+            _ => Raw.send(b'-'),
+        }
+    }
+}
+
+// Send `data` as a packet until GDB acknowledges it
+fn send(data: &[u8]) {
+    let sum = checksum(data);
+    loop {
+        Raw.send(b'$');
+        for &byte in data {
+            Raw.send(byte);
+        }
+        Raw.send(b'#');
+        Raw.send(hex_digit(sum >> 4));
+        Raw.send(hex_digit(sum));
+
+        loop {
+            match read_byte() {
+                b'+' => return,
+                b'-' => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+struct Reply {
+    buffer: [u8; PACKET_SIZE],
+    len: usize,
+}
+
+impl Reply {
+    fn new() -> Reply {
+        Reply {
+            buffer: [0; PACKET_SIZE],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, text: &[u8]) {
+        let end = (self.len + text.len()).min(PACKET_SIZE);
+        self.buffer[self.len..end].copy_from_slice(&text[..end - self.len]);
+        self.len = end;
+    }
+
+    // Bytes as they are in memory, two hex digits each
+    fn push_hex(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.push(&[hex_digit(byte >> 4), hex_digit(byte)]);
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Parsing
+// ----------------------------------------------------------------------------
+
+fn parse_number(text: &[u8]) -> Option<usize> {
+    if text.is_empty() {
+        return None;
+    }
+    text.iter().try_fold(0usize, |value, &digit| {
+        Some(value.checked_mul(16)? | from_hex_digit(digit)? as usize)
+    })
+}
+
+// Hex pairs into `out`, false if any is not hex or the lengths differ
+fn parse_bytes(text: &[u8], out: &mut [u8]) -> bool {
+    if text.len() != out.len() * 2 {
+        return false;
+    }
+    for (pair, byte) in text.chunks(2).zip(out.iter_mut()) {
+        match (from_hex_digit(pair[0]), from_hex_digit(pair[1])) {
+            (Some(high), Some(low)) => *byte = high << 4 | low,
+            _ => return false,
+        }
+    }
+    true
+}
+
+// "addr,length" of m, M and Z packets, the rest after `end`
+fn parse_range(text: &[u8], end: u8) -> Option<(usize, usize, &[u8])> {
+    let comma = text.iter().position(|&byte| byte == b',')?;
+    let (length, rest) = match text[comma + 1..].iter().position(|&byte| byte == end) {
+        Some(at) => text[comma + 1..].split_at(at),
+        None => (&text[comma + 1..], &[][..]),
+    };
+    let rest = rest.get(1..).unwrap_or(&[]);
+    Some((parse_number(&text[..comma])?, parse_number(length)?, rest))
+}
+
+// No MMU to catch a bad address, so stay inside RAM: anything else may be a
+// device register where even a read does something, or nothing at all
+fn in_ram(address: usize, length: usize) -> bool {
+    address
+        .checked_add(length)
+        .is_some_and(|end| end <= map::ram_end())
+}
+
+// A changed instruction must reach the instruction fetch
+fn sync_instruction(address: usize) {
+    unsafe {
+        asm!(
+            "dc cvau, {0}",
+            "dsb ish",
+            "ic ivau, {0}",
+            "dsb ish",
+            "isb",
+            in(reg) address,
+            options(nostack),
+        );
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Registers
+// ----------------------------------------------------------------------------
+
+// The stack pointer of the code that stopped: SP_EL0 when it ran on that,
+// otherwise right above the frame vectors.s pushed
+fn stack_pointer(frame: &TrapFrame, on_sp_el0: bool) -> u64 {
+    if on_sp_el0 {
+        frame.sp_el0
+    } else {
+        frame as *const TrapFrame as u64 + FRAME_SIZE as u64
+    }
+}
+
+fn read_registers(frame: &TrapFrame, on_sp_el0: bool, reply: &mut Reply) {
+    for value in frame.x {
+        reply.push_hex(&value.to_le_bytes());
+    }
+    reply.push_hex(&stack_pointer(frame, on_sp_el0).to_le_bytes());
+    reply.push_hex(&frame.elr.to_le_bytes());
+    reply.push_hex(&(frame.spsr as u32).to_le_bytes());
+}
+
+fn write_registers(frame: &mut TrapFrame, text: &[u8]) -> bool {
+    let mut values = [0u64; REGISTER_CPSR + 1];
+    for (number, value) in values.iter_mut().enumerate() {
+        let (start, size) = match number {
+            REGISTER_CPSR => (16 * REGISTER_CPSR, 4),
+            _ => (16 * number, 8),
+        };
+        let mut bytes = [0u8; 8];
+        let Some(digits) = text.get(start..start + 2 * size) else {
+            return false;
+        };
+        if !parse_bytes(digits, &mut bytes[..size]) {
+            return false;
+        }
+        *value = u64::from_le_bytes(bytes);
+    }
+
+    frame.x.copy_from_slice(&values[..REGISTER_SP]);
+    frame.elr = values[REGISTER_PC];
+    frame.spsr = frame.spsr & !0xFFFF_FFFF | values[REGISTER_CPSR];
+    true
+}
+
+// ----------------------------------------------------------------------------
+// Memory and breakpoints
+// ----------------------------------------------------------------------------
+
+fn read_memory(address: usize, length: usize, reply: &mut Reply) -> bool {
+    if length > PACKET_SIZE / 2 || !in_ram(address, length) {
+        return false;
+    }
+    for offset in 0..length {
+        let byte = unsafe { core::ptr::read_volatile((address + offset) as *const u8) };
+        reply.push_hex(&[byte]);
+    }
+    true
+}
+
+fn write_memory(address: usize, data: &[u8]) -> bool {
+    let mut bytes = [0u8; PACKET_SIZE / 2];
+    let length = data.len() / 2;
+    if length > bytes.len() || !in_ram(address, length) {
+        return false;
+    }
+    if !parse_bytes(data, &mut bytes[..length]) {
+        return false;
+    }
+    for (offset, &byte) in bytes[..length].iter().enumerate() {
+        unsafe { core::ptr::write_volatile((address + offset) as *mut u8, byte) };
+    }
+    // It may have been code
+    for line in (address..address + length).step_by(4) {
+        sync_instruction(line);
+    }
+    true
+}
+
+fn insert_breakpoint(address: usize) -> bool {
+    if !address.is_multiple_of(4) || !in_ram(address, 4) {
+        return false;
+    }
+    let mut breakpoints = BREAKPOINTS.lock();
+    if breakpoints.iter().flatten().any(|bp| bp.address == address) {
+        return true;
+    }
+    let Some(slot) = breakpoints.iter_mut().find(|slot| slot.is_none()) else {
+        return false;
+    };
+
+    let instruction = address as *mut u32;
+    unsafe {
+        *slot = Some(Breakpoint {
+            address,
+            original: instruction.read_volatile(),
+        });
+        instruction.write_volatile(BRK_0);
+    }
+    sync_instruction(address);
+    true
+}
+
+fn remove_breakpoint(address: usize) -> bool {
+    let mut breakpoints = BREAKPOINTS.lock();
+    let Some(slot) = breakpoints
+        .iter_mut()
+        .find(|slot| slot.is_some_and(|bp| bp.address == address))
+    else {
+        return false;
+    };
+
+    if let Some(bp) = slot.take() {
+        unsafe { (bp.address as *mut u32).write_volatile(bp.original) };
+        sync_instruction(bp.address);
+    }
+    true
+}
+
+fn remove_all_breakpoints() {
+    let addresses = BREAKPOINTS.lock().map(|slot| slot.map(|bp| bp.address));
+    for address in addresses.into_iter().flatten() {
+        remove_breakpoint(address);
+    }
+}
+
+fn is_breakpoint(address: usize) -> bool {
+    BREAKPOINTS
+        .lock()
+        .iter()
+        .flatten()
+        .any(|bp| bp.address == address)
+}
+
+// ----------------------------------------------------------------------------
+// The stub
+// ----------------------------------------------------------------------------
+
+fn signal(class: u64) -> u8 {
+    match class {
+        EC_BRK | EC_SOFTWARE_STEP => SIGTRAP,
+        EC_UNKNOWN => SIGILL,
+        EC_INSTRUCTION_ABORT | EC_DATA_ABORT | EC_PC_ALIGNMENT | EC_SP_ALIGNMENT => SIGSEGV,
+        _ => SIGTRAP,
+    }
+}
+
+fn set_single_step(on: bool) {
+    unsafe {
+        let mut mdscr: u64;
+        asm!("mrs {0}, mdscr_el1", out(reg) mdscr, options(nomem, nostack));
+        if on {
+            mdscr |= MDSCR_SS;
+        } else {
+            mdscr &= !MDSCR_SS;
+        }
+        asm!("msr mdscr_el1, {0}", "isb", in(reg) mdscr, options(nomem, nostack));
+    }
+}
+
+// Called by exception_handler for a synchronous exception from EL1 while
+// the stub is on. Returns once GDB lets the code run again.
+pub fn handle(frame: &mut TrapFrame, on_sp_el0: bool, esr: u64) {
+    let class = esr >> 26;
+    set_single_step(false);
+
+    // A BRK compiled into the code (breakpoint()) rather than one of ours:
+    // step over it, or continuing would stop on it again right away
+    if class == EC_BRK && !is_breakpoint(frame.elr as usize) {
+        frame.elr += 4;
+    }
+
+    let signal = signal(class);
+    let stop = [b'S', hex_digit(signal >> 4), hex_digit(signal)];
+
+    // Plain load/store, no swap: the RPi5 has no atomic RMW with the MMU off.
+    // Only the stopped core gets here.
+    if RUNNING.load(Ordering::Relaxed) {
+        RUNNING.store(false, Ordering::Relaxed);
+        send(&stop);
+    } else {
+        let _ = writeln!(
+            Raw,
+            "\n[gdb] stopped at {:#x} (ESR {:#x}), waiting for GDB on this line",
+            frame.elr, esr
+        );
+    }
+
+    let mut buffer = [0u8; PACKET_SIZE];
+    loop {
+        let packet = receive(&mut buffer);
+        let Some((&command, body)) = packet.split_first() else {
+            send(b"");
+            continue;
+        };
+        let mut reply = Reply::new();
+
+        match command {
+            b'?' => reply.push(&stop),
+            b'g' => read_registers(frame, on_sp_el0, &mut reply),
+            b'G' if write_registers(frame, body) => reply.push(b"OK"),
+            b'm' => match parse_range(body, b':') {
+                Some((address, length, _)) if read_memory(address, length, &mut reply) => {}
+                _ => reply.push(b"E01"),
+            },
+            b'M' => match parse_range(body, b':') {
+                Some((address, length, data))
+                    if data.len() == length * 2 && write_memory(address, data) =>
+                {
+                    reply.push(b"OK")
+                }
+                _ => reply.push(b"E01"),
+            },
+            b'Z' | b'z' if body.starts_with(b"0,") => {
+                let done = match parse_range(&body[2..], b',') {
+                    Some((address, _, _)) if command == b'Z' => insert_breakpoint(address),
+                    Some((address, _, _)) => remove_breakpoint(address),
+                    None => false,
+                };
+                reply.push(if done { b"OK" } else { b"E01" });
+            }
+            b'c' | b's' => {
+                if let Some(address) = parse_number(body) {
+                    frame.elr = address as u64;
+                }
+                if command == b's' {
+                    // Step exactly one instruction with debug exceptions on
+                    frame.spsr = (frame.spsr | SPSR_SS) & !SPSR_D;
+                    set_single_step(true);
+                }
+                RUNNING.store(true, Ordering::Relaxed);
+                return;
+            }
+            b'D' | b'k' => {
+                remove_all_breakpoints();
+                if command == b'D' {
+                    send(b"OK");
+                }
+                return;
+            }
+            b'q' if body.starts_with(b"Supported") => reply.push(b"PacketSize=400"),
+            b'q' if body == b"Attached" => reply.push(b"1"),
+            b'H' => reply.push(b"OK"),
+            b'G' => reply.push(b"E01"),
+            // Unknown: the empty reply
+            _ => {}
+        }
+
+        send(reply.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checksum, parse_bytes, parse_range};
+    use crate::utils::testing::{Failure, check, check_eq};
+
+    #[test_case]
+    fn gdb_packet_parsing() -> Result<(), Failure> {
+        // $OK#9a
+        check_eq!(checksum(b"OK"), 0x9A);
+        check_eq!(
+            parse_range(b"80000,4:deadbeef", b':'),
+            Some((0x80000, 4, &b"deadbeef"[..]))
+        );
+        check_eq!(parse_range(b"1000,2", b','), Some((0x1000, 2, &[][..])));
+        check_eq!(parse_range(b"1000", b','), None);
+
+        let mut bytes = [0u8; 2];
+        check!(parse_bytes(b"beef", &mut bytes));
+        check_eq!(bytes, [0xBE, 0xEF]);
+        check!(!parse_bytes(b"bee", &mut bytes));
+        Ok(())
+    }
+}
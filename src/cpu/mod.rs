@@ -0,0 +1,5 @@
+pub mod backtrace;
+pub mod exception;
+pub mod gdb;
+pub mod smp;
+pub mod usermode;
@@ -0,0 +1,213 @@
+#[cfg(not(feature = "rpi5"))]
+use crate::devicetree;
+use crate::drivers::locked::{MAX_CORES, core_id};
+use crate::drivers::profile;
+use crate::drivers::spin_until;
+use crate::memory::config::{CORE_STACK_SIZE, KERNEL_START, STACK_LIMIT};
+use crate::memory::{frame, stack};
+use crate::println;
+use crate::utils::log;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// ============================================================================
+// SMP
+// The Pi has four cores. Core 0 runs the kernel; the other three wait until
+// start() tells them where to go, in one of three ways:
+//
+//     spin table   Pi 3/4 firmware: each core polls its slot (0xe0, 0xe8,
+//                  0xf0, or cpu-release-addr from the device tree) and
+//                  jumps to the address that shows up after an SEV
+//     PSCI         Pi 5 firmware: CPU_ON through an SMC
+//     smp_release  cores that started at _start with core 0 (QEMU with an
+//                  ELF kernel) wait on the same kind of slot in boot.s
+//
+// Every core gets a stack from the frame allocator and a PerCore entry in
+// CORES, indexed by its core id. They come up at EL1 with interrupts masked
+// and sit in idle(), which counts its wake-ups and prints the core's ID when
+// `cores ping` asks. Nothing runs on them yet beyond that.
+// ============================================================================
+
+unsafe extern "C" {
+    fn secondary_entry();
+    #[cfg(not(feature = "rpi5"))]
+    static smp_release: [usize; MAX_CORES];
+    static smp_stacks: [usize; MAX_CORES];
+}
+
+// Where the Pi 3/4 firmware's spin table sits without a device tree
+#[cfg(not(feature = "rpi5"))]
+const SPIN_TABLE: usize = 0xd8;
+
+// PSCI 0.2 CPU_ON, SMC64 calling convention
+#[cfg(feature = "rpi5")]
+const PSCI_CPU_ON: u64 = 0xC400_0003;
+
+// How long a core gets to report in after it was released
+const START_TIMEOUT_MS: u64 = 100;
+
+// Loads and stores only, no read-modify-write: these are shared between cores
+// on the RPi5 too, where there are no atomics with the MMU off
+pub struct PerCore {
+    online: AtomicBool,
+    ping: AtomicBool,
+    stack_top: AtomicUsize,
+    // Only ever written by the core itself
+    wakeups: AtomicUsize,
+}
+
+impl PerCore {
+    const fn new() -> Self {
+        PerCore {
+            online: AtomicBool::new(false),
+            ping: AtomicBool::new(false),
+            stack_top: AtomicUsize::new(0),
+            wakeups: AtomicUsize::new(0),
+        }
+    }
+}
+
+static CORES: [PerCore; MAX_CORES] = [const { PerCore::new() }; MAX_CORES];
+
+#[derive(Clone, Copy)]
+pub struct CoreInfo {
+    pub online: bool,
+    // (lowest, highest) address of the stack
+    pub stack: (usize, usize),
+    pub wakeups: usize,
+}
+
+pub fn info(core: usize) -> CoreInfo {
+    let this = &CORES[core];
+    CoreInfo {
+        online: core == 0 || this.online.load(Ordering::Acquire),
+        stack: stack_bounds_of(core),
+        wakeups: this.wakeups.load(Ordering::Relaxed),
+    }
+}
+
+fn stack_bounds_of(core: usize) -> (usize, usize) {
+    if core == 0 {
+        return (STACK_LIMIT, KERNEL_START);
+    }
+    let top = CORES[core].stack_top.load(Ordering::Relaxed);
+    (top.saturating_sub(CORE_STACK_SIZE), top)
+}
+
+// The stack of the core running this, for the backtrace
+pub fn stack_bounds() -> (usize, usize) {
+    stack_bounds_of(core_id())
+}
+
+// Wake any core waiting in WFE
+fn send_event() {
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("dsb sy", "sev", options(nostack, preserves_flags));
+    }
+}
+
+#[cfg(feature = "rpi5")]
+fn release(core: usize, entry: usize) -> bool {
+    let result: i64;
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!(
+            "smc #0",
+            inout("x0") PSCI_CPU_ON => result,
+            in("x1") (core as u64) << 8,
+            in("x2") entry,
+            in("x3") 0u64,
+            options(nostack),
+        );
+    }
+    #[cfg(not(target_arch = "aarch64"))]
+    {
+        let _ = (core, entry, PSCI_CPU_ON);
+        result = -1;
+    }
+    result == 0
+}
+
+#[cfg(not(feature = "rpi5"))]
+fn release(core: usize, entry: usize) -> bool {
+    let slot = devicetree::cpu_release_addr(core).unwrap_or(SPIN_TABLE + core * 8);
+    unsafe {
+        (slot as *mut usize).write_volatile(entry);
+        let parked = (&raw const smp_release as *mut usize).add(core);
+        parked.write_volatile(entry);
+    }
+    send_event();
+    true
+}
+
+// Give cores 1-3 their stacks and release them. Returns how many came up.
+pub fn start() -> usize {
+    let entry = secondary_entry as *const () as usize;
+    let mut started = 0;
+
+    for (core, this) in CORES.iter().enumerate().skip(1) {
+        let order = frame::order_for(CORE_STACK_SIZE);
+        let Some(stack) = frame::allocate(order) else {
+            log::warn!("SMP: no memory for core {}'s stack", core);
+            break;
+        };
+        let top = stack + frame::block_size(order);
+        this.stack_top.store(top, Ordering::Relaxed);
+        stack::paint(core);
+        unsafe {
+            let slot = (&raw const smp_stacks as *mut usize).add(core);
+            slot.write_volatile(top);
+        }
+
+        if !release(core, entry) {
+            log::warn!("SMP: firmware refused to start core {}", core);
+            frame::free(stack, order);
+            continue;
+        }
+
+        let online = spin_until!(this.online.load(Ordering::Acquire), START_TIMEOUT_MS);
+        match online {
+            Ok(()) => started += 1,
+            Err(_) => log::warn!("SMP: core {} did not come up", core),
+        }
+    }
+
+    log::info!("SMP: {} of {} cores running", started + 1, MAX_CORES);
+    started
+}
+
+// Ask every running core to print its ID from its idle loop
+pub fn ping() {
+    for this in &CORES[1..] {
+        this.ping.store(true, Ordering::Release);
+    }
+    send_event();
+}
+
+// Cores 1-3 land here from boot.s, on their own stack with interrupts masked
+#[unsafe(no_mangle)]
+pub extern "C" fn secondary_main(core: usize) -> ! {
+    let this = &CORES[core];
+    profile::init();
+    this.online.store(true, Ordering::Release);
+    println!("[SMP] core {} online", core);
+
+    idle(this, core)
+}
+
+fn idle(this: &PerCore, core: usize) -> ! {
+    loop {
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            core::arch::asm!("wfe", options(nomem, nostack, preserves_flags));
+        }
+
+        let wakeups = this.wakeups.load(Ordering::Relaxed) + 1;
+        this.wakeups.store(wakeups, Ordering::Relaxed);
+
+        if this.ping.load(Ordering::Acquire) {
+            this.ping.store(false, Ordering::Relaxed);
+            println!("[SMP] core {} idle, {} wake-ups", core, wakeups);
+        }
+    }
+}
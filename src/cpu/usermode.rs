@@ -0,0 +1,59 @@
+use core::arch::global_asm;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+global_asm!(include_str!("usermode.s"));
+
+// ============================================================================
+// EL0 ENTRY AND EXIT
+// run() drops to EL0 and comes back once the program calls exit (or faults).
+// There is no MMU yet, so EL0 sees the same flat physical memory as the
+// kernel; the only protection is what the syscall layer checks.
+// ============================================================================
+
+// Callee-saved kernel state, filled in by enter_user (layout fixed in usermode.s)
+#[repr(C)]
+struct KernelContext {
+    regs: [u64; 12],
+    sp: u64,
+}
+
+unsafe extern "C" {
+    fn enter_user(
+        entry: usize,
+        user_sp: usize,
+        argc: usize,
+        argv: usize,
+        context: *mut KernelContext,
+    ) -> i64;
+    fn leave_user(context: *const KernelContext, status: i64) -> !;
+}
+
+// Context of the program currently at EL0, null when none is. Plain load/store
+// only, fine on RPi5 with the MMU off.
+static CURRENT: AtomicPtr<KernelContext> = AtomicPtr::new(ptr::null_mut());
+
+// Run `entry` at EL0 on `user_sp` with (argc, argv). Returns the exit status.
+pub fn run(entry: usize, user_sp: usize, argc: usize, argv: usize) -> i64 {
+    let mut context = KernelContext {
+        regs: [0; 12],
+        sp: 0,
+    };
+
+    CURRENT.store(&mut context, Ordering::Relaxed);
+    let status = unsafe { enter_user(entry, user_sp, argc, argv, &mut context) };
+    CURRENT.store(ptr::null_mut(), Ordering::Relaxed);
+
+    status
+}
+
+// Called from the exception handler: abandon the program and resume the
+// kernel where run() entered it
+pub fn exit(status: i64) -> ! {
+    let context = CURRENT.load(Ordering::Relaxed);
+    if context.is_null() {
+        panic!("exit from EL0 with no program running");
+    }
+
+    unsafe { leave_user(context, status) }
+}
@@ -0,0 +1,71 @@
+// Switching between the kernel and an EL0 program
+//
+// enter_user(entry, user_sp, argc, argv, context) -> exit status
+//   Saves the kernel's callee-saved registers and stack pointer in `context`,
+//   then erets to `entry` at EL0 with x0 = argc, x1 = argv. It "returns" only
+//   when leave_user is called with the same context.
+//
+// leave_user(context, status) -> !
+//   Restores what enter_user saved and returns `status` from enter_user.
+//
+// Context layout: x19-x30 at 0..96, kernel sp at 96.
+
+.section .text
+.global enter_user
+enter_user:
+    stp     x19, x20, [x4, #0]
+    stp     x21, x22, [x4, #16]
+    stp     x23, x24, [x4, #32]
+    stp     x25, x26, [x4, #48]
+    stp     x27, x28, [x4, #64]
+    stp     x29, x30, [x4, #80]
+    mov     x9, sp
+    str     x9, [x4, #96]
+
+    msr     elr_el1, x0
+    msr     sp_el0, x1
+    // EL0t with every interrupt unmasked
+    msr     spsr_el1, xzr
+
+    mov     x0, x2
+    mov     x1, x3
+    // Returning from the program's entry point lands in the exit trampoline
+    ldr     x30, =user_exit_trampoline
+
+    // Do not hand kernel values to the program
+    mov     x2, xzr
+    mov     x3, xzr
+    mov     x4, xzr
+    mov     x9, xzr
+    mov     x19, xzr
+    mov     x20, xzr
+    mov     x21, xzr
+    mov     x22, xzr
+    mov     x23, xzr
+    mov     x24, xzr
+    mov     x25, xzr
+    mov     x26, xzr
+    mov     x27, xzr
+    mov     x28, xzr
+    mov     x29, xzr
+    eret
+
+.global leave_user
+leave_user:
+    ldp     x19, x20, [x0, #0]
+    ldp     x21, x22, [x0, #16]
+    ldp     x23, x24, [x0, #32]
+    ldp     x25, x26, [x0, #48]
+    ldp     x27, x28, [x0, #64]
+    ldp     x29, x30, [x0, #80]
+    ldr     x9, [x0, #96]
+    mov     sp, x9
+    mov     x0, x1
+    ret
+
+// Runs at EL0: exit(x0). There is no MMU yet, so EL0 can execute kernel text.
+.global user_exit_trampoline
+user_exit_trampoline:
+    mov     x8, #0
+    svc     #0
+    b       user_exit_trampoline
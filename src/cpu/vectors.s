@@ -0,0 +1,86 @@
+// Exception vector table for EL1
+// 16 entries of 0x80 bytes: (current EL with SP_EL0, current EL with SP_ELx,
+// lower EL AArch64, lower EL AArch32) x (sync, IRQ, FIQ, SError).
+// Each entry makes room for a TrapFrame, saves x0/x1 and jumps to the common
+// path with its index in x0.
+
+.equ FRAME_SIZE, 272
+
+.macro VECTOR index
+    .balign 0x80
+    sub     sp, sp, #FRAME_SIZE
+    stp     x0, x1, [sp, #0]
+    mov     x0, #\index
+    b       exception_common
+.endm
+
+.section .text.vectors
+.balign 0x800
+.global exception_vectors
+exception_vectors:
+    VECTOR 0
+    VECTOR 1
+    VECTOR 2
+    VECTOR 3
+    VECTOR 4
+    VECTOR 5
+    VECTOR 6
+    VECTOR 7
+    VECTOR 8
+    VECTOR 9
+    VECTOR 10
+    VECTOR 11
+    VECTOR 12
+    VECTOR 13
+    VECTOR 14
+    VECTOR 15
+
+exception_common:
+    stp     x2, x3, [sp, #16]
+    stp     x4, x5, [sp, #32]
+    stp     x6, x7, [sp, #48]
+    stp     x8, x9, [sp, #64]
+    stp     x10, x11, [sp, #80]
+    stp     x12, x13, [sp, #96]
+    stp     x14, x15, [sp, #112]
+    stp     x16, x17, [sp, #128]
+    stp     x18, x19, [sp, #144]
+    stp     x20, x21, [sp, #160]
+    stp     x22, x23, [sp, #176]
+    stp     x24, x25, [sp, #192]
+    stp     x26, x27, [sp, #208]
+    stp     x28, x29, [sp, #224]
+    mrs     x2, elr_el1
+    stp     x30, x2, [sp, #240]
+    mrs     x2, spsr_el1
+    mrs     x3, sp_el0
+    stp     x2, x3, [sp, #256]
+
+    // exception_handler(frame: &mut TrapFrame, kind: u64)
+    mov     x1, x0
+    mov     x0, sp
+    bl      exception_handler
+
+    // The handler may have changed x0 (syscall result) or ELR
+    ldp     x2, x3, [sp, #256]
+    msr     spsr_el1, x2
+    msr     sp_el0, x3
+    ldp     x30, x2, [sp, #240]
+    msr     elr_el1, x2
+    ldp     x0, x1, [sp, #0]
+    ldp     x2, x3, [sp, #16]
+    ldp     x4, x5, [sp, #32]
+    ldp     x6, x7, [sp, #48]
+    ldp     x8, x9, [sp, #64]
+    ldp     x10, x11, [sp, #80]
+    ldp     x12, x13, [sp, #96]
+    ldp     x14, x15, [sp, #112]
+    ldp     x16, x17, [sp, #128]
+    ldp     x18, x19, [sp, #144]
+    ldp     x20, x21, [sp, #160]
+    ldp     x22, x23, [sp, #176]
+    ldp     x24, x25, [sp, #192]
+    ldp     x26, x27, [sp, #208]
+    ldp     x28, x29, [sp, #224]
+    add     sp, sp, #FRAME_SIZE
+    eret
@@ -0,0 +1,216 @@
+use crate::drivers::console::{self, Backend, CONSOLE};
+use crate::drivers::miniuart::MINI_UART;
+use crate::drivers::uart::UART;
+use crate::fs::FsError;
+use crate::fs::block::BlockDevice;
+use crate::initcall::{InitError, initcall};
+use crate::utils::locked::SpinLock;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+// ============================================================================
+// DEVICES
+// The devices the kernel knows about, by name and class, the way /dev names
+// them. Drivers register here once they are up (usually from their
+// initcall) and everyone else looks them up by name instead of reaching for
+// the driver's static:
+//
+//     devices::register("uart0", Class::Char(&Pl011));
+//     devices::get_char("uart0")
+//
+//     char    a byte stream, reads never block: &'static dyn CharDevice
+//     block   512-byte sectors: open() hands out a fs::block::BlockDevice
+//
+// devfs shows the char devices as /dev/<name>, `lsdev` lists everything.
+// Nothing is ever unregistered.
+// ============================================================================
+
+// Reads return what is there right now, 0 meaning "nothing yet"
+pub trait CharDevice: Sync {
+    fn read(&self, buffer: &mut [u8]) -> Result<usize, FsError>;
+    fn write(&self, buffer: &[u8]) -> Result<usize, FsError>;
+}
+
+#[derive(Clone, Copy)]
+pub enum Class {
+    Char(&'static dyn CharDevice),
+    // A new handle on the device per call
+    Block(fn() -> Box<dyn BlockDevice>),
+}
+
+impl Class {
+    pub fn name(self) -> &'static str {
+        match self {
+            Class::Char(_) => "char",
+            Class::Block(_) => "block",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Device {
+    pub name: &'static str,
+    pub class: Class,
+}
+
+static DEVICES: SpinLock<Vec<Device>> = SpinLock::new(Vec::new());
+
+// False when the name is taken
+pub fn register(name: &'static str, class: Class) -> bool {
+    let mut devices = DEVICES.lock();
+    if devices.iter().any(|device| device.name == name) {
+        return false;
+    }
+    devices.push(Device { name, class });
+    true
+}
+
+pub fn get(name: &str) -> Option<Device> {
+    DEVICES
+        .lock()
+        .iter()
+        .find(|device| device.name == name)
+        .copied()
+}
+
+pub fn get_char(name: &str) -> Option<&'static dyn CharDevice> {
+    match get(name)?.class {
+        Class::Char(device) => Some(device),
+        _ => None,
+    }
+}
+
+pub fn get_block(name: &str) -> Option<Box<dyn BlockDevice>> {
+    match get(name)?.class {
+        Class::Block(open) => Some(open()),
+        _ => None,
+    }
+}
+
+// Everything registered, in registration order
+pub fn list() -> Vec<Device> {
+    DEVICES.lock().clone()
+}
+
+// ============================================================================
+// BUILT-IN DEVICES
+// null, and the two UARTs as raw byte streams past the console. uart0 is
+// the PL011, uart1 the mini UART where the board has one. Writes to the one
+// the console is on go through the console's ring, behind whatever output
+// it still has queued.
+// ============================================================================
+
+struct Null;
+
+impl CharDevice for Null {
+    fn read(&self, _buffer: &mut [u8]) -> Result<usize, FsError> {
+        Ok(0)
+    }
+
+    fn write(&self, buffer: &[u8]) -> Result<usize, FsError> {
+        Ok(buffer.len())
+    }
+}
+
+struct Pl011;
+
+impl CharDevice for Pl011 {
+    fn read(&self, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let uart = UART.lock();
+        Ok(read_with(buffer, || uart.try_read_byte()))
+    }
+
+    fn write(&self, buffer: &[u8]) -> Result<usize, FsError> {
+        if let Some(written) = write_console(Backend::Pl011, buffer) {
+            return written;
+        }
+        let uart = UART.lock();
+        for &byte in buffer {
+            uart.send(byte as char).map_err(|_| FsError::Io)?;
+        }
+        Ok(buffer.len())
+    }
+}
+
+struct MiniUart;
+
+impl CharDevice for MiniUart {
+    fn read(&self, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let uart = MINI_UART.lock();
+        Ok(read_with(buffer, || uart.try_read_byte()))
+    }
+
+    fn write(&self, buffer: &[u8]) -> Result<usize, FsError> {
+        if let Some(written) = write_console(Backend::MiniUart, buffer) {
+            return written;
+        }
+        let uart = MINI_UART.lock();
+        for &byte in buffer {
+            uart.send(byte as char).map_err(|_| FsError::Io)?;
+        }
+        Ok(buffer.len())
+    }
+}
+
+// None when `backend` is not the console's UART
+fn write_console(backend: Backend, buffer: &[u8]) -> Option<Result<usize, FsError>> {
+    let mut console = CONSOLE.lock();
+    if console::active() != backend {
+        return None;
+    }
+    for &byte in buffer {
+        if console.send(byte as char).is_err() {
+            return Some(Err(FsError::Io));
+        }
+    }
+    Some(Ok(buffer.len()))
+}
+
+// Fill `buffer` until `try_read` runs dry
+fn read_with(buffer: &mut [u8], mut try_read: impl FnMut() -> Option<u8>) -> usize {
+    let mut count = 0;
+    while count < buffer.len() {
+        match try_read() {
+            Some(byte) => buffer[count] = byte,
+            None => break,
+        }
+        count += 1;
+    }
+    count
+}
+
+fn init() -> Result<(), InitError> {
+    register("null", Class::Char(&Null));
+    register("uart0", Class::Char(&Pl011));
+    if Backend::MiniUart.available() {
+        register("uart1", Class::Char(&MiniUart));
+    }
+    Ok(())
+}
+
+initcall!(Core, "devices", init);
+
+#[cfg(test)]
+mod tests {
+    use super::{Class, Null, get, get_block, get_char, register};
+    use crate::utils::testing::{Failure, check, check_eq};
+
+    #[test_case]
+    fn device_registry() -> Result<(), Failure> {
+        check!(register("test-null", Class::Char(&Null)));
+        check!(!register("test-null", Class::Char(&Null)));
+
+        check_eq!(
+            get("test-null").map(|device| device.class.name()),
+            Some("char")
+        );
+        let mut buffer = [0u8; 4];
+        check_eq!(
+            get_char("test-null").map(|null| null.read(&mut buffer)),
+            Some(Ok(0))
+        );
+        check!(get_block("test-null").is_none());
+        check!(get("test-missing").is_none());
+        Ok(())
+    }
+}
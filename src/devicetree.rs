@@ -0,0 +1,406 @@
+use crate::hardwareselect;
+use crate::memory::map;
+use crate::utils::locked::SpinLock;
+use crate::utils::log;
+use alloc::boxed::Box;
+#[cfg(not(feature = "rpi5"))]
+use alloc::format;
+
+// ============================================================================
+// DEVICE TREE
+// The firmware passes the address of a flattened device tree (DTB) in x0.
+// This walks it in place, no allocation, so it can run before the heap and
+// the UART: the peripheral base, UART0, the size of the first RAM bank and
+// the kernel command line (/chosen/bootargs).
+//
+// Addresses in a node's `reg` are in its parent's bus space. They are turned
+// into CPU physical addresses by walking `ranges` up to the root, which is
+// how 0x7E20_1000 on the VideoCore bus becomes 0x3F20_1000 or 0xFE20_1000.
+// ============================================================================
+
+const FDT_MAGIC: u32 = 0xD00D_FEED;
+const FDT_HEADER_SIZE: usize = 40;
+// size_dt_struct only exists from version 17 on
+const FDT_MIN_VERSION: u32 = 17;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+// Where the legacy Broadcom peripherals sit on the VideoCore bus
+const VC_PERIPHERAL_BUS: u64 = 0x7E00_0000;
+
+// Defaults from the devicetree spec when a node does not say
+const DEFAULT_ADDRESS_CELLS: usize = 2;
+const DEFAULT_SIZE_CELLS: usize = 1;
+
+#[derive(Clone, Copy)]
+pub struct DeviceTree {
+    blob: &'static [u8],
+    structs: &'static [u8],
+    strings: &'static [u8],
+}
+
+enum Token {
+    BeginNode(&'static str),
+    EndNode,
+    Property(&'static str, &'static [u8]),
+}
+
+struct Tokens {
+    tree: DeviceTree,
+    offset: usize,
+}
+
+static TREE: SpinLock<Option<DeviceTree>> = SpinLock::new(None);
+
+fn be32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let word = bytes.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([word[0], word[1], word[2], word[3]]))
+}
+
+// A NUL terminated string starting at `offset`
+fn c_str(bytes: &'static [u8], offset: usize) -> Option<&'static str> {
+    let tail = bytes.get(offset..)?;
+    let len = tail.iter().position(|&b| b == 0)?;
+    core::str::from_utf8(&tail[..len]).ok()
+}
+
+// Big-endian value made of `cells` 32-bit cells. Wider values (3-cell PCI
+// addresses) keep their low 64 bits.
+fn read_cells(bytes: &[u8], cells: usize) -> Option<u64> {
+    let mut value = 0u64;
+    for index in 0..cells {
+        value = (value << 32) | be32(bytes, index * 4)? as u64;
+    }
+    Some(value)
+}
+
+// "memory" matches "memory@0", "serial@7e201000" only matches itself
+fn name_matches(name: &str, wanted: &str) -> bool {
+    name == wanted || (!wanted.contains('@') && name.split('@').next() == Some(wanted))
+}
+
+// "/soc/serial@7e201000" -> "/soc", "/soc" -> "/"
+fn parent(path: &str) -> &str {
+    match path.trim_end_matches('/').rfind('/') {
+        Some(0) | None => "/",
+        Some(index) => &path[..index],
+    }
+}
+
+impl Iterator for Tokens {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let structs = self.tree.structs;
+
+        loop {
+            let token = be32(structs, self.offset)?;
+            self.offset += 4;
+
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = c_str(structs, self.offset)?;
+                    self.offset = (self.offset + name.len() + 1 + 3) & !3;
+                    return Some(Token::BeginNode(name));
+                }
+                FDT_END_NODE => return Some(Token::EndNode),
+                FDT_PROP => {
+                    let len = be32(structs, self.offset)? as usize;
+                    let name_offset = be32(structs, self.offset + 4)? as usize;
+                    let start = self.offset + 8;
+                    let value = structs.get(start..start + len)?;
+
+                    self.offset = (start + len + 3) & !3;
+                    let name = c_str(self.tree.strings, name_offset)?;
+                    return Some(Token::Property(name, value));
+                }
+                FDT_NOP => {}
+                FDT_END => return None,
+                // Corrupt tree, stop rather than guess
+                _ => return None,
+            }
+        }
+    }
+}
+
+impl DeviceTree {
+    // Check the header at `address` and borrow the blob in place
+    pub unsafe fn from_address(address: usize) -> Option<DeviceTree> {
+        let header = unsafe { core::slice::from_raw_parts(address as *const u8, FDT_HEADER_SIZE) };
+
+        if be32(header, 0)? != FDT_MAGIC || be32(header, 20)? < FDT_MIN_VERSION {
+            return None;
+        }
+
+        let total_size = be32(header, 4)? as usize;
+        let blob = unsafe { core::slice::from_raw_parts(address as *const u8, total_size) };
+        DeviceTree::from_bytes(blob)
+    }
+
+    pub fn from_bytes(blob: &'static [u8]) -> Option<DeviceTree> {
+        let struct_offset = be32(blob, 8)? as usize;
+        let strings_offset = be32(blob, 12)? as usize;
+        let strings_size = be32(blob, 32)? as usize;
+        let struct_size = be32(blob, 36)? as usize;
+
+        Some(DeviceTree {
+            blob,
+            structs: blob.get(struct_offset..struct_offset + struct_size)?,
+            strings: blob.get(strings_offset..strings_offset + strings_size)?,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.blob.len()
+    }
+
+    fn tokens(&self) -> Tokens {
+        Tokens {
+            tree: *self,
+            offset: 0,
+        }
+    }
+
+    // Raw value of property `name` on the node at `path` ("/" is the root)
+    pub fn property(&self, path: &str, name: &str) -> Option<&'static [u8]> {
+        let components = || path.split('/').filter(|c| !c.is_empty());
+        let wanted = components().count();
+
+        // depth counts open nodes including the root, matched counts how many
+        // of them below the root are the leading components of `path`.
+        // The innermost open node is on the path while depth == matched + 1.
+        let mut depth = 0usize;
+        let mut matched = 0usize;
+
+        for token in self.tokens() {
+            let on_path = depth > 0 && depth == matched + 1;
+
+            match token {
+                Token::BeginNode(node) => {
+                    if on_path
+                        && components()
+                            .nth(matched)
+                            .is_some_and(|c| name_matches(node, c))
+                    {
+                        matched += 1;
+                    }
+                    depth += 1;
+                }
+                Token::EndNode => {
+                    // Closing the node we were after: it has no such property
+                    if on_path && matched == wanted {
+                        return None;
+                    }
+                    if on_path {
+                        matched = matched.saturating_sub(1);
+                    }
+                    depth = depth.checked_sub(1)?;
+                }
+                Token::Property(property, value) => {
+                    if on_path && matched == wanted && property == name {
+                        return Some(value);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn string(&self, path: &str, name: &str) -> Option<&'static str> {
+        let value = self.property(path, name)?;
+        let value = value.strip_suffix(&[0]).unwrap_or(value);
+        core::str::from_utf8(value).ok()
+    }
+
+    pub fn u32(&self, path: &str, name: &str) -> Option<u32> {
+        be32(self.property(path, name)?, 0)
+    }
+
+    fn address_cells(&self, path: &str) -> usize {
+        self.u32(path, "#address-cells")
+            .map_or(DEFAULT_ADDRESS_CELLS, |cells| cells as usize)
+    }
+
+    fn size_cells(&self, path: &str) -> usize {
+        self.u32(path, "#size-cells")
+            .map_or(DEFAULT_SIZE_CELLS, |cells| cells as usize)
+    }
+
+    // Entry `index` of the `reg` property as (address, size), untranslated
+    pub fn reg(&self, path: &str, index: usize) -> Option<(u64, u64)> {
+        let bus = parent(path);
+        let address_cells = self.address_cells(bus);
+        let size_cells = self.size_cells(bus);
+        let entry = (address_cells + size_cells) * 4;
+
+        let reg = self.property(path, "reg")?;
+        let bytes = reg.get(index * entry..(index + 1) * entry)?;
+        Some((
+            read_cells(bytes, address_cells)?,
+            read_cells(&bytes[address_cells * 4..], size_cells)?,
+        ))
+    }
+
+    // Translate `address`, as seen by children of the node at `bus`, into a
+    // CPU physical address. None if some bus on the way has no mapping for it.
+    pub fn translate(&self, bus: &str, mut address: u64) -> Option<u64> {
+        let mut node = bus;
+
+        while node != "/" {
+            let ranges = self.property(node, "ranges")?;
+            let child_cells = self.address_cells(node);
+            let parent_cells = self.address_cells(parent(node));
+            let size_cells = self.size_cells(node);
+            let entry = (child_cells + parent_cells + size_cells) * 4;
+
+            // Empty ranges means the child and parent address spaces are the same
+            if !ranges.is_empty() {
+                address = ranges.chunks_exact(entry).find_map(|range| {
+                    let child = read_cells(range, child_cells)?;
+                    let parent = read_cells(&range[child_cells * 4..], parent_cells)?;
+                    let size = read_cells(&range[(child_cells + parent_cells) * 4..], size_cells)?;
+                    (address >= child && address - child < size).then(|| address - child + parent)
+                })?;
+            }
+
+            node = parent(node);
+        }
+
+        Some(address)
+    }
+
+    // CPU physical address of the first `reg` entry of the node at `path`
+    pub fn address_of(&self, path: &str) -> Option<u64> {
+        let (address, _) = self.reg(path, 0)?;
+        self.translate(parent(path), address)
+    }
+
+    // ------------------------------------------------------------------------
+    // What the kernel wants out of it
+    // ------------------------------------------------------------------------
+
+    pub fn model(&self) -> Option<&'static str> {
+        self.string("/", "model")
+    }
+
+    pub fn bootargs(&self) -> Option<&'static str> {
+        self.string("/chosen", "bootargs")
+            .filter(|args| !args.is_empty())
+    }
+
+    // End of the RAM bank starting at address 0. Later banks (above 1 GiB on
+    // bigger Pi4s) are not reachable without an MMU mapping anyway.
+    pub fn ram_end(&self) -> Option<u64> {
+        let (base, size) = self.reg("/memory", 0)?;
+        (base == 0).then_some(size)
+    }
+
+    pub fn peripheral_base(&self) -> Option<u64> {
+        self.translate("/soc", VC_PERIPHERAL_BUS)
+    }
+
+    pub fn uart0_base(&self) -> Option<u64> {
+        let path = self.string("/aliases", "uart0")?;
+        self.address_of(path)
+    }
+
+    // Spin table slot of a core the firmware holds back, always two cells.
+    // The Pi 5 starts its cores through PSCI instead.
+    #[cfg(not(feature = "rpi5"))]
+    pub fn cpu_release_addr(&self, core: usize) -> Option<u64> {
+        let path = format!("/cpus/cpu@{}", core);
+        if self.string(&path, "enable-method")? != "spin-table" {
+            return None;
+        }
+        read_cells(self.property(&path, "cpu-release-addr")?, 2)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Boot
+// ----------------------------------------------------------------------------
+
+// Called first thing in _main with the x0 the firmware gave us, before the
+// UART is up, so nothing can be printed yet. QEMU only passes a DTB for
+// -dtb, otherwise x0 is 0 and the built-in addresses stay.
+pub fn init(address: usize) {
+    if address == 0 {
+        return;
+    }
+
+    let Some(tree) = (unsafe { DeviceTree::from_address(address) }) else {
+        return;
+    };
+
+    hardwareselect::set_discovered(
+        tree.peripheral_base().map(|base| base as usize),
+        tree.uart0_base().map(|base| base as usize),
+    );
+
+    if let Some(end) = tree.ram_end() {
+        map::set_ram_end(end as usize);
+    }
+
+    *TREE.lock() = Some(tree);
+}
+
+// Spin table slot of `core` from the device tree, once relocate() ran
+#[cfg(not(feature = "rpi5"))]
+pub fn cpu_release_addr(core: usize) -> Option<usize> {
+    let tree = (*TREE.lock())?;
+    tree.cpu_release_addr(core).map(|address| address as usize)
+}
+
+// The kernel command line. Works before relocate() too, the blob is only
+// overwritten once the heap is up.
+pub fn bootargs() -> Option<&'static str> {
+    (*TREE.lock())?.bootargs()
+}
+
+// Once the heap is up: copy the blob out of free memory (the memory tester
+// would overwrite it) and report what was found
+pub fn relocate() {
+    let mut slot = TREE.lock();
+
+    let Some(tree) = *slot else {
+        log::warn!(
+            "No device tree found, using the built-in {} addresses",
+            hardwareselect::get_platform_name()
+        );
+        return;
+    };
+
+    let copy: &'static [u8] = Box::leak(Box::from(tree.blob));
+    let tree = DeviceTree::from_bytes(copy).unwrap_or(tree);
+    *slot = Some(tree);
+    drop(slot);
+
+    log::info!(
+        "Device tree: {} ({} bytes)",
+        tree.model().unwrap_or("unknown model"),
+        tree.size()
+    );
+    log::info!(
+        "Peripherals at {:#x}, UART0 at {:#x}, RAM up to {:#x}",
+        hardwareselect::peripheral_base(),
+        hardwareselect::uart0_base(),
+        map::ram_end()
+    );
+
+    if let Some(args) = tree.bootargs() {
+        log::info!("Command line: {}", args);
+    }
+
+    if hardwareselect::peripheral_base() != hardwareselect::PERIPHERAL_BASE {
+        log::warn!(
+            "Built for {} ({}), device tree moved the peripherals",
+            hardwareselect::get_platform_name(),
+            hardwareselect::get_peripheral_base_display()
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use super::FsError;
+use super::block::{BlockDevice, SECTOR_SIZE, SdBlockDevice};
+use super::cache::BlockCache;
+use super::partition;
+use crate::drivers::timer;
+use crate::println;
+use crate::utils::progress::Progress;
+use alloc::format;
+use alloc::vec::Vec;
+
+// ============================================================================
+// SD CARD BENCHMARK
+// Times single-sector requests against the raw driver and through the block
+// cache. Everything happens inside the boot partition. Write passes read each
+// sector first and write the same bytes back, so the card contents never
+// change (only a power cut halfway through a write could hurt).
+// ============================================================================
+
+#[derive(Clone, Copy)]
+enum Access {
+    Sequential,
+    Random,
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Read,
+    Write,
+}
+
+// xorshift64, plenty for picking sector numbers
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+struct Report {
+    ops: usize,
+    total_us: u64,
+    p50_us: u64,
+    p90_us: u64,
+    p99_us: u64,
+    max_us: u64,
+}
+
+impl Report {
+    fn from_samples(mut samples: Vec<u64>, total_us: u64) -> Self {
+        samples.sort_unstable();
+        let percentile = |p: usize| match samples.len() {
+            0 => 0,
+            len => samples[(len * p / 100).min(len - 1)],
+        };
+
+        Report {
+            ops: samples.len(),
+            total_us,
+            p50_us: percentile(50),
+            p90_us: percentile(90),
+            p99_us: percentile(99),
+            max_us: samples.last().copied().unwrap_or(0),
+        }
+    }
+
+    fn kib_per_sec(&self) -> u64 {
+        let bytes = (self.ops * SECTOR_SIZE) as u64;
+        bytes * 1_000_000 / 1024 / self.total_us.max(1)
+    }
+}
+
+fn pass<D: BlockDevice>(
+    label: &str,
+    device: &mut D,
+    start_lba: u64,
+    blocks: u64,
+    access: Access,
+    op: Op,
+    rng: &mut Rng,
+) -> Result<Report, FsError> {
+    let mut sector = [0u8; SECTOR_SIZE];
+    let mut samples = Vec::with_capacity(blocks as usize);
+    let mut total_ticks = 0;
+    let mut progress = Progress::bar(&format!("  {}", label), blocks);
+
+    for i in 0..blocks {
+        let lba = match access {
+            Access::Sequential => start_lba + i,
+            Access::Random => start_lba + rng.next() % blocks,
+        };
+
+        let ticks = match op {
+            Op::Read => {
+                let start = timer::counter();
+                device.read_blocks(lba, &mut sector)?;
+                timer::counter() - start
+            }
+            Op::Write => {
+                // Untimed read so the write puts back exactly what was there
+                device.read_blocks(lba, &mut sector)?;
+                let start = timer::counter();
+                device.write_blocks(lba, &sector)?;
+                timer::counter() - start
+            }
+        };
+
+        total_ticks += ticks;
+        samples.push(timer::ticks_to_us(ticks));
+        progress.advance(1);
+    }
+    progress.clear();
+
+    Ok(Report::from_samples(
+        samples,
+        timer::ticks_to_us(total_ticks),
+    ))
+}
+
+fn print_report(label: &str, report: &Report) {
+    println!(
+        "  {:<22} {:>6} KiB/s   p50 {:>6} us  p90 {:>6} us  p99 {:>6} us  max {:>6} us",
+        label,
+        report.kib_per_sec(),
+        report.p50_us,
+        report.p90_us,
+        report.p99_us,
+        report.max_us
+    );
+}
+
+fn run_device<D: BlockDevice>(
+    device: &mut D,
+    start_lba: u64,
+    blocks: u64,
+    write: bool,
+    rng: &mut Rng,
+) -> Result<(), FsError> {
+    let mut passes = Vec::new();
+    passes.push(("sequential read", Access::Sequential, Op::Read));
+    passes.push(("random read", Access::Random, Op::Read));
+    if write {
+        passes.push(("sequential write", Access::Sequential, Op::Write));
+        passes.push(("random write", Access::Random, Op::Write));
+    }
+
+    for (label, access, op) in passes {
+        let report = pass(label, device, start_lba, blocks, access, op, rng)?;
+        print_report(label, &report);
+    }
+
+    Ok(())
+}
+
+// Benchmark `blocks` sectors at the start of the boot partition, without and
+// then with the block cache in front of the driver
+pub fn run(blocks: u64, write: bool) -> Result<(), FsError> {
+    let mut raw = SdBlockDevice;
+    let start_lba = partition::find_fat32(&mut raw)?;
+    let mut rng = Rng(timer::counter() | 1);
+
+    println!(
+        "sdbench: {} sectors from LBA {} ({})",
+        blocks,
+        start_lba,
+        if write { "read + rewrite" } else { "read only" }
+    );
+
+    println!("raw driver:");
+    run_device(&mut raw, start_lba, blocks, write, &mut rng)?;
+
+    let mut cached = BlockCache::new(SdBlockDevice);
+    println!("block cache:");
+    run_device(&mut cached, start_lba, blocks, write, &mut rng)?;
+    println!("  cache hits {} / misses {}", cached.hits, cached.misses);
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use super::FsError;
+use crate::drivers::sdcard::{self, SDCARD};
+use alloc::boxed::Box;
+
+pub const SECTOR_SIZE: usize = 512;
+
+// Anything that stores fixed 512-byte sectors: the SD card today, a ramdisk or
+// USB stick later. Buffers are always a whole number of sectors.
+pub trait BlockDevice {
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), FsError>;
+    fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> Result<(), FsError>;
+}
+
+// A handle from devices::get_block()
+impl<D: BlockDevice + ?Sized> BlockDevice for Box<D> {
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), FsError> {
+        (**self).read_blocks(lba, buffer)
+    }
+
+    fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> Result<(), FsError> {
+        (**self).write_blocks(lba, buffer)
+    }
+}
+
+// The SD card behind its global lock, taken per request
+pub struct SdBlockDevice;
+
+impl BlockDevice for SdBlockDevice {
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), FsError> {
+        let lba = u32::try_from(lba).map_err(|_| FsError::Io)?;
+        SDCARD
+            .lock()
+            .read_blocks(lba, buffer)
+            .map_err(|_: sdcard::SdError| FsError::Io)
+    }
+
+    fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> Result<(), FsError> {
+        let lba = u32::try_from(lba).map_err(|_| FsError::Io)?;
+        SDCARD
+            .lock()
+            .write_blocks(lba, buffer)
+            .map_err(|_: sdcard::SdError| FsError::Io)
+    }
+}
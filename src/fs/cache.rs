@@ -0,0 +1,111 @@
+use super::FsError;
+use super::block::{BlockDevice, SECTOR_SIZE};
+use alloc::boxed::Box;
+
+// ============================================================================
+// BLOCK CACHE
+// Small write-through sector cache in front of a BlockDevice. FAT walks keep
+// re-reading the same FAT and directory sectors, which is slow over a polled
+// SD bus. Write-through means there is never dirty data to lose.
+// ============================================================================
+
+pub const CACHE_SECTORS: usize = 64;
+
+struct Slot {
+    lba: Option<u64>,
+    last_used: u64,
+    data: [u8; SECTOR_SIZE],
+}
+
+pub struct BlockCache<D: BlockDevice> {
+    device: D,
+    slots: Box<[Slot]>,
+    clock: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl<D: BlockDevice> BlockCache<D> {
+    pub fn new(device: D) -> Self {
+        let slots = (0..CACHE_SECTORS)
+            .map(|_| Slot {
+                lba: None,
+                last_used: 0,
+                data: [0; SECTOR_SIZE],
+            })
+            .collect();
+
+        BlockCache {
+            device,
+            slots,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn find(&self, lba: u64) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.lba == Some(lba))
+    }
+
+    // Least recently used slot, empty ones first
+    fn victim(&self) -> usize {
+        self.slots
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, slot)| (slot.lba.is_some(), slot.last_used))
+            .map(|(index, _)| index)
+            .unwrap_or(0)
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for BlockCache<D> {
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), FsError> {
+        for (i, sector) in buffer.chunks_exact_mut(SECTOR_SIZE).enumerate() {
+            let lba = lba + i as u64;
+            let now = self.tick();
+
+            let index = match self.find(lba) {
+                Some(index) => {
+                    self.hits += 1;
+                    index
+                }
+                None => {
+                    self.misses += 1;
+                    let index = self.victim();
+                    let slot = &mut self.slots[index];
+
+                    // Only mark the slot valid once the read actually succeeded
+                    slot.lba = None;
+                    self.device.read_blocks(lba, &mut slot.data)?;
+                    slot.lba = Some(lba);
+                    index
+                }
+            };
+
+            let slot = &mut self.slots[index];
+            slot.last_used = now;
+            sector.copy_from_slice(&slot.data);
+        }
+
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> Result<(), FsError> {
+        self.device.write_blocks(lba, buffer)?;
+
+        // Keep any cached copies in step with what is now on the card
+        for (i, sector) in buffer.chunks_exact(SECTOR_SIZE).enumerate() {
+            if let Some(index) = self.find(lba + i as u64) {
+                self.slots[index].data.copy_from_slice(sector);
+            }
+        }
+
+        Ok(())
+    }
+}
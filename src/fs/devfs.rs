@@ -0,0 +1,80 @@
+use super::FsError;
+use super::vfs::{Dir, DirEntry, File, FileSystem, NodeKind};
+use crate::devices::{self, CharDevice, Class};
+use alloc::boxed::Box;
+use alloc::string::String;
+
+// ============================================================================
+// DEVFS
+// The char devices of the device registry (devices.rs) as files under /dev,
+// looked up when opened, so a device registered later shows up right away.
+// Device reads never block: they return whatever is available right now, 0
+// meaning "nothing yet".
+// ============================================================================
+
+pub struct DevFs;
+
+struct DeviceFile {
+    device: &'static dyn CharDevice,
+}
+
+impl File for DeviceFile {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, FsError> {
+        self.device.read(buffer)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, FsError> {
+        self.device.write(buffer)
+    }
+
+    fn size(&self) -> Option<u64> {
+        None
+    }
+}
+
+struct DeviceDir {
+    next: usize,
+}
+
+impl Dir for DeviceDir {
+    fn next_entry(&mut self) -> Result<Option<DirEntry>, FsError> {
+        let mut names = devices::list()
+            .into_iter()
+            .filter(|device| matches!(device.class, Class::Char(_)))
+            .map(|device| device.name);
+        let Some(name) = names.nth(self.next) else {
+            return Ok(None);
+        };
+
+        self.next += 1;
+        Ok(Some(DirEntry {
+            name: String::from(name),
+            kind: NodeKind::Device,
+            size: 0,
+        }))
+    }
+}
+
+impl FileSystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn open(&'static self, path: &str) -> Result<Box<dyn File>, FsError> {
+        if path.is_empty() {
+            return Err(FsError::NotAFile);
+        }
+
+        devices::get_char(path)
+            .map(|device| Box::new(DeviceFile { device }) as Box<dyn File>)
+            .ok_or(FsError::NotFound)
+    }
+
+    fn open_dir(&'static self, path: &str) -> Result<Box<dyn Dir>, FsError> {
+        match path {
+            "" => Ok(Box::new(DeviceDir { next: 0 })),
+            _ if devices::get_char(path).is_some() => Err(FsError::NotADirectory),
+            _ => Err(FsError::NotFound),
+        }
+    }
+}
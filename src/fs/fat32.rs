@@ -0,0 +1,870 @@
+use super::FsError;
+use super::block::{BlockDevice, SECTOR_SIZE};
+use super::partition::{le16, le32};
+use super::vfs::{self, Dir, File, FileSystem, NodeKind};
+use crate::utils::locked::SpinLock;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+// ============================================================================
+// FAT32
+// Layout on disk: [reserved sectors][FAT #1][FAT #2...][data clusters]
+// Files and directories are chains of clusters linked through the FAT.
+// Directories are arrays of 32-byte entries, with long file names stored as
+// extra entries placed just before the 8.3 entry they belong to.
+//
+// Writing covers what the shell needs to keep small files on the card:
+// creating files with 8.3 names, writing and truncating them. No mkdir or
+// rm yet, and no long names for new files.
+// ============================================================================
+
+const DIR_ENTRY_SIZE: usize = 32;
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_HIDDEN: u8 = 0x02;
+const ATTR_SYSTEM: u8 = 0x04;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+const ENTRY_END: u8 = 0x00;
+const ENTRY_DELETED: u8 = 0xE5;
+
+const LFN_LAST: u8 = 0x40;
+const LFN_CHARS_PER_ENTRY: usize = 13;
+// Byte offsets of the 13 UCS-2 characters inside a long-name entry
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS_PER_ENTRY] =
+    [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+// Name case flags Windows stores in the reserved byte of 8.3 entries
+const CASE_LOWER_BASE: u8 = 0x08;
+const CASE_LOWER_EXT: u8 = 0x10;
+
+const FAT_ENTRY_MASK: u32 = 0x0FFF_FFFF;
+const FAT_END_OF_CHAIN: u32 = 0x0FFF_FFF8;
+const FAT_BAD_CLUSTER: u32 = 0x0FFF_FFF7;
+const FAT_FREE: u32 = 0;
+// What we write to end a chain
+const FAT_CHAIN_END: u32 = 0x0FFF_FFFF;
+
+// FSInfo sector: a free-cluster count and next-free hint we do not maintain,
+// so the first write marks both unknown
+const FSINFO_LEAD_SIG: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIG: u32 = 0x6141_7272;
+const FSINFO_FREE_COUNT: usize = 488;
+const FSINFO_NEXT_FREE: usize = 492;
+const FSINFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+// Characters allowed in an 8.3 name besides letters and digits
+const SHORT_NAME_SPECIAL: &[u8] = b"$%'-_@~`!(){}^#&";
+
+#[derive(Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u32,
+    pub first_cluster: u32,
+    // Sector and byte offset of the 8.3 entry on disk, None for the root
+    location: Option<(u64, usize)>,
+}
+
+pub struct Fat32<D: BlockDevice> {
+    device: D,
+    sectors_per_cluster: u32,
+    fat_start: u64,
+    fat_sectors: u32,
+    fat_count: u32,
+    fsinfo_lba: Option<u64>,
+    data_start: u64,
+    root_cluster: u32,
+    cluster_count: u32,
+    // One-sector cache for FAT lookups, chain walks hit the same sector repeatedly
+    fat_cache: [u8; SECTOR_SIZE],
+    fat_cache_lba: Option<u64>,
+    // Where the next free-cluster search starts
+    next_free: u32,
+}
+
+impl<D: BlockDevice> Fat32<D> {
+    pub fn mount(mut device: D, partition_lba: u64) -> Result<Self, FsError> {
+        let mut boot = [0u8; SECTOR_SIZE];
+        device.read_blocks(partition_lba, &mut boot)?;
+
+        if le16(&boot, 510) != 0xAA55 {
+            return Err(FsError::NoFilesystem);
+        }
+
+        let bytes_per_sector = le16(&boot, 11) as usize;
+        let sectors_per_cluster = boot[13] as u32;
+        let reserved_sectors = le16(&boot, 14) as u32;
+        let fat_count = boot[16] as u32;
+        let root_entries = le16(&boot, 17);
+        let total_sectors = le32(&boot, 32);
+        let fat_sectors = le32(&boot, 36);
+        let root_cluster = le32(&boot, 44);
+        let fsinfo_sector = le16(&boot, 48);
+
+        // FAT12/16 have a fixed root directory and 16-bit FAT size instead
+        if bytes_per_sector != SECTOR_SIZE
+            || sectors_per_cluster == 0
+            || !sectors_per_cluster.is_power_of_two()
+            || root_entries != 0
+            || fat_sectors == 0
+            || fat_count == 0
+        {
+            return Err(FsError::NoFilesystem);
+        }
+
+        // Reserved sectors and FATs, which must leave room for data. In u64
+        // and checked, since the fields come off the card as they are.
+        let metadata_sectors = (fat_count as u64)
+            .checked_mul(fat_sectors as u64)
+            .and_then(|fats| fats.checked_add(reserved_sectors as u64))
+            .filter(|&sectors| sectors < total_sectors as u64)
+            .ok_or(FsError::NoFilesystem)?;
+        let data_sectors = total_sectors as u64 - metadata_sectors;
+
+        let fat_start = partition_lba + reserved_sectors as u64;
+        let data_start = partition_lba + metadata_sectors;
+
+        Ok(Fat32 {
+            device,
+            sectors_per_cluster,
+            fat_start,
+            fat_sectors,
+            fat_count,
+            // 0 and 0xFFFF both mean "no FSInfo sector"
+            fsinfo_lba: match fsinfo_sector {
+                0 | 0xFFFF => None,
+                sector => Some(partition_lba + sector as u64),
+            },
+            data_start,
+            root_cluster,
+            cluster_count: (data_sectors / sectors_per_cluster as u64) as u32,
+            fat_cache: [0; SECTOR_SIZE],
+            fat_cache_lba: None,
+            next_free: 2,
+        })
+    }
+
+    pub fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster as usize * SECTOR_SIZE
+    }
+
+    pub fn root(&self) -> DirEntry {
+        DirEntry {
+            name: String::from("/"),
+            is_dir: true,
+            size: 0,
+            first_cluster: self.root_cluster,
+            location: None,
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Cluster chain
+    // ------------------------------------------------------------------------
+
+    fn cluster_lba(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster as u64 - 2) * self.sectors_per_cluster as u64
+    }
+
+    fn valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster - 2 < self.cluster_count
+    }
+
+    // Pull the FAT sector holding `cluster` into the cache, returns the
+    // sector index within the FAT and the entry's offset inside it
+    fn load_fat_sector(&mut self, cluster: u32) -> Result<(u64, usize), FsError> {
+        let byte_offset = cluster as u64 * 4;
+        let sector = byte_offset / SECTOR_SIZE as u64;
+
+        if sector >= self.fat_sectors as u64 {
+            return Err(FsError::Corrupt);
+        }
+
+        let lba = self.fat_start + sector;
+        if self.fat_cache_lba != Some(lba) {
+            self.device.read_blocks(lba, &mut self.fat_cache)?;
+            self.fat_cache_lba = Some(lba);
+        }
+
+        Ok((sector, (byte_offset % SECTOR_SIZE as u64) as usize))
+    }
+
+    fn fat_entry(&mut self, cluster: u32) -> Result<u32, FsError> {
+        let (_, offset) = self.load_fat_sector(cluster)?;
+        Ok(le32(&self.fat_cache, offset) & FAT_ENTRY_MASK)
+    }
+
+    // Update one FAT entry in every copy of the FAT. The top four bits are
+    // reserved and kept as they were.
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), FsError> {
+        let (sector, offset) = self.load_fat_sector(cluster)?;
+
+        let old = le32(&self.fat_cache, offset);
+        let new = (old & !FAT_ENTRY_MASK) | (value & FAT_ENTRY_MASK);
+        self.fat_cache[offset..offset + 4].copy_from_slice(&new.to_le_bytes());
+
+        for copy in 0..self.fat_count as u64 {
+            let lba = self.fat_start + copy * self.fat_sectors as u64 + sector;
+            self.device.write_blocks(lba, &self.fat_cache)?;
+        }
+
+        Ok(())
+    }
+
+    fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>, FsError> {
+        match self.fat_entry(cluster)? {
+            e if e >= FAT_END_OF_CHAIN => Ok(None),
+            FAT_BAD_CLUSTER => Err(FsError::Corrupt),
+            e if self.valid_cluster(e) => Ok(Some(e)),
+            _ => Err(FsError::Corrupt),
+        }
+    }
+
+    // Every cluster of a chain in order, refusing loops and out-of-range links
+    fn cluster_chain(&mut self, first: u32) -> Result<Vec<u32>, FsError> {
+        let mut chain = Vec::new();
+
+        if first == 0 {
+            return Ok(chain);
+        }
+        if !self.valid_cluster(first) {
+            return Err(FsError::Corrupt);
+        }
+
+        let mut current = Some(first);
+        while let Some(cluster) = current {
+            if chain.len() > self.cluster_count as usize {
+                return Err(FsError::Corrupt);
+            }
+            chain.push(cluster);
+            current = self.next_cluster(cluster)?;
+        }
+
+        Ok(chain)
+    }
+
+    fn read_cluster(&mut self, cluster: u32, buffer: &mut [u8]) -> Result<(), FsError> {
+        let lba = self.cluster_lba(cluster);
+        self.device.read_blocks(lba, buffer)
+    }
+
+    fn write_cluster(&mut self, cluster: u32, buffer: &[u8]) -> Result<(), FsError> {
+        let lba = self.cluster_lba(cluster);
+        self.device.write_blocks(lba, buffer)
+    }
+
+    fn invalidate_fsinfo(&mut self) -> Result<(), FsError> {
+        let Some(lba) = self.fsinfo_lba.take() else {
+            return Ok(());
+        };
+
+        let mut sector = [0u8; SECTOR_SIZE];
+        self.device.read_blocks(lba, &mut sector)?;
+
+        if le32(&sector, 0) != FSINFO_LEAD_SIG || le32(&sector, 484) != FSINFO_STRUCT_SIG {
+            return Ok(());
+        }
+
+        sector[FSINFO_FREE_COUNT..FSINFO_FREE_COUNT + 4]
+            .copy_from_slice(&FSINFO_UNKNOWN.to_le_bytes());
+        sector[FSINFO_NEXT_FREE..FSINFO_NEXT_FREE + 4]
+            .copy_from_slice(&FSINFO_UNKNOWN.to_le_bytes());
+        self.device.write_blocks(lba, &sector)
+    }
+
+    // Claim a free cluster, zero it and append it to the chain ending at `last`
+    fn allocate_cluster(&mut self, last: Option<u32>) -> Result<u32, FsError> {
+        self.invalidate_fsinfo()?;
+
+        let start = self.next_free.saturating_sub(2);
+        let mut found = None;
+
+        for i in 0..self.cluster_count {
+            let cluster = 2 + (start + i) % self.cluster_count;
+            if self.fat_entry(cluster)? == FAT_FREE {
+                found = Some(cluster);
+                break;
+            }
+        }
+
+        let cluster = found.ok_or(FsError::NoSpace)?;
+        self.set_fat_entry(cluster, FAT_CHAIN_END)?;
+        if let Some(last) = last {
+            self.set_fat_entry(last, cluster)?;
+        }
+        self.next_free = cluster + 1;
+
+        self.write_cluster(cluster, &vec![0u8; self.cluster_size()])?;
+        Ok(cluster)
+    }
+
+    fn free_chain(&mut self, first: u32) -> Result<(), FsError> {
+        for cluster in self.cluster_chain(first)? {
+            self.set_fat_entry(cluster, FAT_FREE)?;
+        }
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------------
+    // Directories
+    // ------------------------------------------------------------------------
+
+    pub fn read_dir(&mut self, dir: &DirEntry) -> Result<Vec<DirEntry>, FsError> {
+        if !dir.is_dir {
+            return Err(FsError::NotADirectory);
+        }
+
+        let chain = self.cluster_chain(dir.first_cluster)?;
+        let mut buffer = vec![0u8; self.cluster_size()];
+        let mut entries = Vec::new();
+        let mut long_name = LongName::new();
+
+        for cluster in chain {
+            self.read_cluster(cluster, &mut buffer)?;
+
+            for (index, raw) in buffer.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+                match raw[0] {
+                    ENTRY_END => return Ok(entries),
+                    ENTRY_DELETED => {
+                        long_name.reset();
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                let attributes = raw[11];
+
+                if attributes & ATTR_LONG_NAME == ATTR_LONG_NAME {
+                    long_name.push(raw);
+                    continue;
+                }
+
+                if attributes & ATTR_VOLUME_ID != 0 {
+                    long_name.reset();
+                    continue;
+                }
+
+                let short = short_name(raw);
+                let name = long_name.take(checksum(&raw[0..11])).unwrap_or(short);
+
+                if name == "." || name == ".." {
+                    continue;
+                }
+
+                let cluster_high = le16(raw, 20) as u32;
+                let cluster_low = le16(raw, 26) as u32;
+
+                entries.push(DirEntry {
+                    name,
+                    is_dir: attributes & ATTR_DIRECTORY != 0,
+                    size: le32(raw, 28),
+                    first_cluster: (cluster_high << 16) | cluster_low,
+                    location: Some(self.entry_location(cluster, index)),
+                });
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn entry_location(&self, cluster: u32, index: usize) -> (u64, usize) {
+        let byte_offset = index * DIR_ENTRY_SIZE;
+        (
+            self.cluster_lba(cluster) + (byte_offset / SECTOR_SIZE) as u64,
+            byte_offset % SECTOR_SIZE,
+        )
+    }
+
+    // First unused 32-byte slot in `dir`, growing the directory by a cluster
+    // when it is full
+    fn free_slot(&mut self, dir: &DirEntry) -> Result<(u64, usize), FsError> {
+        let chain = self.cluster_chain(dir.first_cluster)?;
+        let mut buffer = vec![0u8; self.cluster_size()];
+
+        for &cluster in &chain {
+            self.read_cluster(cluster, &mut buffer)?;
+
+            let free = buffer
+                .chunks_exact(DIR_ENTRY_SIZE)
+                .position(|raw| raw[0] == ENTRY_END || raw[0] == ENTRY_DELETED);
+            if let Some(index) = free {
+                return Ok(self.entry_location(cluster, index));
+            }
+        }
+
+        let cluster = self.allocate_cluster(chain.last().copied())?;
+        Ok(self.entry_location(cluster, 0))
+    }
+
+    // Write the size and first cluster of `entry` back to its directory
+    fn update_entry(&mut self, entry: &DirEntry) -> Result<(), FsError> {
+        let (lba, offset) = entry.location.ok_or(FsError::Unsupported)?;
+        let mut sector = [0u8; SECTOR_SIZE];
+        self.device.read_blocks(lba, &mut sector)?;
+
+        let raw = &mut sector[offset..offset + DIR_ENTRY_SIZE];
+        raw[20..22].copy_from_slice(&((entry.first_cluster >> 16) as u16).to_le_bytes());
+        raw[26..28].copy_from_slice(&(entry.first_cluster as u16).to_le_bytes());
+        raw[28..32].copy_from_slice(&entry.size.to_le_bytes());
+
+        self.device.write_blocks(lba, &sector)
+    }
+
+    // Resolve "/dir/file.txt" (case-insensitive, like FAT itself) from the root
+    pub fn lookup(&mut self, path: &str) -> Result<DirEntry, FsError> {
+        let mut current = self.root();
+
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            if !current.is_dir {
+                return Err(FsError::NotADirectory);
+            }
+
+            current = self
+                .read_dir(&current)?
+                .into_iter()
+                .find(|entry| entry.name.eq_ignore_ascii_case(component))
+                .ok_or(FsError::NotFound)?;
+        }
+
+        Ok(current)
+    }
+
+    // ------------------------------------------------------------------------
+    // Files
+    // ------------------------------------------------------------------------
+
+    // Read from `offset` into `buffer`, returns how many bytes were copied
+    pub fn read(
+        &mut self,
+        file: &DirEntry,
+        offset: usize,
+        buffer: &mut [u8],
+    ) -> Result<usize, FsError> {
+        if file.is_dir {
+            return Err(FsError::NotAFile);
+        }
+
+        let size = file.size as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let wanted = buffer.len().min(size - offset);
+        let cluster_size = self.cluster_size();
+        let chain = self.cluster_chain(file.first_cluster)?;
+        let mut cluster_buffer = vec![0u8; cluster_size];
+        let mut copied = 0;
+
+        while copied < wanted {
+            let position = offset + copied;
+            let cluster = *chain.get(position / cluster_size).ok_or(FsError::Corrupt)?;
+            let within = position % cluster_size;
+            let count = (cluster_size - within).min(wanted - copied);
+
+            self.read_cluster(cluster, &mut cluster_buffer)?;
+            buffer[copied..copied + count].copy_from_slice(&cluster_buffer[within..within + count]);
+            copied += count;
+        }
+
+        Ok(copied)
+    }
+
+    // Write `data` at `offset`, growing the file (and its cluster chain) as
+    // needed. `file` is updated along with its directory entry.
+    pub fn write(
+        &mut self,
+        file: &mut DirEntry,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<usize, FsError> {
+        if file.is_dir {
+            return Err(FsError::NotAFile);
+        }
+
+        let end = offset.checked_add(data.len()).ok_or(FsError::NoSpace)?;
+        if end > u32::MAX as usize {
+            return Err(FsError::NoSpace);
+        }
+
+        // A gap past the end reads back as zeros
+        let size = file.size as usize;
+        if offset > size {
+            self.write(file, size, &vec![0u8; offset - size])?;
+        }
+
+        let cluster_size = self.cluster_size();
+        let mut chain = self.cluster_chain(file.first_cluster)?;
+
+        while chain.len() < end.div_ceil(cluster_size) {
+            let cluster = self.allocate_cluster(chain.last().copied())?;
+            if chain.is_empty() {
+                file.first_cluster = cluster;
+            }
+            chain.push(cluster);
+        }
+
+        let mut cluster_buffer = vec![0u8; cluster_size];
+        let mut written = 0;
+
+        while written < data.len() {
+            let position = offset + written;
+            let cluster = chain[position / cluster_size];
+            let within = position % cluster_size;
+            let count = (cluster_size - within).min(data.len() - written);
+
+            // Partial clusters keep the bytes around the written range
+            if count < cluster_size {
+                self.read_cluster(cluster, &mut cluster_buffer)?;
+            }
+            cluster_buffer[within..within + count].copy_from_slice(&data[written..written + count]);
+            self.write_cluster(cluster, &cluster_buffer)?;
+            written += count;
+        }
+
+        file.size = file.size.max(end as u32);
+        self.update_entry(file)?;
+        Ok(written)
+    }
+
+    pub fn truncate(&mut self, file: &mut DirEntry) -> Result<(), FsError> {
+        if file.is_dir {
+            return Err(FsError::NotAFile);
+        }
+
+        self.free_chain(file.first_cluster)?;
+        file.first_cluster = 0;
+        file.size = 0;
+        self.update_entry(file)
+    }
+
+    // Create an empty file, or truncate it if it already exists
+    pub fn create(&mut self, path: &str) -> Result<DirEntry, FsError> {
+        let path = path.trim_matches('/');
+        let (parent_path, name) = path.rsplit_once('/').unwrap_or(("", path));
+
+        let parent = self.lookup(parent_path)?;
+        if !parent.is_dir {
+            return Err(FsError::NotADirectory);
+        }
+
+        let existing = self
+            .read_dir(&parent)?
+            .into_iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name));
+
+        match existing {
+            Some(entry) if entry.is_dir => return Err(FsError::NotAFile),
+            Some(mut entry) => {
+                self.truncate(&mut entry)?;
+                return Ok(entry);
+            }
+            None => {}
+        }
+
+        let (short, case) = encode_short_name(name).ok_or(FsError::Unsupported)?;
+        let (lba, offset) = self.free_slot(&parent)?;
+
+        let mut sector = [0u8; SECTOR_SIZE];
+        self.device.read_blocks(lba, &mut sector)?;
+
+        // No clock to stamp it with, times and dates stay zero
+        let raw = &mut sector[offset..offset + DIR_ENTRY_SIZE];
+        raw.fill(0);
+        raw[0..11].copy_from_slice(&short);
+        raw[11] = ATTR_ARCHIVE;
+        raw[12] = case;
+        self.device.write_blocks(lba, &sector)?;
+
+        Ok(DirEntry {
+            name: short_name(&short_entry(&short, case)),
+            is_dir: false,
+            size: 0,
+            first_cluster: 0,
+            location: Some((lba, offset)),
+        })
+    }
+}
+
+// "README  TXT" -> "README.TXT" (or "readme.txt" with the case flags set)
+fn short_name(raw: &[u8]) -> String {
+    let case = raw[12];
+    let mut name = String::new();
+
+    for &b in raw[0..8].iter().take_while(|&&b| b != b' ') {
+        // 0x05 stands in for a real 0xE5 first byte
+        let b = if b == 0x05 { 0xE5 } else { b };
+        let c = if case & CASE_LOWER_BASE != 0 {
+            b.to_ascii_lowercase()
+        } else {
+            b
+        };
+        name.push(c as char);
+    }
+
+    let ext: Vec<u8> = raw[8..11]
+        .iter()
+        .copied()
+        .take_while(|&b| b != b' ')
+        .collect();
+    if !ext.is_empty() {
+        name.push('.');
+        for b in ext {
+            let c = if case & CASE_LOWER_EXT != 0 {
+                b.to_ascii_lowercase()
+            } else {
+                b
+            };
+            name.push(c as char);
+        }
+    }
+
+    name
+}
+
+// "history.txt" -> ("HISTORY TXT", lower-case flags). None when the name does
+// not fit 8.3 or mixes cases in one part, which would need a long name.
+fn encode_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((base, ext)) => (base, ext),
+        None => (name, ""),
+    };
+
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+
+    let mut short = [b' '; 11];
+    let mut case = 0;
+
+    for (part, field, lower_flag) in [(base, 0..8, CASE_LOWER_BASE), (ext, 8..11, CASE_LOWER_EXT)] {
+        let bytes = part.as_bytes();
+
+        if !bytes
+            .iter()
+            .all(|&b| b.is_ascii_alphanumeric() || SHORT_NAME_SPECIAL.contains(&b))
+        {
+            return None;
+        }
+
+        let has_lower = bytes.iter().any(|b| b.is_ascii_lowercase());
+        let has_upper = bytes.iter().any(|b| b.is_ascii_uppercase());
+        match (has_lower, has_upper) {
+            (true, true) => return None,
+            (true, false) => case |= lower_flag,
+            _ => {}
+        }
+
+        for (slot, &b) in short[field].iter_mut().zip(bytes) {
+            *slot = b.to_ascii_uppercase();
+        }
+    }
+
+    Some((short, case))
+}
+
+// The first 13 bytes of a directory entry, enough for short_name()
+fn short_entry(short: &[u8; 11], case: u8) -> [u8; 13] {
+    let mut raw = [0u8; 13];
+    raw[0..11].copy_from_slice(short);
+    raw[12] = case;
+    raw
+}
+
+// Checksum of the 8.3 name every LFN entry carries, to detect orphaned long names
+fn checksum(short: &[u8]) -> u8 {
+    short.iter().fold(0u8, |sum, &b| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b)
+    })
+}
+
+// Collects long-name fragments. They appear on disk last-part-first, each
+// tagged with its sequence number.
+struct LongName {
+    units: Vec<u16>,
+    checksum: u8,
+    valid: bool,
+}
+
+impl LongName {
+    fn new() -> Self {
+        LongName {
+            units: Vec::new(),
+            checksum: 0,
+            valid: false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.units.clear();
+        self.valid = false;
+    }
+
+    fn push(&mut self, raw: &[u8]) {
+        let sequence = raw[0];
+        let index = (sequence & 0x1F) as usize;
+
+        if index == 0 {
+            self.reset();
+            return;
+        }
+
+        if sequence & LFN_LAST != 0 {
+            self.units = vec![0xFFFF; index * LFN_CHARS_PER_ENTRY];
+            self.checksum = raw[13];
+            self.valid = true;
+        } else if !self.valid || raw[13] != self.checksum {
+            self.reset();
+            return;
+        }
+
+        let base = (index - 1) * LFN_CHARS_PER_ENTRY;
+        if base + LFN_CHARS_PER_ENTRY > self.units.len() {
+            self.reset();
+            return;
+        }
+
+        for (i, &offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            self.units[base + i] = le16(raw, offset);
+        }
+    }
+
+    fn take(&mut self, short_checksum: u8) -> Option<String> {
+        if !self.valid || self.checksum != short_checksum {
+            self.reset();
+            return None;
+        }
+
+        let end = self
+            .units
+            .iter()
+            .position(|&u| u == 0x0000 || u == 0xFFFF)
+            .unwrap_or(self.units.len());
+
+        let name = char::decode_utf16(self.units[..end].iter().copied())
+            .map(|c| c.unwrap_or('?'))
+            .collect();
+
+        self.reset();
+        Some(name)
+    }
+}
+
+// ============================================================================
+// VFS GLUE
+// ============================================================================
+
+pub struct Fat32Fs<D: BlockDevice> {
+    inner: SpinLock<Fat32<D>>,
+}
+
+impl<D: BlockDevice> Fat32Fs<D> {
+    pub fn new(fs: Fat32<D>) -> Self {
+        Fat32Fs {
+            inner: SpinLock::new(fs),
+        }
+    }
+}
+
+fn vfs_entry(entry: &DirEntry) -> vfs::DirEntry {
+    vfs::DirEntry {
+        name: entry.name.clone(),
+        kind: if entry.is_dir {
+            NodeKind::Directory
+        } else {
+            NodeKind::File
+        },
+        size: entry.size as u64,
+    }
+}
+
+struct Fat32File<D: BlockDevice + 'static> {
+    fs: &'static SpinLock<Fat32<D>>,
+    entry: DirEntry,
+    position: u64,
+}
+
+impl<D: BlockDevice + 'static> File for Fat32File<D> {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let count = self
+            .fs
+            .lock()
+            .read(&self.entry, self.position as usize, buffer)?;
+        self.position += count as u64;
+        Ok(count)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, FsError> {
+        let count = self
+            .fs
+            .lock()
+            .write(&mut self.entry, self.position as usize, buffer)?;
+        self.position += count as u64;
+        Ok(count)
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64, FsError> {
+        self.position = position.min(self.entry.size as u64);
+        Ok(self.position)
+    }
+
+    fn size(&self) -> Option<u64> {
+        Some(self.entry.size as u64)
+    }
+}
+
+// The whole listing is read on open, directories are small
+struct Fat32Dir {
+    entries: Vec<DirEntry>,
+    next: usize,
+}
+
+impl Dir for Fat32Dir {
+    fn next_entry(&mut self) -> Result<Option<vfs::DirEntry>, FsError> {
+        let entry = self.entries.get(self.next).map(vfs_entry);
+        self.next += 1;
+        Ok(entry)
+    }
+}
+
+impl<D: BlockDevice + 'static> FileSystem for Fat32Fs<D> {
+    fn name(&self) -> &'static str {
+        "fat32"
+    }
+
+    fn open(&'static self, path: &str) -> Result<Box<dyn File>, FsError> {
+        let entry = self.inner.lock().lookup(path)?;
+        if entry.is_dir {
+            return Err(FsError::NotAFile);
+        }
+
+        Ok(Box::new(Fat32File {
+            fs: &self.inner,
+            entry,
+            position: 0,
+        }))
+    }
+
+    fn open_dir(&'static self, path: &str) -> Result<Box<dyn Dir>, FsError> {
+        let mut fs = self.inner.lock();
+        let dir = fs.lookup(path)?;
+        let entries = fs.read_dir(&dir)?;
+
+        Ok(Box::new(Fat32Dir { entries, next: 0 }))
+    }
+
+    fn create(&'static self, path: &str) -> Result<Box<dyn File>, FsError> {
+        let entry = self.inner.lock().create(path)?;
+
+        Ok(Box::new(Fat32File {
+            fs: &self.inner,
+            entry,
+            position: 0,
+        }))
+    }
+}
@@ -0,0 +1,103 @@
+pub mod bench;
+pub mod block;
+pub mod cache;
+pub mod devfs;
+pub mod fat32;
+pub mod partition;
+pub mod ramfs;
+pub mod vfs;
+
+use crate::devices::{self, Class};
+use crate::drivers::sdcard::SDCARD;
+use crate::initcall::{InitError, initcall};
+use crate::utils::events::{self, Event};
+use crate::utils::log;
+use alloc::boxed::Box;
+use block::SdBlockDevice;
+use cache::BlockCache;
+use core::fmt;
+use fat32::{Fat32, Fat32Fs};
+
+// ============================================================================
+// FILESYSTEMS
+// Block devices at the bottom, partition tables in the middle, filesystems
+// on top, and the VFS gluing them into one tree of paths.
+// ============================================================================
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FsError {
+    Io,
+    NoFilesystem,
+    Corrupt,
+    NotFound,
+    NotADirectory,
+    NotAFile,
+    ReadOnly,
+    Unsupported,
+    Busy,
+    AlreadyExists,
+    NotEmpty,
+    NoSpace,
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            FsError::Io => "I/O error",
+            FsError::NoFilesystem => "no supported filesystem found",
+            FsError::Corrupt => "filesystem structures are corrupt",
+            FsError::NotFound => "no such file or directory",
+            FsError::NotADirectory => "not a directory",
+            FsError::NotAFile => "is a directory",
+            FsError::ReadOnly => "read-only filesystem",
+            FsError::Unsupported => "operation not supported",
+            FsError::Busy => "mount point already in use",
+            FsError::AlreadyExists => "file exists",
+            FsError::NotEmpty => "directory not empty",
+            FsError::NoSpace => "no space left on device",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+// Filesystems that need no hardware, mounted before anything else
+fn init() -> Result<(), InitError> {
+    vfs::mount("/dev", Box::new(devfs::DevFs))
+        .map_err(|err| InitError::failed(format_args!("/dev: {}", err)))?;
+    vfs::mount("/tmp", Box::new(ramfs::RamFs::new()))
+        .map_err(|err| InitError::failed(format_args!("/tmp: {}", err)))
+}
+
+initcall!(Core, "vfs", init);
+
+// The SD card, and its boot partition at /boot
+fn init_sdcard() -> Result<(), InitError> {
+    SDCARD.lock().init().map_err(InitError::failed)?;
+
+    let (width, clock) = {
+        let card = SDCARD.lock();
+        (card.bus_width(), card.clock_hz())
+    };
+    log::info!("SD card ready ({}-bit, {} kHz).", width, clock / 1000);
+    events::publish(Event::SdCardReady {
+        bus_width: width,
+        clock_hz: clock,
+    });
+    devices::register("sd0", Class::Block(|| Box::new(SdBlockDevice)));
+
+    mount_boot().map_err(|err| InitError::failed(format_args!("/boot: {}", err)))?;
+    log::info!("Boot partition mounted at /boot (FAT32).");
+    Ok(())
+}
+
+initcall!(Device, "sdcard", init_sdcard);
+
+// FAT32 boot partition of the SD card, mounted at /boot when a card is present
+pub fn mount_boot() -> Result<(), FsError> {
+    let device = devices::get_block("sd0").ok_or(FsError::NotFound)?;
+    let mut device = BlockCache::new(device);
+    let start_lba = partition::find_fat32(&mut device)?;
+    let fs = Fat32::mount(device, start_lba)?;
+
+    vfs::mount("/boot", Box::new(Fat32Fs::new(fs)))
+}
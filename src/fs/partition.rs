@@ -0,0 +1,169 @@
+use super::FsError;
+use super::block::{BlockDevice, SECTOR_SIZE};
+use alloc::vec;
+use alloc::vec::Vec;
+
+// ============================================================================
+// PARTITION TABLES (MBR + GPT)
+// ============================================================================
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PartitionKind {
+    Fat32,
+    Fat16,
+    Linux,
+    EfiSystem,
+    Other(u8),
+}
+
+#[derive(Clone, Copy)]
+pub struct Partition {
+    pub kind: PartitionKind,
+    pub start_lba: u64,
+}
+
+const MBR_SIGNATURE: u16 = 0xAA55;
+const MBR_TABLE_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+
+// GUIDs as they are stored on disk (first three fields little-endian)
+const GUID_BASIC_DATA: [u8; 16] = [
+    0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44, 0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7,
+];
+const GUID_EFI_SYSTEM: [u8; 16] = [
+    0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B,
+];
+const GUID_LINUX_DATA: [u8; 16] = [
+    0xAF, 0x3D, 0xC6, 0x0F, 0x83, 0x84, 0x72, 0x47, 0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4,
+];
+
+pub fn le16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+pub fn le32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+pub fn le64(bytes: &[u8], offset: usize) -> u64 {
+    le32(bytes, offset) as u64 | ((le32(bytes, offset + 4) as u64) << 32)
+}
+
+fn mbr_kind(type_byte: u8) -> PartitionKind {
+    match type_byte {
+        0x0B | 0x0C => PartitionKind::Fat32,
+        0x04 | 0x06 | 0x0E => PartitionKind::Fat16,
+        0x83 => PartitionKind::Linux,
+        0xEF => PartitionKind::EfiSystem,
+        other => PartitionKind::Other(other),
+    }
+}
+
+fn gpt_kind(guid: &[u8]) -> PartitionKind {
+    if guid == GUID_BASIC_DATA {
+        // Basic data can be any Microsoft filesystem, the BPB decides later
+        PartitionKind::Fat32
+    } else if guid == GUID_EFI_SYSTEM {
+        PartitionKind::EfiSystem
+    } else if guid == GUID_LINUX_DATA {
+        PartitionKind::Linux
+    } else {
+        PartitionKind::Other(0)
+    }
+}
+
+pub fn read_partitions<D: BlockDevice>(device: &mut D) -> Result<Vec<Partition>, FsError> {
+    let mut sector = [0u8; SECTOR_SIZE];
+    device.read_blocks(0, &mut sector)?;
+
+    // No signature, or a FAT boot sector sitting at LBA 0 (card formatted without
+    // a partition table): either way there is nothing to list
+    if le16(&sector, 510) != MBR_SIGNATURE || &sector[82..90] == b"FAT32   " {
+        return Ok(Vec::new());
+    }
+
+    let mut partitions = Vec::new();
+
+    for index in 0..4 {
+        let entry = &sector[MBR_TABLE_OFFSET + index * MBR_ENTRY_SIZE..][..MBR_ENTRY_SIZE];
+        let type_byte = entry[4];
+
+        if type_byte == 0 {
+            continue;
+        }
+
+        if type_byte == MBR_TYPE_GPT_PROTECTIVE {
+            return read_gpt(device);
+        }
+
+        partitions.push(Partition {
+            kind: mbr_kind(type_byte),
+            start_lba: le32(entry, 8) as u64,
+        });
+    }
+
+    Ok(partitions)
+}
+
+fn read_gpt<D: BlockDevice>(device: &mut D) -> Result<Vec<Partition>, FsError> {
+    let mut header = [0u8; SECTOR_SIZE];
+    device.read_blocks(1, &mut header)?;
+
+    if &header[0..8] != GPT_SIGNATURE {
+        return Err(FsError::Corrupt);
+    }
+
+    let entries_lba = le64(&header, 72);
+    let entry_count = le32(&header, 80) as usize;
+    let entry_size = le32(&header, 84) as usize;
+
+    if !(128..=SECTOR_SIZE).contains(&entry_size) || entry_count > 256 {
+        return Err(FsError::Corrupt);
+    }
+
+    let table_bytes = entry_count * entry_size;
+    let table_sectors = table_bytes.div_ceil(SECTOR_SIZE);
+    let mut table = vec![0u8; table_sectors * SECTOR_SIZE];
+    device.read_blocks(entries_lba, &mut table)?;
+
+    let mut partitions = Vec::new();
+
+    for index in 0..entry_count {
+        let entry = &table[index * entry_size..][..entry_size];
+        let type_guid = &entry[0..16];
+
+        if type_guid.iter().all(|&b| b == 0) {
+            continue;
+        }
+
+        partitions.push(Partition {
+            kind: gpt_kind(type_guid),
+            start_lba: le64(entry, 32),
+        });
+    }
+
+    Ok(partitions)
+}
+
+// Start LBA of the first FAT32 partition, or 0 for a partitionless "superfloppy"
+pub fn find_fat32<D: BlockDevice>(device: &mut D) -> Result<u64, FsError> {
+    let partitions = read_partitions(device)?;
+
+    if partitions.is_empty() {
+        return Ok(0);
+    }
+
+    partitions
+        .iter()
+        .find(|p| p.kind == PartitionKind::Fat32 || p.kind == PartitionKind::EfiSystem)
+        .map(|p| p.start_lba)
+        .ok_or(FsError::NoFilesystem)
+}
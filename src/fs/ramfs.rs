@@ -0,0 +1,232 @@
+use super::FsError;
+use super::vfs::{Dir, DirEntry, File, FileSystem, NodeKind};
+use crate::utils::locked::SpinLock;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+// ============================================================================
+// RAMFS
+// A directory tree kept entirely on the heap. Directories are BTreeMaps so
+// listings come out sorted; file contents are plain Vec<u8>. Everything is
+// gone after a reboot.
+// ============================================================================
+
+enum Node {
+    File(Vec<u8>),
+    Dir(BTreeMap<String, Node>),
+}
+
+pub struct RamFs {
+    root: SpinLock<Node>,
+}
+
+impl RamFs {
+    pub fn new() -> Self {
+        RamFs {
+            root: SpinLock::new(Node::Dir(BTreeMap::new())),
+        }
+    }
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|c| !c.is_empty())
+}
+
+// "a/b/c" -> ("a/b", "c")
+fn split_parent(path: &str) -> Result<(&str, &str), FsError> {
+    let path = path.trim_matches('/');
+    match path.rsplit_once('/') {
+        Some((parent, name)) => Ok((parent, name)),
+        None if !path.is_empty() => Ok(("", path)),
+        None => Err(FsError::NotFound),
+    }
+}
+
+fn find<'a>(root: &'a mut Node, path: &str) -> Result<&'a mut Node, FsError> {
+    let mut node = root;
+
+    for component in components(path) {
+        node = match node {
+            Node::Dir(children) => children.get_mut(component).ok_or(FsError::NotFound)?,
+            Node::File(_) => return Err(FsError::NotADirectory),
+        };
+    }
+
+    Ok(node)
+}
+
+fn find_dir<'a>(root: &'a mut Node, path: &str) -> Result<&'a mut BTreeMap<String, Node>, FsError> {
+    match find(root, path)? {
+        Node::Dir(children) => Ok(children),
+        Node::File(_) => Err(FsError::NotADirectory),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Handles
+// ----------------------------------------------------------------------------
+
+// Looks its node up again on every call, so a removed file just reports
+// NotFound instead of leaving a dangling handle
+struct RamFile {
+    fs: &'static RamFs,
+    path: String,
+    position: usize,
+}
+
+impl RamFile {
+    fn with_data<R>(&self, f: impl FnOnce(&mut Vec<u8>) -> R) -> Result<R, FsError> {
+        let mut root = self.fs.root.lock();
+        match find(&mut root, &self.path)? {
+            Node::File(data) => Ok(f(data)),
+            Node::Dir(_) => Err(FsError::NotAFile),
+        }
+    }
+}
+
+impl File for RamFile {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let position = self.position;
+        let count = self.with_data(|data| {
+            let available = data.len().saturating_sub(position);
+            let count = available.min(buffer.len());
+            buffer[..count].copy_from_slice(&data[position..position + count]);
+            count
+        })?;
+
+        self.position += count;
+        Ok(count)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, FsError> {
+        let position = self.position;
+        self.with_data(|data| {
+            let end = position + buffer.len();
+            if data.len() < end {
+                data.resize(end, 0);
+            }
+            data[position..end].copy_from_slice(buffer);
+        })?;
+
+        self.position += buffer.len();
+        Ok(buffer.len())
+    }
+
+    // Seeking past the end is allowed, the gap is zero-filled on the next write
+    fn seek(&mut self, position: u64) -> Result<u64, FsError> {
+        self.position = position as usize;
+        Ok(position)
+    }
+
+    fn size(&self) -> Option<u64> {
+        self.with_data(|data| data.len() as u64).ok()
+    }
+}
+
+// Listing is copied on open so the tree is not locked while it is read
+struct RamDir {
+    entries: Vec<DirEntry>,
+    next: usize,
+}
+
+impl Dir for RamDir {
+    fn next_entry(&mut self) -> Result<Option<DirEntry>, FsError> {
+        let entry = self.entries.get(self.next).cloned();
+        self.next += 1;
+        Ok(entry)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// FileSystem
+// ----------------------------------------------------------------------------
+
+impl FileSystem for RamFs {
+    fn name(&self) -> &'static str {
+        "ramfs"
+    }
+
+    fn open(&'static self, path: &str) -> Result<Box<dyn File>, FsError> {
+        if let Node::Dir(_) = find(&mut self.root.lock(), path)? {
+            return Err(FsError::NotAFile);
+        }
+
+        Ok(Box::new(RamFile {
+            fs: self,
+            path: path.to_string(),
+            position: 0,
+        }))
+    }
+
+    fn open_dir(&'static self, path: &str) -> Result<Box<dyn Dir>, FsError> {
+        let mut root = self.root.lock();
+        let entries = find_dir(&mut root, path)?
+            .iter()
+            .map(|(name, node)| match node {
+                Node::File(data) => DirEntry {
+                    name: name.clone(),
+                    kind: NodeKind::File,
+                    size: data.len() as u64,
+                },
+                Node::Dir(_) => DirEntry {
+                    name: name.clone(),
+                    kind: NodeKind::Directory,
+                    size: 0,
+                },
+            })
+            .collect();
+
+        Ok(Box::new(RamDir { entries, next: 0 }))
+    }
+
+    fn create(&'static self, path: &str) -> Result<Box<dyn File>, FsError> {
+        let (parent, name) = split_parent(path)?;
+
+        {
+            let mut root = self.root.lock();
+            let children = find_dir(&mut root, parent)?;
+
+            match children.get_mut(name) {
+                Some(Node::Dir(_)) => return Err(FsError::NotAFile),
+                Some(Node::File(data)) => data.clear(),
+                None => {
+                    children.insert(name.to_string(), Node::File(Vec::new()));
+                }
+            }
+        }
+
+        self.open(path)
+    }
+
+    fn mkdir(&'static self, path: &str) -> Result<(), FsError> {
+        let (parent, name) = split_parent(path)?;
+        let mut root = self.root.lock();
+        let children = find_dir(&mut root, parent)?;
+
+        if children.contains_key(name) {
+            return Err(FsError::AlreadyExists);
+        }
+
+        children.insert(name.to_string(), Node::Dir(BTreeMap::new()));
+        Ok(())
+    }
+
+    fn remove(&'static self, path: &str) -> Result<(), FsError> {
+        let (parent, name) = split_parent(path)?;
+        let mut root = self.root.lock();
+        let children = find_dir(&mut root, parent)?;
+
+        match children.get(name) {
+            None => return Err(FsError::NotFound),
+            Some(Node::Dir(grandchildren)) if !grandchildren.is_empty() => {
+                return Err(FsError::NotEmpty);
+            }
+            Some(_) => {}
+        }
+
+        children.remove(name);
+        Ok(())
+    }
+}
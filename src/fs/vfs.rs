@@ -0,0 +1,285 @@
+use super::FsError;
+use crate::utils::events::{self, Event};
+use crate::utils::locked::SpinLock;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+// ============================================================================
+// VIRTUAL FILE SYSTEM
+// One path namespace over every mounted filesystem. The mount table maps a
+// directory ("/boot", "/dev") to a FileSystem, lookups pick the longest
+// matching mount point and hand the rest of the path to that filesystem.
+//
+// Filesystems are leaked into &'static on mount. Open File/Dir handles point
+// straight at them, which keeps handles simple and avoids Arc (refcounts need
+// atomic read-modify-write, which the RPi5 cannot do with the MMU off).
+// ============================================================================
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    File,
+    Directory,
+    Device,
+}
+
+#[derive(Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub kind: NodeKind,
+    pub size: u64,
+}
+
+pub trait File: Send {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, FsError>;
+
+    fn write(&mut self, _buffer: &[u8]) -> Result<usize, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    // Move the read/write position, returns the new position
+    fn seek(&mut self, _position: u64) -> Result<u64, FsError> {
+        Err(FsError::Unsupported)
+    }
+
+    // None for devices and other streams without a length
+    fn size(&self) -> Option<u64>;
+}
+
+pub trait Dir: Send {
+    // Ok(None) once every entry has been returned
+    fn next_entry(&mut self) -> Result<Option<DirEntry>, FsError>;
+}
+
+// Paths passed in are relative to the mount point, "" is its root.
+// Read-only filesystems only implement the first three.
+pub trait FileSystem: Sync {
+    fn name(&self) -> &'static str;
+    fn open(&'static self, path: &str) -> Result<Box<dyn File>, FsError>;
+    fn open_dir(&'static self, path: &str) -> Result<Box<dyn Dir>, FsError>;
+
+    // Create an empty file, or truncate an existing one
+    fn create(&'static self, _path: &str) -> Result<Box<dyn File>, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn mkdir(&'static self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    // Files, or directories that are already empty
+    fn remove(&'static self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+}
+
+struct Mount {
+    path: String,
+    fs: &'static dyn FileSystem,
+}
+
+static MOUNTS: SpinLock<Vec<Mount>> = SpinLock::new(Vec::new());
+
+// ----------------------------------------------------------------------------
+// Paths
+// ----------------------------------------------------------------------------
+
+// "/boot//./x/../config.txt" -> "/boot/config.txt"
+pub fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            name => parts.push(name),
+        }
+    }
+
+    let mut normalized = String::from("/");
+    normalized.push_str(&parts.join("/"));
+    normalized
+}
+
+// Is `path` equal to, or inside, directory `dir`? Returns the part below it.
+fn strip_dir<'a>(path: &'a str, dir: &str) -> Option<&'a str> {
+    if dir == "/" {
+        return Some(path.trim_start_matches('/'));
+    }
+
+    let rest = path.strip_prefix(dir)?;
+    match rest {
+        "" => Some(""),
+        _ => rest.strip_prefix('/'),
+    }
+}
+
+// Filesystem responsible for `path`, plus the path relative to its mount point
+fn resolve(path: &str) -> Result<(&'static dyn FileSystem, String), FsError> {
+    let mounts = MOUNTS.lock();
+
+    mounts
+        .iter()
+        .filter_map(|mount| strip_dir(path, &mount.path).map(|rest| (mount, rest)))
+        .max_by_key(|(mount, _)| mount.path.len())
+        .map(|(mount, rest)| (mount.fs, rest.to_string()))
+        .ok_or(FsError::NotFound)
+}
+
+// Mount points directly below `dir`, shown as directories when listing it
+fn child_mounts(dir: &str) -> Vec<DirEntry> {
+    let mounts = MOUNTS.lock();
+
+    mounts
+        .iter()
+        .filter_map(|mount| strip_dir(&mount.path, dir))
+        .filter(|rest| !rest.is_empty() && !rest.contains('/'))
+        .map(|name| DirEntry {
+            name: name.to_string(),
+            kind: NodeKind::Directory,
+            size: 0,
+        })
+        .collect()
+}
+
+// ----------------------------------------------------------------------------
+// Mount table
+// ----------------------------------------------------------------------------
+
+pub fn mount(path: &str, fs: Box<dyn FileSystem>) -> Result<(), FsError> {
+    let path = normalize(path);
+    let mut mounts = MOUNTS.lock();
+
+    if mounts.iter().any(|mount| mount.path == path) {
+        return Err(FsError::Busy);
+    }
+
+    let fs: &'static dyn FileSystem = Box::leak(fs);
+    mounts.push(Mount {
+        path: path.clone(),
+        fs,
+    });
+    drop(mounts);
+
+    events::publish(Event::Mounted {
+        path,
+        fs: fs.name(),
+    });
+    Ok(())
+}
+
+// The filesystem itself stays allocated, open handles may still point at it
+pub fn unmount(path: &str) -> Result<(), FsError> {
+    let path = normalize(path);
+    let mut mounts = MOUNTS.lock();
+
+    let index = mounts
+        .iter()
+        .position(|mount| mount.path == path)
+        .ok_or(FsError::NotFound)?;
+    mounts.remove(index);
+    drop(mounts);
+
+    events::publish(Event::Unmounted { path });
+    Ok(())
+}
+
+// (mount point, filesystem name) for every mount
+pub fn mounts() -> Vec<(String, &'static str)> {
+    MOUNTS
+        .lock()
+        .iter()
+        .map(|mount| (mount.path.clone(), mount.fs.name()))
+        .collect()
+}
+
+// ----------------------------------------------------------------------------
+// Files and directories
+// ----------------------------------------------------------------------------
+
+pub fn open(path: &str) -> Result<Box<dyn File>, FsError> {
+    let path = normalize(path);
+    let (fs, rest) = resolve(&path)?;
+    fs.open(&rest)
+}
+
+pub fn create(path: &str) -> Result<Box<dyn File>, FsError> {
+    let path = normalize(path);
+    let (fs, rest) = resolve(&path)?;
+    fs.create(&rest)
+}
+
+pub fn mkdir(path: &str) -> Result<(), FsError> {
+    let path = normalize(path);
+    let (fs, rest) = resolve(&path)?;
+
+    match rest.as_str() {
+        "" => Err(FsError::AlreadyExists),
+        _ => fs.mkdir(&rest),
+    }
+}
+
+pub fn remove(path: &str) -> Result<(), FsError> {
+    let path = normalize(path);
+    let (fs, rest) = resolve(&path)?;
+
+    // The root of a mount goes away with unmount, not rm
+    match rest.as_str() {
+        "" => Err(FsError::Busy),
+        _ => fs.remove(&rest),
+    }
+}
+
+pub fn read_dir(path: &str) -> Result<Vec<DirEntry>, FsError> {
+    let path = normalize(path);
+    let mut entries = Vec::new();
+
+    match resolve(&path).and_then(|(fs, rest)| fs.open_dir(&rest)) {
+        Ok(mut dir) => {
+            while let Some(entry) = dir.next_entry()? {
+                entries.push(entry);
+            }
+        }
+        // A directory that only exists because something is mounted below it
+        Err(FsError::NotFound) if !child_mounts(&path).is_empty() => {}
+        Err(err) => return Err(err),
+    }
+
+    for mount in child_mounts(&path) {
+        if !entries.iter().any(|entry| entry.name == mount.name) {
+            entries.push(mount);
+        }
+    }
+
+    Ok(entries)
+}
+
+// Create (or truncate) `path` and write all of `data` to it
+pub fn write_all(path: &str, data: &[u8]) -> Result<(), FsError> {
+    let mut file = create(path)?;
+    let mut written = 0;
+
+    while written < data.len() {
+        match file.write(&data[written..])? {
+            0 => return Err(FsError::Io),
+            count => written += count,
+        }
+    }
+
+    Ok(())
+}
+
+pub fn read_to_vec(path: &str) -> Result<Vec<u8>, FsError> {
+    let mut file = open(path)?;
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 512];
+
+    loop {
+        match file.read(&mut chunk)? {
+            0 => return Ok(data),
+            count => data.extend_from_slice(&chunk[..count]),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use crate::drivers::timer;
+use crate::utils::log;
+use alloc::string::{String, ToString};
+use core::fmt;
+
+// ============================================================================
+// INIT CALLS
+// Drivers and services set themselves up from here instead of from _main.
+// Each registers an init function where it is defined:
+//
+//     initcall!(Device, "rtc", init);
+//
+// which puts an InitCall into the .initcalls section (link.ld). _main runs
+// the stages in order with run(), and every call of a stage in link order,
+// so within a stage nothing may rely on another call having run first:
+//
+//     Core     services the rest builds on: /dev and /tmp
+//     Device   hardware: SD card (and /boot on it), RTC
+//     Late     once everything else is up: the watchdog
+//
+// Each call reports a line and each stage a summary. Absent means the board
+// does not have the thing, which is not a failure.
+// ============================================================================
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    Core,
+    Device,
+    Late,
+}
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Core => "core",
+            Stage::Device => "device",
+            Stage::Late => "late",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum InitError {
+    // Not on this board, with why
+    Absent(&'static str),
+    Failed(String),
+}
+
+impl InitError {
+    pub fn failed(err: impl fmt::Display) -> InitError {
+        InitError::Failed(err.to_string())
+    }
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InitError::Absent(reason) => write!(f, "absent ({})", reason),
+            InitError::Failed(reason) => write!(f, "failed: {}", reason),
+        }
+    }
+}
+
+pub struct InitCall {
+    pub name: &'static str,
+    pub stage: Stage,
+    pub init: fn() -> Result<(), InitError>,
+}
+
+// Register `init` to run in `stage`, e.g. initcall!(Device, "rtc", init)
+macro_rules! initcall {
+    ($stage:ident, $name:literal, $init:path) => {
+        const _: () = {
+            #[used]
+            #[unsafe(link_section = ".initcalls")]
+            static INITCALL: $crate::initcall::InitCall = $crate::initcall::InitCall {
+                name: $name,
+                stage: $crate::initcall::Stage::$stage,
+                init: $init,
+            };
+        };
+    };
+}
+
+pub(crate) use initcall;
+
+unsafe extern "C" {
+    static __initcalls_start: u8;
+    static __initcalls_end: u8;
+}
+
+// Every registered call, in link order
+pub fn all() -> &'static [InitCall] {
+    unsafe {
+        let start = &raw const __initcalls_start as *const InitCall;
+        let end = &raw const __initcalls_end as *const InitCall;
+        core::slice::from_raw_parts(start, end.offset_from(start) as usize)
+    }
+}
+
+// Run the calls of `stage`, returns how many failed
+pub fn run(stage: Stage) -> usize {
+    let (mut ok, mut absent, mut failed) = (0, 0, 0);
+
+    for call in all().iter().filter(|call| call.stage == stage) {
+        let start = timer::uptime_ms();
+        let result = (call.init)();
+        let elapsed = timer::uptime_ms() - start;
+
+        match result {
+            Ok(()) => {
+                ok += 1;
+                log::debug!("init {}: ok ({} ms)", call.name, elapsed);
+            }
+            Err(err @ InitError::Absent(_)) => {
+                absent += 1;
+                log::info!("init {}: {}", call.name, err);
+            }
+            Err(err) => {
+                failed += 1;
+                log::warn!("init {}: {}", call.name, err);
+            }
+        }
+    }
+
+    log::debug!(
+        "init stage {}: {} ok, {} absent, {} failed",
+        stage.name(),
+        ok,
+        absent,
+        failed
+    );
+    failed
+}
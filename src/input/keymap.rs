@@ -0,0 +1,90 @@
+use super::KeyCode;
+
+// ============================================================================
+// KEYMAPS
+// A layout lists, per HID usage id, the character a key types without and
+// with Shift. Keys that type nothing (Enter, arrows, ...) are not listed;
+// the shell handles those by KeyCode.
+// ============================================================================
+
+pub struct Layout {
+    // (usage id, plain, shifted)
+    pub keys: &'static [(u8, char, char)],
+}
+
+pub static US: Layout = Layout {
+    keys: &[
+        (0x04, 'a', 'A'),
+        (0x05, 'b', 'B'),
+        (0x06, 'c', 'C'),
+        (0x07, 'd', 'D'),
+        (0x08, 'e', 'E'),
+        (0x09, 'f', 'F'),
+        (0x0A, 'g', 'G'),
+        (0x0B, 'h', 'H'),
+        (0x0C, 'i', 'I'),
+        (0x0D, 'j', 'J'),
+        (0x0E, 'k', 'K'),
+        (0x0F, 'l', 'L'),
+        (0x10, 'm', 'M'),
+        (0x11, 'n', 'N'),
+        (0x12, 'o', 'O'),
+        (0x13, 'p', 'P'),
+        (0x14, 'q', 'Q'),
+        (0x15, 'r', 'R'),
+        (0x16, 's', 'S'),
+        (0x17, 't', 'T'),
+        (0x18, 'u', 'U'),
+        (0x19, 'v', 'V'),
+        (0x1A, 'w', 'W'),
+        (0x1B, 'x', 'X'),
+        (0x1C, 'y', 'Y'),
+        (0x1D, 'z', 'Z'),
+        (0x1E, '1', '!'),
+        (0x1F, '2', '@'),
+        (0x20, '3', '#'),
+        (0x21, '4', '$'),
+        (0x22, '5', '%'),
+        (0x23, '6', '^'),
+        (0x24, '7', '&'),
+        (0x25, '8', '*'),
+        (0x26, '9', '('),
+        (0x27, '0', ')'),
+        (0x2C, ' ', ' '),
+        (0x2D, '-', '_'),
+        (0x2E, '=', '+'),
+        (0x2F, '[', '{'),
+        (0x30, ']', '}'),
+        (0x31, '\\', '|'),
+        (0x33, ';', ':'),
+        (0x34, '\'', '"'),
+        (0x35, '`', '~'),
+        (0x36, ',', '<'),
+        (0x37, '.', '>'),
+        (0x38, '/', '?'),
+    ],
+};
+
+// US is the only layout so far; a second one would make this switchable
+pub fn layout() -> &'static Layout {
+    &US
+}
+
+pub fn to_char(code: KeyCode, shift: bool) -> Option<char> {
+    let &(_, plain, shifted) = layout().keys.iter().find(|key| key.0 == code.0)?;
+    Some(if shift { shifted } else { plain })
+}
+
+// The key (and whether Shift is needed) that types `c`, for sources such
+// as a serial terminal that only deliver characters
+pub fn from_char(c: char) -> Option<(KeyCode, bool)> {
+    layout().keys.iter().find_map(|&(usage, plain, shifted)| {
+        if c == plain {
+            Some((KeyCode(usage), false))
+        } else if c == shifted {
+            Some((KeyCode(usage), true))
+        } else {
+            None
+        }
+    })
+}
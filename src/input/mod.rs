@@ -0,0 +1,227 @@
+pub mod keymap;
+pub mod serial;
+
+use crate::drivers::timer;
+use crate::utils::events::{self, Event};
+use crate::utils::locked::SpinLock;
+
+// ============================================================================
+// INPUT EVENTS
+// Every keyboard-like source (the UART today, a USB keyboard later) turns
+// what it receives into KeyEvents and pushes them onto one queue; the shell
+// only ever reads that queue. Key codes are USB HID usage ids (page 0x07),
+// so a HID keyboard can pass them straight through, and keymap.rs maps them
+// to characters for a layout.
+//
+// Sources have no interrupts to run from, so each registers a poll function
+// and poll() runs them all before looking at the queue.
+//
+// poll() also notices when nobody has typed for the idle timeout and says so
+// on the event bus (Event::InputIdle, then Event::InputActive with the next
+// key), so housekeeping can run while nobody is waiting for it.
+// ============================================================================
+
+pub const QUEUE_SIZE: usize = 32;
+pub const MAX_SOURCES: usize = 4;
+
+// Default idle timeout, None never reports idle
+pub const IDLE_TIMEOUT_MS: Option<u64> = Some(60_000);
+
+// USB HID usage id of a key, independent of the layout
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyCode(pub u8);
+
+impl KeyCode {
+    pub const A: KeyCode = KeyCode(0x04);
+    pub const ENTER: KeyCode = KeyCode(0x28);
+    pub const ESCAPE: KeyCode = KeyCode(0x29);
+    pub const BACKSPACE: KeyCode = KeyCode(0x2A);
+    pub const TAB: KeyCode = KeyCode(0x2B);
+    pub const HOME: KeyCode = KeyCode(0x4A);
+    pub const PAGE_UP: KeyCode = KeyCode(0x4B);
+    pub const DELETE: KeyCode = KeyCode(0x4C);
+    pub const END: KeyCode = KeyCode(0x4D);
+    pub const PAGE_DOWN: KeyCode = KeyCode(0x4E);
+    pub const RIGHT: KeyCode = KeyCode(0x4F);
+    pub const LEFT: KeyCode = KeyCode(0x50);
+    pub const DOWN: KeyCode = KeyCode(0x51);
+    pub const UP: KeyCode = KeyCode(0x52);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Modifiers(pub u8);
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers(0);
+    pub const CTRL: Modifiers = Modifiers(1 << 0);
+    pub const SHIFT: Modifiers = Modifiers(1 << 1);
+    pub const ALT: Modifiers = Modifiers(1 << 2);
+
+    pub fn contains(self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn with(self, other: Modifiers) -> Modifiers {
+        Modifiers(self.0 | other.0)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub pressed: bool,
+    pub modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    pub fn press(code: KeyCode, modifiers: Modifiers) -> KeyEvent {
+        KeyEvent {
+            code,
+            pressed: true,
+            modifiers,
+        }
+    }
+
+    pub fn release(code: KeyCode, modifiers: Modifiers) -> KeyEvent {
+        KeyEvent {
+            code,
+            pressed: false,
+            modifiers,
+        }
+    }
+
+    // Printable character this key types in the current layout, if any.
+    // None with Ctrl or Alt held, those are shortcuts.
+    pub fn char(&self) -> Option<char> {
+        if self.modifiers.contains(Modifiers::CTRL) || self.modifiers.contains(Modifiers::ALT) {
+            return None;
+        }
+        keymap::to_char(self.code, self.modifiers.contains(Modifiers::SHIFT))
+    }
+}
+
+struct Queue {
+    events: [Option<KeyEvent>; QUEUE_SIZE],
+    // Next slot to read, and how many are waiting from there on
+    head: usize,
+    len: usize,
+}
+
+static QUEUE: SpinLock<Queue> = SpinLock::new(Queue {
+    events: [None; QUEUE_SIZE],
+    head: 0,
+    len: 0,
+});
+
+// Reads whatever the device has and push()es the events
+pub type Source = fn();
+
+static SOURCES: SpinLock<[Option<Source>; MAX_SOURCES]> = SpinLock::new([None; MAX_SOURCES]);
+
+// False when the table is full
+pub fn add_source(poll: Source) -> bool {
+    let mut sources = SOURCES.lock();
+
+    match sources.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(poll);
+            true
+        }
+        None => false,
+    }
+}
+
+struct Idle {
+    timeout_ms: Option<u64>,
+    last_input_ms: u64,
+    // InputIdle went out and the next key has not come yet
+    reported: bool,
+}
+
+static IDLE: SpinLock<Idle> = SpinLock::new(Idle {
+    timeout_ms: IDLE_TIMEOUT_MS,
+    last_input_ms: 0,
+    reported: false,
+});
+
+pub fn idle_timeout() -> Option<u64> {
+    IDLE.lock().timeout_ms
+}
+
+// Also starts the wait over, so changing it never reports idle right away
+pub fn set_idle_timeout(timeout_ms: Option<u64>) {
+    let mut idle = IDLE.lock();
+    idle.timeout_ms = timeout_ms;
+    idle.last_input_ms = timer::uptime_ms();
+}
+
+// Queue an event. When nobody reads for a while the oldest ones are
+// dropped: what was typed last matters most.
+pub fn push(event: KeyEvent) {
+    let was_idle = {
+        let mut idle = IDLE.lock();
+        idle.last_input_ms = timer::uptime_ms();
+        core::mem::replace(&mut idle.reported, false)
+    };
+    if was_idle {
+        events::publish(Event::InputActive);
+    }
+
+    let mut queue = QUEUE.lock();
+
+    if queue.len == QUEUE_SIZE {
+        queue.head = (queue.head + 1) % QUEUE_SIZE;
+        queue.len -= 1;
+    }
+    let tail = (queue.head + queue.len) % QUEUE_SIZE;
+    queue.events[tail] = Some(event);
+    queue.len += 1;
+}
+
+fn pop() -> Option<KeyEvent> {
+    let mut queue = QUEUE.lock();
+    if queue.len == 0 {
+        return None;
+    }
+
+    let head = queue.head;
+    queue.head = (head + 1) % QUEUE_SIZE;
+    queue.len -= 1;
+    queue.events[head].take()
+}
+
+// Let every source look for new input, then hand out the oldest event
+pub fn poll() -> Option<KeyEvent> {
+    let sources = *SOURCES.lock();
+    for source in sources.iter().flatten() {
+        source();
+    }
+
+    let event = pop();
+    if event.is_none() {
+        check_idle();
+    }
+    event
+}
+
+fn check_idle() {
+    let idle_ms = {
+        let mut idle = IDLE.lock();
+        let idle_ms = timer::uptime_ms() - idle.last_input_ms;
+
+        match idle.timeout_ms {
+            Some(timeout_ms) if !idle.reported && idle_ms >= timeout_ms => {
+                idle.reported = true;
+                idle_ms
+            }
+            _ => return,
+        }
+    };
+
+    events::publish(Event::InputIdle { idle_ms });
+}
+
+// The serial console is always there
+pub fn init() {
+    add_source(serial::poll);
+}
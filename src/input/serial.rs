@@ -0,0 +1,118 @@
+use super::{KeyCode, KeyEvent, Modifiers, keymap, push};
+use crate::drivers::console::CONSOLE;
+use crate::drivers::timer;
+use crate::utils::locked::SpinLock;
+
+// ============================================================================
+// SERIAL TERMINAL INPUT
+// A terminal sends characters, not keys: control characters for Ctrl+letter,
+// and ANSI escape sequences (ESC [ A for Up, ESC [ 3 ~ for Delete, ...) for
+// keys without one. This source turns them back into key presses, each
+// followed by its release since a terminal never reports those.
+//
+// ESC on its own is the Escape key, but only once ESCAPE_TIMEOUT_MS passes
+// without the rest of a sequence; ESC followed by a plain character is how
+// terminals send Alt+character.
+// ============================================================================
+
+const ESC: u8 = 0x1B;
+const ESCAPE_TIMEOUT_MS: u64 = 30;
+
+#[derive(Clone, Copy)]
+enum Decode {
+    Ground,
+    // ESC seen, at this uptime
+    Escape(u64),
+    // ESC [ seen, with the first numeric parameter so far. Later ones (the
+    // modifier in ESC [ 1 ; 5 A) are skipped, `skipping` once past a ';'.
+    Csi { parameter: u32, skipping: bool },
+}
+
+static STATE: SpinLock<Decode> = SpinLock::new(Decode::Ground);
+
+fn tap(code: KeyCode, modifiers: Modifiers) {
+    push(KeyEvent::press(code, modifiers));
+    push(KeyEvent::release(code, modifiers));
+}
+
+fn character(byte: u8, modifiers: Modifiers) {
+    let (code, extra) = match byte {
+        b'\r' | b'\n' => (KeyCode::ENTER, Modifiers::NONE),
+        0x7F | 0x08 => (KeyCode::BACKSPACE, Modifiers::NONE),
+        b'\t' => (KeyCode::TAB, Modifiers::NONE),
+        // Ctrl+A is 0x01 up to Ctrl+Z at 0x1A
+        0x01..=0x1A => (KeyCode(KeyCode::A.0 + byte - 1), Modifiers::CTRL),
+        _ => match keymap::from_char(byte as char) {
+            Some((code, true)) => (code, Modifiers::SHIFT),
+            Some((code, false)) => (code, Modifiers::NONE),
+            // Not on the current layout
+            None => return,
+        },
+    };
+
+    tap(code, modifiers.with(extra));
+}
+
+// Final byte of ESC [ ... sequences
+fn csi(byte: u8, parameter: u32) {
+    let code = match (byte, parameter) {
+        (b'A', _) => KeyCode::UP,
+        (b'B', _) => KeyCode::DOWN,
+        (b'C', _) => KeyCode::RIGHT,
+        (b'D', _) => KeyCode::LEFT,
+        (b'H', _) | (b'~', 1 | 7) => KeyCode::HOME,
+        (b'F', _) | (b'~', 4 | 8) => KeyCode::END,
+        (b'~', 3) => KeyCode::DELETE,
+        (b'~', 5) => KeyCode::PAGE_UP,
+        (b'~', 6) => KeyCode::PAGE_DOWN,
+        // Function keys and the like are dropped
+        _ => return,
+    };
+
+    tap(code, Modifiers::NONE);
+}
+
+pub fn poll() {
+    let mut state = STATE.lock();
+
+    loop {
+        let Some(byte) = CONSOLE.lock().try_read_byte() else {
+            if let Decode::Escape(since) = *state
+                && timer::uptime_ms() - since >= ESCAPE_TIMEOUT_MS
+            {
+                tap(KeyCode::ESCAPE, Modifiers::NONE);
+                *state = Decode::Ground;
+            }
+            return;
+        };
+
+        *state = match (*state, byte) {
+            (Decode::Ground, ESC) => Decode::Escape(timer::uptime_ms()),
+            (Decode::Ground, byte) => {
+                character(byte, Modifiers::NONE);
+                Decode::Ground
+            }
+            (Decode::Escape(_), b'[') => Decode::Csi {
+                parameter: 0,
+                skipping: false,
+            },
+            (Decode::Escape(_), byte) => {
+                character(byte, Modifiers::ALT);
+                Decode::Ground
+            }
+            (state @ Decode::Csi { skipping: true, .. }, b'0'..=b'9' | b';') => state,
+            (Decode::Csi { parameter, .. }, b';') => Decode::Csi {
+                parameter,
+                skipping: true,
+            },
+            (Decode::Csi { parameter, .. }, b'0'..=b'9') => Decode::Csi {
+                parameter: parameter.saturating_mul(10) + (byte - b'0') as u32,
+                skipping: false,
+            },
+            (Decode::Csi { parameter, .. }, byte) => {
+                csi(byte, parameter);
+                Decode::Ground
+            }
+        };
+    }
+}
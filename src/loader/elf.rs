@@ -0,0 +1,95 @@
+use super::LoadError;
+use crate::fs::partition::{le16, le32, le64};
+use alloc::vec::Vec;
+
+// ============================================================================
+// ELF64 PARSING
+// Just enough of the format to find the entry point and the PT_LOAD segments
+// of a little-endian AArch64 executable. Section headers are never looked at.
+// ============================================================================
+
+const ELF_MAGIC: &[u8; 4] = b"\x7FELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_AARCH64: u16 = 183;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+const PT_LOAD: u32 = 1;
+
+pub struct Segment {
+    pub offset: usize,
+    pub address: usize,
+    pub file_size: usize,
+    pub mem_size: usize,
+}
+
+pub struct Elf {
+    pub entry: usize,
+    pub segments: Vec<Segment>,
+}
+
+pub fn parse(image: &[u8]) -> Result<Elf, LoadError> {
+    if image.len() < EHDR_SIZE || &image[0..4] != ELF_MAGIC {
+        return Err(LoadError::NotElf);
+    }
+
+    if image[4] != ELFCLASS64 || image[5] != ELFDATA2LSB {
+        return Err(LoadError::Unsupported("not a 64-bit little-endian ELF"));
+    }
+    if le16(image, 18) != EM_AARCH64 {
+        return Err(LoadError::Unsupported("not an AArch64 binary"));
+    }
+    if le16(image, 16) != ET_EXEC {
+        return Err(LoadError::Unsupported("not a static executable"));
+    }
+
+    let entry = le64(image, 24) as usize;
+    let ph_offset = le64(image, 32) as usize;
+    let ph_entry_size = le16(image, 54) as usize;
+    let ph_count = le16(image, 56) as usize;
+
+    if ph_entry_size < PHDR_SIZE {
+        return Err(LoadError::BadSegment);
+    }
+
+    let table_end = ph_count
+        .checked_mul(ph_entry_size)
+        .and_then(|size| size.checked_add(ph_offset))
+        .ok_or(LoadError::BadSegment)?;
+    if table_end > image.len() {
+        return Err(LoadError::BadSegment);
+    }
+
+    let mut segments = Vec::new();
+
+    for index in 0..ph_count {
+        let header = &image[ph_offset + index * ph_entry_size..][..PHDR_SIZE];
+
+        if le32(header, 0) != PT_LOAD {
+            continue;
+        }
+
+        let segment = Segment {
+            offset: le64(header, 8) as usize,
+            address: le64(header, 16) as usize,
+            file_size: le64(header, 32) as usize,
+            mem_size: le64(header, 40) as usize,
+        };
+
+        let file_end = segment.offset.checked_add(segment.file_size);
+        if segment.file_size > segment.mem_size || file_end.is_none_or(|end| end > image.len()) {
+            return Err(LoadError::BadSegment);
+        }
+
+        segments.push(segment);
+    }
+
+    if segments.is_empty() {
+        return Err(LoadError::BadSegment);
+    }
+
+    Ok(Elf { entry, segments })
+}
@@ -0,0 +1,193 @@
+pub mod elf;
+
+use crate::cpu::usermode;
+use crate::fs::{FsError, vfs};
+use crate::memory::config::{PROGRAM_SIZE, PROGRAM_START, USER_STACK_SIZE};
+use crate::syscall;
+use crate::utils::events::{self, Event};
+use alloc::string::String;
+use core::arch::asm;
+use core::fmt;
+use core::ptr;
+
+// ============================================================================
+// PROGRAM LOADER
+// Loads a static AArch64 ELF from the VFS and runs it at EL0. There is no MMU
+// yet, so "mapping" a segment means copying it to its link address, which
+// must fall inside the program window from memory::config. The top
+// USER_STACK_SIZE bytes of the window are the program's stack, the space
+// between the image and the stack is its sbrk heap.
+//
+//     extern "C" fn _start(argc: usize, argv: *const *const u8) -> i32
+//
+// argv holds NUL-terminated strings copied onto the user stack; argv[0] is
+// the path. Returning from _start is the same as calling exit.
+// ============================================================================
+
+#[derive(Clone, Copy, Debug)]
+pub enum LoadError {
+    Fs(FsError),
+    NotElf,
+    Unsupported(&'static str),
+    BadSegment,
+    // Segment or entry point outside the program window
+    OutOfWindow(usize),
+    // Arguments do not fit on the user stack
+    TooManyArgs,
+}
+
+pub struct Image {
+    pub entry: usize,
+    // First byte after the highest segment
+    pub end: usize,
+}
+
+impl From<FsError> for LoadError {
+    fn from(err: FsError) -> Self {
+        LoadError::Fs(err)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Fs(err) => write!(f, "{}", err),
+            LoadError::NotElf => write!(f, "not an ELF file"),
+            LoadError::Unsupported(why) => write!(f, "unsupported: {}", why),
+            LoadError::BadSegment => write!(f, "malformed program headers"),
+            LoadError::OutOfWindow(address) => write!(
+                f,
+                "address {:#x} outside program window {:#x}-{:#x}",
+                address,
+                PROGRAM_START,
+                stack_bottom()
+            ),
+            LoadError::TooManyArgs => write!(f, "arguments do not fit on the user stack"),
+        }
+    }
+}
+
+fn stack_top() -> usize {
+    PROGRAM_START + PROGRAM_SIZE
+}
+
+fn stack_bottom() -> usize {
+    stack_top() - USER_STACK_SIZE
+}
+
+// Inside the window and below the user stack
+fn in_window(start: usize, size: usize) -> bool {
+    start >= PROGRAM_START
+        && start
+            .checked_add(size)
+            .is_some_and(|end| end <= stack_bottom())
+}
+
+// Copy every PT_LOAD segment into place and zero its BSS tail
+pub fn load(image: &[u8]) -> Result<Image, LoadError> {
+    let elf = elf::parse(image)?;
+
+    // Check everything before touching memory, a half-loaded program is useless
+    for segment in &elf.segments {
+        if !in_window(segment.address, segment.mem_size) {
+            return Err(LoadError::OutOfWindow(segment.address));
+        }
+    }
+    if !in_window(elf.entry, 4) {
+        return Err(LoadError::OutOfWindow(elf.entry));
+    }
+
+    for segment in &elf.segments {
+        let destination = segment.address as *mut u8;
+        let source = &image[segment.offset..segment.offset + segment.file_size];
+
+        unsafe {
+            ptr::copy_nonoverlapping(source.as_ptr(), destination, segment.file_size);
+            ptr::write_bytes(
+                destination.add(segment.file_size),
+                0,
+                segment.mem_size - segment.file_size,
+            );
+        }
+    }
+
+    sync_instruction_cache();
+
+    let end = elf
+        .segments
+        .iter()
+        .map(|segment| segment.address + segment.mem_size)
+        .max()
+        .unwrap_or(PROGRAM_START);
+
+    Ok(Image {
+        entry: elf.entry,
+        end,
+    })
+}
+
+// The code we just wrote went through the data side; make sure instruction
+// fetch cannot see stale lines from a previous program
+fn sync_instruction_cache() {
+    unsafe {
+        asm!(
+            "dsb ish",
+            "ic iallu",
+            "dsb ish",
+            "isb",
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+// Lay out argv at the top of the user stack: the strings, then the pointer
+// array (NULL terminated). The array is 16-byte aligned and doubles as the
+// initial stack pointer; returns its address.
+fn push_args(args: &[&str]) -> Result<usize, LoadError> {
+    let strings: usize = args.iter().map(|arg| arg.len() + 1).sum();
+    let pointers = (args.len() + 1) * size_of::<usize>();
+
+    // Leave most of the stack for the program itself
+    if strings + pointers + 16 > USER_STACK_SIZE / 2 {
+        return Err(LoadError::TooManyArgs);
+    }
+
+    let mut cursor = stack_top() - strings;
+    let string_start = cursor;
+    let argv = (string_start - pointers) & !0xF;
+
+    for (i, arg) in args.iter().enumerate() {
+        unsafe {
+            ptr::copy_nonoverlapping(arg.as_ptr(), cursor as *mut u8, arg.len());
+            *((cursor + arg.len()) as *mut u8) = 0;
+            *((argv + i * size_of::<usize>()) as *mut usize) = cursor;
+        }
+        cursor += arg.len() + 1;
+    }
+    unsafe { *((argv + args.len() * size_of::<usize>()) as *mut usize) = 0 };
+
+    Ok(argv)
+}
+
+// Load `path` and run it at EL0 with `args` (argv[0] is the path). Returns
+// the program's exit status.
+pub fn exec(path: &str, args: &[&str]) -> Result<i64, LoadError> {
+    let image = vfs::read_to_vec(path)?;
+    let loaded = load(&image)?;
+    drop(image);
+
+    let mut argv_strings = alloc::vec![path];
+    argv_strings.extend_from_slice(args);
+    let argv = push_args(&argv_strings)?;
+
+    // The sbrk heap starts on the next 16-byte boundary after the image
+    syscall::set_break((loaded.end + 0xF) & !0xF, stack_bottom());
+
+    let status = usermode::run(loaded.entry, argv, argv_strings.len(), argv);
+
+    events::publish(Event::ProgramExited {
+        path: String::from(path),
+        status,
+    });
+    Ok(status)
+}
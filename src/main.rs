@@ -1,69 +1,262 @@
 #![feature(alloc_error_handler)]
 #![no_std]
 #![no_main]
+// `cargo test --features qemu`: #[test_case] functions run in QEMU, see
+// utils/testing.rs
+#![cfg_attr(test, feature(custom_test_frameworks))]
+#![cfg_attr(test, test_runner(crate::utils::testing::runner))]
+#![cfg_attr(test, reexport_test_harness_main = "test_main")]
 
 extern crate alloc;
 use alloc::boxed::Box;
 use alloc::vec::Vec;
 
-mod drivers;
-mod hardwareselect;
+mod bootmenu;
+mod buildinfo;
+mod cmdline;
+mod cpu;
+mod devices;
+mod devicetree;
+mod fs;
+mod initcall;
+mod input;
+mod loader;
 mod memory;
+mod power;
+// Only the test build ends QEMU itself, see qemu.rs
+#[cfg(all(test, feature = "qemu"))]
+mod qemu;
+mod rtc;
+#[cfg(feature = "selftest")]
+mod selftest;
+mod shell;
+mod syscall;
 mod utils;
+mod xmodem;
+
+// Drivers, board addresses and locks come from the ddos-drivers library
+use ddos_drivers as drivers;
+use ddos_drivers::hardwareselect;
 
 use core::arch::global_asm;
+use utils::log;
+use utils::testing::{Failure, Suite, check, check_eq};
 global_asm!(include_str!("cpu/boot.s"));
 
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 #[unsafe(no_mangle)]
-pub extern "C" fn _main() -> ! {
-    // 1. Initialize the global hardware UART ONCE at boot
-    drivers::uart::UART.lock().init();
+pub extern "C" fn _main(dtb: usize) -> ! {
+    // Before anything runs deep, so the watermark sees every byte used
+    memory::stack::paint(0);
+
+    // 0. Let the firmware's device tree correct the peripheral addresses
+    devicetree::init(dtb);
+
+    // 1. Initialize the serial console ONCE at boot, on the UART and at the
+    // line settings the command line asks for, or else the PL011 at 115200 8N1
+    if !cmdline::console().is_some_and(drivers::console::select) {
+        drivers::console::CONSOLE.lock().init();
+    }
+    if let Some(settings) = cmdline::line_settings() {
+        // cmdline::apply() complains later if this fails
+        let _ = drivers::uart::UART.lock().set_line_settings(settings);
+    }
+    utils::early::uart_ready();
+
+    utils::early::stage("crash log");
+    utils::crashlog::init();
+    input::init();
 
-    println!("\n[KERNEL] Booting DDOS...");
+    println!(
+        "\n[KERNEL] Booting DDOS {} ({}, built {}, {})...",
+        buildinfo::VERSION,
+        buildinfo::GIT_HASH,
+        buildinfo::BUILD_TIME,
+        buildinfo::FEATURES
+    );
 
+    // 2. No heap until this returns, see utils/early.rs
     memory::init();
+    utils::early::done();
+
+    log::info!(
+        "Heap Initialized: {:#x}-{:#x} ({} KiB, RAM ends at {:#x}).",
+        memory::map::heap_start(),
+        memory::map::heap_end(),
+        (memory::map::heap_end() - memory::map::heap_start()) / 1024,
+        memory::map::ram_end()
+    );
+
+    if drivers::profile::init() {
+        log::info!(
+            "PMU counting cycles{} (`perf` shows the probes).",
+            if drivers::profile::has_events() {
+                ", instructions and cache misses"
+            } else {
+                ""
+            }
+        );
+    } else {
+        log::info!("No PMU, profiling is off.");
+    }
+
+    let (free_pages, _) = memory::frame::stats();
+    log::info!(
+        "{} KiB of page frames free for the heap and others.",
+        free_pages * memory::frame::PAGE_SIZE / 1024
+    );
+
+    if let Some((session, previous)) = utils::crashlog::previous() {
+        log::info!(
+            "Log of boot #{} kept ({} bytes), `lastlog` shows its tail.",
+            session,
+            previous.len()
+        );
+    }
+
+    devicetree::relocate();
+    cmdline::apply();
+    if cpu::gdb::enabled() && cmdline::gdb_wait() {
+        log::info!("gdb=wait: stopping for GDB on the console.");
+        cpu::gdb::breakpoint();
+    }
+
+    utils::events::subscribe(utils::events::log_subscriber);
+    utils::events::subscribe(memory::idle_subscriber);
+
+    // The test build stops here: run the tests and exit QEMU with the result
+    #[cfg(test)]
+    test_main();
+
+    // 3. Drivers and services, each registered where it lives, see initcall.rs
+    initcall::run(initcall::Stage::Core);
+    initcall::run(initcall::Stage::Device);
 
-    println!("[KERNEL] Heap Initialized.");
-    println!("Welcome to DDOS Kernel v0.1");
-    println!("Testing Heap Allocation...");
+    println!("Welcome to DDOS Kernel v{}", buildinfo::VERSION);
 
-    let heap_val = Box::new(42);
+    // Only asks with `bootmenu` on the command line, see bootmenu.rs
+    let choices = bootmenu::run();
 
-    println!("- Box allocated at {:p}, value: {}", heap_val, *heap_val);
+    let mut heap_tests = Suite::new("heap");
+    heap_tests.run("box", test_box);
+    heap_tests.run("vec", test_vec);
+    heap_tests.finish();
+
+    if choices.memtest {
+        memory::memtest::run(usize::MAX);
+    }
+
+    initcall::run(initcall::Stage::Late);
+
+    cpu::smp::start();
+
+    #[cfg(feature = "selftest")]
+    if choices.selftest && !selftest::run() {
+        log::warn!("Self-test failed, see above.");
+    }
 
+    println!("[KERNEL] UART console mode (type `help`)");
+    shell::run();
+}
+
+// Quick checks that the heap works before the shell starts relying on it
+fn test_box() -> Result<(), Failure> {
+    let value = Box::new(42);
+    check_eq!(*value, 42);
+    Ok(())
+}
+
+fn test_vec() -> Result<(), Failure> {
     let mut vec = Vec::new();
     for i in 0..5 {
         vec.push(i);
     }
+    check!(vec.capacity() >= 5);
+    check_eq!(vec, [0, 1, 2, 3, 4]);
+    Ok(())
+}
+
+// QEMU's VideoCore answers the firmware mailbox like the real one does
+#[cfg(test)]
+mod tests {
+    use crate::drivers::mailbox;
+    use crate::drivers::profile::{self, Probe};
+    use crate::utils::testing::{Failure, check, check_eq};
+
+    #[test_case]
+    fn mailbox_arm_memory() -> Result<(), Failure> {
+        let (base, size) = mailbox::arm_memory().unwrap_or((usize::MAX, 0));
+        check_eq!(base, 0);
+        check!(size > 0);
+        Ok(())
+    }
 
-    println!("- Vec allocated: {:?} (Success!)", vec);
-    println!("[KERNEL] UART console mode");
-    print!("\n> ");
+    #[test_case]
+    fn mailbox_board_info() -> Result<(), Failure> {
+        check!(mailbox::board_revision().is_ok_and(|revision| revision != 0));
+        check!(mailbox::clock_rate(mailbox::CLOCK_ARM).is_ok_and(|hz| hz > 0));
+        Ok(())
+    }
 
-    loop {
-        let byte = drivers::uart::UART.lock().read_byte();
+    // QEMU's Cortex-A53 has a PMU; its cycle counter follows the instructions
+    #[test_case]
+    fn probe_counts_calls() -> Result<(), Failure> {
+        static PROBE: Probe = Probe::new("test");
 
-        match byte {
-            b'\r' | b'\n' => {
-                print!("\r\n> ");
-            }
-            127 | 8 => {
-                print!("\x08 \x08");
-            }
-            b' '..=b'~' => {
-                print!("{}", byte as char);
-            }
-            _ => {}
+        let (sum, sample) = profile::Profiler::measure(|| (0..1000u64).sum::<u64>());
+        check_eq!(sum, 499_500);
+        check!(sample.cycles > 0);
+
+        for _ in 0..3 {
+            PROBE.measure(|| core::hint::black_box(1 + 1));
         }
+        check_eq!(PROBE.totals().calls, 3);
+        check!(profile::probes().any(|probe| probe.name() == "test"));
+        Ok(())
     }
 }
 
+// Set on the way into the panic handler, so a fault while it prints (say,
+// walking a smashed stack) does not start another full report
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    let registers = cpu::backtrace::capture();
+
+    if PANICKING.load(Ordering::Relaxed) {
+        println!("\n!!! PANIC WHILE PANICKING: {}", info);
+        drivers::console::flush();
+        loop {}
+    }
+    PANICKING.store(true, Ordering::Relaxed);
+
     // Even the panic handler gets a massive clean up
     println!("\n!!! KERNEL PANIC !!!");
     println!("Details: {}", info);
-    loop {}
+    if let Some(stage) = utils::early::current() {
+        println!("Early boot, stage: {}", stage);
+    }
+
+    println!("Registers at the panic handler:");
+    registers.print();
+    cpu::backtrace::print(registers.fp as usize);
+    drivers::console::flush();
+
+    // A failing `cargo test` must not leave QEMU running
+    #[cfg(all(test, feature = "qemu"))]
+    qemu::exit(101);
+
+    #[cfg(not(all(test, feature = "qemu")))]
+    {
+        if let Some(seconds) = power::PANIC_REBOOT_SECONDS {
+            println!("Rebooting in {} s...", seconds);
+            drivers::console::flush();
+            drivers::timer::delay_ms(seconds * 1000);
+            power::reboot();
+        }
+        loop {}
+    }
 }
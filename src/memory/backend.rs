@@ -0,0 +1,177 @@
+use super::super::drivers::profile;
+use super::super::utils::early;
+use super::super::utils::locked::SpinLock;
+use super::heap::HeapType;
+use super::trace;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ops::Deref;
+use core::ptr::null_mut;
+
+// ============================================================================
+// HEAP BACKENDS
+// Everything the kernel needs from a heap, so the global allocator can be
+// swapped with a feature flag instead of by editing memory/mod.rs:
+//
+//     (default)       slab caches over the FreeList   memory/slab.rs
+//     heap-freelist   the FreeList alone              memory/heap.rs
+//     heap-bump       a pointer that only moves up    memory/bump.rs
+//
+// The bump allocator never frees, which makes it the baseline to measure
+// the other two against. Allocator wraps whichever one is picked and does
+// what they all share: refusing to run before memory::init() and counting
+// the calls it serves.
+// ============================================================================
+
+pub trait Backend: Sync {
+    // Shown by `heapcheck` and in the boot log
+    const NAME: &'static str;
+
+    // Holds nothing until init(); the static starts out as this
+    const EMPTY: Self;
+
+    // Hand over the first block. Runs once, before the first allocation.
+    unsafe fn init(&self, start: usize, size: usize);
+
+    // Null when out of memory
+    fn allocate(&self, layout: Layout) -> *mut u8;
+
+    fn deallocate(&self, ptr: *mut u8, layout: Layout);
+
+    // Backends that can resize in place override this
+    fn reallocate(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        relocate(self, ptr, layout, new_size)
+    }
+
+    // (used bytes, capacity)
+    fn usage(&self) -> (usize, usize);
+
+    // (free blocks, free bytes, largest free block)
+    fn free_stats(&self) -> (usize, usize, usize);
+
+    // Walk whatever the backend keeps and panic with the details if it is
+    // damaged. Returns (free blocks, free bytes).
+    fn check(&self) -> (usize, usize) {
+        let (blocks, free, _) = self.free_stats();
+        (blocks, free)
+    }
+
+    // Give memory outside [keep_start, keep_end) back to the frame
+    // allocator. Returns how many bytes went back.
+    fn trim(&self, _keep_start: usize, _keep_end: usize) -> usize {
+        0
+    }
+
+    // The FreeList's fit, None for backends without a FreeList
+    fn strategy(&self) -> Option<HeapType> {
+        None
+    }
+
+    // False when the backend has no FreeList to switch
+    fn set_strategy(&self, _strategy: HeapType) -> bool {
+        false
+    }
+}
+
+// Reallocate by moving: allocate the new size, copy, free the old block
+pub fn relocate<B: Backend + ?Sized>(
+    backend: &B,
+    ptr: *mut u8,
+    layout: Layout,
+    new_size: usize,
+) -> *mut u8 {
+    let Ok(new_layout) = Layout::from_size_align(new_size, layout.align()) else {
+        return null_mut();
+    };
+
+    let new_ptr = backend.allocate(new_layout);
+    if !new_ptr.is_null() {
+        unsafe { core::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size)) };
+        backend.deallocate(ptr, layout);
+    }
+    new_ptr
+}
+
+// Calls the global allocator has served since boot. Counted for every
+// backend, so heapcheck can show them and a boot-ordering bug shows up as
+// numbers that do not add up rather than as corruption later on.
+#[derive(Clone, Copy)]
+pub struct Counts {
+    pub allocations: usize,
+    pub reallocations: usize,
+    pub frees: usize,
+}
+
+// The global allocator. It needs a type of its own since the backends'
+// SpinLock comes from ddos-drivers and GlobalAlloc from core.
+pub struct Allocator<B: Backend> {
+    backend: B,
+    counts: SpinLock<Counts>,
+}
+
+impl<B: Backend> Allocator<B> {
+    pub const fn new() -> Self {
+        Allocator {
+            backend: B::EMPTY,
+            counts: SpinLock::new(Counts {
+                allocations: 0,
+                reallocations: 0,
+                frees: 0,
+            }),
+        }
+    }
+
+    pub fn counts(&self) -> Counts {
+        *self.counts.lock()
+    }
+}
+
+impl<B: Backend> Deref for Allocator<B> {
+    type Target = B;
+
+    fn deref(&self) -> &B {
+        &self.backend
+    }
+}
+
+// Touching the heap before memory::init() is a bug in the boot order. Say so
+// on the early console, which needs nothing from the heap, and panic,
+// instead of letting the empty backend look like an out-of-memory error.
+fn assert_heap_ready(what: &str, layout: Layout) {
+    if !super::heap_ready() {
+        early::report(format_args!("{} of {:?} before heap init", what, layout));
+        panic!("{} before heap init", what);
+    }
+}
+
+unsafe impl<B: Backend> GlobalAlloc for Allocator<B> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        assert_heap_ready("allocation", layout);
+
+        let ptr = profile!("alloc", self.backend.allocate(layout));
+        if !ptr.is_null() {
+            self.counts.lock().allocations += 1;
+            trace::record(ptr, layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        assert_heap_ready("free", layout);
+
+        profile!("free", self.backend.deallocate(ptr, layout));
+        self.counts.lock().frees += 1;
+        trace::forget(ptr);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        assert_heap_ready("reallocation", layout);
+
+        let new_ptr = profile!("realloc", self.backend.reallocate(ptr, layout, new_size));
+        if !new_ptr.is_null() {
+            self.counts.lock().reallocations += 1;
+            trace::forget(ptr);
+            trace::record(new_ptr, new_size);
+        }
+        new_ptr
+    }
+}
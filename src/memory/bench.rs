@@ -0,0 +1,241 @@
+use super::heap::{FreeList, HeapType};
+use super::{Fragmentation, frame};
+use crate::drivers::timer;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+// ============================================================================
+// HEAP BENCHMARKS
+// Scripted allocation workloads run against a FreeList of each HeapType, to
+// compare the OSTEP fits on equal terms. Every run gets a fresh FreeList over
+// the same block of frames (not the kernel heap, whose state depends on what
+// ran before) and the same pseudo-random sequence, so two runs with the same
+// arguments do the same allocations.
+//
+//     random         allocate or free a random slot, sizes 16 bytes to 2 KiB
+//     queue          producer/consumer: messages allocated in order, the
+//                    oldest half freed whenever the queue fills up
+//     fragmentation  fill the arena with small/large pairs, free the large
+//                    ones, then ask for blocks a little larger than the holes
+//
+// Timing is the generic timer counter around the whole loop, bookkeeping
+// included, reported in counter cycles per operation (the counter runs at
+// timer::frequency(), not the CPU clock).
+// Fragmentation is measured when the workload ends, with its blocks still
+// allocated.
+// ============================================================================
+
+pub const DEFAULT_OPS: usize = 4000;
+pub const DEFAULT_ARENA: usize = 256 * 1024;
+
+const ALIGN: usize = 16;
+const SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+const RANDOM_SLOTS: usize = 256;
+const RANDOM_MAX_SIZE: usize = 2048;
+
+const QUEUE_DEPTH: usize = 128;
+const QUEUE_MIN_SIZE: usize = 64;
+const QUEUE_MAX_SIZE: usize = 512;
+
+const SMALL_SIZE: usize = 48;
+const LARGE_SIZE: usize = 400;
+// Just too big for the hole a freed LARGE_SIZE block leaves
+const MEDIUM_SIZE: usize = 480;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    Random,
+    Queue,
+    Fragmentation,
+}
+
+pub const WORKLOADS: [Workload; 3] = [Workload::Random, Workload::Queue, Workload::Fragmentation];
+
+impl Workload {
+    pub fn name(self) -> &'static str {
+        match self {
+            Workload::Random => "random",
+            Workload::Queue => "queue",
+            Workload::Fragmentation => "fragmentation",
+        }
+    }
+}
+
+pub struct Outcome {
+    // Allocations and frees attempted
+    pub ops: usize,
+    pub failures: usize,
+    pub ticks: u64,
+    pub peak_used: usize,
+    pub fragmentation: Fragmentation,
+}
+
+impl Outcome {
+    pub fn cycles_per_op(&self) -> u64 {
+        self.ticks / self.ops.max(1) as u64
+    }
+}
+
+// xorshift64, the same sequence on every board
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // In [low, high]
+    fn between(&mut self, low: usize, high: usize) -> usize {
+        low + self.next() as usize % (high - low + 1)
+    }
+}
+
+// Counts what a workload does to the list
+struct Run<'a> {
+    list: &'a mut FreeList,
+    ops: usize,
+    failures: usize,
+    peak_used: usize,
+}
+
+impl Run<'_> {
+    fn allocate(&mut self, size: usize) -> Option<usize> {
+        self.ops += 1;
+        let block = self.list.allocate(size, ALIGN).map(|ptr| ptr as usize);
+        if block.is_none() {
+            self.failures += 1;
+        }
+        self.peak_used = self.peak_used.max(self.list.used);
+        block
+    }
+
+    fn free(&mut self, block: usize) {
+        self.ops += 1;
+        if self.list.deallocate(block).is_err() {
+            self.failures += 1;
+        }
+    }
+}
+
+fn random(run: &mut Run, rng: &mut Rng, ops: usize) {
+    let mut slots: Vec<Option<usize>> = Vec::new();
+    slots.resize(RANDOM_SLOTS, None);
+
+    while run.ops < ops {
+        let slot = rng.between(0, RANDOM_SLOTS - 1);
+        slots[slot] = match slots[slot] {
+            Some(block) => {
+                run.free(block);
+                None
+            }
+            None => run.allocate(rng.between(ALIGN, RANDOM_MAX_SIZE)),
+        };
+    }
+}
+
+fn queue(run: &mut Run, rng: &mut Rng, ops: usize) {
+    let mut queue = VecDeque::with_capacity(QUEUE_DEPTH);
+
+    while run.ops < ops {
+        if queue.len() == QUEUE_DEPTH {
+            for _ in 0..QUEUE_DEPTH / 2 {
+                if let Some(block) = queue.pop_front() {
+                    run.free(block);
+                }
+            }
+        }
+        if let Some(block) = run.allocate(rng.between(QUEUE_MIN_SIZE, QUEUE_MAX_SIZE)) {
+            queue.push_back(block);
+        }
+    }
+}
+
+fn fragmentation(run: &mut Run, ops: usize) {
+    let mut large = Vec::with_capacity(ops / 2);
+
+    // Fill up, or stop at a third of the budget
+    while run.ops < ops / 3 {
+        let Some(_) = run.allocate(SMALL_SIZE) else {
+            break;
+        };
+        let Some(block) = run.allocate(LARGE_SIZE) else {
+            break;
+        };
+        large.push(block);
+    }
+
+    for block in large {
+        run.free(block);
+    }
+
+    // None of these fit a hole, only what the fill left at the end
+    while run.ops < ops {
+        run.allocate(MEDIUM_SIZE);
+    }
+}
+
+// One workload over a fresh FreeList on [start, start + size)
+pub fn run(
+    workload: Workload,
+    strategy: HeapType,
+    start: usize,
+    size: usize,
+    ops: usize,
+) -> Outcome {
+    let mut list = unsafe { FreeList::init(start, size, strategy) };
+    let mut rng = Rng(SEED);
+    let mut run = Run {
+        list: &mut list,
+        ops: 0,
+        failures: 0,
+        peak_used: 0,
+    };
+
+    let begin = timer::counter();
+    match workload {
+        Workload::Random => random(&mut run, &mut rng, ops),
+        Workload::Queue => queue(&mut run, &mut rng, ops),
+        Workload::Fragmentation => fragmentation(&mut run, ops),
+    }
+    let ticks = timer::counter() - begin;
+
+    let (ops, failures, peak_used) = (run.ops, run.failures, run.peak_used);
+    let (free_blocks, free_bytes, largest_free) = list.free_stats();
+    Outcome {
+        ops,
+        failures,
+        ticks,
+        peak_used,
+        fragmentation: Fragmentation {
+            free_blocks,
+            free_bytes,
+            largest_free,
+        },
+    }
+}
+
+// Every workload against every fit, on an arena of at least `arena` bytes
+// borrowed from the frame allocator. None when there are not enough frames.
+pub fn run_all(arena: usize, ops: usize) -> Option<Vec<(Workload, HeapType, Outcome)>> {
+    let order = frame::order_for(arena);
+    let start = frame::allocate(order)?;
+    let size = frame::block_size(order);
+
+    let mut outcomes = Vec::with_capacity(WORKLOADS.len() * HeapType::ALL.len());
+    for workload in WORKLOADS {
+        for strategy in HeapType::ALL {
+            outcomes.push((
+                workload,
+                strategy,
+                run(workload, strategy, start, size, ops),
+            ));
+        }
+    }
+
+    frame::free(start, order);
+    Some(outcomes)
+}
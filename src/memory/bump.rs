@@ -0,0 +1,72 @@
+use super::super::utils::locked::SpinLock;
+use super::backend::Backend;
+use super::watermark;
+use core::alloc::Layout;
+use core::ptr::null_mut;
+
+// ============================================================================
+// BUMP ALLOCATOR
+// The simplest heap there is: a pointer into the first block that only moves
+// up. Allocating is an align and an add, freeing does nothing, and the heap
+// never grows, so a long session runs it dry. It is here as the baseline the
+// FreeList and the slab caches are measured against (`--features heap-bump`).
+// ============================================================================
+
+struct Region {
+    start: usize,
+    next: usize,
+    end: usize,
+}
+
+pub struct Bump(SpinLock<Region>);
+
+impl Backend for Bump {
+    const NAME: &'static str = "bump";
+
+    const EMPTY: Self = Bump(SpinLock::new(Region {
+        start: 0,
+        next: 0,
+        end: 0,
+    }));
+
+    unsafe fn init(&self, start: usize, size: usize) {
+        *self.0.lock() = Region {
+            start,
+            next: start,
+            end: start + size,
+        };
+    }
+
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        let mut region = self.0.lock();
+
+        let Some(address) = region
+            .next
+            .checked_next_multiple_of(layout.align())
+            .filter(|&address| address <= region.end && region.end - address >= layout.size())
+        else {
+            return null_mut();
+        };
+        region.next = address + layout.size();
+
+        let (used, capacity) = (region.next - region.start, region.end - region.start);
+        drop(region);
+        watermark::check(used, capacity);
+
+        address as *mut u8
+    }
+
+    fn deallocate(&self, _ptr: *mut u8, _layout: Layout) {}
+
+    fn usage(&self) -> (usize, usize) {
+        let region = self.0.lock();
+        (region.next - region.start, region.end - region.start)
+    }
+
+    // Everything above the pointer is one free block
+    fn free_stats(&self) -> (usize, usize, usize) {
+        let region = self.0.lock();
+        let free = region.end - region.next;
+        (usize::from(free > 0), free, free)
+    }
+}
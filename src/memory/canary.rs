@@ -0,0 +1,151 @@
+use super::super::utils::locked::SpinLock;
+use core::mem::size_of;
+use core::ptr::null_mut;
+
+// ============================================================================
+// DEBUG HEAP GUARDS
+// With config::HEAP_DEBUG on, every allocation is padded like this:
+//
+//     [ Guard: size, prev, next, canary ][ payload ... ][ tail canary ]
+//
+// The guard links the allocation into a list of live blocks, so heap_check()
+// can look at all of them, not just the one being freed. On free both
+// canaries are checked and the whole block is overwritten with 0xDEADBEEF,
+// which makes use-after-free reads stand out in a register dump.
+// ============================================================================
+
+const CANARY: u64 = 0x5AFE_C0DE_5AFE_C0DE;
+const TAIL_BYTE: u8 = 0xCA;
+const POISON: [u8; 4] = 0xDEAD_BEEFu32.to_le_bytes();
+// What a poisoned canary reads back as
+const POISONED: u64 = 0xDEAD_BEEF_DEAD_BEEF;
+
+// Keep the payload 16-byte aligned like every other heap block
+#[repr(C, align(16))]
+struct Guard {
+    size: usize,
+    prev: *mut Guard,
+    next: *mut Guard,
+    canary: u64,
+}
+
+pub const FRONT: usize = size_of::<Guard>();
+pub const TAIL: usize = 16;
+
+struct Live {
+    head: *mut Guard,
+    count: usize,
+}
+
+static LIVE: SpinLock<Live> = SpinLock::new(Live {
+    head: null_mut(),
+    count: 0,
+});
+
+// Block size the FreeList has to provide for a `size` byte allocation
+pub fn padded(size: usize) -> Option<usize> {
+    size.checked_add(FRONT + TAIL)
+}
+
+// Fill in the guard and tail of a fresh block, returns the payload pointer
+pub unsafe fn arm(block: *mut u8, size: usize) -> *mut u8 {
+    let guard = block as *mut Guard;
+    let mut live = LIVE.lock();
+
+    unsafe {
+        guard.write(Guard {
+            size,
+            prev: null_mut(),
+            next: live.head,
+            canary: CANARY,
+        });
+        if !live.head.is_null() {
+            (*live.head).prev = guard;
+        }
+
+        // Byte writes: the tail is not aligned to anything
+        let tail = block.add(FRONT + size);
+        for offset in 0..TAIL {
+            tail.add(offset).write_volatile(TAIL_BYTE);
+        }
+    }
+
+    live.head = guard;
+    live.count += 1;
+    unsafe { block.add(FRONT) }
+}
+
+// Check and unlink the block behind `payload` and poison it. Returns the
+// block pointer to hand back to the FreeList. Panics on a bad canary.
+pub unsafe fn disarm(payload: *mut u8) -> *mut u8 {
+    let block = unsafe { payload.sub(FRONT) };
+    let guard = block as *mut Guard;
+    let mut live = LIVE.lock();
+
+    unsafe {
+        check(guard);
+
+        let Guard {
+            size, prev, next, ..
+        } = guard.read();
+
+        if prev.is_null() {
+            live.head = next;
+        } else {
+            (*prev).next = next;
+        }
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+        live.count -= 1;
+        drop(live);
+
+        for offset in 0..FRONT + size + TAIL {
+            block.add(offset).write_volatile(POISON[offset % 4]);
+        }
+    }
+
+    block
+}
+
+unsafe fn check(guard: *mut Guard) {
+    let payload = guard as usize + FRONT;
+    let (size, canary) = unsafe { ((*guard).size, (*guard).canary) };
+
+    if canary != CANARY {
+        if canary == POISONED {
+            panic!("heap: double free or use after free of {:#x}", payload);
+        }
+        panic!(
+            "heap: block {:#x} front canary is {:#018x}, expected {:#018x} (underrun or bad pointer)",
+            payload, canary, CANARY
+        );
+    }
+
+    for offset in 0..TAIL {
+        let byte = unsafe { (payload as *const u8).add(size + offset).read_volatile() };
+        if byte != TAIL_BYTE {
+            panic!(
+                "heap: block {:#x} ({} bytes) overrun: byte {} past the end is {:#04x}, expected {:#04x}",
+                payload, size, offset, byte, TAIL_BYTE
+            );
+        }
+    }
+}
+
+// Check every live allocation's canaries, returns (allocations, bytes)
+pub fn check_live() -> (usize, usize) {
+    let live = LIVE.lock();
+    let mut guard = live.head;
+    let mut bytes = 0;
+
+    while !guard.is_null() {
+        unsafe {
+            check(guard);
+            bytes += (*guard).size;
+            guard = (*guard).next;
+        }
+    }
+
+    (live.count, bytes)
+}
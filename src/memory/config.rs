@@ -1,7 +1,77 @@
+use ddos_heap::HeapType;
+
 pub const KERNEL_START: usize = 0x80000;
 
-pub const KERNEL_STACK_START: usize = 0x80000;
+// Lowest address the boot stack, growing down from KERNEL_START, may reach.
+// The firmware's armstub and spin tables sit below it.
+pub const STACK_LIMIT: usize = 0x10000;
+
+// Fixed window programs are loaded into (and must be linked for) until there
+// is an MMU to give each one its own address space
+pub const PROGRAM_START: usize = 0x48_0000;
+
+pub const PROGRAM_SIZE: usize = 0x100000;
+
+// Top of the program window, used as the EL0 stack
+pub const USER_STACK_SIZE: usize = 0x10000;
+
+// Stack of each of cores 1-3, from the frame allocator (see cpu/smp.rs)
+pub const CORE_STACK_SIZE: usize = 0x10000;
+
+// Log ring kept just below the program window, where nothing else ever
+// writes, so a warm reboot finds the last session's output still there
+// (see utils/crashlog.rs)
+pub const CRASH_LOG_SIZE: usize = 0x4000;
+
+pub const CRASH_LOG_START: usize = PROGRAM_START - CRASH_LOG_SIZE;
+
+// RAM after the kernel image and the program window, whichever ends last,
+// belongs to the frame allocator. The heap starts with one block of about
+// 1/HEAP_RAM_SHARE of it (rounded down to a power of two, never less than
+// HEAP_MIN_SIZE) and takes at least HEAP_GROW_SIZE more whenever it runs out.
+pub const HEAP_MIN_SIZE: usize = 0x200000;
+
+pub const HEAP_RAM_SHARE: usize = 4;
+
+pub const HEAP_GROW_SIZE: usize = 0x100000;
+
+// How the FreeList picks a free block, unless `heap.strategy=` on the kernel
+// command line says otherwise. The bump heap ignores it.
+pub const HEAP_STRATEGY: HeapType = HeapType::BestFit;
+
+// Dedicated pools next to the general heap (see memory/pool.rs). The DMA
+// pool comes from the frame allocator, the graphics pool off the top of RAM,
+// below the VideoCore's share. Neither grows.
+pub const DMA_POOL_SIZE: usize = 0x10_0000;
+
+pub const GRAPHICS_POOL_SIZE: usize = 0x80_0000;
+
+// Debug heap: canaries around every allocation, checked on free, and freed
+// memory poisoned with 0xDEADBEEF (see memory/canary.rs). Costs 48 bytes per
+// allocation and turns the slab caches off. The bump heap ignores it.
+pub const HEAP_DEBUG: bool = false;
+
+// Heap tracing from boot on: every live allocation with its size and call
+// chain in a side table of HEAP_TRACE_SLOTS entries, for `heap leaks` (see
+// memory/trace.rs). `heap trace on` starts it later instead.
+pub const HEAP_TRACE: bool = false;
+
+pub const HEAP_TRACE_SLOTS: usize = 2048;
+
+// End of ARM-visible RAM assumed until firmware tells us otherwise. The GPU
+// carves its memory off the top of the first gigabyte.
+#[cfg(not(feature = "rpi4"))]
+pub const DEFAULT_RAM_END: usize = 0x3C00_0000;
+
+#[cfg(feature = "rpi4")]
+pub const DEFAULT_RAM_END: usize = 0x3B40_0000;
+
+// Run a memory test over all free RAM before the shell starts
+pub const MEMTEST_AT_BOOT: bool = false;
 
-pub const HEAP_START: usize = KERNEL_STACK_START + 0x200000;
+// How often the shell's idle loop checks the sentinel words past the heap,
+// below the stack and around registered buffers (see memory/sentinel.rs)
+pub const SENTINEL_CHECK_MS: u64 = 1000;
 
-pub const HEAP_SIZE: usize = 0x200000;
+// Heap usage percentages that trigger a warning when crossed (ascending)
+pub const HEAP_WATERMARKS: [usize; 2] = [75, 90];
@@ -0,0 +1,230 @@
+use super::super::utils::locked::SpinLock;
+use core::ptr::null_mut;
+
+// ============================================================================
+// PHYSICAL FRAME ALLOCATOR
+// Buddy system over 4 KiB pages for everything above the kernel and the
+// program window. A block of order n is 2^n pages and starts on a multiple of
+// 2^n pages from the base, so its buddy is found by flipping one bit of the
+// page index, and two free buddies merge back into the block above.
+//
+// Bookkeeping lives in the managed RAM itself: one state byte per page at the
+// bottom of the range, and a doubly linked list node in the first bytes of
+// every free block. Nothing here touches the heap, since the heap is built on
+// top of this.
+// ============================================================================
+
+pub const PAGE_SIZE: usize = 0x1000;
+
+// 2^18 pages = 1 GiB, more than any Pi gives us below the first bank's end
+pub const MAX_ORDER: usize = 18;
+
+// State byte of the first page of a free block: FREE | order.
+// Every other page (allocated, or inside a free block) is 0.
+const FREE: u8 = 0x80;
+
+#[repr(C)]
+struct FreeBlock {
+    next: *mut FreeBlock,
+    prev: *mut FreeBlock,
+}
+
+pub struct FrameAllocator {
+    // First managed page (after the state bytes) and how many there are
+    base: usize,
+    pages: usize,
+    state: *mut u8,
+    free: [*mut FreeBlock; MAX_ORDER + 1],
+    free_pages: usize,
+}
+
+static FRAMES: SpinLock<FrameAllocator> = SpinLock::new(FrameAllocator {
+    base: 0,
+    pages: 0,
+    state: null_mut(),
+    free: [null_mut(); MAX_ORDER + 1],
+    free_pages: 0,
+});
+
+// Smallest order whose block holds `bytes`
+pub fn order_for(bytes: usize) -> usize {
+    let pages = bytes.div_ceil(PAGE_SIZE).max(1);
+    pages.next_power_of_two().trailing_zeros() as usize
+}
+
+pub fn block_size(order: usize) -> usize {
+    PAGE_SIZE << order
+}
+
+impl FrameAllocator {
+    // Take over [start, end). Everything in it is assumed unused.
+    unsafe fn init(&mut self, start: usize, end: usize) {
+        let start = (start + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let end = end & !(PAGE_SIZE - 1);
+        let total = end.saturating_sub(start) / PAGE_SIZE;
+
+        // The state bytes cover their own pages too, which wastes a few
+        // bytes but keeps the arithmetic simple
+        let state_pages = total.div_ceil(PAGE_SIZE);
+        if total <= state_pages {
+            return;
+        }
+
+        self.state = start as *mut u8;
+        self.base = start + state_pages * PAGE_SIZE;
+        self.pages = total - state_pages;
+        self.free = [null_mut(); MAX_ORDER + 1];
+        self.free_pages = 0;
+
+        unsafe {
+            core::ptr::write_bytes(self.state, 0, self.pages);
+        }
+
+        // Carve the range into the biggest blocks its alignment allows
+        let mut index = 0;
+        while index < self.pages {
+            let mut order = MAX_ORDER;
+            while index % (1 << order) != 0 || index + (1 << order) > self.pages {
+                order -= 1;
+            }
+
+            unsafe { self.push(index, order) };
+            self.free_pages += 1 << order;
+            index += 1 << order;
+        }
+    }
+
+    fn address(&self, index: usize) -> usize {
+        self.base + index * PAGE_SIZE
+    }
+
+    fn index(&self, address: usize) -> usize {
+        (address - self.base) / PAGE_SIZE
+    }
+
+    fn is_free(&self, index: usize, order: usize) -> bool {
+        index < self.pages && unsafe { *self.state.add(index) } == FREE | order as u8
+    }
+
+    unsafe fn push(&mut self, index: usize, order: usize) {
+        let block = self.address(index) as *mut FreeBlock;
+        let head = self.free[order];
+
+        unsafe {
+            block.write(FreeBlock {
+                next: head,
+                prev: null_mut(),
+            });
+            if !head.is_null() {
+                (*head).prev = block;
+            }
+            *self.state.add(index) = FREE | order as u8;
+        }
+        self.free[order] = block;
+    }
+
+    unsafe fn unlink(&mut self, index: usize, order: usize) {
+        let block = self.address(index) as *mut FreeBlock;
+
+        unsafe {
+            let FreeBlock { next, prev } = block.read();
+
+            if prev.is_null() {
+                self.free[order] = next;
+            } else {
+                (*prev).next = next;
+            }
+            if !next.is_null() {
+                (*next).prev = prev;
+            }
+            *self.state.add(index) = 0;
+        }
+    }
+
+    pub fn allocate(&mut self, order: usize) -> Option<usize> {
+        if order > MAX_ORDER {
+            return None;
+        }
+
+        // Smallest free block that is big enough, split down to size
+        let mut found = (order..=MAX_ORDER).find(|&o| !self.free[o].is_null())?;
+        let index = self.index(self.free[found] as usize);
+
+        unsafe {
+            self.unlink(index, found);
+
+            while found > order {
+                found -= 1;
+                self.push(index + (1 << found), found);
+            }
+        }
+
+        self.free_pages -= 1 << order;
+        Some(self.address(index))
+    }
+
+    // `order` must be the one the block was allocated with
+    pub fn free(&mut self, address: usize, mut order: usize) {
+        let mut index = self.index(address);
+        self.free_pages += 1 << order;
+
+        while order < MAX_ORDER {
+            let buddy = index ^ (1 << order);
+            if !self.is_free(buddy, order) {
+                break;
+            }
+
+            unsafe { self.unlink(buddy, order) };
+            index = index.min(buddy);
+            order += 1;
+        }
+
+        unsafe { self.push(index, order) };
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Global allocator
+// ----------------------------------------------------------------------------
+
+pub fn init(start: usize, end: usize) {
+    unsafe { FRAMES.lock().init(start, end) };
+}
+
+// 2^order contiguous pages, aligned to their own size relative to the base
+pub fn allocate(order: usize) -> Option<usize> {
+    FRAMES.lock().allocate(order)
+}
+
+pub fn free(address: usize, order: usize) {
+    FRAMES.lock().free(address, order);
+}
+
+// The biggest block there is, for callers that want "as much as possible"
+pub fn allocate_largest() -> Option<(usize, usize)> {
+    let mut frames = FRAMES.lock();
+    let order = (0..=MAX_ORDER).rev().find(|&o| !frames.free[o].is_null())?;
+
+    frames.allocate(order).map(|address| (address, order))
+}
+
+// (free pages, managed pages)
+pub fn stats() -> (usize, usize) {
+    let frames = FRAMES.lock();
+    (frames.free_pages, frames.pages)
+}
+
+// Free block count per order, for the `frames` command
+pub fn free_blocks() -> [usize; MAX_ORDER + 1] {
+    let frames = FRAMES.lock();
+    let mut counts = [0; MAX_ORDER + 1];
+
+    for (order, count) in counts.iter_mut().enumerate() {
+        let mut block = frames.free[order];
+        while !block.is_null() {
+            *count += 1;
+            block = unsafe { (*block).next };
+        }
+    }
+    counts
+}
@@ -1,396 +1,218 @@
+#[cfg(not(feature = "heap-bump"))]
+use super::super::cmdline;
+use super::super::utils::early;
 use super::super::utils::locked::SpinLock;
-use core::alloc::{GlobalAlloc, Layout};
-use core::mem::size_of;
+use super::backend::{Backend, relocate};
+use super::config::{HEAP_DEBUG, HEAP_GROW_SIZE, HEAP_STRATEGY};
+use super::{canary, frame, watermark};
+use core::alloc::Layout;
+use core::ops::Deref;
 use core::ptr::null_mut;
 
-const ALIGN: usize = 16;
+// The FreeList itself lives in ddos-heap so it can be tested on the host
+pub use ddos_heap::{FreeError, FreeList, HeapType};
 
-pub enum HeapType {
-    BestFit,
-    WorstFit,
-    FirstFit,
-    NextFit,
+// A free or realloc the FreeList refused (double free, wild pointer). The
+// debug heap stops right there; otherwise the block is leaked, which beats
+// a corrupted free list. Reported on the early console since the normal one
+// may allocate.
+pub fn refused_free(error: FreeError) {
+    if HEAP_DEBUG {
+        panic!("heap: {}", error);
+    }
+    early::report(format_args!("heap: {}, ignored", error));
 }
 
-pub struct FreeList {
-    pub head: Option<*mut FreeListNode>,
-    pub start_address: usize,
-    pub capacity: usize,
-    pub heap_type: HeapType,
-    pub(crate) next_fit_cursor: Option<*mut FreeListNode>,
+// Pull at least enough frames for a `requested_size` allocation into the
+// heap. False when the frame allocator has nothing that big left, or the
+// heap already keeps as many separate regions as it can.
+#[cfg(not(feature = "heap-bump"))]
+fn grow(list: &mut FreeList, requested_size: usize) -> bool {
+    let Some(needed) = FreeList::block_size_for(requested_size) else {
+        return false;
+    };
+    let order = frame::order_for(needed.max(HEAP_GROW_SIZE));
+
+    let Some(start) = frame::allocate(order) else {
+        return false;
+    };
+    if !unsafe { list.add_region(start, frame::block_size(order)) } {
+        frame::free(start, order);
+        return false;
+    }
+    true
 }
 
-#[repr(C, align(16))]
-pub struct FreeListNode {
+// An allocation of `size` just failed: merge whatever free blocks touch and
+// try again, then pull more frames in and try once more
+#[cfg(not(feature = "heap-bump"))]
+fn recover<T>(
+    list: &mut FreeList,
     size: usize,
-    next: Option<*mut FreeListNode>,
-}
-
-impl FreeListNode {
-    fn new(size: usize, next: Option<*mut FreeListNode>) -> Self {
-        FreeListNode { size, next }
+    mut attempt: impl FnMut(&mut FreeList) -> Option<T>,
+) -> Option<T> {
+    if list.coalesce() > 0
+        && let Some(result) = attempt(list)
+    {
+        return Some(result);
     }
+    if grow(list, size) {
+        return attempt(list);
+    }
+    None
 }
 
-impl FreeList {
-    pub unsafe fn init(start: usize, capacity: usize, heap_type: HeapType) -> Self {
-        let aligned_start = Self::align_up(start).expect("heap start alignment overflow");
-        let alignment_loss = aligned_start - start;
-        let usable_capacity = capacity.saturating_sub(alignment_loss) & !(ALIGN - 1);
-
-        assert!(usable_capacity >= Self::block_overhead());
-
-        let node_ptr = aligned_start as *mut FreeListNode;
-        unsafe {
-            node_ptr.write(FreeListNode::new(usable_capacity, None));
+// The FreeList behind a lock, as a heap backend. Also what the slab caches
+// cut their chunks from (see slab.rs). The bump backend has no use for it,
+// only the pools do for the FreeList itself (see pool.rs).
+#[cfg(not(feature = "heap-bump"))]
+pub struct Heap(SpinLock<FreeList>);
 
-            let footer = (aligned_start + usable_capacity - size_of::<usize>()) as *mut usize;
-            footer.write(usable_capacity);
-        }
+#[cfg(not(feature = "heap-bump"))]
+impl Deref for Heap {
+    type Target = SpinLock<FreeList>;
 
-        FreeList {
-            head: Some(node_ptr),
-            start_address: aligned_start,
-            capacity: usable_capacity,
-            heap_type,
-            next_fit_cursor: Some(node_ptr),
-        }
+    fn deref(&self) -> &SpinLock<FreeList> {
+        &self.0
     }
+}
 
-    fn contains_node(&self, target: *mut FreeListNode) -> bool {
-        let mut current = self.head;
+#[cfg(not(feature = "heap-bump"))]
+impl Heap {
+    pub(super) fn list_alloc(&self, size: usize, align: usize) -> *mut u8 {
+        // 2. Add 'mut' here so the guard can mutate the FreeListx
+        let mut allocator = self.lock();
 
-        while let Some(node_ptr) = current {
-            if node_ptr == target {
-                return true;
-            }
+        let ptr = match allocator.allocate(size, align) {
+            Some(ptr) => ptr,
+            None => recover(&mut allocator, size, |list| list.allocate(size, align))
+                .unwrap_or(null_mut()),
+        };
 
-            unsafe {
-                current = (*node_ptr).next;
-            }
-        }
+        let (used, capacity) = (allocator.used, allocator.capacity);
+        drop(allocator);
+        watermark::check(used, capacity);
 
-        false
+        ptr
     }
 
-    fn find_prev_node(&self, target: *mut FreeListNode) -> Option<*mut FreeListNode> {
-        let mut current = self.head;
-        let mut prev = None;
-
-        while let Some(node_ptr) = current {
-            if node_ptr == target {
-                return prev;
-            }
+    fn debug_alloc(&self, layout: Layout) -> *mut u8 {
+        let Some(size) = canary::padded(layout.size()) else {
+            return null_mut();
+        };
 
-            unsafe {
-                prev = current;
-                current = (*node_ptr).next;
-            }
+        let block = self.list_alloc(size, layout.align());
+        if block.is_null() {
+            return block;
         }
-
-        None
+        unsafe { canary::arm(block, layout.size()) }
     }
 
-    fn find_region_best_fit(
-        &mut self,
-        requested_size: usize,
-    ) -> (Option<*mut FreeListNode>, Option<*mut FreeListNode>) {
-        let mut current = self.head;
-        let mut prev: Option<*mut FreeListNode> = None;
-
-        let mut best: Option<*mut FreeListNode> = None;
-        let mut best_prev: Option<*mut FreeListNode> = None;
-
-        if requested_size > self.capacity {
-            return (None, None);
-        }
-
-        while let Some(node_ptr) = current {
-            unsafe {
-                let node = &*node_ptr;
+    fn list_dealloc(&self, ptr: *mut u8) {
+        // 3. Add 'mut' here too!
+        let mut allocator = self.lock();
+        let freed = allocator.deallocate(ptr as usize);
 
-                if node.size >= requested_size {
-                    if best.is_none() || node.size < (*best.unwrap()).size {
-                        best = Some(node_ptr);
-                        best_prev = prev;
-                    }
-                }
+        let (used, capacity) = (allocator.used, allocator.capacity);
+        drop(allocator);
+        watermark::check(used, capacity);
 
-                prev = current;
-                current = node.next;
-            }
+        if let Err(error) = freed {
+            refused_free(error);
         }
-
-        (best, best_prev)
     }
+}
 
-    fn find_region_worst_fit(
-        &mut self,
-        requested_size: usize,
-    ) -> (Option<*mut FreeListNode>, Option<*mut FreeListNode>) {
-        let mut current = self.head;
-        let mut prev: Option<*mut FreeListNode> = None;
-
-        let mut worst: Option<*mut FreeListNode> = None;
-        let mut worst_prev: Option<*mut FreeListNode> = None;
-
-        if requested_size > self.capacity {
-            return (None, None);
-        }
+#[cfg(not(feature = "heap-bump"))]
+impl Backend for Heap {
+    const NAME: &'static str = "freelist";
 
-        while let Some(node_ptr) = current {
-            unsafe {
-                let node = &*node_ptr;
+    const EMPTY: Self = Heap(SpinLock::new(FreeList::empty(HEAP_STRATEGY)));
 
-                if node.size >= requested_size {
-                    if worst.is_none() || node.size > (*worst.unwrap()).size {
-                        worst = Some(node_ptr);
-                        worst_prev = prev;
-                    }
-                }
+    unsafe fn init(&self, start: usize, size: usize) {
+        let strategy = cmdline::heap_strategy().unwrap_or(HEAP_STRATEGY);
+        *self.lock() = unsafe { FreeList::init(start, size, strategy) };
+    }
 
-                prev = current;
-                current = node.next;
-            }
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        if HEAP_DEBUG {
+            return self.debug_alloc(layout);
         }
-
-        (worst, worst_prev)
+        self.list_alloc(layout.size(), layout.align())
     }
 
-    fn find_region_next_fit(
-        &mut self,
-        requested_size: usize,
-    ) -> (Option<*mut FreeListNode>, Option<*mut FreeListNode>) {
-        if requested_size > self.capacity {
-            return (None, None);
+    fn deallocate(&self, ptr: *mut u8, _layout: Layout) {
+        if HEAP_DEBUG {
+            return self.list_dealloc(unsafe { canary::disarm(ptr) });
         }
+        self.list_dealloc(ptr)
+    }
 
-        let head = match self.head {
-            Some(head) => head,
-            None => return (None, None),
-        };
-
-        let start = match self.next_fit_cursor {
-            Some(cursor) if self.contains_node(cursor) => cursor,
-            _ => head,
-        };
-
-        self.next_fit_cursor = Some(start);
-
-        let mut current = Some(start);
-        let mut prev = self.find_prev_node(start);
-
-        while let Some(node_ptr) = current {
-            unsafe {
-                let node = &*node_ptr;
-
-                if node.size >= requested_size {
-                    return (Some(node_ptr), prev);
-                }
-
-                prev = current;
-                current = node.next;
-            }
+    fn reallocate(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // The debug heap always moves, so stale pointers hit poison
+        if HEAP_DEBUG {
+            return relocate(self, ptr, layout, new_size);
         }
 
-        current = self.head;
-        prev = None;
+        let mut allocator = self.lock();
 
-        while let Some(node_ptr) = current {
-            if node_ptr == start {
-                break;
+        let (ptr, refused) = match allocator.reallocate(ptr as usize, new_size, layout.align()) {
+            Ok(Some(ptr)) => (ptr, None),
+            Ok(None) => {
+                let ptr = recover(&mut allocator, new_size, |list| {
+                    list.reallocate(ptr as usize, new_size, layout.align())
+                        .ok()
+                        .flatten()
+                });
+                (ptr.unwrap_or(null_mut()), None)
             }
+            Err(error) => (null_mut(), Some(error)),
+        };
 
-            unsafe {
-                let node = &*node_ptr;
-
-                if node.size >= requested_size {
-                    return (Some(node_ptr), prev);
-                }
+        let (used, capacity) = (allocator.used, allocator.capacity);
+        drop(allocator);
+        watermark::check(used, capacity);
 
-                prev = current;
-                current = node.next;
-            }
+        if let Some(error) = refused {
+            refused_free(error);
         }
-
-        (None, None)
+        ptr
     }
 
-    fn find_region_first_fit(
-        &mut self,
-        requested_size: usize,
-    ) -> (Option<*mut FreeListNode>, Option<*mut FreeListNode>) {
-        let mut current = self.head;
-        let mut prev = None;
-
-        if requested_size > self.capacity {
-            return (None, None);
-        }
-
-        while let Some(node_ptr) = current {
-            unsafe {
-                let node = &*node_ptr;
-
-                if node.size >= requested_size {
-                    return (Some(node_ptr), prev);
-                }
-
-                prev = current;
-                current = node.next;
-            }
-        }
-
-        (None, None)
+    fn usage(&self) -> (usize, usize) {
+        let allocator = self.lock();
+        (allocator.used, allocator.capacity)
     }
 
-    fn align_up(size: usize) -> Option<usize> {
-        size.checked_add(ALIGN - 1).map(|s| s & !(ALIGN - 1))
+    fn free_stats(&self) -> (usize, usize, usize) {
+        self.lock().free_stats()
     }
 
-    fn block_overhead() -> usize {
-        size_of::<FreeListNode>() + size_of::<usize>()
+    fn check(&self) -> (usize, usize) {
+        self.lock().check()
     }
 
-    pub fn allocate(&mut self, requested_size: usize, requested_align: usize) -> Option<*mut u8> {
-        if requested_align > ALIGN {
-            return None;
-        }
-
-        let request = requested_size.max(1);
-        let aligned_payload = Self::align_up(request)?;
-        let raw_total = aligned_payload.checked_add(Self::block_overhead())?;
-        let total_size = Self::align_up(raw_total)?;
-
-        let (region, prev) = match self.heap_type {
-            HeapType::FirstFit => self.find_region_first_fit(total_size),
-            HeapType::BestFit => self.find_region_best_fit(total_size),
-            HeapType::WorstFit => self.find_region_worst_fit(total_size),
-            HeapType::NextFit => self.find_region_next_fit(total_size),
-        };
-
-        let node_ptr = region?;
-
-        unsafe {
-            let node = &mut *node_ptr;
-            let next_cursor;
-
-            if node.size >= total_size + Self::block_overhead() {
-                let remaining_size = node.size - total_size;
-
-                let new_node_ptr = (node_ptr as *mut u8).add(total_size) as *mut FreeListNode;
-
-                new_node_ptr.write(FreeListNode::new(remaining_size, node.next));
-
-                let new_footer =
-                    (new_node_ptr as usize + remaining_size - size_of::<usize>()) as *mut usize;
-                new_footer.write(remaining_size);
-
-                if let Some(prev_ptr) = prev {
-                    (*prev_ptr).next = Some(new_node_ptr);
-                } else {
-                    self.head = Some(new_node_ptr);
-                }
-
-                next_cursor = Some(new_node_ptr);
-
-                node.size = total_size;
-            } else {
-                let next_node = node.next;
-
-                if let Some(prev_ptr) = prev {
-                    (*prev_ptr).next = next_node;
-                } else {
-                    self.head = next_node;
-                }
-
-                next_cursor = next_node.or(self.head);
-            }
-
-            self.next_fit_cursor = next_cursor.or(self.head);
-
-            let footer_ptr = (node_ptr as usize + node.size - size_of::<usize>()) as *mut usize;
-            footer_ptr.write(node.size);
-
-            Some((node_ptr as *mut u8).add(size_of::<FreeListNode>()))
-        }
+    fn strategy(&self) -> Option<HeapType> {
+        Some(self.lock().heap_type)
     }
 
-    pub fn deallocate(&mut self, address: usize) {
-        unsafe {
-            let node_ptr = (address - size_of::<FreeListNode>()) as *mut FreeListNode;
-
-            let node = &mut *node_ptr;
-
-            let mut current = self.head;
-            let mut prev: Option<*mut FreeListNode> = None;
-
-            while let Some(curr_ptr) = current {
-                if curr_ptr as usize > node_ptr as usize {
-                    break;
-                }
-                prev = current;
-                current = (*curr_ptr).next;
-            }
-
-            node.next = current;
-
-            if let Some(prev_ptr) = prev {
-                (*prev_ptr).next = Some(node_ptr);
-            } else {
-                self.head = Some(node_ptr);
-            }
-
-            if let Some(next_ptr) = node.next {
-                let node_end = node_ptr as usize + node.size;
-
-                if node_end == next_ptr as usize {
-                    node.size += (*next_ptr).size;
-                    node.next = (*next_ptr).next;
-
-                    let footer = (node_ptr as usize + node.size - size_of::<usize>()) as *mut usize;
-                    footer.write(node.size);
-                }
-            }
-
-            if node_ptr as usize > self.start_address {
-                let prev_footer_ptr = (node_ptr as usize - size_of::<usize>()) as *mut usize;
-                let prev_size = prev_footer_ptr.read();
-                let prev_start = node_ptr as usize - prev_size;
-
-                if prev_start >= self.start_address {
-                    let prev_node_ptr = prev_start as *mut FreeListNode;
-                    let prev_node = &mut *prev_node_ptr;
-
-                    if prev_start + prev_node.size == node_ptr as usize {
-                        prev_node.size += node.size;
-                        prev_node.next = node.next;
-
-                        let footer =
-                            (prev_start + prev_node.size - size_of::<usize>()) as *mut usize;
-                        footer.write(prev_node.size);
-                    }
-                }
-            }
-
-            self.next_fit_cursor = self.head;
-        }
+    fn set_strategy(&self, strategy: HeapType) -> bool {
+        self.lock().set_heap_type(strategy);
+        true
     }
-}
 
-unsafe impl GlobalAlloc for SpinLock<FreeList> {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        // 2. Add 'mut' here so the guard can mutate the FreeListx
+    // Merge what the free list can first, so whole pages show up
+    fn trim(&self, keep_start: usize, keep_end: usize) -> usize {
         let mut allocator = self.lock();
+        allocator.coalesce();
+        let released = allocator.trim(keep_start, keep_end, frame::PAGE_SIZE, |address| {
+            frame::free(address, 0)
+        });
 
-        match allocator.allocate(layout.size(), layout.align()) {
-            Some(ptr) => ptr,
-            None => null_mut(),
-        }
-    }
+        let (used, capacity) = (allocator.used, allocator.capacity);
+        drop(allocator);
+        watermark::check(used, capacity);
 
-    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
-        // 3. Add 'mut' here too!
-        let mut allocator = self.lock();
-        allocator.deallocate(ptr as usize);
+        released
     }
 }
@@ -0,0 +1,164 @@
+use super::config::{
+    CRASH_LOG_SIZE, CRASH_LOG_START, DEFAULT_RAM_END, KERNEL_START, PROGRAM_SIZE, PROGRAM_START,
+};
+use super::pool::{self, Pool};
+use crate::hardwareselect::{PERIPHERAL_SIZE, peripheral_base};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// ============================================================================
+// PHYSICAL MEMORY MAP
+// What the kernel knows about RAM: which ranges are in use (stack, kernel image,
+// crash log, heap, program window) and which are free for things like the
+// memory tester.
+// Page-granularity allocation above the program window is frame.rs' job.
+// ============================================================================
+
+unsafe extern "C" {
+    static __bss_end: u8;
+}
+
+// Plain load/store only, so this is fine on RPi5 with the MMU off
+static RAM_END: AtomicUsize = AtomicUsize::new(DEFAULT_RAM_END);
+
+// The heap's first block, as memory::init() got it from the frame allocator.
+// Blocks it grows into later are not tracked here.
+static HEAP_START: AtomicUsize = AtomicUsize::new(0);
+static HEAP_END: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Copy)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    pub name: &'static str,
+}
+
+impl Region {
+    pub fn size(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+pub fn ram_end() -> usize {
+    RAM_END.load(Ordering::Relaxed)
+}
+
+pub fn set_ram_end(end: usize) {
+    RAM_END.store(end, Ordering::Relaxed);
+}
+
+pub fn kernel_end() -> usize {
+    unsafe { &__bss_end as *const u8 as usize }
+}
+
+pub fn set_heap(start: usize, size: usize) {
+    HEAP_START.store(start, Ordering::Relaxed);
+    HEAP_END.store(start + size, Ordering::Relaxed);
+}
+
+pub fn heap_start() -> usize {
+    HEAP_START.load(Ordering::Relaxed)
+}
+
+pub fn heap_end() -> usize {
+    HEAP_END.load(Ordering::Relaxed)
+}
+
+pub fn program_end() -> usize {
+    PROGRAM_START + PROGRAM_SIZE
+}
+
+pub fn reserved_regions() -> [Region; 7] {
+    [
+        // Firmware spin tables and the boot stack growing down from _start
+        Region {
+            start: 0,
+            end: KERNEL_START,
+            name: "stack",
+        },
+        Region {
+            start: KERNEL_START,
+            end: kernel_end(),
+            name: "kernel",
+        },
+        Region {
+            start: CRASH_LOG_START,
+            end: CRASH_LOG_START + CRASH_LOG_SIZE,
+            name: "crash log",
+        },
+        Region {
+            start: PROGRAM_START,
+            end: program_end(),
+            name: "programs",
+        },
+        Region {
+            start: heap_start(),
+            end: heap_end(),
+            name: "heap",
+        },
+        pool_region(Pool::Dma),
+        pool_region(Pool::Graphics),
+    ]
+}
+
+// Empty for a pool that did not fit
+fn pool_region(pool: Pool) -> Region {
+    let (start, end) = match pool::stats(pool) {
+        Some(stats) => (stats.start, stats.start + stats.capacity),
+        None => (0, 0),
+    };
+    Region {
+        start,
+        end,
+        name: pool.name(),
+    }
+}
+
+// Where the shell's md, mw and mmio may go. With the MMU off nothing stops
+// an access anywhere else, and one that nothing answers hangs the core.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    Ram,
+    Peripherals,
+}
+
+pub fn peripherals() -> Region {
+    Region {
+        start: peripheral_base(),
+        end: peripheral_base() + PERIPHERAL_SIZE,
+        name: "peripherals",
+    }
+}
+
+// The window all of [start, start + len) lies in
+pub fn window(start: usize, len: usize) -> Option<Window> {
+    let end = start.checked_add(len)?;
+    let peripherals = peripherals();
+
+    if end <= ram_end() {
+        Some(Window::Ram)
+    } else if start >= peripherals.start && end <= peripherals.end {
+        Some(Window::Peripherals)
+    } else {
+        None
+    }
+}
+
+// The reserved region `address` is in, if any
+pub fn reserved_at(address: usize) -> Option<Region> {
+    reserved_regions()
+        .into_iter()
+        .find(|region| region.start <= address && address < region.end)
+}
+
+// The gap between the end of the kernel image and the crash log. RAM
+// above the program window belongs to the frame allocator; memtest borrows
+// whatever frames are free instead.
+pub fn free_regions() -> [Region; 1] {
+    let page = 0x1000;
+
+    [Region {
+        start: (kernel_end() + page - 1) & !(page - 1),
+        end: CRASH_LOG_START,
+        name: "kernel-program gap",
+    }]
+}
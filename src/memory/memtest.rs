@@ -0,0 +1,167 @@
+use super::frame;
+use super::map::{self, Region};
+use crate::println;
+use crate::utils::progress::Progress;
+use alloc::format;
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+
+// ============================================================================
+// RAM TESTER
+// Write/read-verify patterns over RAM nobody is using. Solid patterns catch
+// stuck bits, address-in-address catches shorted or floating address lines.
+// Only ever pass regions from map::free_regions() or frames allocated for the
+// purpose, never the kernel or heap.
+// ============================================================================
+
+const WORD: usize = size_of::<u64>();
+
+// How often the progress bar hears about it, in bytes
+const PROGRESS_STEP: usize = 0x10000;
+
+#[derive(Clone, Copy)]
+enum Pattern {
+    Solid(u64),
+    AddressInAddress,
+}
+
+const PATTERNS: [Pattern; 5] = [
+    Pattern::Solid(0x0000_0000_0000_0000),
+    Pattern::Solid(0xFFFF_FFFF_FFFF_FFFF),
+    Pattern::Solid(0x5555_5555_5555_5555),
+    Pattern::Solid(0xAAAA_AAAA_AAAA_AAAA),
+    Pattern::AddressInAddress,
+];
+
+impl Pattern {
+    fn value_at(self, address: usize) -> u64 {
+        match self {
+            Pattern::Solid(value) => value,
+            Pattern::AddressInAddress => address as u64,
+        }
+    }
+}
+
+pub struct Failure {
+    pub address: usize,
+    pub expected: u64,
+    pub found: u64,
+}
+
+// Progress is counted in bytes: one write and one verify pass per pattern
+pub fn test_region(region: &Region, progress: &mut Progress) -> Result<(), Failure> {
+    let start = (region.start + WORD - 1) & !(WORD - 1);
+    let end = region.end & !(WORD - 1);
+
+    if end <= start {
+        return Ok(());
+    }
+
+    for pattern in PATTERNS {
+        let mut address = start;
+        while address < end {
+            unsafe { write_volatile(address as *mut u64, pattern.value_at(address)) };
+            address += WORD;
+
+            if address.is_multiple_of(PROGRESS_STEP) {
+                progress.advance(PROGRESS_STEP as u64);
+            }
+        }
+
+        let mut address = start;
+        while address < end {
+            let expected = pattern.value_at(address);
+            let found = unsafe { read_volatile(address as *const u64) };
+
+            if found != expected {
+                return Err(Failure {
+                    address,
+                    expected,
+                    found,
+                });
+            }
+            address += WORD;
+
+            if address.is_multiple_of(PROGRESS_STEP) {
+                progress.advance(PROGRESS_STEP as u64);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Bytes run() can test: the free regions plus every free frame
+pub fn available() -> usize {
+    let regions: usize = map::free_regions().iter().map(Region::size).sum();
+    regions + frame::stats().0 * frame::PAGE_SIZE
+}
+
+// Test every free region, then every free frame, at most `limit` bytes in
+// total. Returns the number of failing regions.
+pub fn run(limit: usize) -> usize {
+    let mut remaining = limit;
+    let mut failures = 0;
+
+    for region in map::free_regions() {
+        test_limited(region, &mut remaining, &mut failures);
+    }
+
+    // Frames are taken out of the allocator while they are scribbled on, so
+    // the heap cannot grow into them halfway through. Biggest blocks first.
+    let mut borrowed = Vec::new();
+    while remaining > 0 {
+        let Some((start, order)) = frame::allocate_largest() else {
+            break;
+        };
+        borrowed.push((start, order));
+
+        let region = Region {
+            start,
+            end: start + frame::block_size(order),
+            name: "frames",
+        };
+        test_limited(region, &mut remaining, &mut failures);
+    }
+
+    for (start, order) in borrowed {
+        frame::free(start, order);
+    }
+
+    failures
+}
+
+fn test_limited(region: Region, remaining: &mut usize, failures: &mut usize) {
+    let size = region.size().min(*remaining);
+    if size == 0 {
+        return;
+    }
+
+    let target = Region {
+        start: region.start,
+        end: region.start + size,
+        name: region.name,
+    };
+    *remaining -= size;
+
+    let label = format!(
+        "memtest: {} {:#x}-{:#x} ({} KB)",
+        target.name,
+        target.start,
+        target.end,
+        size / 1024
+    );
+    let mut progress = Progress::bar(&label, (size * PATTERNS.len() * 2) as u64);
+
+    match test_region(&target, &mut progress) {
+        Ok(()) => progress.finish("ok"),
+        Err(failure) => {
+            *failures += 1;
+            progress.finish("FAIL");
+            println!(
+                "  at {:#x}: expected {:#018x}, found {:#018x}",
+                failure.address, failure.expected, failure.found
+            );
+        }
+    }
+}
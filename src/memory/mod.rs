@@ -1,31 +1,370 @@
+pub mod backend;
+pub mod bench;
+#[cfg(feature = "heap-bump")]
+pub mod bump;
+pub mod canary;
 pub mod config;
+pub mod frame;
 pub mod heap;
+pub mod map;
+pub mod memtest;
+pub mod pool;
+pub mod sentinel;
+#[cfg(not(any(feature = "heap-freelist", feature = "heap-bump")))]
+pub mod slab;
+pub mod stack;
+pub mod trace;
+pub mod watermark;
 
 use core::alloc::Layout;
+use core::sync::atomic::{AtomicBool, Ordering};
 
-use super::utils::locked::SpinLock;
-use config::{HEAP_SIZE, HEAP_START};
-use heap::{FreeList, HeapType};
+use super::drivers::mailbox;
+use super::utils::early;
+use super::utils::events::Event;
+use super::utils::log;
+use backend::{Allocator, Backend, Counts};
+use config::{CRASH_LOG_START, HEAP_MIN_SIZE, HEAP_RAM_SHARE, STACK_LIMIT};
+use heap::HeapType;
+
+// Picked with a feature flag, see backend.rs
+#[cfg(not(any(feature = "heap-freelist", feature = "heap-bump")))]
+type HeapBackend = slab::SlabHeap;
+
+#[cfg(feature = "heap-freelist")]
+type HeapBackend = heap::Heap;
+
+#[cfg(feature = "heap-bump")]
+type HeapBackend = bump::Bump;
+
+#[cfg(all(feature = "heap-freelist", feature = "heap-bump"))]
+compile_error!("Cannot enable both heap-freelist and heap-bump. Pick one heap backend!");
 
 #[global_allocator]
-static ALLOCATOR: SpinLock<FreeList> = SpinLock::new(FreeList {
-    head: None,
-    start_address: 0,
-    capacity: 0,
+static ALLOCATOR: Allocator<HeapBackend> = Allocator::new();
 
-    heap_type: HeapType::BestFit,
-    next_fit_cursor: None,
-});
+// Set once init() has the allocator up. The allocator refuses to run before
+// that with a panic naming the request, rather than failing in some odd way
+// (see backend.rs).
+static HEAP_READY: AtomicBool = AtomicBool::new(false);
+
+pub fn heap_ready() -> bool {
+    HEAP_READY.load(Ordering::Acquire)
+}
 
 pub fn init() {
-    unsafe {
-        let mut allocator = ALLOCATOR.lock();
+    // A second run would hand out the live heap all over again
+    assert!(!heap_ready(), "memory::init() ran twice");
+
+    // The firmware's own answer beats the device tree and the built-in guess
+    early::stage("memory: RAM size");
+    match mailbox::arm_memory() {
+        Ok((0, size)) => map::set_ram_end(size),
+        Ok(_) => {}
+        Err(err) => log::debug!("memory: no RAM size from the firmware: {}", err),
+    }
+
+    assert!(
+        map::kernel_end() <= CRASH_LOG_START,
+        "kernel image runs into the crash log and program window"
+    );
+
+    let page = frame::PAGE_SIZE;
+    let frames_start = (map::kernel_end().max(map::program_end()) + page - 1) & !(page - 1);
+    assert!(
+        frames_start < map::ram_end(),
+        "no RAM left after the kernel: frames would start at {:#x}, RAM ends at {:#x}",
+        frames_start,
+        map::ram_end()
+    );
+
+    // The graphics pool sits right below the VideoCore's share of RAM, so it
+    // comes off the top before the frame allocator takes the rest
+    early::stage("memory: frame allocator");
+    let frames_end = pool::reserve_graphics(map::ram_end(), frames_start);
+    frame::init(frames_start, frames_end);
+
+    // The last bytes of the block hold a sentinel instead of heap
+    early::stage("memory: heap block");
+    let (start, block_size) = heap_block();
+    let size = block_size - sentinel::SENTINEL_SIZE;
+    map::set_heap(start, size);
+
+    unsafe { ALLOCATOR.init(start, size) };
+
+    HEAP_READY.store(true, Ordering::Release);
+    log::debug!("memory: {} heap, {} KiB", HeapBackend::NAME, size / 1024);
+
+    if config::HEAP_TRACE && !trace::start() {
+        log::warn!("memory: no frames for the heap trace table");
+    }
+
+    // After the heap, so the heap's first block is sized from all of RAM
+    early::stage("memory: pools");
+    pool::reserve_dma();
+    for pool in pool::POOLS {
+        match pool::stats(pool) {
+            Some(stats) => log::debug!(
+                "memory: {} pool at {:#x}, {} KiB",
+                pool.name(),
+                stats.start,
+                stats.capacity / 1024
+            ),
+            None => log::warn!("memory: no room for the {} pool", pool.name()),
+        }
+    }
+
+    early::stage("memory: sentinels");
+    sentinel::place(start + size, "heap end");
+    sentinel::place(STACK_LIMIT - sentinel::SENTINEL_SIZE, "stack limit");
+}
+
+// The heap's first block from the frame allocator, sized from the RAM it
+// manages (see HEAP_RAM_SHARE). Settles for less if RAM is short, the heap
+// grows later anyway.
+fn heap_block() -> (usize, usize) {
+    let (_, pages) = frame::stats();
+    let share = (pages / HEAP_RAM_SHARE).checked_ilog2().unwrap_or(0) as usize;
+    let wanted = share
+        .max(frame::order_for(HEAP_MIN_SIZE))
+        .min(frame::MAX_ORDER);
+
+    for order in (0..=wanted).rev() {
+        if let Some(start) = frame::allocate(order) {
+            return (start, frame::block_size(order));
+        }
+    }
+    panic!("no RAM left for the heap");
+}
+
+pub struct HeapReport {
+    pub backend: &'static str,
+    pub free_blocks: usize,
+    pub free_bytes: usize,
+    pub used: usize,
+    pub capacity: usize,
+    // Only counted with HEAP_DEBUG, there is no list of them otherwise
+    pub live: Option<(usize, usize)>,
+    pub counts: Counts,
+}
+
+// Check the free list, and with the debug heap every live allocation's
+// canaries too. Panics with the details on the first problem found.
+pub fn heap_check() -> HeapReport {
+    let (free_blocks, free_bytes) = ALLOCATOR.check();
+    let (used, capacity) = ALLOCATOR.usage();
+
+    HeapReport {
+        backend: HeapBackend::NAME,
+        free_blocks,
+        free_bytes,
+        used,
+        capacity,
+        live: config::HEAP_DEBUG.then(canary::check_live),
+        counts: ALLOCATOR.counts(),
+    }
+}
+
+// How scattered the heap's free space is
+#[derive(Clone, Copy)]
+pub struct Fragmentation {
+    pub free_blocks: usize,
+    pub free_bytes: usize,
+    pub largest_free: usize,
+}
+
+impl Fragmentation {
+    // Share of the free bytes outside the largest free block: 0 when it is
+    // all one block, close to 100 when it is all crumbs
+    pub fn percent(&self) -> usize {
+        match self.free_bytes {
+            0 => 0,
+            free => 100 - self.largest_free * 100 / free,
+        }
+    }
+}
+
+pub fn fragmentation() -> Fragmentation {
+    let (free_blocks, free_bytes, largest_free) = ALLOCATOR.free_stats();
+    Fragmentation {
+        free_blocks,
+        free_bytes,
+        largest_free,
+    }
+}
+
+// Which fit the heap's FreeList uses, None for the bump heap
+pub fn strategy() -> Option<HeapType> {
+    ALLOCATOR.strategy()
+}
+
+// Switch the FreeList's fit. Takes the heap lock, so it lands between two
+// allocations; blocks already handed out stay where they are. False for the
+// bump heap, which has no FreeList.
+pub fn set_strategy(strategy: HeapType) -> bool {
+    let switched = ALLOCATOR.set_strategy(strategy);
+    if switched {
+        log::info!("memory: heap strategy now {}", strategy.name());
+    }
+    switched
+}
+
+// Give the free pages of regions the heap grew into back to the frame
+// allocator. The first block always stays. Returns how many bytes went back.
+pub fn trim() -> usize {
+    ALLOCATOR.trim(map::heap_start(), map::heap_end())
+}
 
-        *allocator = FreeList::init(HEAP_START, HEAP_SIZE, HeapType::BestFit);
+// Nobody is typing: a good moment for housekeeping no command should wait on
+pub fn idle_subscriber(event: &Event) {
+    if let Event::InputIdle { .. } = event {
+        let released = trim();
+        if released > 0 {
+            log::info!(
+                "memory: idle, {} KiB back to the frame allocator",
+                released / 1024
+            );
+        }
     }
 }
 
+// By the time this runs the allocator has already coalesced and tried to
+// grow, so say which of the two ran out: total free space (exhaustion) or a
+// single block big enough (fragmentation)
 #[alloc_error_handler]
 fn alloc_error_handler(layout: Layout) -> ! {
-    panic!("allocation error: {:?}", layout)
+    let (blocks, free, largest) = ALLOCATOR.free_stats();
+    let (used, capacity) = ALLOCATOR.usage();
+
+    let (free_pages, _) = frame::stats();
+    let cause = if free >= layout.size() {
+        "fragmentation"
+    } else {
+        "exhaustion"
+    };
+
+    panic!(
+        "allocation error: {:?} ({}): heap {} / {} bytes used, {} free in {} block(s), largest {}, {} free page frames",
+        layout, cause, used, capacity, free, blocks, largest, free_pages
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pool::{self, Buffer, Pool};
+    use super::{ALLOCATOR, heap_check, stack, trace};
+    use crate::utils::testing::{Failure, check, check_eq};
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    // Mixed sizes on both sides of the slab limit, freed out of order
+    #[test_case]
+    fn churn_leaves_the_heap_consistent() -> Result<(), Failure> {
+        let mut blocks: Vec<Vec<u8>> = (0..64).map(|i| alloc::vec![i as u8; 24 + i * 40]).collect();
+        for i in (0..blocks.len()).rev().step_by(3) {
+            blocks.swap_remove(i);
+        }
+        for block in &blocks {
+            check!(block.iter().all(|&byte| byte == block[0]));
+        }
+        drop(blocks);
+
+        // Panics with the details if the free list is damaged
+        heap_check();
+        Ok(())
+    }
+
+    // Growing a Vec moves it from slab objects to FreeList blocks
+    #[test_case]
+    fn realloc_keeps_contents() -> Result<(), Failure> {
+        let mut values = Vec::new();
+        for i in 0..2000u32 {
+            values.push(i);
+        }
+        check!(
+            values
+                .iter()
+                .enumerate()
+                .all(|(i, &value)| value == i as u32)
+        );
+
+        values.truncate(10);
+        values.shrink_to_fit();
+        check_eq!(values, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        Ok(())
+    }
+
+    #[test_case]
+    fn counts_follow_allocations() -> Result<(), Failure> {
+        let before = ALLOCATOR.counts();
+        drop(Box::new(7u64));
+        let after = ALLOCATOR.counts();
+
+        check_eq!(after.allocations, before.allocations + 1);
+        check_eq!(after.frees, before.frees + 1);
+        Ok(())
+    }
+
+    #[test_case]
+    fn trace_finds_live_allocations() -> Result<(), Failure> {
+        let was_tracing = trace::enabled();
+        check!(trace::start());
+
+        let leak = Box::new([0u8; 200]);
+        let address = &*leak as *const [u8; 200] as usize;
+        let traced = |address| {
+            trace::report()
+                .live
+                .iter()
+                .any(|entry| entry.ptr == address && entry.size == 200 && entry.callers[0] != 0)
+        };
+        check!(traced(address));
+
+        drop(leak);
+        check!(!traced(address));
+
+        if !was_tracing {
+            trace::stop();
+        }
+        Ok(())
+    }
+
+    // 4 KiB on the stack in a call of its own
+    #[inline(never)]
+    fn deep_frame() -> u8 {
+        let buffer = core::hint::black_box([1u8; 4096]);
+        buffer.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+    }
+
+    #[test_case]
+    fn stack_watermark_rises() -> Result<(), Failure> {
+        let before = stack::high_water_mark(0);
+        check!(before.is_some());
+
+        check_eq!(deep_frame(), 0);
+        let after = stack::high_water_mark(0).unwrap_or(0);
+        check!(after >= 4096);
+        check!(after >= before.unwrap_or(0));
+        check_eq!(stack::check(), 1);
+        Ok(())
+    }
+
+    #[test_case]
+    fn dma_pool_round_trip() -> Result<(), Failure> {
+        let used = |pool| pool::stats(pool).map(|stats| stats.used);
+        let before = used(Pool::Dma);
+        check!(before.is_some());
+
+        let mut buffer = Buffer::new(Pool::Dma, 4096, 32);
+        check!(buffer.is_some());
+        if let Some(buffer) = &mut buffer {
+            check!(buffer.as_mut_slice().iter().all(|&byte| byte == 0));
+            check_eq!(buffer.as_mut_slice().as_ptr() as usize % 32, 0);
+        }
+        check!(used(Pool::Dma) > before);
+
+        drop(buffer);
+        check_eq!(used(Pool::Dma), before);
+        Ok(())
+    }
 }
@@ -0,0 +1,185 @@
+use super::super::utils::locked::SpinLock;
+use super::backend::Backend;
+use super::config::{DMA_POOL_SIZE, GRAPHICS_POOL_SIZE};
+use super::heap::{self, FreeList, HeapType};
+use super::{ALLOCATOR, frame, map};
+use core::alloc::Layout;
+
+// ============================================================================
+// MEMORY POOLS
+// Besides the general heap behind the global allocator, memory::init() sets
+// aside pools for memory with needs of its own. Each is a FreeList over a
+// fixed range that never grows, so a big buffer in one pool cannot starve
+// small kernel allocations in another:
+//
+//     general    the global allocator (Box, Vec, String, ...)
+//     dma        buffers and control blocks a DMA engine reads or writes;
+//                meant to stay uncached once there is an MMU (with the MMU
+//                off all RAM is uncached anyway)
+//     graphics   large buffers, cut from the top of ARM RAM right below the
+//                VideoCore's share, outside the frame allocator
+//
+// allocate_from()/free_to() take the pool explicitly; memory from one pool
+// must go back to the same pool. A pool that did not fit in RAM stays empty
+// and every allocation from it fails.
+// ============================================================================
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Pool {
+    General,
+    Dma,
+    Graphics,
+}
+
+pub const POOLS: [Pool; 3] = [Pool::General, Pool::Dma, Pool::Graphics];
+
+impl Pool {
+    pub fn name(self) -> &'static str {
+        match self {
+            Pool::General => "general",
+            Pool::Dma => "dma",
+            Pool::Graphics => "graphics",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Pool> {
+        POOLS.iter().copied().find(|pool| pool.name() == text)
+    }
+
+    // Dedicated pools only, the general one is the global allocator
+    fn slot(self) -> Option<&'static SpinLock<Option<FreeList>>> {
+        match self {
+            Pool::General => None,
+            Pool::Dma => Some(&DMA),
+            Pool::Graphics => Some(&GRAPHICS),
+        }
+    }
+}
+
+// None until init(), or when the pool did not fit
+static DMA: SpinLock<Option<FreeList>> = SpinLock::new(None);
+static GRAPHICS: SpinLock<Option<FreeList>> = SpinLock::new(None);
+
+#[derive(Clone, Copy)]
+pub struct PoolStats {
+    pub start: usize,
+    pub capacity: usize,
+    pub used: usize,
+    pub free_blocks: usize,
+    pub largest_free: usize,
+}
+
+// Take the graphics pool off the top of RAM. Returns the new end of RAM for
+// the frame allocator; runs before frame::init().
+pub fn reserve_graphics(ram_end: usize, frames_start: usize) -> usize {
+    let start = ram_end.saturating_sub(GRAPHICS_POOL_SIZE) & !(frame::PAGE_SIZE - 1);
+
+    // Leave the frame allocator at least as much as the pool takes
+    if start < frames_start || start - frames_start < GRAPHICS_POOL_SIZE {
+        return ram_end;
+    }
+
+    let list = unsafe { FreeList::init(start, ram_end - start, HeapType::BestFit) };
+    *GRAPHICS.lock() = Some(list);
+    start
+}
+
+// Take the DMA pool from the frame allocator. Runs after frame::init().
+pub fn reserve_dma() {
+    let order = frame::order_for(DMA_POOL_SIZE);
+    let Some(start) = frame::allocate(order) else {
+        return;
+    };
+
+    let size = frame::block_size(order);
+    let list = unsafe { FreeList::init(start, size, HeapType::BestFit) };
+    *DMA.lock() = Some(list);
+}
+
+// Null when the pool is out of memory (or was never set up)
+pub fn allocate_from(pool: Pool, layout: Layout) -> *mut u8 {
+    let Some(slot) = pool.slot() else {
+        return unsafe { alloc::alloc::alloc(layout) };
+    };
+
+    match slot.lock().as_mut() {
+        Some(list) => list
+            .allocate(layout.size(), layout.align())
+            .unwrap_or(core::ptr::null_mut()),
+        None => core::ptr::null_mut(),
+    }
+}
+
+// `ptr` must have come from allocate_from() with the same pool and layout
+pub fn free_to(pool: Pool, ptr: *mut u8, layout: Layout) {
+    let Some(slot) = pool.slot() else {
+        return unsafe { alloc::alloc::dealloc(ptr, layout) };
+    };
+
+    let freed = match slot.lock().as_mut() {
+        Some(list) => list.deallocate(ptr as usize),
+        None => Ok(()),
+    };
+    if let Err(error) = freed {
+        heap::refused_free(error);
+    }
+}
+
+// None for a dedicated pool that was never set up. The general pool's
+// capacity includes the regions the heap grew into, its start is the first
+// block's.
+pub fn stats(pool: Pool) -> Option<PoolStats> {
+    let Some(slot) = pool.slot() else {
+        let (used, capacity) = ALLOCATOR.usage();
+        let (free_blocks, _, largest_free) = ALLOCATOR.free_stats();
+        return Some(PoolStats {
+            start: map::heap_start(),
+            capacity,
+            used,
+            free_blocks,
+            largest_free,
+        });
+    };
+
+    let pools = slot.lock();
+    let list = pools.as_ref()?;
+    let (free_blocks, _, largest_free) = list.free_stats();
+    Some(PoolStats {
+        start: list.start_address,
+        capacity: list.capacity,
+        used: list.used,
+        free_blocks,
+        largest_free,
+    })
+}
+
+// A block from a pool that goes back to it when dropped
+pub struct Buffer {
+    pool: Pool,
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl Buffer {
+    // Zero-filled. None when the pool has no block that big left.
+    pub fn new(pool: Pool, size: usize, align: usize) -> Option<Buffer> {
+        let layout = Layout::from_size_align(size.max(1), align).ok()?;
+        let ptr = allocate_from(pool, layout);
+        if ptr.is_null() {
+            return None;
+        }
+
+        unsafe { core::ptr::write_bytes(ptr, 0, layout.size()) };
+        Some(Buffer { pool, ptr, layout })
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.ptr, self.layout.size()) }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        free_to(self.pool, self.ptr, self.layout);
+    }
+}
@@ -0,0 +1,96 @@
+use super::super::drivers::timer;
+use super::super::utils::locked::SpinLock;
+use super::config::SENTINEL_CHECK_MS;
+use core::ptr::{read_volatile, write_volatile};
+
+// ============================================================================
+// SENTINEL WORDS
+// A known pattern written at the edge of a region nobody should touch: past
+// the end of the heap, below the lowest address the boot stack may reach, or
+// around a buffer a driver hands to the GPU. A wild pointer or a runaway
+// stack tends to hit one of them long before it corrupts something that
+// crashes, so they are checked often: by the shell's idle loop every
+// SENTINEL_CHECK_MS, and by `heapcheck`. poll() looks at the painted
+// stacks' bottoms too (stack.rs).
+//
+// Sentinels stay for good, so only regions that live until reboot get one.
+// There are no threads, so "in the background" means between keystrokes.
+// ============================================================================
+
+pub const MAX_SENTINELS: usize = 8;
+
+// Room one sentinel takes, keeps whatever follows it 16-byte aligned
+pub const SENTINEL_SIZE: usize = 16;
+
+// Written as PATTERN, !PATTERN, so a region cleared or filled with one
+// repeated value never looks intact
+const PATTERN: u64 = 0x5E47_1E15_A5C3_3C5A;
+
+#[derive(Clone, Copy)]
+struct Sentinel {
+    address: usize,
+    name: &'static str,
+}
+
+struct Sentinels {
+    table: [Option<Sentinel>; MAX_SENTINELS],
+    last_check: u64,
+}
+
+static SENTINELS: SpinLock<Sentinels> = SpinLock::new(Sentinels {
+    table: [None; MAX_SENTINELS],
+    last_check: 0,
+});
+
+// Write a sentinel over the SENTINEL_SIZE bytes at `address` (8-byte
+// aligned) and start watching it. False when the table is full.
+pub fn place(address: usize, name: &'static str) -> bool {
+    let mut sentinels = SENTINELS.lock();
+    let Some(slot) = sentinels.table.iter_mut().find(|slot| slot.is_none()) else {
+        return false;
+    };
+
+    let words = address as *mut u64;
+    unsafe {
+        write_volatile(words, PATTERN);
+        write_volatile(words.add(1), !PATTERN);
+    }
+
+    *slot = Some(Sentinel { address, name });
+    true
+}
+
+// Panics naming the first clobbered sentinel, returns how many were checked
+pub fn check() -> usize {
+    // Copied out so the panic does not happen with the lock held
+    let table = SENTINELS.lock().table;
+
+    for sentinel in table.iter().flatten() {
+        let words = sentinel.address as *const u64;
+        let (first, second) = unsafe { (read_volatile(words), read_volatile(words.add(1))) };
+
+        if first != PATTERN || second != !PATTERN {
+            panic!(
+                "sentinel: {} at {:#x} clobbered ({:#018x} {:#018x}), wild write or overflow",
+                sentinel.name, sentinel.address, first, second
+            );
+        }
+    }
+
+    table.iter().flatten().count()
+}
+
+// Cheap enough to call in any polling loop, only checks every SENTINEL_CHECK_MS
+pub fn poll() {
+    let now = timer::uptime_ms();
+    {
+        let mut sentinels = SENTINELS.lock();
+        if now - sentinels.last_check < SENTINEL_CHECK_MS {
+            return;
+        }
+        sentinels.last_check = now;
+    }
+
+    check();
+    super::stack::check();
+}
@@ -0,0 +1,165 @@
+use super::super::utils::locked::SpinLock;
+use super::backend::{Backend, relocate};
+use super::config::HEAP_DEBUG;
+use super::heap::{Heap, HeapType};
+use core::alloc::Layout;
+use core::ptr::null_mut;
+
+// ============================================================================
+// SLAB CACHES
+// Small allocations skip the FreeList scan: each size class (16 to 512 bytes)
+// keeps a singly linked list of free objects, so allocating and freeing are
+// a pop and a push. When a class runs dry it takes a SLAB_CHUNK block from
+// the FreeList and cuts it into objects. Chunks are never handed back.
+//
+// GlobalAlloc gets the layout back on dealloc, so the size class (and with
+// it whether a pointer belongs here at all) always follows from the layout.
+//
+// This is the default heap backend; `--features heap-freelist` leaves the
+// caches out (see backend.rs).
+// ============================================================================
+
+pub const CLASS_SIZES: [usize; 6] = [16, 32, 64, 128, 256, 512];
+pub const SLAB_CHUNK: usize = 4096;
+
+// FreeList blocks are only 16-byte aligned, so are the objects cut from them
+const MAX_ALIGN: usize = 16;
+
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+pub struct Slabs {
+    free: [*mut FreeObject; CLASS_SIZES.len()],
+}
+
+static SLABS: SpinLock<Slabs> = SpinLock::new(Slabs {
+    free: [null_mut(); CLASS_SIZES.len()],
+});
+
+// Which class serves `layout`, None for anything the FreeList should handle
+pub fn class_for(layout: Layout) -> Option<usize> {
+    if layout.align() > MAX_ALIGN {
+        return None;
+    }
+    CLASS_SIZES.iter().position(|&size| layout.size() <= size)
+}
+
+// `refill` hands out a SLAB_CHUNK sized block when the class is empty. It
+// runs without the slab lock: the FreeList may publish a watermark event and
+// whoever hears it may well allocate.
+pub fn allocate(class: usize, refill: impl FnOnce() -> Option<*mut u8>) -> Option<*mut u8> {
+    if let Some(object) = pop(class) {
+        return Some(object);
+    }
+
+    let chunk = refill()?;
+    let size = CLASS_SIZES[class];
+
+    // Keep the first object, put the rest on the list
+    for index in 1..SLAB_CHUNK / size {
+        free(class, unsafe { chunk.add(index * size) });
+    }
+    Some(chunk)
+}
+
+fn pop(class: usize) -> Option<*mut u8> {
+    let mut slabs = SLABS.lock();
+    let object = slabs.free[class];
+
+    if object.is_null() {
+        return None;
+    }
+    slabs.free[class] = unsafe { (*object).next };
+    Some(object as *mut u8)
+}
+
+pub fn free(class: usize, ptr: *mut u8) {
+    let mut slabs = SLABS.lock();
+    let object = ptr as *mut FreeObject;
+
+    unsafe {
+        object.write(FreeObject {
+            next: slabs.free[class],
+        })
+    };
+    slabs.free[class] = object;
+}
+
+// The slab caches in front of the FreeList heap, as a heap backend
+pub struct SlabHeap(Heap);
+
+// Guarded blocks need the FreeList, so the debug heap bypasses the slabs
+fn slab_class(layout: Layout) -> Option<usize> {
+    if HEAP_DEBUG { None } else { class_for(layout) }
+}
+
+impl Backend for SlabHeap {
+    const NAME: &'static str = "slab";
+
+    const EMPTY: Self = SlabHeap(Heap::EMPTY);
+
+    unsafe fn init(&self, start: usize, size: usize) {
+        unsafe { self.0.init(start, size) }
+    }
+
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        match slab_class(layout) {
+            Some(class) => allocate(class, || {
+                let chunk = self.0.list_alloc(SLAB_CHUNK, MAX_ALIGN);
+                (!chunk.is_null()).then_some(chunk)
+            })
+            .unwrap_or(null_mut()),
+            None => self.0.allocate(layout),
+        }
+    }
+
+    fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        match slab_class(layout) {
+            Some(class) => free(class, ptr),
+            None => self.0.deallocate(ptr, layout),
+        }
+    }
+
+    fn reallocate(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let Ok(new_layout) = Layout::from_size_align(new_size, layout.align()) else {
+            return null_mut();
+        };
+
+        match (slab_class(layout), slab_class(new_layout)) {
+            // Same slab object still fits
+            (Some(old), Some(new)) if old == new => ptr,
+            (None, None) => self.0.reallocate(ptr, layout, new_size),
+            // Crossing between slab and FreeList (or between classes) always
+            // moves, since dealloc finds the owner from the size alone
+            _ => relocate(self, ptr, layout, new_size),
+        }
+    }
+
+    // Objects sitting in the caches count as used, the FreeList handed
+    // their chunks out
+    fn usage(&self) -> (usize, usize) {
+        self.0.usage()
+    }
+
+    fn free_stats(&self) -> (usize, usize, usize) {
+        self.0.free_stats()
+    }
+
+    fn check(&self) -> (usize, usize) {
+        self.0.check()
+    }
+
+    fn trim(&self, keep_start: usize, keep_end: usize) -> usize {
+        self.0.trim(keep_start, keep_end)
+    }
+
+    // Only what gets past the slab caches
+    fn strategy(&self) -> Option<HeapType> {
+        self.0.strategy()
+    }
+
+    fn set_strategy(&self, strategy: HeapType) -> bool {
+        self.0.set_strategy(strategy)
+    }
+}
@@ -0,0 +1,109 @@
+use super::super::cpu::smp;
+use super::super::utils::locked::{MAX_CORES, core_id};
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+// ============================================================================
+// STACK WATERMARKS
+// Without an MMU nothing stops a stack from growing past its end, so every
+// stack is painted with a pattern before it is used: the boot stack at the
+// top of _main, each other core's block before smp::start() releases the
+// core. Whatever still holds the pattern was never reached, which gives
+//
+//     high_water_mark(core)  the most bytes of the stack ever used
+//     check()                panics once the bottom CANARY_BYTES of any
+//                            painted stack lost the pattern: the stack ran
+//                            all the way down and probably beyond
+//
+// check() runs with the sentinel checks (shell idle loop, `heapcheck`). The
+// bottom is a tripwire, not a wall: it catches an overflow after the fact,
+// before the damage below it gets a chance to look like some other bug.
+// ============================================================================
+
+const PAINT: u64 = 0x57AC_C0DE_57AC_C0DE;
+
+pub const CANARY_BYTES: usize = 64;
+
+// Left alone below the stack pointer when painting the running stack, for
+// paint()'s own calls
+#[cfg(target_arch = "aarch64")]
+const MARGIN: usize = 256;
+
+static PAINTED: [AtomicBool; MAX_CORES] = [const { AtomicBool::new(false) }; MAX_CORES];
+
+fn current_sp() -> usize {
+    #[cfg(target_arch = "aarch64")]
+    {
+        let sp: usize;
+        unsafe {
+            core::arch::asm!("mov {0}, sp", out(reg) sp, options(nomem, nostack, preserves_flags));
+        }
+        sp - MARGIN
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    {
+        0
+    }
+}
+
+// Paint the stack of `core`: all of it for a core that is not running yet,
+// everything below the stack pointer for the calling core's own
+#[inline(never)]
+pub fn paint(core: usize) {
+    let (low, high) = smp::info(core).stack;
+    let end = if core == core_id() {
+        current_sp().min(high)
+    } else {
+        high
+    };
+    if end <= low {
+        return;
+    }
+
+    for address in (low..end).step_by(8) {
+        unsafe { write_volatile(address as *mut u64, PAINT) };
+    }
+    PAINTED[core].store(true, Ordering::Release);
+}
+
+// Most bytes of its stack `core` ever used, None if it was not painted
+pub fn high_water_mark(core: usize) -> Option<usize> {
+    if !PAINTED[core].load(Ordering::Acquire) {
+        return None;
+    }
+
+    let (low, high) = smp::info(core).stack;
+    let mut address = low;
+    while address < high && unsafe { read_volatile(address as *const u64) } == PAINT {
+        address += 8;
+    }
+    Some(high - address)
+}
+
+// Panics naming the core whose stack reached its bottom, returns how many
+// stacks were checked
+pub fn check() -> usize {
+    let mut checked = 0;
+
+    for (core, painted) in PAINTED.iter().enumerate() {
+        if !painted.load(Ordering::Acquire) {
+            continue;
+        }
+        let (low, high) = smp::info(core).stack;
+        let intact = (low..low + CANARY_BYTES)
+            .step_by(8)
+            .all(|address| unsafe { read_volatile(address as *const u64) } == PAINT);
+
+        if !intact {
+            panic!(
+                "stack overflow on core {}: all {} KiB used, down to {:#x} and maybe further",
+                core,
+                (high - low) / 1024,
+                low
+            );
+        }
+        checked += 1;
+    }
+    checked
+}
@@ -0,0 +1,175 @@
+use super::super::cpu::backtrace;
+use super::super::utils::locked::SpinLock;
+use super::config::HEAP_TRACE_SLOTS;
+use super::frame;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr::null_mut;
+
+// ============================================================================
+// HEAP TRACING
+// While tracing is on, every allocation the global allocator hands out is
+// written to a side table with its size and the call chain that asked for
+// it, and struck off again when it is freed. What is left after a while of
+// running the same thing over and over is the leak:
+//
+//     heap trace on       start (config::HEAP_TRACE starts it at boot)
+//     heap leaks          list what is still allocated, oldest first
+//
+// The table lives in frames of its own, not on the heap it watches, and
+// holds HEAP_TRACE_SLOTS allocations; past that new ones go uncounted (the
+// report says how many). Allocations from before tracing started are not in
+// it, and their frees are ignored. Call sites are return addresses, for
+// addr2line like the panic backtrace.
+// ============================================================================
+
+// Return addresses kept per allocation. The first ones are usually inside
+// alloc (Box::new, RawVec::grow); the caller that matters is further out.
+pub const DEPTH: usize = 6;
+
+// The allocator's own frames (GlobalAlloc::alloc and record()) are skipped
+const SKIP: usize = 1;
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub ptr: usize,
+    pub size: usize,
+    pub callers: [usize; DEPTH],
+}
+
+struct Table {
+    entries: *mut Entry,
+    order: usize,
+    count: usize,
+    // Allocations that found the table full
+    dropped: usize,
+}
+
+impl Table {
+    fn entries(&self) -> &[Entry] {
+        if self.entries.is_null() {
+            return &[];
+        }
+        unsafe { core::slice::from_raw_parts(self.entries, self.count) }
+    }
+}
+
+static TABLE: SpinLock<Table> = SpinLock::new(Table {
+    entries: null_mut(),
+    order: 0,
+    count: 0,
+    dropped: 0,
+});
+
+pub fn enabled() -> bool {
+    !TABLE.lock().entries.is_null()
+}
+
+// Start with an empty table. False when there are no frames for it.
+pub fn start() -> bool {
+    let order = frame::order_for(HEAP_TRACE_SLOTS * size_of::<Entry>());
+    let mut table = TABLE.lock();
+    if !table.entries.is_null() {
+        return true;
+    }
+
+    let Some(start) = frame::allocate(order) else {
+        return false;
+    };
+    *table = Table {
+        entries: start as *mut Entry,
+        order,
+        count: 0,
+        dropped: 0,
+    };
+    true
+}
+
+// Stop and hand the table's frames back
+pub fn stop() {
+    let mut table = TABLE.lock();
+    if !table.entries.is_null() {
+        frame::free(table.entries as usize, table.order);
+        table.entries = null_mut();
+        table.count = 0;
+    }
+}
+
+// Called by the global allocator for every allocation it hands out
+#[inline(never)]
+pub fn record(ptr: *mut u8, size: usize) {
+    let mut table = TABLE.lock();
+    if table.entries.is_null() {
+        return;
+    }
+    if table.count == HEAP_TRACE_SLOTS {
+        table.dropped += 1;
+        return;
+    }
+
+    let mut callers = [0; DEPTH];
+    backtrace::callers(SKIP, &mut callers);
+
+    unsafe {
+        table.entries.add(table.count).write(Entry {
+            ptr: ptr as usize,
+            size,
+            callers,
+        });
+    }
+    table.count += 1;
+}
+
+// Called by the global allocator for every free
+pub fn forget(ptr: *mut u8) {
+    let mut table = TABLE.lock();
+    let Some(index) = table
+        .entries()
+        .iter()
+        .rposition(|entry| entry.ptr == ptr as usize)
+    else {
+        return;
+    };
+
+    // Shift the rest down to keep them oldest first
+    let count = table.count;
+    unsafe {
+        let entries = core::slice::from_raw_parts_mut(table.entries, count);
+        entries.copy_within(index + 1.., index);
+    }
+    table.count -= 1;
+}
+
+pub struct Report {
+    // Oldest first
+    pub live: Vec<Entry>,
+    pub bytes: usize,
+    pub dropped: usize,
+}
+
+// What is allocated right now among what the table saw
+pub fn report() -> Report {
+    // Sized first (which lands in the table itself) so no allocation
+    // happens while the table is locked
+    let capacity = TABLE.lock().count + 1;
+    let mut live: Vec<Entry> = Vec::with_capacity(capacity);
+    let own = live.as_ptr() as usize;
+
+    let table = TABLE.lock();
+    live.extend(
+        table
+            .entries()
+            .iter()
+            .filter(|entry| entry.ptr != own)
+            .take(capacity),
+    );
+    let dropped = table.dropped;
+    drop(table);
+
+    let bytes = live.iter().map(|entry| entry.size).sum();
+    Report {
+        live,
+        bytes,
+        dropped,
+    }
+}
@@ -0,0 +1,115 @@
+use super::super::utils::events::{self, Event};
+use super::super::utils::locked::SpinLock;
+use super::super::utils::log;
+use super::config::HEAP_WATERMARKS;
+use alloc::vec::Vec;
+
+// ============================================================================
+// HEAP WATERMARK ALERTS
+// Warns once each time heap usage climbs past a threshold, so a long session
+// gets a heads-up well before alloc_error_handler ends everything. The
+// thresholds start out as config::HEAP_WATERMARKS, `heap watermarks` changes
+// them. Whoever wants to act on a crossing subscribes to the event bus
+// (Event::HeapWatermark).
+// ============================================================================
+
+pub const MAX_WATERMARKS: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct WatermarkEvent {
+    pub threshold_percent: usize,
+    pub used: usize,
+    pub capacity: usize,
+}
+
+struct Watermarks {
+    thresholds: [usize; MAX_WATERMARKS],
+    count: usize,
+    // How many thresholds (sorted ascending) usage is currently above
+    crossed: usize,
+}
+
+static WATERMARKS: SpinLock<Watermarks> = SpinLock::new(Watermarks::from_config());
+
+impl Watermarks {
+    const fn from_config() -> Self {
+        let mut thresholds = [0; MAX_WATERMARKS];
+        let mut i = 0;
+
+        while i < HEAP_WATERMARKS.len() && i < MAX_WATERMARKS {
+            thresholds[i] = HEAP_WATERMARKS[i];
+            i += 1;
+        }
+
+        Watermarks {
+            thresholds,
+            count: i,
+            crossed: 0,
+        }
+    }
+}
+
+// Replace the thresholds (percentages, any order). Extra entries are ignored.
+pub fn set_thresholds(percents: &[usize]) {
+    let mut marks = WATERMARKS.lock();
+    let count = percents.len().min(MAX_WATERMARKS);
+
+    marks.thresholds = [0; MAX_WATERMARKS];
+    marks.thresholds[..count].copy_from_slice(&percents[..count]);
+    marks.thresholds[..count].sort_unstable();
+    marks.count = count;
+    marks.crossed = 0;
+}
+
+// The current thresholds, ascending
+pub fn thresholds() -> Vec<usize> {
+    // Copied out first: allocating under the lock would come back into
+    // check() and deadlock
+    let (thresholds, count) = {
+        let marks = WATERMARKS.lock();
+        (marks.thresholds, marks.count)
+    };
+    thresholds[..count].to_vec()
+}
+
+// Called by the allocator after every operation, with the allocator lock released
+pub fn check(used: usize, capacity: usize) {
+    if capacity == 0 {
+        return;
+    }
+
+    let percent = used * 100 / capacity;
+
+    let event = {
+        let mut marks = WATERMARKS.lock();
+        let count = marks.count;
+        let now_crossed = marks.thresholds[..count]
+            .iter()
+            .filter(|&&threshold| percent >= threshold)
+            .count();
+
+        let event = if now_crossed > marks.crossed {
+            Some(WatermarkEvent {
+                threshold_percent: marks.thresholds[now_crossed - 1],
+                used,
+                capacity,
+            })
+        } else {
+            None
+        };
+
+        // Dropping back below a threshold re-arms it
+        marks.crossed = now_crossed;
+        event
+    };
+
+    if let Some(event) = event {
+        log::warn!(
+            "heap usage passed {}% ({} / {} bytes)",
+            event.threshold_percent,
+            event.used,
+            event.capacity
+        );
+        events::publish(Event::HeapWatermark(event));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::drivers::console::{self, CONSOLE};
+use crate::drivers::mailbox::{
+    self, CLOCK_ARM, DEVICE_SD_CARD, DEVICE_USB, MboxMessageBuilder, TAG_GET_CLOCK_RATE,
+    TAG_GET_MIN_CLOCK_RATE,
+};
+use crate::drivers::timer;
+use crate::drivers::watchdog;
+use crate::initcall::{InitError, initcall};
+use crate::utils::log;
+use core::arch::asm;
+
+// ============================================================================
+// LOW-POWER IDLE
+// deep_idle() lowers the ARM clock to the firmware's minimum, then sleeps
+// the core until a byte arrives on the UART or the alarm runs out. Cores 1-3
+// never leave the wfe loop in boot.s, so there is nothing else to park.
+//
+// There is no interrupt controller driver yet, so a plain WFI would never
+// see a UART or timer interrupt. Instead the generic timer's event stream
+// (CNTKCTL_EL1.EVNTEN) wakes WFE about once a millisecond; each wake-up
+// costs a couple of register reads before the core goes back to sleep.
+// ============================================================================
+
+// Event stream period we aim for
+const EVENT_PERIOD_US: u64 = 1000;
+
+const CNTKCTL_EVNTEN: u64 = 1 << 2;
+const CNTKCTL_EVNTI_SHIFT: u64 = 4;
+const CNTKCTL_EVNTI_MASK: u64 = 0xF << CNTKCTL_EVNTI_SHIFT;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Wake {
+    Uart,
+    Alarm,
+}
+
+fn read_cntkctl() -> u64 {
+    let value: u64;
+    unsafe {
+        asm!("mrs {0}, cntkctl_el1", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+fn write_cntkctl(value: u64) {
+    unsafe {
+        asm!("msr cntkctl_el1, {0}", "isb", in(reg) value, options(nomem, nostack, preserves_flags));
+    }
+}
+
+// An event fires every 2^(EVNTI + 1) counter ticks
+fn event_stream_on() -> u64 {
+    let saved = read_cntkctl();
+    let ticks = timer::frequency() * EVENT_PERIOD_US / 1_000_000;
+    let evnti = (ticks.max(2).ilog2() as u64 - 1).min(15);
+
+    write_cntkctl((saved & !CNTKCTL_EVNTI_MASK) | CNTKCTL_EVNTEN | (evnti << CNTKCTL_EVNTI_SHIFT));
+    saved
+}
+
+// Slowest ARM clock the firmware allows, returning the rate to go back to
+fn slow_down() -> Option<u32> {
+    // Both rates in one round trip
+    let mut message = MboxMessageBuilder::new();
+    let current = message.add_tag(TAG_GET_CLOCK_RATE, &[CLOCK_ARM, 0]);
+    let minimum = message.add_tag(TAG_GET_MIN_CLOCK_RATE, &[CLOCK_ARM, 0]);
+    message.send().ok()?;
+    let current = *message.response(current).ok()?.get(1)?;
+    let minimum = *message.response(minimum).ok()?.get(1)?;
+
+    if minimum == 0 || minimum >= current {
+        return None;
+    }
+    mailbox::set_clock_rate(CLOCK_ARM, minimum).ok()?;
+    Some(current)
+}
+
+// Sleep until UART input or `timeout_ms` (None: no alarm). The byte that
+// woke us is left in the FIFO for the caller.
+pub fn deep_idle(timeout_ms: Option<u64>) -> Wake {
+    let restore_rate = slow_down();
+    let saved_cntkctl = event_stream_on();
+    let deadline = timeout_ms.map(|ms| timer::uptime_ms().saturating_add(ms));
+
+    let wake = loop {
+        watchdog::feed();
+        console::drain();
+        if CONSOLE.lock().has_input() {
+            break Wake::Uart;
+        }
+        if deadline.is_some_and(|deadline| timer::uptime_ms() >= deadline) {
+            break Wake::Alarm;
+        }
+        unsafe { asm!("wfe", options(nomem, nostack, preserves_flags)) };
+    };
+
+    write_cntkctl(saved_cntkctl);
+    if let Some(rate) = restore_rate
+        && let Err(err) = mailbox::set_clock_rate(CLOCK_ARM, rate)
+    {
+        log::warn!("idle: could not restore the ARM clock: {}", err);
+    }
+
+    wake
+}
+
+// ============================================================================
+// REBOOT AND HALT
+// Both go through the PM watchdog (drivers/watchdog.rs): arm it with a few
+// ticks and let it reset the chip. The firmware then boots from the
+// partition number left in PM_RSTS; partition 63 is its convention for
+// "stay halted" (until power is cycled or, on the RPi4, the power button is
+// pressed). RAM keeps its contents across the reset, which is what the crash
+// log relies on.
+//
+// The RPi5 has no PM block at WATCHDOG_BASE, so there both just park the core.
+// ============================================================================
+
+// Seconds the panic handler waits before rebooting, None to stay halted
+pub const PANIC_REBOOT_SECONDS: Option<u64> = None;
+
+// Partition number (in the PM_RSTS encoding) the firmware takes as "halt"
+const PARTITION_HALT: u32 = 0x555;
+
+fn reset(partition: u32) -> ! {
+    console::flush();
+    watchdog::reset(partition);
+
+    loop {
+        unsafe { asm!("wfe", options(nomem, nostack, preserves_flags)) };
+    }
+}
+
+pub fn reboot() -> ! {
+    reset(0)
+}
+
+// Power down the SD card and USB, then reset into the firmware's halt state
+pub fn halt() -> ! {
+    for (name, device) in [("SD card", DEVICE_SD_CARD), ("USB", DEVICE_USB)] {
+        if let Err(err) = mailbox::set_power_state(device, false) {
+            log::debug!("halt: {} left powered: {}", name, err);
+        }
+    }
+
+    reset(PARTITION_HALT)
+}
+
+// Arm the watchdog when watchdog::START_AT_BOOT_MS asks for it
+fn init_watchdog() -> Result<(), InitError> {
+    let Some(timeout_ms) = watchdog::START_AT_BOOT_MS else {
+        return Ok(());
+    };
+    if !watchdog::start(timeout_ms) {
+        return Err(InitError::Absent("no PM block"));
+    }
+    log::info!("Watchdog armed ({} ms).", timeout_ms);
+    Ok(())
+}
+
+initcall!(Late, "watchdog", init_watchdog);
@@ -0,0 +1,38 @@
+// ============================================================================
+// QEMU EXIT
+// Ends the emulator with a status code, which is how `cargo test --features
+// qemu` reports its result to whoever started it. It goes through Arm
+// semihosting: an `hlt #0xf000` with the call number in x0 and a parameter
+// block in x1, which QEMU handles itself when started with -semihosting
+// (scripts/qemu-run.sh does that).
+//
+// Without -semihosting, or on real hardware, the hlt is an undefined
+// instruction, so this only exists in the qemu test build.
+// ============================================================================
+
+const SYS_EXIT: u64 = 0x18;
+
+// "The application exited", with the status in the second word
+const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x20026;
+
+pub fn exit(code: u32) -> ! {
+    // The report must be out before the emulator goes
+    crate::drivers::console::flush();
+    let block: [u64; 2] = [ADP_STOPPED_APPLICATION_EXIT, code as u64];
+
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!(
+            "hlt #0xf000",
+            in("x0") SYS_EXIT,
+            in("x1") &block,
+            options(nostack),
+        );
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    let _ = (SYS_EXIT, block);
+
+    // Only reached if nothing took the exit
+    loop {}
+}
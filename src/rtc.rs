@@ -0,0 +1,146 @@
+use crate::buildinfo;
+use crate::drivers::rtc::{self, DateTime, RtcError};
+use crate::drivers::timer;
+use crate::initcall::{InitError, initcall};
+use crate::utils::locked::SpinLock;
+use crate::utils::log;
+
+// ============================================================================
+// WALL-CLOCK TIME
+// rtc::now() is the date and time, UTC. init() looks for something that
+// kept the time while the board was off, in this order:
+//
+//     ds3231      an RTC module on the I2C header
+//     firmware    the firmware's RTC, on boards with an RTC battery
+//     build time  neither answered: the clock starts at the time the kernel
+//                 was built, which is wrong but at least moves forward
+//
+// The clock is read once and carried on with the generic timer, so now()
+// costs no I2C traffic. set() (`date 2026-01-31 12:00:00`) moves the clock
+// and writes the new time back to whichever RTC it came from.
+// ============================================================================
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Ds3231,
+    Firmware,
+    BuildTime,
+    // Set by hand with nothing to keep it across a reboot
+    Manual,
+}
+
+impl Source {
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Ds3231 => "ds3231",
+            Source::Firmware => "firmware",
+            Source::BuildTime => "build time",
+            Source::Manual => "set by hand",
+        }
+    }
+}
+
+struct Clock {
+    source: Source,
+    // Seconds since 1970 when the generic timer read zero
+    boot_unix: u64,
+}
+
+static CLOCK: SpinLock<Clock> = SpinLock::new(Clock {
+    source: Source::BuildTime,
+    boot_unix: 0,
+});
+
+fn uptime_s() -> u64 {
+    timer::uptime_ms() / 1000
+}
+
+fn build_time() -> DateTime {
+    let text = buildinfo::BUILD_TIME.trim_end_matches(" UTC");
+    DateTime::parse(text).unwrap_or(DateTime::from_unix(0))
+}
+
+fn start(source: Source, time: DateTime) {
+    *CLOCK.lock() = Clock {
+        source,
+        boot_unix: time.to_unix().saturating_sub(uptime_s()),
+    };
+}
+
+fn init() -> Result<(), InitError> {
+    let (source, time) = match rtc::ds3231_read() {
+        Ok(time) => (Source::Ds3231, time),
+        Err(ds3231) => match rtc::firmware_read() {
+            Ok(time) => (Source::Firmware, time),
+            Err(firmware) => {
+                log::debug!("No RTC: ds3231: {}; firmware: {}", ds3231, firmware);
+                (Source::BuildTime, build_time())
+            }
+        },
+    };
+
+    start(source, time);
+    log::info!("Clock: {} UTC ({}).", time, source.name());
+    Ok(())
+}
+
+initcall!(Device, "rtc", init);
+
+pub fn now() -> DateTime {
+    let boot_unix = CLOCK.lock().boot_unix;
+    DateTime::from_unix(boot_unix + uptime_s())
+}
+
+pub fn source() -> Source {
+    CLOCK.lock().source
+}
+
+// Moves the clock even when writing the RTC fails; the error says the
+// time will not survive a reboot
+pub fn set(time: DateTime) -> Result<(), RtcError> {
+    let source = source();
+    let written = match source {
+        Source::Ds3231 => rtc::ds3231_write(&time),
+        Source::Firmware => rtc::firmware_write(&time),
+        Source::BuildTime | Source::Manual => Ok(()),
+    };
+
+    let source = match source {
+        Source::BuildTime => Source::Manual,
+        source => source,
+    };
+    start(source, time);
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::drivers::rtc::DateTime;
+    use crate::utils::testing::{Failure, check, check_eq};
+
+    #[test_case]
+    fn unix_round_trip() -> Result<(), Failure> {
+        check_eq!(
+            DateTime::from_unix(0),
+            DateTime::new(1970, 1, 1, 0, 0, 0).unwrap()
+        );
+
+        // 2024-02-29 12:34:56, a leap day
+        let leap_day = DateTime::new(2024, 2, 29, 12, 34, 56).unwrap();
+        check_eq!(leap_day.to_unix(), 1_709_210_096);
+        check_eq!(DateTime::from_unix(1_709_210_096), leap_day);
+        check_eq!(leap_day.weekday(), 4);
+        Ok(())
+    }
+
+    #[test_case]
+    fn parse_dates() -> Result<(), Failure> {
+        let expected = DateTime::new(2026, 10, 16, 8, 5, 0);
+        check_eq!(DateTime::parse("2026-10-16 08:05:00"), expected);
+        check_eq!(DateTime::parse("2026-10-16T08:05:00"), expected);
+        check!(DateTime::parse("2025-02-29 00:00:00").is_none());
+        check!(DateTime::parse("2026-10-16 24:00:00").is_none());
+        check!(DateTime::parse("2026-10-16").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use crate::drivers::mailbox::{self, MailboxError};
+use crate::drivers::timer;
+use crate::drivers::uart::UART;
+use crate::memory::frame;
+use crate::memory::heap::{FreeList, HeapType};
+use crate::utils::testing::{Failure, Suite, check, check_eq};
+
+// ============================================================================
+// POWER-ON SELF-TEST
+// With the `selftest` feature, boot runs these checks right before the
+// shell and prints one line per check and a summary, on the UART and every
+// other console. They look at the hardware as much as at the code, so a
+// board that boots but has something broken shows it right away:
+//
+//     allocator    split and coalesce on a scratch FreeList
+//     mailbox      a round trip to the firmware
+//     uart         bytes through the PL011's internal loopback
+//     timer        the generic timer runs, and agrees with the system timer
+//
+// A check for hardware the board does not have is skipped, not failed.
+// ============================================================================
+
+const ARENA: usize = 64 * 1024;
+
+const LOOPBACK_PATTERN: &[u8] = b"\x55\xAAddos\x00\xFF";
+
+const DELAY_MS: u64 = 5;
+// How far the two timers may disagree over DELAY_MS, in percent
+const TIMER_TOLERANCE: u64 = 20;
+
+// Returns whether every check that ran passed
+pub fn run() -> bool {
+    let mut suite = Suite::new("selftest");
+
+    suite.run("allocator", allocator);
+
+    match mailbox::board_revision() {
+        Err(MailboxError::Unavailable) => suite.skip("mailbox", "no firmware mailbox"),
+        _ => suite.run("mailbox", mailbox_round_trip),
+    }
+
+    if cfg!(feature = "rpi5") {
+        suite.skip("uart", "console UART set up by the firmware");
+    } else {
+        suite.run("uart", uart_loopback);
+    }
+
+    suite.run("timer", timer_sanity);
+
+    suite.finish()
+}
+
+fn allocator() -> Result<(), Failure> {
+    let order = frame::order_for(ARENA);
+    let start = frame::allocate(order);
+    check!(start.is_some());
+    let start = start.unwrap_or_default();
+
+    let result = split_and_coalesce(start, frame::block_size(order));
+    frame::free(start, order);
+    result
+}
+
+fn split_and_coalesce(start: usize, size: usize) -> Result<(), Failure> {
+    let mut list = unsafe { FreeList::init(start, size, HeapType::FirstFit) };
+    let initial = list.free_stats();
+    check_eq!(initial.0, 1);
+
+    let blocks = [100, 200, 300].map(|size| list.allocate(size, 16));
+    check!(blocks.iter().all(Option::is_some));
+    let [first, middle, last] = blocks.map(|block| block.map_or(0, |ptr| ptr as usize));
+
+    // A hole in the middle: a block of its own next to the rest
+    check!(list.deallocate(middle).is_ok());
+    check_eq!(list.free_stats().0, 2);
+
+    // Freeing its neighbours merges everything back into one block
+    check!(list.deallocate(first).is_ok());
+    check!(list.deallocate(last).is_ok());
+    check_eq!(list.free_stats(), initial);
+    check_eq!(list.used, 0);
+
+    // And a double free is refused
+    check!(list.deallocate(middle).is_err());
+    Ok(())
+}
+
+fn mailbox_round_trip() -> Result<(), Failure> {
+    check!(mailbox::board_revision().is_ok_and(|revision| revision != 0));
+    check!(mailbox::arm_memory().is_ok_and(|(_, size)| size > 0));
+    check!(mailbox::clock_rate(mailbox::CLOCK_ARM).is_ok_and(|hz| hz > 0));
+    Ok(())
+}
+
+fn uart_loopback() -> Result<(), Failure> {
+    let matched = UART.lock().loopback(LOOPBACK_PATTERN);
+    check_eq!(matched, Ok(LOOPBACK_PATTERN.len()));
+    Ok(())
+}
+
+fn timer_sanity() -> Result<(), Failure> {
+    check!(timer::frequency() > 0);
+
+    let (start, system_start) = (timer::counter(), timer::system_timer_us());
+    timer::delay_ms(DELAY_MS);
+    let (end, system_end) = (timer::counter(), timer::system_timer_us());
+
+    let elapsed_us = timer::ticks_to_us(end - start);
+    check!(end > start);
+    check!(elapsed_us >= DELAY_MS * 1000);
+
+    if let (Some(system_start), Some(system_end)) = (system_start, system_end) {
+        let system_us = system_end.wrapping_sub(system_start) as u64;
+        let tolerance = elapsed_us * TIMER_TOLERANCE / 100;
+        check!(system_us.abs_diff(elapsed_us) <= tolerance);
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use crate::fs::{FsError, vfs};
+use crate::utils::locked::SpinLock;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// ============================================================================
+// SHELL ALIASES
+// `alias hm='hexdump 0x280000 256'` makes `hm` expand to that text before the
+// command is looked up. Aliases are saved to ALIAS_FILE, one `name=text` per
+// line, and loaded again when the shell starts.
+// ============================================================================
+
+pub const ALIAS_FILE: &str = "/boot/aliases.txt";
+
+static ALIASES: SpinLock<Vec<(String, String)>> = SpinLock::new(Vec::new());
+
+// "hm='hexdump 0x280000 256'" -> ("hm", "hexdump 0x280000 256")
+pub fn parse(definition: &str) -> Option<(&str, &str)> {
+    let (name, text) = definition.split_once('=')?;
+    let name = name.trim();
+    let text = text.trim();
+
+    let text = text
+        .strip_prefix('\'')
+        .and_then(|t| t.strip_suffix('\''))
+        .or_else(|| text.strip_prefix('"').and_then(|t| t.strip_suffix('"')))
+        .unwrap_or(text);
+
+    if name.is_empty() || name.contains(char::is_whitespace) || text.is_empty() {
+        return None;
+    }
+    Some((name, text))
+}
+
+pub fn set(name: &str, text: &str) {
+    let mut aliases = ALIASES.lock();
+
+    match aliases.iter_mut().find(|(n, _)| n == name) {
+        Some(entry) => entry.1 = String::from(text),
+        None => aliases.push((String::from(name), String::from(text))),
+    }
+}
+
+pub fn remove(name: &str) -> bool {
+    let mut aliases = ALIASES.lock();
+    let before = aliases.len();
+    aliases.retain(|(n, _)| n != name);
+    aliases.len() != before
+}
+
+pub fn list() -> Vec<(String, String)> {
+    ALIASES.lock().clone()
+}
+
+// Replace the first word of `line` if it is an alias. Only one level deep, so
+// `ls='ls /boot'` does what it looks like instead of looping.
+pub fn expand(line: &str) -> Option<String> {
+    let trimmed = line.trim_start();
+    let (first, rest) = match trimmed.split_once(char::is_whitespace) {
+        Some((first, rest)) => (first, rest),
+        None => (trimmed, ""),
+    };
+
+    let aliases = ALIASES.lock();
+    let (_, text) = aliases.iter().find(|(name, _)| name == first)?;
+
+    let mut expanded = text.clone();
+    if !rest.is_empty() {
+        expanded.push(' ');
+        expanded.push_str(rest);
+    }
+    Some(expanded)
+}
+
+pub fn save() -> Result<(), FsError> {
+    let mut contents = String::new();
+    for (name, text) in list() {
+        contents.push_str(&name);
+        contents.push('=');
+        contents.push_str(&text);
+        contents.push('\n');
+    }
+
+    vfs::write_all(ALIAS_FILE, contents.as_bytes())
+}
+
+// Returns how many aliases were read. A missing file just means none yet.
+pub fn load() -> Result<usize, FsError> {
+    let data = match vfs::read_to_vec(ALIAS_FILE) {
+        Ok(data) => data,
+        Err(FsError::NotFound) => return Ok(0),
+        Err(err) => return Err(err),
+    };
+
+    let mut count = 0;
+    for line in String::from_utf8_lossy(&data).lines() {
+        if let Some((name, text)) = parse(line) {
+            set(name, text);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
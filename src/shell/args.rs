@@ -0,0 +1,282 @@
+use super::parse_number;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+// ============================================================================
+// COMMAND ARGUMENTS
+// Each command lists its arguments as a table of Arg specs. The shell splits
+// the line into words (honouring quotes), checks them against the table and
+// hands the command an Args to query by name. The same table produces the
+// usage line shown by `help` and on errors, so the two cannot drift apart.
+// ============================================================================
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    // --name or -n
+    Flag,
+    // --name value or -n value
+    Option,
+    Required,
+    Optional,
+    // Everything left over, taken verbatim (no flag parsing)
+    Rest,
+}
+
+#[derive(Clone, Copy)]
+pub struct Arg {
+    pub name: &'static str,
+    pub short: Option<char>,
+    pub kind: Kind,
+    // Value must parse as a decimal or 0x-prefixed number
+    pub numeric: bool,
+    pub help: &'static str,
+}
+
+impl Arg {
+    pub const fn flag(name: &'static str, short: char, help: &'static str) -> Arg {
+        Arg::new(name, Some(short), Kind::Flag, help)
+    }
+
+    pub const fn option(name: &'static str, short: char, help: &'static str) -> Arg {
+        Arg::new(name, Some(short), Kind::Option, help)
+    }
+
+    pub const fn required(name: &'static str, help: &'static str) -> Arg {
+        Arg::new(name, None, Kind::Required, help)
+    }
+
+    pub const fn optional(name: &'static str, help: &'static str) -> Arg {
+        Arg::new(name, None, Kind::Optional, help)
+    }
+
+    pub const fn rest(name: &'static str, help: &'static str) -> Arg {
+        Arg::new(name, None, Kind::Rest, help)
+    }
+
+    const fn new(name: &'static str, short: Option<char>, kind: Kind, help: &'static str) -> Arg {
+        Arg {
+            name,
+            short,
+            kind,
+            numeric: false,
+            help,
+        }
+    }
+
+    pub const fn number(self) -> Arg {
+        Arg {
+            numeric: true,
+            ..self
+        }
+    }
+
+    fn is_named(&self) -> bool {
+        matches!(self.kind, Kind::Flag | Kind::Option)
+    }
+}
+
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            Kind::Flag => write!(f, "[-{}]", self.short.unwrap_or('?')),
+            Kind::Option => write!(f, "[-{} <{}>]", self.short.unwrap_or('?'), self.name),
+            Kind::Required => write!(f, "<{}>", self.name),
+            Kind::Optional => write!(f, "[{}]", self.name),
+            Kind::Rest => write!(f, "[{}]...", self.name),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ArgError {
+    UnknownFlag(String),
+    MissingValue(&'static str),
+    Missing(&'static str),
+    Unexpected(String),
+    NotANumber(&'static str, String),
+    UnclosedQuote,
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgError::UnknownFlag(flag) => write!(f, "unknown option {}", flag),
+            ArgError::MissingValue(name) => write!(f, "--{} needs a value", name),
+            ArgError::Missing(name) => write!(f, "missing <{}>", name),
+            ArgError::Unexpected(word) => write!(f, "unexpected argument '{}'", word),
+            ArgError::NotANumber(name, word) => write!(f, "{}: '{}' is not a number", name, word),
+            ArgError::UnclosedQuote => write!(f, "unterminated quote"),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Splitting
+// ----------------------------------------------------------------------------
+
+// Split on whitespace, keeping 'single' or "double" quoted text together.
+// Quotes can start mid-word: name='a b' is one word, name=a b.
+pub fn split(line: &str) -> Result<Vec<String>, ArgError> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(core::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err(ArgError::UnclosedQuote);
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+// ----------------------------------------------------------------------------
+// Parsing
+// ----------------------------------------------------------------------------
+
+pub struct Args<'a> {
+    flags: Vec<&'static str>,
+    values: Vec<(&'static str, &'a str)>,
+    rest: Vec<&'a str>,
+}
+
+impl<'a> Args<'a> {
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.contains(&name)
+    }
+
+    // An option or positional argument by name
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.values
+            .iter()
+            .find(|(arg, _)| *arg == name)
+            .map(|&(_, value)| value)
+    }
+
+    // Only for arguments declared with .number(), which parse() has checked
+    pub fn number(&self, name: &str) -> Option<usize> {
+        self.get(name).and_then(parse_number)
+    }
+
+    pub fn rest(&self) -> &[&'a str] {
+        &self.rest
+    }
+}
+
+fn check_number(arg: &Arg, word: &str) -> Result<(), ArgError> {
+    if arg.numeric && parse_number(word).is_none() {
+        return Err(ArgError::NotANumber(arg.name, String::from(word)));
+    }
+    Ok(())
+}
+
+fn find_named<'s>(spec: &'s [Arg], word: &str) -> Option<&'s Arg> {
+    match word.strip_prefix("--") {
+        Some(long) => spec.iter().find(|arg| arg.is_named() && arg.name == long),
+        None => {
+            let mut chars = word[1..].chars();
+            let short = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            spec.iter()
+                .find(|arg| arg.is_named() && arg.short == Some(short))
+        }
+    }
+}
+
+pub fn parse<'a>(spec: &[Arg], words: &[&'a str]) -> Result<Args<'a>, ArgError> {
+    let mut args = Args {
+        flags: Vec::new(),
+        values: Vec::new(),
+        rest: Vec::new(),
+    };
+
+    let positionals: Vec<&Arg> = spec
+        .iter()
+        .filter(|arg| matches!(arg.kind, Kind::Required | Kind::Optional))
+        .collect();
+    let takes_rest = spec.iter().any(|arg| arg.kind == Kind::Rest);
+    let mut next_positional = 0;
+    let mut flags_done = false;
+    let mut words = words.iter().copied();
+
+    while let Some(word) = words.next() {
+        if !flags_done && word == "--" {
+            flags_done = true;
+            continue;
+        }
+
+        // Past the last positional, anything that is not one of our own flags
+        // starts the rest, taken verbatim: `exec prog -v` passes -v on
+        let own_flag = !flags_done && word.starts_with('-') && find_named(spec, word).is_some();
+        if takes_rest && next_positional == positionals.len() && !own_flag {
+            args.rest.push(word);
+            args.rest.extend(words.by_ref());
+            break;
+        }
+
+        if !flags_done && word.len() > 1 && word.starts_with('-') {
+            let arg =
+                find_named(spec, word).ok_or_else(|| ArgError::UnknownFlag(String::from(word)))?;
+
+            if arg.kind == Kind::Flag {
+                args.flags.push(arg.name);
+            } else {
+                let value = words.next().ok_or(ArgError::MissingValue(arg.name))?;
+                check_number(arg, value)?;
+                args.values.push((arg.name, value));
+            }
+            continue;
+        }
+
+        let Some(arg) = positionals.get(next_positional) else {
+            return Err(ArgError::Unexpected(String::from(word)));
+        };
+        check_number(arg, word)?;
+        args.values.push((arg.name, word));
+        next_positional += 1;
+    }
+
+    if let Some(missing) = positionals[next_positional..]
+        .iter()
+        .find(|arg| arg.kind == Kind::Required)
+    {
+        return Err(ArgError::Missing(missing.name));
+    }
+
+    Ok(args)
+}
+
+// "sdbench [-w] [sectors]"
+pub fn usage(name: &str, spec: &[Arg]) -> String {
+    let mut text = String::from(name);
+
+    for arg in spec {
+        let _ = write!(text, " {}", arg);
+    }
+    text
+}
@@ -0,0 +1,125 @@
+use crate::drivers::button::{self, Press};
+use crate::fs::{FsError, vfs};
+use crate::utils::locked::SpinLock;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+// ============================================================================
+// BUTTON BINDINGS
+// `button 3 long reboot` runs `reboot` when the button on GPIO3 is held, so
+// a headless board can be driven without the serial console. Bindings are
+// saved to BUTTON_FILE, one `pin short|long command` per line, and loaded
+// again when the shell starts.
+// ============================================================================
+
+pub const BUTTON_FILE: &str = "/boot/buttons.txt";
+
+pub struct Binding {
+    pub pin: u32,
+    pub press: Press,
+    pub command: String,
+}
+
+static BINDINGS: SpinLock<Vec<Binding>> = SpinLock::new(Vec::new());
+
+pub fn parse_press(text: &str) -> Option<Press> {
+    match text {
+        "short" => Some(Press::Short),
+        "long" => Some(Press::Long),
+        _ => None,
+    }
+}
+
+pub fn press_name(press: Press) -> &'static str {
+    match press {
+        Press::Short => "short",
+        Press::Long => "long",
+    }
+}
+
+// False when the button driver has no slot left for another pin
+pub fn bind(pin: u32, press: Press, command: &str) -> bool {
+    if !button::watch(pin) {
+        return false;
+    }
+
+    let mut bindings = BINDINGS.lock();
+    match bindings
+        .iter_mut()
+        .find(|binding| binding.pin == pin && binding.press == press)
+    {
+        Some(binding) => binding.command = String::from(command),
+        None => bindings.push(Binding {
+            pin,
+            press,
+            command: String::from(command),
+        }),
+    }
+    true
+}
+
+// Drop every binding of `pin` and stop watching it
+pub fn unbind(pin: u32) -> bool {
+    let mut bindings = BINDINGS.lock();
+    let before = bindings.len();
+    bindings.retain(|binding| binding.pin != pin);
+
+    let removed = bindings.len() != before;
+    drop(bindings);
+
+    button::unwatch(pin);
+    removed
+}
+
+pub fn list() -> Vec<(u32, Press, String)> {
+    BINDINGS
+        .lock()
+        .iter()
+        .map(|binding| (binding.pin, binding.press, binding.command.clone()))
+        .collect()
+}
+
+// Poll the buttons, returns the command bound to whatever just happened
+pub fn poll() -> Option<String> {
+    let (pin, press) = button::poll()?;
+
+    BINDINGS
+        .lock()
+        .iter()
+        .find(|binding| binding.pin == pin && binding.press == press)
+        .map(|binding| binding.command.clone())
+}
+
+pub fn save() -> Result<(), FsError> {
+    let mut contents = String::new();
+    for (pin, press, command) in list() {
+        let _ = writeln!(contents, "{} {} {}", pin, press_name(press), command);
+    }
+
+    vfs::write_all(BUTTON_FILE, contents.as_bytes())
+}
+
+// Returns how many bindings were read. A missing file just means none yet.
+pub fn load() -> Result<usize, FsError> {
+    let data = match vfs::read_to_vec(BUTTON_FILE) {
+        Ok(data) => data,
+        Err(FsError::NotFound) => return Ok(0),
+        Err(err) => return Err(err),
+    };
+
+    let mut count = 0;
+    for line in String::from_utf8_lossy(&data).lines() {
+        let mut parts = line.trim().splitn(3, ' ');
+        let pin = parts.next().and_then(|pin| pin.parse().ok());
+        let press = parts.next().and_then(parse_press);
+        let command = parts.next().map(str::trim).filter(|c| !c.is_empty());
+
+        if let (Some(pin), Some(press), Some(command)) = (pin, press, command)
+            && bind(pin, press, command)
+        {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,1744 @@
+use super::args::{self, Arg, Args, Kind};
+use super::{alias, buttons, history, script, theme as theme_file};
+use crate::buildinfo;
+use crate::cmdline;
+use crate::cpu::{gdb, smp};
+use crate::devices;
+use crate::drivers::console::{self, Backend, CONSOLE};
+use crate::drivers::dma;
+use crate::drivers::gpio::{self, Edge, Function, Pull};
+use crate::drivers::locked::{MAX_CORES, core_id};
+use crate::drivers::mailbox::{self, CLOCK_ARM, CLOCK_CORE};
+use crate::drivers::profile;
+use crate::drivers::pulse;
+use crate::drivers::pwm;
+use crate::drivers::rng;
+use crate::drivers::rtc::DateTime;
+use crate::drivers::sdio;
+use crate::drivers::timer;
+use crate::drivers::uart::{LineSettings, UART};
+use crate::drivers::watchdog;
+use crate::fs::{self, vfs};
+use crate::hardwareselect;
+use crate::input;
+use crate::loader;
+use crate::memory::config::PROGRAM_SIZE;
+use crate::memory::heap::HeapType;
+use crate::memory::map::Window;
+use crate::memory::pool::{self, Pool};
+use crate::memory::{self, bench, frame, map, memtest, sentinel, stack, trace, watermark};
+use crate::power::{self, Wake};
+use crate::rtc;
+use crate::utils::theme::{Normal, Paint};
+use crate::utils::{crashlog, log, theme};
+use crate::xmodem;
+use crate::{print, println};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+pub struct Command {
+    pub name: &'static str,
+    pub args: &'static [Arg],
+    pub help: &'static str,
+    pub run: fn(&Args),
+}
+
+impl Command {
+    pub fn usage(&self) -> String {
+        args::usage(self.name, self.args)
+    }
+}
+
+pub static COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        args: &[Arg::optional(
+            "command",
+            "show the arguments of one command",
+        )],
+        help: "list available commands",
+        run: cmd_help,
+    },
+    Command {
+        name: "alias",
+        args: &[Arg::rest("name='command args'", "alias to define")],
+        help: "list aliases, or define one (saved to /boot/aliases.txt)",
+        run: cmd_alias,
+    },
+    Command {
+        name: "unalias",
+        args: &[Arg::required("name", "alias to remove")],
+        help: "remove an alias",
+        run: cmd_unalias,
+    },
+    Command {
+        name: "run",
+        args: &[
+            Arg::flag("trace", 'x', "print each command before running it"),
+            Arg::required("path", "file of shell commands"),
+        ],
+        help: "run the commands in a file (# starts a comment)",
+        run: cmd_run,
+    },
+    Command {
+        name: "set",
+        args: &[Arg::rest("name=value", "variable to define")],
+        help: "list variables, or define one ($name in commands)",
+        run: cmd_set,
+    },
+    Command {
+        name: "unset",
+        args: &[Arg::required("name", "variable to remove")],
+        help: "remove a variable",
+        run: cmd_unset,
+    },
+    Command {
+        name: "echo",
+        args: &[Arg::rest("text", "words to print")],
+        help: "print the words, e.g. to show a variable",
+        run: cmd_echo,
+    },
+    Command {
+        name: "button",
+        args: &[
+            Arg::option("delete", 'd', "remove the bindings of this pin").number(),
+            Arg::optional("pin", "GPIO the button pulls to ground").number(),
+            Arg::optional("press", "short or long"),
+            Arg::rest("command", "shell command to run"),
+        ],
+        help: "list or bind button presses to shell commands",
+        run: cmd_button,
+    },
+    Command {
+        name: "history",
+        args: &[
+            Arg::flag("clear", 'c', "forget all previous commands"),
+            Arg::option("count", 'n', "only show the last <count> lines").number(),
+        ],
+        help: "list previous commands (run one again with !n or !!)",
+        run: cmd_history,
+    },
+    Command {
+        name: "lastlog",
+        args: &[Arg::option("count", 'n', "only show the last <count> lines").number()],
+        help: "show what the previous boot printed (kept across warm reboots)",
+        run: cmd_lastlog,
+    },
+    Command {
+        name: "loglevel",
+        args: &[Arg::rest(
+            "directive",
+            "level, module=level or module=default",
+        )],
+        help: "show or change global and per-module log levels",
+        run: cmd_loglevel,
+    },
+    Command {
+        name: "console",
+        args: &[
+            Arg::optional("backend", "uart or miniuart"),
+            Arg::optional("settings", "line settings for uart, e.g. 9600n8"),
+        ],
+        help: "show or move the serial console, or change its speed",
+        run: cmd_console,
+    },
+    Command {
+        name: "theme",
+        args: &[
+            Arg::flag("list", 'l', "list the built-in themes"),
+            Arg::flag("reload", 'r', "reload /boot/theme.txt"),
+            Arg::optional("name", "built-in theme to switch to"),
+        ],
+        help: "show or switch the console colors",
+        run: cmd_theme,
+    },
+    Command {
+        name: "ls",
+        args: &[Arg::optional("path", "directory to list (default /)")],
+        help: "list a directory",
+        run: cmd_ls,
+    },
+    Command {
+        name: "cat",
+        args: &[Arg::required("path", "file to print")],
+        help: "print a file",
+        run: cmd_cat,
+    },
+    Command {
+        name: "write",
+        args: &[
+            Arg::required("path", "file to create or overwrite"),
+            Arg::rest("text", "words written as one line"),
+        ],
+        help: "create or overwrite a file with a line of text",
+        run: cmd_write,
+    },
+    Command {
+        name: "mkdir",
+        args: &[Arg::required("path", "directory to create")],
+        help: "create a directory",
+        run: cmd_mkdir,
+    },
+    Command {
+        name: "rm",
+        args: &[Arg::required("path", "file or empty directory")],
+        help: "remove a file or an empty directory",
+        run: cmd_rm,
+    },
+    Command {
+        name: "sdio",
+        args: &[],
+        help: "probe the WiFi chip over SDIO and dump CCCR/FBR/CIS",
+        run: cmd_sdio,
+    },
+    Command {
+        name: "exec",
+        args: &[
+            Arg::required("path", "static ELF to run"),
+            Arg::rest("args", "passed to the program as argv[1..]"),
+        ],
+        help: "load a static ELF into the program window and run it",
+        run: cmd_exec,
+    },
+    Command {
+        name: "rx",
+        args: &[
+            Arg::flag("exec", 'x', "run the file once it is in"),
+            Arg::required("name", "file to receive, into /tmp unless a path"),
+            Arg::rest("args", "passed to the program with -x"),
+        ],
+        help: "receive a file over the serial console with XMODEM (sx -k)",
+        run: cmd_rx,
+    },
+    Command {
+        name: "mount",
+        args: &[],
+        help: "list mounted filesystems",
+        run: cmd_mount,
+    },
+    Command {
+        name: "umount",
+        args: &[Arg::required("path", "mount point, e.g. /boot")],
+        help: "detach a mounted filesystem until the next boot",
+        run: cmd_umount,
+    },
+    Command {
+        name: "lsdev",
+        args: &[],
+        help: "list registered devices",
+        run: cmd_lsdev,
+    },
+    Command {
+        name: "frames",
+        args: &[],
+        help: "show free page frames by block size",
+        run: cmd_frames,
+    },
+    Command {
+        name: "heapcheck",
+        args: &[],
+        help: "check the heap's free list, the sentinels (and canaries with the debug heap)",
+        run: cmd_heapcheck,
+    },
+    Command {
+        name: "heap",
+        args: &[
+            Arg::optional("action", "strategy, trace, leaks or watermarks"),
+            Arg::optional(
+                "value",
+                "bestfit, worstfit, firstfit or nextfit for strategy; on or off for trace; \
+                 percentages like 60,85 (or off) for watermarks",
+            ),
+        ],
+        help: "show heap usage and fragmentation, switch its strategy, trace allocations for leaks or set usage warnings",
+        run: cmd_heap,
+    },
+    Command {
+        name: "heapbench",
+        args: &[
+            Arg::optional("ops", "allocations and frees per run (default 4000)").number(),
+            Arg::optional("KiB", "arena size (default 256)").number(),
+        ],
+        help: "time allocation workloads against each heap strategy",
+        run: cmd_heapbench,
+    },
+    Command {
+        name: "perf",
+        args: &[Arg::optional("action", "on, off or reset the probes")],
+        help: "show cycles, instructions and cache misses per profiling probe",
+        run: cmd_perf,
+    },
+    Command {
+        name: "pools",
+        args: &[],
+        help: "show usage of the general heap and the DMA and graphics pools",
+        run: cmd_pools,
+    },
+    Command {
+        name: "cores",
+        args: &[Arg::optional("ping", "have every other core print its ID")],
+        help: "show which cores are running, and their stacks",
+        run: cmd_cores,
+    },
+    Command {
+        name: "gdb",
+        args: &[],
+        help: "turn the GDB stub on and stop for GDB on the console",
+        run: cmd_gdb,
+    },
+    Command {
+        name: "idle",
+        args: &[Arg::optional("seconds", "wake up after this long").number()],
+        help: "sleep in low-power idle until a key is pressed",
+        run: cmd_idle,
+    },
+    Command {
+        name: "idletimeout",
+        args: &[Arg::optional("seconds", "new timeout, 0 turns it off").number()],
+        help: "show or set how long without input counts as idle (heap trim)",
+        run: cmd_idletimeout,
+    },
+    Command {
+        name: "servo",
+        args: &[
+            Arg::required("pin", "GPIO 12, 13, 18 or 19").number(),
+            Arg::required("angle", "0-180 degrees, or off"),
+        ],
+        help: "turn a hobby servo to an angle (50 Hz, 1-2 ms pulses)",
+        run: cmd_servo,
+    },
+    Command {
+        name: "motor",
+        args: &[
+            Arg::required("pin", "GPIO 12, 13, 18 or 19").number(),
+            Arg::required("percent", "duty cycle, 0 stops").number(),
+        ],
+        help: "drive a motor driver input with a 1 kHz PWM signal",
+        run: cmd_motor,
+    },
+    Command {
+        name: "led",
+        args: &[
+            Arg::required("pin", "GPIO 12, 13, 18 or 19").number(),
+            Arg::required("percent", "brightness, 0 turns it off").number(),
+        ],
+        help: "dim an LED with a 1 kHz PWM signal",
+        run: cmd_led,
+    },
+    Command {
+        name: "beep",
+        args: &[
+            Arg::optional("hz", "pitch (default 880)").number(),
+            Arg::optional("ms", "length (default 200)").number(),
+        ],
+        help: "play a tone on the headphone jack",
+        run: cmd_beep,
+    },
+    Command {
+        name: "freq",
+        args: &[
+            Arg::flag("both", 'b', "count falling edges too"),
+            Arg::required("pin", "GPIO to count edges on").number(),
+            Arg::optional("gate-ms", "how long to count (default 1000)").number(),
+        ],
+        help: "measure the frequency on a pin (pulled up input)",
+        run: cmd_freq,
+    },
+    Command {
+        name: "date",
+        args: &[Arg::rest(
+            "YYYY-MM-DD HH:MM:SS",
+            "set the clock (and the RTC) to this UTC time",
+        )],
+        help: "show or set the date and time",
+        run: cmd_date,
+    },
+    Command {
+        name: "random",
+        args: &[Arg::optional("count", "how many numbers (default 4)").number()],
+        help: "print random 64-bit numbers and where they come from",
+        run: cmd_random,
+    },
+    Command {
+        name: "cmdline",
+        args: &[],
+        help: "show the kernel command line and the options read from it",
+        run: cmd_cmdline,
+    },
+    Command {
+        name: "version",
+        args: &[],
+        help: "show the kernel version, commit, build time and compiler",
+        run: cmd_version,
+    },
+    Command {
+        name: "sysinfo",
+        args: &[Arg::flag(
+            "max-clock",
+            'm',
+            "raise the ARM clock to its maximum first",
+        )],
+        help: "show the board, clock rates and SoC temperature",
+        run: cmd_sysinfo,
+    },
+    Command {
+        name: "reboot",
+        args: &[],
+        help: "restart the board (the crash log survives)",
+        run: cmd_reboot,
+    },
+    Command {
+        name: "poweroff",
+        args: &[],
+        help: "power down the SD card and USB and halt until power is cycled",
+        run: cmd_poweroff,
+    },
+    Command {
+        name: "watchdog",
+        args: &[
+            Arg::flag("stop", 's', "disarm the watchdog"),
+            Arg::optional("ms", "arm it with this timeout").number(),
+        ],
+        help: "show, arm or stop the watchdog (fed while the shell waits)",
+        run: cmd_watchdog,
+    },
+    Command {
+        name: "dmatest",
+        args: &[
+            Arg::optional("KiB", "buffer size (default 256)").number(),
+            Arg::optional("pool", "memory pool for the buffers (default dma)"),
+        ],
+        help: "copy and fill a buffer with DMA, check it and compare with the CPU",
+        run: cmd_dmatest,
+    },
+    Command {
+        name: "memtest",
+        args: &[Arg::optional("max-MB", "stop after this many megabytes").number()],
+        help: "pattern-test free RAM outside the kernel and heap",
+        run: cmd_memtest,
+    },
+    Command {
+        name: "md",
+        args: &[
+            Arg::required("address", "where to start").number(),
+            Arg::optional("length", "bytes to show (default 64)").number(),
+        ],
+        help: "hexdump RAM or peripheral registers (each read once, as a word)",
+        run: cmd_md,
+    },
+    Command {
+        name: "mw",
+        args: &[
+            Arg::flag("force", 'f', "write into the kernel or its stack too"),
+            Arg::required("address", "word-aligned address").number(),
+            Arg::required("value", "32-bit value").number(),
+        ],
+        help: "write a word to RAM or a register, then read it back (a read has side effects on FIFOs)",
+        run: cmd_mw,
+    },
+    Command {
+        name: "mmio",
+        args: &[
+            Arg::required("register", "offset from the peripheral base, or an address").number(),
+            Arg::optional("value", "32-bit value to write first").number(),
+        ],
+        help: "read a peripheral register, or write and read back (reads pop FIFOs, clear status bits)",
+        run: cmd_mmio,
+    },
+    Command {
+        name: "sdbench",
+        args: &[
+            Arg::flag("write", 'w', "also rewrite each sector with its own data"),
+            Arg::optional("sectors", "sectors per pass (default 256)").number(),
+        ],
+        help: "time SD card reads (and same-data rewrites) with and without cache",
+        run: cmd_sdbench,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+fn cmd_help(args: &Args) {
+    let Some(name) = args.get("command") else {
+        for command in COMMANDS {
+            println!("  {:<40} {}", command.usage(), command.help);
+        }
+        return;
+    };
+
+    let Some(command) = find(name) else {
+        println!("help: unknown command: {}", name);
+        return;
+    };
+
+    println!("usage: {}", command.usage());
+    println!("{}", command.help);
+    for arg in command.args {
+        let label = match arg.kind {
+            Kind::Flag | Kind::Option => format!("-{}, --{}", arg.short.unwrap_or('?'), arg.name),
+            _ => format!("{}", arg),
+        };
+        println!("  {:<24} {}", label, arg.help);
+    }
+}
+
+fn save_aliases() {
+    if let Err(err) = alias::save() {
+        println!("alias: not saved to {}: {}", alias::ALIAS_FILE, err);
+    }
+}
+
+fn cmd_alias(args: &Args) {
+    if args.rest().is_empty() {
+        for (name, text) in alias::list() {
+            println!("  {}='{}'", name, text);
+        }
+        return;
+    }
+
+    // Quoted text arrives as one word, but accept `alias hm=hexdump 0x80000` too
+    let definition = args.rest().join(" ");
+    match alias::parse(&definition) {
+        Some((name, text)) => {
+            alias::set(name, text);
+            save_aliases();
+        }
+        None => println!("usage: alias name='command args'"),
+    }
+}
+
+fn cmd_unalias(args: &Args) {
+    let name = args.get("name").unwrap_or_default();
+
+    if alias::remove(name) {
+        save_aliases();
+    } else {
+        println!("unalias: {}: not found", name);
+    }
+}
+
+fn cmd_run(args: &Args) {
+    let path = args.get("path").unwrap_or_default();
+
+    if let Err(err) = script::run(path, args.flag("trace")) {
+        println!("run: {}: {}", path, err);
+    }
+}
+
+fn cmd_set(args: &Args) {
+    if args.rest().is_empty() {
+        for (name, value) in script::list() {
+            println!("  {}={}", name, value);
+        }
+        return;
+    }
+
+    let definition = args.rest().join(" ");
+    match script::parse(&definition) {
+        Some((name, value)) => script::set(name, value),
+        None => println!("usage: set name=value"),
+    }
+}
+
+fn cmd_unset(args: &Args) {
+    let name = args.get("name").unwrap_or_default();
+
+    if !script::unset(name) {
+        println!("unset: {}: not set", name);
+    }
+}
+
+fn cmd_echo(args: &Args) {
+    println!("{}", args.rest().join(" "));
+}
+
+fn save_buttons() {
+    if let Err(err) = buttons::save() {
+        println!("button: not saved to {}: {}", buttons::BUTTON_FILE, err);
+    }
+}
+
+fn cmd_button(args: &Args) {
+    if let Some(pin) = args.number("delete") {
+        if buttons::unbind(pin as u32) {
+            save_buttons();
+        } else {
+            println!("button: GPIO{} has no bindings", pin);
+        }
+        return;
+    }
+
+    let Some(pin) = args.number("pin") else {
+        for (pin, press, command) in buttons::list() {
+            println!(
+                "  GPIO{:<3} {:<6} {}",
+                pin,
+                buttons::press_name(press),
+                command
+            );
+        }
+        return;
+    };
+
+    let Some(press) = args.get("press").and_then(buttons::parse_press) else {
+        println!("button: press must be short or long");
+        return;
+    };
+    if args.rest().is_empty() {
+        println!("button: missing the command to run");
+        return;
+    }
+
+    if buttons::bind(pin as u32, press, &args.rest().join(" ")) {
+        save_buttons();
+    } else {
+        println!("button: no room to watch another pin");
+    }
+}
+
+fn cmd_history(args: &Args) {
+    if args.flag("clear") {
+        history::clear();
+        if let Err(err) = history::save() {
+            println!("history: not saved to {}: {}", history::HISTORY_FILE, err);
+        }
+        return;
+    }
+
+    let lines = history::list();
+    let count = args.number("count").unwrap_or(lines.len());
+
+    for (number, line) in &lines[lines.len().saturating_sub(count)..] {
+        println!("{:>5}  {}", number, line);
+    }
+}
+
+fn cmd_lastlog(args: &Args) {
+    let Some((session, bytes)) = crashlog::previous() else {
+        println!("lastlog: no log from a previous boot");
+        return;
+    };
+
+    let text = String::from_utf8_lossy(&bytes);
+    let lines: Vec<&str> = text.lines().collect();
+    let count = args.number("count").unwrap_or(lines.len());
+
+    println!("--- boot #{}, last {} bytes ---", session, bytes.len());
+    for line in &lines[lines.len().saturating_sub(count)..] {
+        println!("{}", line);
+    }
+    println!("--- end of boot #{} ---", session);
+}
+
+fn cmd_loglevel(args: &Args) {
+    if args.rest().is_empty() {
+        println!("global: {}", log::level().name());
+        for (module, level) in log::module_overrides() {
+            println!("  {}: {}", module, level.name());
+        }
+        return;
+    }
+
+    for directive in args.rest() {
+        if let Err(err) = log::apply_directive(directive) {
+            println!("loglevel: {}: {}", directive, err);
+        }
+    }
+}
+
+fn cmd_console(args: &Args) {
+    if let Some(name) = args.get("backend") {
+        let Some(backend) = Backend::parse(name) else {
+            println!("console: unknown backend '{}' (uart or miniuart)", name);
+            return;
+        };
+        let settings = match args.get("settings") {
+            Some(text) if backend != Backend::Pl011 => {
+                println!("console: {}: only uart takes line settings", text);
+                return;
+            }
+            Some(text) => match LineSettings::parse(text) {
+                Some(settings) => Some(settings),
+                None => {
+                    println!("console: {}: expected e.g. 9600n8", text);
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        // Everything from here on goes out on the new one, at the new speed
+        if !console::select(backend) {
+            println!("console: no {} on this board", backend.name());
+            return;
+        }
+        if let Some(settings) = settings
+            && let Err(err) = UART.lock().set_line_settings(settings)
+        {
+            println!("console: {}", err);
+        }
+    }
+
+    let active = console::active();
+    for backend in Backend::ALL {
+        let mark = if backend == active { '*' } else { ' ' };
+        match backend {
+            _ if !backend.available() => {
+                println!("{} {} (not on this board)", mark, backend.name())
+            }
+            Backend::Pl011 => println!("{} uart {}", mark, UART.lock().line_settings()),
+            Backend::MiniUart => println!("{} miniuart", mark),
+        }
+    }
+}
+
+fn cmd_theme(args: &Args) {
+    if args.flag("list") {
+        for builtin in theme::BUILTIN {
+            println!("  {}", builtin.name);
+        }
+        return;
+    }
+
+    if args.flag("reload") {
+        match theme_file::load() {
+            Ok(true) => {}
+            Ok(false) => println!("theme: no {}", theme_file::THEME_FILE),
+            Err(err) => println!("theme: {}: {}", theme_file::THEME_FILE, err),
+        }
+    } else if let Some(name) = args.get("name") {
+        match theme::builtin(name) {
+            Some(builtin) => theme::set(*builtin),
+            None => {
+                println!("theme: unknown theme '{}' (try theme -l)", name);
+                return;
+            }
+        }
+    }
+
+    let current = theme::current();
+    println!("theme: {}", current.name);
+    let colors = [
+        ("fg", current.foreground),
+        ("bg", current.background),
+        ("error", current.error),
+        ("warn", current.warn),
+        ("info", current.info),
+        ("debug", current.debug),
+        ("prompt", current.prompt),
+    ];
+    for (key, color) in colors {
+        println!("  {:<7} {}{}{}", key, Paint(color), color.name(), Normal);
+    }
+}
+
+fn cmd_frames(_args: &Args) {
+    let (free, total) = frame::stats();
+    println!(
+        "{} of {} pages free ({} of {} KiB)",
+        free,
+        total,
+        free * frame::PAGE_SIZE / 1024,
+        total * frame::PAGE_SIZE / 1024
+    );
+
+    for (order, &count) in frame::free_blocks().iter().enumerate() {
+        if count > 0 {
+            println!(
+                "  order {:>2} ({:>7} KiB): {}",
+                order,
+                frame::block_size(order) / 1024,
+                count
+            );
+        }
+    }
+}
+
+fn cmd_heap(args: &Args) {
+    match args.get("action") {
+        None => {}
+        Some("strategy") => {
+            if let Some(name) = args.get("value") {
+                let Some(strategy) = HeapType::parse(name) else {
+                    println!("heap: expected bestfit, worstfit, firstfit or nextfit");
+                    return;
+                };
+                if !memory::set_strategy(strategy) {
+                    println!("heap: this heap has no allocation strategy");
+                    return;
+                }
+            }
+        }
+        Some("trace") => {
+            match args.get("value") {
+                None => {}
+                Some("on") if !trace::start() => {
+                    return println!("heap: no frames for the trace table");
+                }
+                Some("on") => {}
+                Some("off") => trace::stop(),
+                Some(other) => return println!("heap: trace takes on or off, not '{}'", other),
+            }
+            let state = if trace::enabled() { "on" } else { "off" };
+            return println!("heap tracing {}", state);
+        }
+        Some("leaks") => return print_leaks(),
+        Some("watermarks") => return heap_watermarks(args.get("value")),
+        Some(other) => {
+            println!(
+                "heap: unknown subcommand {} (try strategy, trace, leaks or watermarks)",
+                other
+            );
+            return;
+        }
+    }
+
+    let report = memory::heap_check();
+    let fragmentation = memory::fragmentation();
+    let strategy = memory::strategy().map_or("-", HeapType::name);
+
+    println!("backend:       {} ({})", report.backend, strategy);
+    println!(
+        "used:          {} of {} KiB",
+        report.used / 1024,
+        report.capacity / 1024
+    );
+    println!(
+        "free:          {} KiB in {} block(s), largest {} KiB",
+        fragmentation.free_bytes / 1024,
+        fragmentation.free_blocks,
+        fragmentation.largest_free / 1024
+    );
+    println!("fragmentation: {}%", fragmentation.percent());
+}
+
+// Show the usage warning thresholds, or set them from "60,85" ("off" for none)
+fn heap_watermarks(value: Option<&str>) {
+    if let Some(value) = value {
+        let mut percents = Vec::new();
+        if value != "off" {
+            for word in value.split(',') {
+                match word.trim().parse::<usize>() {
+                    Ok(percent @ 1..=100) => percents.push(percent),
+                    _ => return println!("heap: '{}' is not a percentage from 1 to 100", word),
+                }
+            }
+        }
+        if percents.len() > watermark::MAX_WATERMARKS {
+            return println!("heap: at most {} watermarks", watermark::MAX_WATERMARKS);
+        }
+        watermark::set_thresholds(&percents);
+    }
+
+    let thresholds = watermark::thresholds();
+    if thresholds.is_empty() {
+        return println!("heap watermarks off");
+    }
+    let list: Vec<String> = thresholds
+        .iter()
+        .map(|percent| format!("{}%", percent))
+        .collect();
+    println!("heap watermarks: {}", list.join(", "));
+}
+
+// Every traced allocation not freed yet, oldest first, with the return
+// addresses that led to it (for addr2line)
+fn print_leaks() {
+    if !trace::enabled() {
+        return println!("heap: tracing is off (`heap trace on`)");
+    }
+
+    let report = trace::report();
+    println!(
+        "{} live allocation(s), {} bytes since tracing started",
+        report.live.len(),
+        report.bytes
+    );
+    if report.dropped > 0 {
+        println!("  ({} more not traced, the table was full)", report.dropped);
+    }
+
+    for entry in &report.live {
+        print!("  {:#010x} {:>7} bytes from", entry.ptr, entry.size);
+        for caller in entry.callers.iter().take_while(|&&caller| caller != 0) {
+            print!(" {:#x}", caller);
+        }
+        println!();
+    }
+}
+
+fn cmd_heapbench(args: &Args) {
+    let ops = args.number("ops").unwrap_or(bench::DEFAULT_OPS).max(1);
+    let Some(arena) = args
+        .number("KiB")
+        .map_or(Some(bench::DEFAULT_ARENA), |kib| {
+            kib.max(16).checked_mul(1024)
+        })
+    else {
+        println!("heapbench: arena size too large");
+        return;
+    };
+
+    let Some(outcomes) = bench::run_all(arena, ops) else {
+        println!(
+            "heapbench: no {} KiB of free frames for the arena",
+            arena / 1024
+        );
+        return;
+    };
+
+    println!(
+        "{:<14} {:<9} {:>6} {:>9} {:>7} {:>9} {:>5}",
+        "workload", "fit", "ops", "cycles/op", "failed", "peak KiB", "frag"
+    );
+    for (workload, strategy, outcome) in outcomes {
+        println!(
+            "{:<14} {:<9} {:>6} {:>9} {:>7} {:>9} {:>4}%",
+            workload.name(),
+            strategy.name(),
+            outcome.ops,
+            outcome.cycles_per_op(),
+            outcome.failures,
+            outcome.peak_used / 1024,
+            outcome.fragmentation.percent()
+        );
+    }
+}
+
+fn cmd_perf(args: &Args) {
+    match args.get("action") {
+        None => {}
+        Some("on") if !profile::set_enabled(true) => {
+            return println!("perf: no PMU on this core");
+        }
+        Some("on") => {}
+        Some("off") => {
+            profile::set_enabled(false);
+        }
+        Some("reset") => profile::reset(),
+        Some(other) => return println!("perf: unknown argument '{}'", other),
+    }
+
+    let counting = if profile::has_events() {
+        "cycles, instructions and cache misses"
+    } else {
+        "cycles only"
+    };
+    let state = if profile::enabled() { "on" } else { "off" };
+    println!("PMU: {}, probes {}", counting, state);
+
+    println!(
+        "{:<10} {:>9} {:>12} {:>12} {:>12} {:>5} {:>12}",
+        "probe", "calls", "cycles/call", "max cycles", "instr/call", "IPC", "misses/call"
+    );
+    for probe in profile::probes() {
+        let totals = probe.totals();
+        let calls = totals.calls.max(1);
+        // Instructions per cycle, in hundredths
+        let ipc = totals.instructions * 100 / totals.cycles.max(1);
+        println!(
+            "{:<10} {:>9} {:>12} {:>12} {:>12} {:>2}.{:02} {:>12}",
+            probe.name(),
+            totals.calls,
+            totals.cycles / calls,
+            totals.max_cycles,
+            totals.instructions / calls,
+            ipc / 100,
+            ipc % 100,
+            totals.cache_misses / calls
+        );
+    }
+}
+
+fn cmd_pools(_args: &Args) {
+    for pool in pool::POOLS {
+        let Some(stats) = pool::stats(pool) else {
+            println!("{:<9} not set up", pool.name());
+            continue;
+        };
+        println!(
+            "{:<9} {:#010x}  {:>6} of {:>6} KiB used, {} free block(s), largest {} KiB",
+            pool.name(),
+            stats.start,
+            stats.used / 1024,
+            stats.capacity / 1024,
+            stats.free_blocks,
+            stats.largest_free / 1024
+        );
+    }
+}
+
+fn cmd_cores(args: &Args) {
+    match args.get("ping") {
+        None => {}
+        Some("ping") => return smp::ping(),
+        Some(other) => return println!("cores: unknown argument '{}'", other),
+    }
+
+    for core in 0..MAX_CORES {
+        let info = smp::info(core);
+        let state = match (info.online, core == core_id()) {
+            (_, true) => "running the shell",
+            (true, false) => "idle",
+            (false, false) => "not started",
+        };
+        let used = match stack::high_water_mark(core) {
+            Some(bytes) => format!("{:>3} KiB", bytes.div_ceil(1024)),
+            None => String::from("  -    "),
+        };
+        println!(
+            "core {}  {:<17} stack {:#010x}-{:#010x}  max used {}  {} wake-ups",
+            core, state, info.stack.0, info.stack.1, used, info.wakeups
+        );
+    }
+}
+
+fn cmd_heapcheck(_args: &Args) {
+    // Corruption panics inside heap_check, so getting here means it is fine
+    let report = memory::heap_check();
+    let sentinels = sentinel::check();
+    let stacks = stack::check();
+
+    println!(
+        "heap ok ({}): {} of {} bytes used, {} bytes free in {} block(s)",
+        report.backend, report.used, report.capacity, report.free_bytes, report.free_blocks
+    );
+    if let Some((count, bytes)) = report.live {
+        println!(
+            "  {} live allocation(s), {} bytes, canaries intact",
+            count, bytes
+        );
+    }
+    println!(
+        "  {} allocation(s), {} reallocation(s), {} free(s) since boot",
+        report.counts.allocations, report.counts.reallocations, report.counts.frees
+    );
+    println!("  {} sentinel(s) intact", sentinels);
+    println!("  {} stack bottom(s) intact", stacks);
+}
+
+fn cmd_gdb(_args: &Args) {
+    if !gdb::enabled() {
+        gdb::enable();
+    }
+    println!("gdb: attach with `target remote` on this line, `detach` to come back");
+    console::flush();
+    gdb::breakpoint();
+    println!("gdb: detached");
+}
+
+fn cmd_idle(args: &Args) {
+    let timeout_ms = args
+        .number("seconds")
+        .map(|seconds| (seconds as u64).saturating_mul(1000));
+    println!("idle: press any key to wake up");
+
+    let start = timer::uptime_ms();
+    let wake = power::deep_idle(timeout_ms);
+    let slept = timer::uptime_ms() - start;
+
+    match wake {
+        Wake::Uart => {
+            // The key was only meant to wake us up, not as shell input
+            CONSOLE.lock().try_read_byte();
+            println!("idle: woken by the UART after {} ms", slept);
+        }
+        Wake::Alarm => println!("idle: woken by the alarm after {} ms", slept),
+    }
+}
+
+fn cmd_idletimeout(args: &Args) {
+    if let Some(seconds) = args.number("seconds") {
+        input::set_idle_timeout((seconds > 0).then_some((seconds as u64).saturating_mul(1000)));
+    }
+
+    match input::idle_timeout() {
+        Some(ms) => println!("idletimeout: {} s", ms / 1000),
+        None => println!("idletimeout: off"),
+    }
+}
+
+fn cmd_servo(args: &Args) {
+    let pin = args.number("pin").unwrap_or(0) as u32;
+    let angle = args.get("angle").unwrap_or("");
+
+    if angle == "off" {
+        if let Err(err) = pwm::stop(pin) {
+            println!("servo: {}", err);
+        }
+        return;
+    }
+
+    let Ok(angle) = angle.parse::<u32>() else {
+        println!("servo: angle must be a number of degrees or off");
+        return;
+    };
+    if angle > pwm::SERVO_MAX_ANGLE {
+        println!(
+            "servo: clamping {} to {} degrees",
+            angle,
+            pwm::SERVO_MAX_ANGLE
+        );
+    }
+
+    match pwm::servo(pin, angle) {
+        Ok(pulse) => println!("servo: GPIO{} pulse {} us", pin, pulse),
+        Err(err) => println!("servo: {}", err),
+    }
+}
+
+fn cmd_motor(args: &Args) {
+    let pin = args.number("pin").unwrap_or(0) as u32;
+    let percent = args.number("percent").unwrap_or(0).min(100) as u32;
+
+    let result = match percent {
+        0 => pwm::stop(pin),
+        _ => pwm::motor(pin, percent),
+    };
+    match result {
+        Ok(()) => println!("motor: GPIO{} at {}%", pin, percent),
+        Err(err) => println!("motor: {}", err),
+    }
+}
+
+fn cmd_led(args: &Args) {
+    let pin = args.number("pin").unwrap_or(0) as u32;
+    let percent = args.number("percent").unwrap_or(0).min(100) as u32;
+
+    let result = match percent {
+        0 => pwm::stop(pin),
+        _ => pwm::led(pin, percent),
+    };
+    match result {
+        Ok(()) => println!("led: GPIO{} at {}%", pin, percent),
+        Err(err) => println!("led: {}", err),
+    }
+}
+
+fn cmd_beep(args: &Args) {
+    let hz = args.number("hz").unwrap_or(880) as u32;
+    let ms = args.number("ms").unwrap_or(200) as u64;
+
+    if let Err(err) = pwm::beep(hz, ms) {
+        println!("beep: {}", err);
+    }
+}
+
+fn cmd_freq(args: &Args) {
+    let pin = args.number("pin").unwrap_or(0) as u32;
+    let gate_ms = args.number("gate-ms").unwrap_or(1000) as u64;
+    let edge = if args.flag("both") {
+        Edge::Both
+    } else {
+        Edge::Rising
+    };
+
+    gpio::set_function(pin, Function::Input);
+    gpio::set_pull(pin, Pull::Up);
+
+    let measurement = pulse::count(pin, edge, gate_ms);
+    let millihertz = measurement.millihertz();
+    println!(
+        "GPIO{}: {} edges in {} ms, {}.{:03} Hz",
+        pin,
+        measurement.edges,
+        measurement.gate_us / 1000,
+        millihertz / 1000,
+        millihertz % 1000
+    );
+}
+
+fn cmd_date(args: &Args) {
+    if !args.rest().is_empty() {
+        let Some(time) = DateTime::parse(&args.rest().join(" ")) else {
+            println!("date: expected YYYY-MM-DD HH:MM:SS");
+            return;
+        };
+        if let Err(err) = rtc::set(time) {
+            println!("date: clock set, but not the RTC: {}", err);
+        }
+    }
+
+    println!("{} UTC ({})", rtc::now(), rtc::source().name());
+}
+
+fn cmd_random(args: &Args) {
+    let count = args.number("count").unwrap_or(4);
+
+    println!("source: {}", rng::source().name());
+    for _ in 0..count {
+        println!("  {:016x}", rng::rand_u64());
+    }
+}
+
+fn cmd_cmdline(_args: &Args) {
+    println!("{}", cmdline::line());
+
+    for key in [
+        "console",
+        "loglevel",
+        "heap.strategy",
+        "gdb",
+        "noautoexec",
+        "bootmenu",
+    ] {
+        let value = match cmdline::get(key) {
+            Some(value) => value,
+            None if cmdline::has(key) => "on",
+            None => "-",
+        };
+        println!("  {:<14} {}", key, value);
+    }
+}
+
+fn cmd_version(_args: &Args) {
+    println!("DDOS {}", buildinfo::VERSION);
+    println!("commit:   {}", buildinfo::GIT_HASH);
+    println!(
+        "built:    {} ({})",
+        buildinfo::BUILD_TIME,
+        buildinfo::PROFILE
+    );
+    println!("features: {}", buildinfo::FEATURES);
+    println!("compiler: {}", buildinfo::RUSTC_VERSION);
+}
+
+fn cmd_sysinfo(args: &Args) {
+    if args.flag("max-clock") {
+        match mailbox::max_clock_rate(CLOCK_ARM)
+            .and_then(|max| mailbox::set_clock_rate(CLOCK_ARM, max))
+        {
+            Ok(rate) => println!("ARM clock set to {} MHz", rate / 1_000_000),
+            Err(err) => println!("sysinfo: could not raise the ARM clock: {}", err),
+        }
+    }
+
+    println!("platform:    {}", hardwareselect::get_platform_name());
+
+    match (mailbox::board_revision(), mailbox::board_serial()) {
+        (Ok(revision), Ok(serial)) => println!(
+            "board:       revision {:#x}, serial {:016x}",
+            revision, serial
+        ),
+        (Err(err), _) | (_, Err(err)) => println!("board:       unknown ({})", err),
+    }
+
+    for (name, clock) in [("ARM clock", CLOCK_ARM), ("core clock", CLOCK_CORE)] {
+        let rates = (
+            mailbox::clock_rate(clock),
+            mailbox::min_clock_rate(clock),
+            mailbox::max_clock_rate(clock),
+        );
+        match rates {
+            (Ok(rate), Ok(min), Ok(max)) => println!(
+                "{:<12} {} MHz (min {}, max {})",
+                format!("{}:", name),
+                rate / 1_000_000,
+                min / 1_000_000,
+                max / 1_000_000
+            ),
+            (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => {
+                println!("{:<12} unknown ({})", format!("{}:", name), err)
+            }
+        }
+    }
+
+    match mailbox::temperature() {
+        Ok(millidegrees) => println!(
+            "temperature: {}.{} C",
+            millidegrees / 1000,
+            millidegrees % 1000 / 100
+        ),
+        Err(err) => println!("temperature: unknown ({})", err),
+    }
+}
+
+fn cmd_reboot(_args: &Args) {
+    println!("Rebooting...");
+    power::reboot();
+}
+
+fn cmd_poweroff(_args: &Args) {
+    println!("Halting, safe to remove power.");
+    power::halt();
+}
+
+fn cmd_watchdog(args: &Args) {
+    if args.flag("stop") {
+        watchdog::stop();
+    } else if let Some(ms) = args.number("ms") {
+        if ms as u64 > watchdog::MAX_TIMEOUT_MS {
+            println!("watchdog: clamping to {} ms", watchdog::MAX_TIMEOUT_MS);
+        }
+        if !watchdog::start(ms as u64) {
+            println!("watchdog: not available on this board");
+            return;
+        }
+    }
+
+    match watchdog::status() {
+        Some((timeout, left)) => println!("watchdog: armed, {} ms, {} ms left", timeout, left),
+        None => println!("watchdog: stopped"),
+    }
+}
+
+fn cmd_memtest(args: &Args) {
+    let limit = match args.number("max-MB").map(|mb| mb.checked_mul(1024 * 1024)) {
+        None => usize::MAX,
+        Some(Some(limit)) if limit <= memtest::available() => limit,
+        Some(_) => {
+            println!(
+                "memtest: max-MB is at most the {} MB free to test",
+                memtest::available() / (1024 * 1024)
+            );
+            return;
+        }
+    };
+
+    for region in map::reserved_regions() {
+        println!(
+            "memtest: skipping {} {:#x}-{:#x}",
+            region.name, region.start, region.end
+        );
+    }
+
+    match memtest::run(limit) {
+        0 => println!("memtest: all patterns passed"),
+        failures => println!("memtest: {} region(s) FAILED", failures),
+    }
+}
+
+// md, mw and mmio are for driver bring-up. They only reach RAM and the
+// peripheral window (map::window()), since an access nothing answers hangs
+// the core. Registers only get aligned 32-bit accesses, the only kind they
+// are made for, and mw stays out of the kernel and its stack unless forced.
+const MD_LIMIT: usize = 4096;
+
+fn cmd_md(args: &Args) {
+    let address = args.number("address").unwrap_or_default();
+    let length = args.number("length").unwrap_or(64).clamp(1, MD_LIMIT);
+
+    let window = map::window(address, length);
+    if window == Some(Window::Peripherals)
+        && (!address.is_multiple_of(4) || !length.is_multiple_of(4))
+    {
+        println!("md: registers are read as whole words: align to 4");
+        return;
+    }
+    if window.is_none() {
+        println!(
+            "md: {:#x}+{:#x} is outside RAM and the peripherals",
+            address, length
+        );
+        return;
+    }
+
+    let mut line = [0u8; 16];
+    for row in (address..address + length).step_by(16) {
+        let count = (address + length - row).min(16);
+        // Byte reads straight from RAM, but each register read exactly once:
+        // a FIFO or a clear-on-read register changes with every read
+        for (offset, bytes) in line[..count].chunks_mut(4).enumerate() {
+            let word = row + offset * 4;
+            match window {
+                Some(Window::Ram) => {
+                    for (index, byte) in bytes.iter_mut().enumerate() {
+                        *byte = unsafe { ((word + index) as *const u8).read_volatile() };
+                    }
+                }
+                _ => {
+                    let value = unsafe { (word as *const u32).read_volatile() };
+                    bytes.copy_from_slice(&value.to_le_bytes()[..bytes.len()]);
+                }
+            }
+        }
+        print_dump_line(row, &line[..count]);
+    }
+}
+
+fn print_dump_line(address: usize, bytes: &[u8]) {
+    let mut hex = String::new();
+    for (index, byte) in bytes.iter().enumerate() {
+        if index == 8 {
+            hex.push(' ');
+        }
+        hex.push_str(&format!("{:02x} ", byte));
+    }
+    let text: String = bytes
+        .iter()
+        .map(|&byte| match byte {
+            0x20..=0x7E => byte as char,
+            _ => '.',
+        })
+        .collect();
+
+    println!("{:010x}  {:<49} |{}|", address, hex, text);
+}
+
+// A word-aligned address mw and mmio may touch, or why not
+fn check_word(address: usize, force: bool) -> Result<(), String> {
+    if !address.is_multiple_of(4) {
+        return Err(format!("{:#x} is not word-aligned", address));
+    }
+    if map::window(address, 4).is_none() {
+        return Err(format!("{:#x} is outside RAM and the peripherals", address));
+    }
+    match map::reserved_at(address) {
+        Some(region) if !force && matches!(region.name, "kernel" | "stack") => Err(format!(
+            "{:#x} is in the {} ({:#x}-{:#x}), -f to write anyway",
+            address, region.name, region.start, region.end
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn parse_word(args: &Args, name: &str) -> Option<u32> {
+    let value = args.number(name)?;
+    let word = u32::try_from(value).ok();
+    if word.is_none() {
+        println!("{}: {:#x} does not fit in 32 bits", name, value);
+    }
+    word
+}
+
+fn cmd_mw(args: &Args) {
+    let address = args.number("address").unwrap_or_default();
+    let Some(value) = parse_word(args, "value") else {
+        return;
+    };
+    if let Err(err) = check_word(address, args.flag("force")) {
+        println!("mw: {}", err);
+        return;
+    }
+
+    let word = address as *mut u32;
+    unsafe { word.write_volatile(value) };
+    // Registers do not always read back what was written
+    let value = unsafe { word.read_volatile() };
+    println!("{:#010x} = {:#010x}", address, value);
+}
+
+fn cmd_mmio(args: &Args) {
+    let register = args.number("register").unwrap_or_default();
+    let peripherals = map::peripherals();
+    let address = if register < peripherals.size() {
+        peripherals.start + register
+    } else {
+        register
+    };
+
+    if map::window(address, 4) != Some(Window::Peripherals) {
+        println!(
+            "mmio: {:#x} is not a register ({:#x}-{:#x})",
+            address, peripherals.start, peripherals.end
+        );
+        return;
+    }
+    if let Err(err) = check_word(address, false) {
+        println!("mmio: {}", err);
+        return;
+    }
+
+    let word = address as *mut u32;
+    if args.get("value").is_some() {
+        let Some(value) = parse_word(args, "value") else {
+            return;
+        };
+        unsafe { word.write_volatile(value) };
+    }
+
+    let value = unsafe { word.read_volatile() };
+    println!("{:#010x} = {:#010x} ({:032b})", address, value, value);
+}
+
+fn cmd_dmatest(args: &Args) {
+    const TIMEOUT_MS: u64 = 1000;
+
+    let Some(bytes) = args.number("KiB").unwrap_or(256).max(1).checked_mul(1024) else {
+        println!("dmatest: buffer size too large");
+        return;
+    };
+    let pool = match args.get("pool").map(Pool::parse) {
+        None => Pool::Dma,
+        Some(Some(pool)) => pool,
+        Some(None) => {
+            println!("dmatest: pools are general, dma and graphics");
+            return;
+        }
+    };
+
+    // Control blocks want 32-byte alignment, the buffers may as well have it
+    let buffers = (
+        pool::Buffer::new(pool, bytes, 32),
+        pool::Buffer::new(pool, bytes, 32),
+    );
+    let (Some(mut source_buffer), Some(mut destination_buffer)) = buffers else {
+        println!(
+            "dmatest: no room for 2 x {} KiB in the {} pool",
+            bytes / 1024,
+            pool.name()
+        );
+        return;
+    };
+    let (_, source, _) = unsafe { source_buffer.as_mut_slice().align_to_mut::<u32>() };
+    let (_, destination, _) = unsafe { destination_buffer.as_mut_slice().align_to_mut::<u32>() };
+    for (i, word) in source.iter_mut().enumerate() {
+        *word = (i as u32).wrapping_mul(0x9E37_79B9);
+    }
+    let source: &[u32] = source;
+
+    let mut channel = match dma::allocate() {
+        Ok(channel) => channel,
+        Err(err) => {
+            println!("dmatest: {}", err);
+            return;
+        }
+    };
+    println!(
+        "dmatest: channel {}, {} KiB from the {} pool",
+        channel.number(),
+        bytes / 1024,
+        pool.name()
+    );
+
+    let start = timer::counter();
+    destination.copy_from_slice(source);
+    let cpu_us = timer::ticks_to_us(timer::counter() - start);
+    destination.fill(0);
+
+    let start = timer::counter();
+    let result = unsafe {
+        channel.copy(
+            destination.as_mut_ptr() as *mut u8,
+            source.as_ptr() as *const u8,
+            bytes,
+            TIMEOUT_MS,
+        )
+    };
+    let dma_us = timer::ticks_to_us(timer::counter() - start);
+
+    if let Err(err) = result {
+        println!("dmatest: copy: {}", err);
+        return;
+    }
+    match destination.iter().zip(source).position(|(a, b)| a != b) {
+        Some(index) => println!("dmatest: copy differs at word {}", index),
+        None => println!("  copy: DMA {} us, CPU {} us", dma_us, cpu_us),
+    }
+
+    let start = timer::counter();
+    let result = unsafe {
+        channel.fill(
+            destination.as_mut_ptr() as *mut u8,
+            0xA5A5_5A5A,
+            bytes,
+            TIMEOUT_MS,
+        )
+    };
+    let dma_us = timer::ticks_to_us(timer::counter() - start);
+
+    if let Err(err) = result {
+        println!("dmatest: fill: {}", err);
+        return;
+    }
+    match destination.iter().position(|&word| word != 0xA5A5_5A5A) {
+        Some(index) => println!("dmatest: fill differs at word {}", index),
+        None => println!("  fill: DMA {} us", dma_us),
+    }
+}
+
+fn cmd_sdbench(args: &Args) {
+    let blocks = args.number("sectors").unwrap_or(256);
+    if blocks == 0 {
+        println!("sdbench: sectors must be at least 1");
+        return;
+    }
+
+    if let Err(err) = fs::bench::run(blocks as u64, args.flag("write")) {
+        println!("sdbench: {}", err);
+    }
+}
+
+fn cmd_ls(args: &Args) {
+    let path = args.get("path").unwrap_or("/");
+
+    match vfs::read_dir(path) {
+        Ok(entries) => {
+            for entry in entries {
+                match entry.kind {
+                    vfs::NodeKind::Directory => println!("  {:>10}  {}/", "<dir>", entry.name),
+                    vfs::NodeKind::Device => println!("  {:>10}  {}", "<dev>", entry.name),
+                    vfs::NodeKind::File => println!("  {:>10}  {}", entry.size, entry.name),
+                }
+            }
+        }
+        Err(err) => println!("ls: {}: {}", path, err),
+    }
+}
+
+fn cat(path: &str) -> Result<(), fs::FsError> {
+    let data = vfs::read_to_vec(path)?;
+
+    for &byte in &data {
+        match byte {
+            b'\n' | b'\t' | b' '..=b'~' => print!("{}", byte as char),
+            b'\r' => {}
+            _ => print!("."),
+        }
+    }
+    if !data.is_empty() && data.last() != Some(&b'\n') {
+        println!();
+    }
+    Ok(())
+}
+
+fn cmd_cat(args: &Args) {
+    let path = args.get("path").unwrap_or_default();
+
+    if let Err(err) = cat(path) {
+        println!("cat: {}: {}", path, err);
+    }
+}
+
+fn cmd_write(args: &Args) {
+    let path = args.get("path").unwrap_or_default();
+
+    let mut text = args.rest().join(" ");
+    text.push('\n');
+
+    if let Err(err) = vfs::write_all(path, text.as_bytes()) {
+        println!("write: {}: {}", path, err);
+    }
+}
+
+fn cmd_mkdir(args: &Args) {
+    let path = args.get("path").unwrap_or_default();
+
+    if let Err(err) = vfs::mkdir(path) {
+        println!("mkdir: {}: {}", path, err);
+    }
+}
+
+fn cmd_rm(args: &Args) {
+    let path = args.get("path").unwrap_or_default();
+
+    if let Err(err) = vfs::remove(path) {
+        println!("rm: {}: {}", path, err);
+    }
+}
+
+fn print_tuples(tuples: &[sdio::Tuple]) {
+    for tuple in tuples {
+        print!("    {:#04x} {:<7}", tuple.code, tuple.name());
+        for byte in tuple.body.iter().take(16) {
+            print!(" {:02x}", byte);
+        }
+        if tuple.body.len() > 16 {
+            print!(" ...");
+        }
+        println!();
+    }
+}
+
+fn cmd_sdio(_args: &Args) {
+    let info = match sdio::probe_wifi() {
+        Ok(info) => info,
+        Err(err) => {
+            println!("sdio: {}", err);
+            return;
+        }
+    };
+
+    println!(
+        "OCR {:#08x}, {} function(s), memory {}",
+        info.ocr, info.function_count, info.memory_present
+    );
+    println!(
+        "CCCR rev {:#04x}, SD spec {:#04x}, caps {:#04x}, bus speed {:#04x}, {}-bit",
+        info.cccr_revision, info.sd_spec, info.capability, info.bus_speed, info.bus_width
+    );
+    if let Some((manufacturer, card)) = info.manufacturer() {
+        println!("manufacturer {:#06x}, card {:#06x}", manufacturer, card);
+    }
+    if let Some(product) = info.product() {
+        println!("product: {}", product);
+    }
+
+    println!("  common CIS:");
+    print_tuples(&info.common_cis);
+
+    for function in &info.functions {
+        println!(
+            "  function {} (interface {:#x}):",
+            function.number, function.interface
+        );
+        print_tuples(&function.cis);
+    }
+}
+
+fn cmd_exec(args: &Args) {
+    let path = args.get("path").unwrap_or_default();
+
+    match loader::exec(path, args.rest()) {
+        Ok(status) => println!("{}: exited with status {}", path, status),
+        Err(err) => println!("exec: {}: {}", path, err),
+    }
+}
+
+fn cmd_rx(args: &Args) {
+    let name = args.get("name").unwrap_or_default();
+    let path = if name.starts_with('/') {
+        String::from(name)
+    } else {
+        format!("/tmp/{}", name)
+    };
+
+    println!("rx: waiting for the sender (Ctrl+X twice to cancel)...");
+    // Nothing bigger fits the program window anyway
+    let received = xmodem::receive(PROGRAM_SIZE);
+    // The sender may still be finishing up on the terminal's side
+    timer::delay_ms(100);
+
+    let data = match received {
+        Ok(data) => data,
+        Err(err) => {
+            println!("\nrx: {}", err);
+            return;
+        }
+    };
+    if let Err(err) = vfs::write_all(&path, &data) {
+        println!("\nrx: {}: {}", path, err);
+        return;
+    }
+    println!("\nrx: {} bytes into {}", data.len(), path);
+    drop(data);
+
+    if args.flag("exec") {
+        match loader::exec(&path, args.rest()) {
+            Ok(status) => println!("{}: exited with status {}", path, status),
+            Err(err) => println!("exec: {}: {}", path, err),
+        }
+    }
+}
+
+fn cmd_mount(_args: &Args) {
+    for (path, name) in vfs::mounts() {
+        println!("  {:<12} {}", path, name);
+    }
+}
+
+fn cmd_umount(args: &Args) {
+    let path = args.get("path").unwrap_or_default();
+    if let Err(err) = vfs::unmount(path) {
+        println!("umount: {}: {}", path, err);
+    }
+}
+
+fn cmd_lsdev(_args: &Args) {
+    for device in devices::list() {
+        println!("  {:<12} {}", device.name, device.class.name());
+    }
+}
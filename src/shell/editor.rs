@@ -0,0 +1,265 @@
+use super::{alias, commands, history, print_prompt};
+use crate::input::{KeyCode, KeyEvent, Modifiers};
+use crate::{print, println};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// ============================================================================
+// LINE EDITOR
+// The line being typed at the prompt, with a cursor in it:
+//
+//     Left Right          move by a character
+//     Home End, ^A ^E     to the start or the end of the line
+//     Backspace Delete    remove before or under the cursor
+//     Up Down             walk the history; Down past the newest line gets
+//                         back what was being typed
+//     Tab                 complete a command or alias name; when several
+//                         fit, complete what they share, or list them
+//     ^C                  drop the line
+//
+// Typing inserts at the cursor. The terminal is kept in step with ANSI
+// cursor movement, so this needs a terminal that understands it (any does).
+// ============================================================================
+
+pub const MAX_LINE: usize = 256;
+
+const CTRL_A: KeyCode = KeyCode(KeyCode::A.0);
+const CTRL_C: KeyCode = KeyCode(KeyCode::A.0 + 2);
+const CTRL_E: KeyCode = KeyCode(KeyCode::A.0 + 4);
+
+pub struct Editor {
+    line: Vec<char>,
+    cursor: usize,
+    // How many lines back into the history Up went, 0 while on the new line
+    browsing: usize,
+    // The new line, kept while browsing
+    typed: Vec<char>,
+}
+
+impl Editor {
+    pub fn new() -> Editor {
+        Editor {
+            line: Vec::new(),
+            cursor: 0,
+            browsing: 0,
+            typed: Vec::new(),
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    // Handle one key press; the finished line on Enter
+    pub fn key(&mut self, event: &KeyEvent) -> Option<String> {
+        let ctrl = event.modifiers.contains(Modifiers::CTRL);
+
+        match event.code {
+            KeyCode::ENTER => {
+                println!();
+                let line = self.text();
+                *self = Editor::new();
+                return Some(line);
+            }
+            CTRL_C if ctrl => {
+                println!("^C");
+                *self = Editor::new();
+                print_prompt("");
+            }
+            KeyCode::LEFT if self.cursor > 0 => {
+                self.cursor -= 1;
+                print!("\x1b[D");
+            }
+            KeyCode::RIGHT if self.cursor < self.line.len() => {
+                self.cursor += 1;
+                print!("\x1b[C");
+            }
+            KeyCode::HOME => self.move_to(0),
+            CTRL_A if ctrl => self.move_to(0),
+            KeyCode::END => self.move_to(self.line.len()),
+            CTRL_E if ctrl => self.move_to(self.line.len()),
+            KeyCode::BACKSPACE if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.line.remove(self.cursor);
+                print!("\x08");
+                self.redraw_tail();
+            }
+            KeyCode::DELETE if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+                self.redraw_tail();
+            }
+            KeyCode::UP => self.browse(self.browsing + 1),
+            KeyCode::DOWN if self.browsing > 0 => self.browse(self.browsing - 1),
+            KeyCode::TAB => self.complete(),
+            _ => {
+                if let Some(c) = event.char() {
+                    self.insert(&[c]);
+                }
+            }
+        }
+        None
+    }
+
+    // The prompt and the line again, cursor where it was, e.g. after a
+    // button's command printed something
+    pub fn redraw(&self) {
+        print_prompt(&self.text());
+        self.move_back(self.line.len() - self.cursor);
+    }
+
+    fn move_back(&self, columns: usize) {
+        if columns > 0 {
+            print!("\x1b[{}D", columns);
+        }
+    }
+
+    fn move_to(&mut self, position: usize) {
+        if position < self.cursor {
+            self.move_back(self.cursor - position);
+        } else if position > self.cursor {
+            print!("\x1b[{}C", position - self.cursor);
+        }
+        self.cursor = position;
+    }
+
+    // Print from the cursor on, clear what is left of a longer line, then
+    // put the terminal's cursor back
+    fn redraw_tail(&self) {
+        let tail: String = self.line[self.cursor..].iter().collect();
+        print!("{}\x1b[K", tail);
+        self.move_back(self.line.len() - self.cursor);
+    }
+
+    fn insert(&mut self, chars: &[char]) {
+        let room = MAX_LINE.saturating_sub(self.line.len());
+        let chars = &chars[..chars.len().min(room)];
+        if chars.is_empty() {
+            return;
+        }
+
+        self.line
+            .splice(self.cursor..self.cursor, chars.iter().copied());
+        let inserted: String = chars.iter().collect();
+        print!("{}", inserted);
+        self.cursor += chars.len();
+        self.redraw_tail();
+    }
+
+    // Replace the whole line, cursor at the end
+    fn replace(&mut self, line: Vec<char>) {
+        self.move_to(0);
+        self.line = line;
+        self.line.truncate(MAX_LINE);
+        self.cursor = self.line.len();
+        let text = self.text();
+        print!("{}\x1b[K", text);
+    }
+
+    fn browse(&mut self, back: usize) {
+        let line = match back {
+            0 => core::mem::take(&mut self.typed),
+            _ => match history::recent(back) {
+                Some(line) => line.chars().collect(),
+                // Already at the oldest
+                None => return,
+            },
+        };
+        if self.browsing == 0 {
+            self.typed = core::mem::take(&mut self.line);
+        }
+        self.browsing = back;
+        self.replace(line);
+    }
+
+    fn complete(&mut self) {
+        // Only the command name, the first word
+        let before: String = self.line[..self.cursor].iter().collect();
+        let prefix = before.trim_start();
+        if prefix.contains(char::is_whitespace) {
+            return;
+        }
+
+        let candidates = candidates(prefix);
+        let [first, ..] = candidates.as_slice() else {
+            return;
+        };
+        let shared = common_prefix(&candidates);
+
+        if candidates.len() == 1 {
+            let mut rest: Vec<char> = first[prefix.len()..].chars().collect();
+            if self.line.get(self.cursor) != Some(&' ') {
+                rest.push(' ');
+            }
+            self.insert(&rest);
+        } else if shared.len() > prefix.len() {
+            let rest: Vec<char> = shared[prefix.len()..].chars().collect();
+            self.insert(&rest);
+        } else {
+            println!();
+            println!("{}", candidates.join("  "));
+            self.redraw();
+        }
+    }
+}
+
+// Command and alias names starting with `prefix`, sorted, no repeats
+fn candidates(prefix: &str) -> Vec<String> {
+    let commands = commands::COMMANDS
+        .iter()
+        .map(|command| String::from(command.name));
+    let aliases = alias::list().into_iter().map(|(name, _)| name);
+
+    let mut names: Vec<String> = commands
+        .chain(aliases)
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+// The longest start all of `names` share
+fn common_prefix(names: &[String]) -> &str {
+    let Some((first, rest)) = names.split_first() else {
+        return "";
+    };
+
+    let mut end = first.len();
+    for name in rest {
+        end = first
+            .bytes()
+            .zip(name.bytes())
+            .take(end)
+            .take_while(|(a, b)| a == b)
+            .count();
+    }
+    while !first.is_char_boundary(end) {
+        end -= 1;
+    }
+    &first[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::common_prefix;
+    use crate::utils::testing::{Failure, check_eq};
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    #[test_case]
+    fn completion_prefix() -> Result<(), Failure> {
+        let names = |list: &[&str]| {
+            list.iter()
+                .map(|&name| String::from(name))
+                .collect::<Vec<_>>()
+        };
+        check_eq!(
+            common_prefix(&names(&["heap", "heapbench", "heapcheck"])),
+            "heap"
+        );
+        check_eq!(common_prefix(&names(&["mount", "motor"])), "mo");
+        check_eq!(common_prefix(&names(&["led"])), "led");
+        check_eq!(common_prefix(&[]), "");
+        Ok(())
+    }
+}
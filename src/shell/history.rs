@@ -0,0 +1,161 @@
+use crate::fs::{FsError, vfs};
+use crate::utils::locked::SpinLock;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// ============================================================================
+// COMMAND HISTORY
+// Every line run at the prompt is numbered and appended to HISTORY_FILE, so
+// `history` and `!n` keep working across reboots. Only the last HISTORY_LIMIT
+// lines are kept; numbers keep counting up when old ones fall off. The
+// line editor (editor.rs) walks them with Up and Down through recent().
+// ============================================================================
+
+pub const HISTORY_FILE: &str = "/boot/history.txt";
+
+const HISTORY_LIMIT: usize = 100;
+
+struct History {
+    lines: VecDeque<String>,
+    // Number of lines[0]
+    first: usize,
+}
+
+static HISTORY: SpinLock<History> = SpinLock::new(History {
+    lines: VecDeque::new(),
+    first: 1,
+});
+
+// Record `line`, skipping blanks and straight repeats of the previous line
+pub fn add(line: &str) -> Result<(), FsError> {
+    let line = line.trim();
+    let trimmed = {
+        let mut history = HISTORY.lock();
+
+        if line.is_empty() || history.lines.back().is_some_and(|last| last == line) {
+            return Ok(());
+        }
+
+        history.lines.push_back(String::from(line));
+        if history.lines.len() > HISTORY_LIMIT {
+            history.lines.pop_front();
+            history.first += 1;
+            true
+        } else {
+            false
+        }
+    };
+
+    // Appending is one small write; once the limit is hit the file is
+    // rewritten so it does not grow forever
+    if trimmed { save() } else { append(line) }
+}
+
+fn append(line: &str) -> Result<(), FsError> {
+    let mut file = match vfs::open(HISTORY_FILE) {
+        Ok(file) => file,
+        Err(FsError::NotFound) => vfs::create(HISTORY_FILE)?,
+        Err(err) => return Err(err),
+    };
+
+    if let Some(size) = file.size() {
+        file.seek(size)?;
+    }
+
+    let mut entry = String::from(line);
+    entry.push('\n');
+    file.write(entry.as_bytes()).map(|_| ())
+}
+
+pub fn save() -> Result<(), FsError> {
+    let mut contents = String::new();
+    for line in HISTORY.lock().lines.iter() {
+        contents.push_str(line);
+        contents.push('\n');
+    }
+
+    vfs::write_all(HISTORY_FILE, contents.as_bytes())
+}
+
+// Returns how many lines were read. A missing file just means no history yet.
+pub fn load() -> Result<usize, FsError> {
+    let data = match vfs::read_to_vec(HISTORY_FILE) {
+        Ok(data) => data,
+        Err(FsError::NotFound) => return Ok(0),
+        Err(err) => return Err(err),
+    };
+
+    let text = String::from_utf8_lossy(&data);
+    let lines: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    let kept = &lines[lines.len().saturating_sub(HISTORY_LIMIT)..];
+
+    let mut history = HISTORY.lock();
+    history.lines = kept.iter().map(|line| String::from(*line)).collect();
+    history.first = 1;
+    Ok(kept.len())
+}
+
+// (number, line) for every remembered line, oldest first
+pub fn list() -> Vec<(usize, String)> {
+    let history = HISTORY.lock();
+    history
+        .lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| (history.first + index, line.clone()))
+        .collect()
+}
+
+pub fn get(number: usize) -> Option<String> {
+    let history = HISTORY.lock();
+    let index = number.checked_sub(history.first)?;
+    history.lines.get(index).cloned()
+}
+
+pub fn clear() {
+    let mut history = HISTORY.lock();
+    history.first += history.lines.len();
+    history.lines.clear();
+}
+
+pub fn last() -> Option<String> {
+    HISTORY.lock().lines.back().cloned()
+}
+
+// The line `back` steps before the end: 1 is the last one
+pub fn recent(back: usize) -> Option<String> {
+    let history = HISTORY.lock();
+    let index = history.lines.len().checked_sub(back)?;
+    history.lines.get(index).cloned()
+}
+
+// "!!" -> last line, "!n" -> line n, followed by any extra arguments.
+// None if `line` is not a history reference, Some(Err) if it names nothing.
+pub fn expand(line: &str) -> Option<Result<String, String>> {
+    let trimmed = line.trim_start();
+    let reference = trimmed.strip_prefix('!')?;
+
+    let (target, rest) = match reference.split_once(char::is_whitespace) {
+        Some((target, rest)) => (target, rest),
+        None => (reference, ""),
+    };
+
+    let found = match target {
+        "!" => last(),
+        number => number.parse().ok().and_then(get),
+    };
+
+    let Some(mut expanded) = found else {
+        return Some(Err(String::from(trimmed)));
+    };
+
+    if !rest.is_empty() {
+        expanded.push(' ');
+        expanded.push_str(rest);
+    }
+    Some(Ok(expanded))
+}
@@ -0,0 +1,183 @@
+pub mod alias;
+pub mod args;
+pub mod buttons;
+pub mod commands;
+pub mod editor;
+pub mod history;
+pub mod script;
+pub mod theme;
+
+use crate::cmdline;
+use crate::drivers::console;
+use crate::drivers::watchdog;
+use crate::input;
+use crate::memory::sentinel;
+use crate::utils::log;
+use crate::utils::theme::{Color, Normal, Paint};
+use crate::{print, println};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use editor::Editor;
+
+// ============================================================================
+// KERNEL SHELL
+// Reads a line from the input queue (the UART, or any other source plugged
+// into input/) through the line editor (editor.rs), resolves `!n` history
+// references, expands variables (script.rs) and a leading alias, splits it
+// into words (see args.rs) and runs the matching entry from
+// commands::COMMANDS with its arguments checked. Scripts run through the
+// same path, /boot/autoexec.rc before the first prompt. Button presses bound to a
+// command (buttons.rs) run it the same way, between keystrokes, and the
+// memory sentinels get checked then too.
+// ============================================================================
+
+const PROMPT: &str = "> ";
+
+// Complain about an unwritable history file once, not after every command
+static HISTORY_WARNED: AtomicBool = AtomicBool::new(false);
+
+pub fn run() -> ! {
+    match alias::load() {
+        Ok(0) => {}
+        Ok(count) => log::info!("Loaded {} alias(es) from {}", count, alias::ALIAS_FILE),
+        Err(err) => log::warn!("Could not read {}: {}", alias::ALIAS_FILE, err),
+    }
+
+    if let Err(err) = history::load() {
+        log::warn!("Could not read {}: {}", history::HISTORY_FILE, err);
+    }
+
+    match buttons::load() {
+        Ok(0) => {}
+        Ok(count) => log::info!(
+            "Loaded {} button binding(s) from {}",
+            count,
+            buttons::BUTTON_FILE
+        ),
+        Err(err) => log::warn!("Could not read {}: {}", buttons::BUTTON_FILE, err),
+    }
+
+    match theme::load() {
+        Ok(false) => {}
+        Ok(true) => log::info!("Loaded the console theme from {}", theme::THEME_FILE),
+        Err(err) => log::warn!("Could not read {}: {}", theme::THEME_FILE, err),
+    }
+
+    if cmdline::has("noautoexec") {
+        log::info!("Not running {} (noautoexec)", script::AUTOEXEC_FILE);
+    } else {
+        match script::autoexec() {
+            Ok(None) => {}
+            Ok(Some(count)) => {
+                log::info!("Ran {} command(s) from {}", count, script::AUTOEXEC_FILE)
+            }
+            Err(err) => log::warn!("Could not run {}: {}", script::AUTOEXEC_FILE, err),
+        }
+    }
+
+    let mut editor = Editor::new();
+    println!();
+    print_prompt("");
+
+    loop {
+        // Buttons and sentinels are polled while waiting for a key, queued
+        // console output drained and the watchdog fed
+        watchdog::feed();
+        let Some(event) = input::poll() else {
+            console::drain();
+            sentinel::poll();
+            if let Some(command) = buttons::poll() {
+                println!("\n[button] {}", command);
+                execute(&command);
+                editor.redraw();
+            }
+            continue;
+        };
+        if !event.pressed {
+            continue;
+        }
+
+        if let Some(line) = editor.key(&event) {
+            run_line(&line);
+            print_prompt("");
+        }
+    }
+}
+
+// The prompt in the theme's color, then whatever has been typed so far
+fn print_prompt(line: &str) {
+    let color = crate::utils::theme::current().prompt;
+
+    if color == Color::Default {
+        print!("{}{}", PROMPT, line);
+    } else {
+        print!("{}{}{}{}", Paint(color), PROMPT, Normal, line);
+    }
+}
+
+// A line typed at the prompt: history references are resolved (and echoed,
+// like other shells do) before it is recorded and run
+fn run_line(line: &str) {
+    let line = match history::expand(line) {
+        None => String::from(line),
+        Some(Ok(expanded)) => {
+            println!("{}", expanded);
+            expanded
+        }
+        Some(Err(reference)) => {
+            println!("{}: event not found", reference);
+            return;
+        }
+    };
+
+    if let Err(err) = history::add(&line) {
+        // Plain load/store, no swap: the RPi5 has no atomic RMW with the MMU off
+        if !HISTORY_WARNED.load(Ordering::Relaxed) {
+            HISTORY_WARNED.store(true, Ordering::Relaxed);
+            log::warn!("History not saved to {}: {}", history::HISTORY_FILE, err);
+        }
+    }
+
+    execute(&line);
+}
+
+pub fn execute(line: &str) {
+    let variables = script::expand(line);
+    let line = variables.as_deref().unwrap_or(line);
+    let expanded = alias::expand(line);
+    let line = expanded.as_deref().unwrap_or(line);
+    let words = match args::split(line) {
+        Ok(words) => words,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+    let Some((&name, rest)) = words.split_first() else {
+        return;
+    };
+
+    let Some(command) = commands::find(name) else {
+        println!("unknown command: {} (try `help`)", name);
+        return;
+    };
+
+    match args::parse(command.args, rest) {
+        Ok(parsed) => (command.run)(&parsed),
+        Err(err) => {
+            println!("{}: {}", name, err);
+            println!("usage: {}", command.usage());
+        }
+    }
+}
+
+// Decimal or 0x-prefixed hex
+pub fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(&hex.replace('_', ""), 16).ok(),
+        None => text.parse().ok(),
+    }
+}
@@ -0,0 +1,178 @@
+use super::execute;
+use crate::fs::{FsError, vfs};
+use crate::println;
+use crate::utils::locked::SpinLock;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// ============================================================================
+// SHELL SCRIPTS AND VARIABLES
+// `run /boot/init.rc` runs a file of shell commands, one per line, as if
+// typed at the prompt (without going into the history). Blank lines and
+// lines starting with # are skipped:
+//
+//     # Colors and programs for this board
+//     theme light
+//     set dir=/boot/programs
+//     exec $dir/blink
+//
+// `set name=value` defines a variable and $name or ${name} anywhere in a
+// command line (typed or from a script) is replaced by its value, an unset
+// one by nothing; $$ is a plain $. Variables live until reboot.
+//
+// AUTOEXEC_FILE runs when the shell starts, unless the command line says
+// `noautoexec`, which gets a board with a broken script back to a prompt.
+// ============================================================================
+
+pub const AUTOEXEC_FILE: &str = "/boot/autoexec.rc";
+
+// Scripts running scripts, so one that runs itself stops somewhere
+const MAX_DEPTH: usize = 8;
+
+static VARIABLES: SpinLock<Vec<(String, String)>> = SpinLock::new(Vec::new());
+
+// Plain load/store, no fetch_add: the RPi5 has no atomic RMW with the MMU off
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+// "dir=/boot" -> ("dir", "/boot"); names are letters, digits and _
+pub fn parse(definition: &str) -> Option<(&str, &str)> {
+    let (name, value) = definition.split_once('=')?;
+    let name = name.trim();
+
+    if name.is_empty() || !name.chars().all(is_name_char) {
+        return None;
+    }
+    Some((name, value.trim()))
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+pub fn set(name: &str, value: &str) {
+    let mut variables = VARIABLES.lock();
+
+    match variables.iter_mut().find(|(n, _)| n == name) {
+        Some(entry) => entry.1 = String::from(value),
+        None => variables.push((String::from(name), String::from(value))),
+    }
+}
+
+pub fn unset(name: &str) -> bool {
+    let mut variables = VARIABLES.lock();
+    let before = variables.len();
+    variables.retain(|(n, _)| n != name);
+    variables.len() != before
+}
+
+pub fn get(name: &str) -> Option<String> {
+    let variables = VARIABLES.lock();
+    variables
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| value.clone())
+}
+
+pub fn list() -> Vec<(String, String)> {
+    VARIABLES.lock().clone()
+}
+
+// `line` with $name, ${name} and $$ replaced. None when it has no $.
+pub fn expand(line: &str) -> Option<String> {
+    if !line.contains('$') {
+        return None;
+    }
+
+    let mut expanded = String::new();
+    let mut rest = line;
+    while let Some(at) = rest.find('$') {
+        expanded.push_str(&rest[..at]);
+        let after = &rest[at + 1..];
+
+        let (name, next) = if let Some(braced) = after.strip_prefix('{')
+            && let Some(end) = braced.find('}')
+        {
+            (&braced[..end], &braced[end + 1..])
+        } else if let Some(next) = after.strip_prefix('$') {
+            expanded.push('$');
+            rest = next;
+            continue;
+        } else {
+            let end = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+
+        if name.is_empty() {
+            // A lone $, kept as it is
+            expanded.push('$');
+        } else if let Some(value) = get(name) {
+            expanded.push_str(&value);
+        }
+        rest = next;
+    }
+    expanded.push_str(rest);
+    Some(expanded)
+}
+
+// Run every command in the file at `path`, echoing each first with
+// `trace`. Returns how many lines ran.
+pub fn run(path: &str, trace: bool) -> Result<usize, FsError> {
+    let depth = DEPTH.load(Ordering::Relaxed);
+    if depth == MAX_DEPTH {
+        println!("run: {}: scripts nested too deep", path);
+        return Ok(0);
+    }
+
+    let data = vfs::read_to_vec(path)?;
+    let text = String::from_utf8_lossy(&data);
+
+    DEPTH.store(depth + 1, Ordering::Relaxed);
+    let mut count = 0;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if trace {
+            println!("+ {}", line);
+        }
+        execute(line);
+        count += 1;
+    }
+    DEPTH.store(depth, Ordering::Relaxed);
+
+    Ok(count)
+}
+
+// AUTOEXEC_FILE if there is one: Ok(None) when not
+pub fn autoexec() -> Result<Option<usize>, FsError> {
+    match run(AUTOEXEC_FILE, false) {
+        Ok(count) => Ok(Some(count)),
+        Err(FsError::NotFound) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand, parse, set, unset};
+    use crate::utils::testing::{Failure, check, check_eq};
+
+    #[test_case]
+    fn variable_expansion() -> Result<(), Failure> {
+        check_eq!(parse("dir = /boot"), Some(("dir", "/boot")));
+        check_eq!(parse("no name=x"), None);
+
+        set("test_dir", "/boot");
+        check_eq!(expand("ls"), None);
+        check_eq!(expand("ls $test_dir/x").as_deref(), Some("ls /boot/x"));
+        check_eq!(expand("ls ${test_dir}x").as_deref(), Some("ls /bootx"));
+        check_eq!(
+            expand("echo $$ $ $test_missing.").as_deref(),
+            Some("echo $ $ .")
+        );
+        check!(unset("test_dir"));
+        check_eq!(expand("ls $test_dir").as_deref(), Some("ls "));
+        Ok(())
+    }
+}
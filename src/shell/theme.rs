@@ -0,0 +1,51 @@
+use crate::fs::{FsError, vfs};
+use crate::utils::log;
+use crate::utils::theme::{self, Theme};
+use alloc::string::String;
+
+// ============================================================================
+// THEME FILE
+// THEME_FILE describes the console colors, one `key = value` per line:
+//
+//     base = color          # start from a built-in theme
+//     prompt = bright-green
+//     error = red
+//
+// Keys are base, fg, bg, error, warn, info, debug and prompt; values are
+// color names (see utils/theme.rs). It is read when the shell starts and
+// again by `theme -r`, so edits take effect without a reboot.
+// ============================================================================
+
+pub const THEME_FILE: &str = "/boot/theme.txt";
+
+// Switch to the theme THEME_FILE describes. Ok(false) when there is no file.
+// Bad lines are reported and skipped.
+pub fn load() -> Result<bool, FsError> {
+    let data = match vfs::read_to_vec(THEME_FILE) {
+        Ok(data) => data,
+        Err(FsError::NotFound) => return Ok(false),
+        Err(err) => return Err(err),
+    };
+
+    let mut theme = theme::PLAIN;
+    for (index, line) in String::from_utf8_lossy(&data).lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let result = match line.split_once('=') {
+            Some((key, value)) => theme::apply(&mut theme, key.trim(), value.trim()),
+            None => Err("expected key = value"),
+        };
+        if let Err(err) = result {
+            log::warn!("{}:{}: {}", THEME_FILE, index + 1, err);
+        }
+    }
+
+    theme::set(Theme {
+        name: THEME_FILE,
+        ..theme
+    });
+    Ok(true)
+}
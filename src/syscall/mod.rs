@@ -0,0 +1,136 @@
+use crate::cpu::usermode;
+use crate::drivers::console::CONSOLE;
+use crate::memory::config::{PROGRAM_SIZE, PROGRAM_START};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// ============================================================================
+// SYSTEM CALLS
+// EL0 programs trap in with `svc #0`, the number in x8 and arguments in
+// x0-x5. The result comes back in x0; failures are negative errno values.
+//
+//     0  exit(status)              never returns
+//     1  write(fd, buf, len)       fd 1/2 -> console
+//     2  read(fd, buf, len)        fd 0 <- console, blocks for the first byte
+//     3  sbrk(increment)           old break, or -ENOMEM
+//
+// Without page tables the only "user memory" is the program window, and
+// every buffer a program passes in must lie inside it.
+// ============================================================================
+
+pub const SYS_EXIT: u64 = 0;
+pub const SYS_WRITE: u64 = 1;
+pub const SYS_READ: u64 = 2;
+pub const SYS_SBRK: u64 = 3;
+
+const EIO: i64 = 5;
+const EBADF: i64 = 9;
+const ENOMEM: i64 = 12;
+const EFAULT: i64 = 14;
+const ENOSYS: i64 = 38;
+
+// Program break, where it started (end of the image) and the lowest address
+// it may not grow into (the user stack)
+static BREAK: AtomicUsize = AtomicUsize::new(0);
+static BREAK_START: AtomicUsize = AtomicUsize::new(0);
+static BREAK_LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+// The loader calls this before starting a program
+pub fn set_break(start: usize, limit: usize) {
+    BREAK.store(start, Ordering::Relaxed);
+    BREAK_START.store(start, Ordering::Relaxed);
+    BREAK_LIMIT.store(limit, Ordering::Relaxed);
+}
+
+fn error(errno: i64) -> u64 {
+    (-errno) as u64
+}
+
+fn user_range(address: u64, len: u64) -> Option<(usize, usize)> {
+    let start = address as usize;
+    let end = start.checked_add(len as usize)?;
+
+    if start >= PROGRAM_START && end <= PROGRAM_START + PROGRAM_SIZE {
+        Some((start, len as usize))
+    } else {
+        None
+    }
+}
+
+pub fn dispatch(number: u64, args: &[u64]) -> u64 {
+    match number {
+        SYS_EXIT => usermode::exit(args[0] as i64),
+        SYS_WRITE => sys_write(args[0], args[1], args[2]),
+        SYS_READ => sys_read(args[0], args[1], args[2]),
+        SYS_SBRK => sys_sbrk(args[0] as i64),
+        _ => error(ENOSYS),
+    }
+}
+
+fn sys_write(fd: u64, buf: u64, len: u64) -> u64 {
+    if fd != 1 && fd != 2 {
+        return error(EBADF);
+    }
+    let Some((start, len)) = user_range(buf, len) else {
+        return error(EFAULT);
+    };
+
+    let bytes = unsafe { core::slice::from_raw_parts(start as *const u8, len) };
+    let mut console = CONSOLE.lock();
+    for (written, &byte) in bytes.iter().enumerate() {
+        let sent = match byte {
+            b'\n' => console.send('\r').and_then(|_| console.send('\n')),
+            _ => console.send(byte as char),
+        };
+        // A stuck UART: report what got out, or an error if nothing did
+        if sent.is_err() {
+            return if written > 0 {
+                written as u64
+            } else {
+                error(EIO)
+            };
+        }
+    }
+
+    len as u64
+}
+
+fn sys_read(fd: u64, buf: u64, len: u64) -> u64 {
+    if fd != 0 {
+        return error(EBADF);
+    }
+    let Some((start, len)) = user_range(buf, len) else {
+        return error(EFAULT);
+    };
+    if len == 0 {
+        return 0;
+    }
+
+    let bytes = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, len) };
+    let mut console = CONSOLE.lock();
+
+    bytes[0] = console.read_byte();
+    let mut count = 1;
+    while count < len {
+        match console.try_read_byte() {
+            Some(byte) => bytes[count] = byte,
+            None => break,
+        }
+        count += 1;
+    }
+
+    count as u64
+}
+
+fn sys_sbrk(increment: i64) -> u64 {
+    let old = BREAK.load(Ordering::Relaxed);
+    let new = old as i64 + increment;
+
+    if new < BREAK_START.load(Ordering::Relaxed) as i64
+        || new > BREAK_LIMIT.load(Ordering::Relaxed) as i64
+    {
+        return error(ENOMEM);
+    }
+
+    BREAK.store(new as usize, Ordering::Relaxed);
+    old as u64
+}
@@ -0,0 +1,133 @@
+use super::super::memory::config::{CRASH_LOG_SIZE, CRASH_LOG_START};
+use super::locked::SpinLock;
+use super::log;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr::{null_mut, read_volatile, write_volatile};
+
+// ============================================================================
+// CRASH LOG
+// Everything printed also goes into a ring in the reserved CRASH_LOG region,
+// which nothing clears on a warm reboot. After a hang (or a panic with nobody
+// watching the serial port) the next boot can still show how the last
+// session ended.
+//
+// The region holds two slots. Each boot writes to the slot with the older
+// session and leaves the other one alone, so the previous session stays
+// readable for the whole of the current one:
+//
+//     [ Header: magic, session, written ][ ring of SLOT_SIZE - header bytes ]
+//
+// Caches are off, so every byte is in RAM as soon as it is stored.
+// ============================================================================
+
+const MAGIC: u64 = u64::from_le_bytes(*b"DDOSLOG!");
+const SLOTS: usize = 2;
+const SLOT_SIZE: usize = CRASH_LOG_SIZE / SLOTS;
+
+#[repr(C)]
+struct Header {
+    magic: u64,
+    // Counts up by one per boot
+    session: u64,
+    // Bytes ever written; the ring holds the last CAPACITY of them
+    written: u64,
+}
+
+const CAPACITY: usize = SLOT_SIZE - size_of::<Header>();
+
+struct Ring {
+    // Null until init()
+    current: *mut Header,
+    previous: *mut Header,
+}
+
+static RING: SpinLock<Ring> = SpinLock::new(Ring {
+    current: null_mut(),
+    previous: null_mut(),
+});
+
+fn slot(index: usize) -> *mut Header {
+    (CRASH_LOG_START + index * SLOT_SIZE) as *mut Header
+}
+
+fn data(header: *mut Header) -> *mut u8 {
+    (header as usize + size_of::<Header>()) as *mut u8
+}
+
+// Session number of the log in `header`, None for garbage or a cold boot
+fn session(header: *mut Header) -> Option<u64> {
+    unsafe {
+        if read_volatile(&(*header).magic) != MAGIC {
+            return None;
+        }
+        Some(read_volatile(&(*header).session))
+    }
+}
+
+// Pick a slot for this boot and start copying output into it. Needs no heap,
+// so it can run as soon as the UART is up.
+pub fn init() {
+    let sessions = [session(slot(0)), session(slot(1))];
+    let (current, previous) = match sessions {
+        [Some(first), Some(second)] if first > second => (1, 0),
+        [Some(_), None] => (1, 0),
+        _ => (0, 1),
+    };
+    let session = sessions.iter().flatten().max().map_or(1, |last| last + 1);
+
+    let header = slot(current);
+    unsafe {
+        // Magic last, so a reset in between leaves an invalid slot
+        write_volatile(&mut (*header).magic, 0);
+        write_volatile(&mut (*header).session, session);
+        write_volatile(&mut (*header).written, 0);
+        write_volatile(&mut (*header).magic, MAGIC);
+    }
+
+    {
+        let mut ring = RING.lock();
+        ring.current = header;
+        ring.previous = match sessions[previous] {
+            Some(_) => slot(previous),
+            None => null_mut(),
+        };
+    }
+
+    log::add_sink(sink);
+}
+
+fn sink(text: &str) {
+    let ring = RING.lock();
+    let header = ring.current;
+    if header.is_null() {
+        return;
+    }
+
+    unsafe {
+        let mut written = read_volatile(&(*header).written);
+        for &byte in text.as_bytes() {
+            write_volatile(data(header).add(written as usize % CAPACITY), byte);
+            written += 1;
+        }
+        write_volatile(&mut (*header).written, written);
+    }
+}
+
+// Session number and the tail of what the previous boot printed, oldest
+// byte first. The tail may start in the middle of a line.
+pub fn previous() -> Option<(u64, Vec<u8>)> {
+    let header = RING.lock().previous;
+    if header.is_null() {
+        return None;
+    }
+
+    let session = session(header)?;
+    let written = unsafe { read_volatile(&(*header).written) } as usize;
+    let start = written.saturating_sub(CAPACITY);
+
+    let bytes = (start..written)
+        .map(|index| unsafe { read_volatile(data(header).add(index % CAPACITY)) })
+        .collect();
+    Some((session, bytes))
+}
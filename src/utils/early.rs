@@ -0,0 +1,54 @@
+use super::locked::SpinLock;
+use crate::drivers::console::CONSOLE;
+use core::fmt::{self, Write};
+
+// ============================================================================
+// EARLY CONSOLE
+// Until memory::init() is done there is no heap, and anything that might
+// allocate (Rcu updates, Vec, format!) must not run. stage() writes straight
+// to the UART, without the sinks, the log filter or the theme, and remembers
+// the stage. If early boot then panics or takes an exception, the panic
+// handler says which stage it was in instead of the board going quiet.
+//
+// Stages reached before the UART is up are recorded but not printed. Each
+// line is flushed onto the wire before going on, in case the next step hangs.
+// ============================================================================
+
+static STAGE: SpinLock<Option<&'static str>> = SpinLock::new(Some("start"));
+
+static UART_READY: SpinLock<bool> = SpinLock::new(false);
+
+// The UART works from here on
+pub fn uart_ready() {
+    *UART_READY.lock() = true;
+}
+
+pub fn stage(name: &'static str) {
+    *STAGE.lock() = Some(name);
+
+    if *UART_READY.lock() {
+        let mut console = CONSOLE.lock();
+        let _ = writeln!(console, "[early] {}", name);
+        let _ = console.flush();
+    }
+}
+
+// A one-off line on the early console, for failures that must not depend
+// on anything the normal console might need. Works after early boot too.
+pub fn report(args: fmt::Arguments) {
+    if *UART_READY.lock() {
+        let mut console = CONSOLE.lock();
+        let _ = writeln!(console, "[early] {}", args);
+        let _ = console.flush();
+    }
+}
+
+// Early boot is over, the normal console takes over
+pub fn done() {
+    *STAGE.lock() = None;
+}
+
+// The stage early boot stopped in, None once done() ran
+pub fn current() -> Option<&'static str> {
+    *STAGE.lock()
+}
@@ -0,0 +1,88 @@
+use super::locked::SpinLock;
+use super::log;
+use crate::memory::watermark::WatermarkEvent;
+use alloc::string::String;
+use core::fmt;
+
+// ============================================================================
+// EVENT BUS
+// Subsystems publish what happened, anyone interested subscribes, and
+// neither side has to know about the other. Subscribers are plain functions
+// kept in a fixed table (no heap needed to subscribe) and are called in
+// order on the publisher's stack, with no lock held, so they may publish in
+// turn. Keep them short: they run in whatever context published.
+// ============================================================================
+
+pub const MAX_SUBSCRIBERS: usize = 8;
+
+#[derive(Clone, Debug)]
+pub enum Event {
+    SdCardReady { bus_width: u8, clock_hz: u32 },
+    Mounted { path: String, fs: &'static str },
+    Unmounted { path: String },
+    ProgramExited { path: String, status: i64 },
+    HeapWatermark(WatermarkEvent),
+    // No input for the idle timeout (see input/mod.rs), then the next key
+    InputIdle { idle_ms: u64 },
+    InputActive,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::SdCardReady {
+                bus_width,
+                clock_hz,
+            } => write!(
+                f,
+                "SD card ready ({}-bit, {} kHz)",
+                bus_width,
+                clock_hz / 1000
+            ),
+            Event::Mounted { path, fs } => write!(f, "{} mounted at {}", fs, path),
+            Event::Unmounted { path } => write!(f, "{} unmounted", path),
+            Event::ProgramExited { path, status } => {
+                write!(f, "{} exited with status {}", path, status)
+            }
+            Event::HeapWatermark(event) => {
+                write!(f, "heap passed {}%", event.threshold_percent)
+            }
+            Event::InputIdle { idle_ms } => write!(f, "no input for {} ms", idle_ms),
+            Event::InputActive => write!(f, "input again"),
+        }
+    }
+}
+
+pub type Subscriber = fn(&Event);
+
+static SUBSCRIBERS: SpinLock<[Option<Subscriber>; MAX_SUBSCRIBERS]> =
+    SpinLock::new([None; MAX_SUBSCRIBERS]);
+
+// For good: false when the table is full
+pub fn subscribe(subscriber: Subscriber) -> bool {
+    let mut subscribers = SUBSCRIBERS.lock();
+    let Some(slot) = subscribers.iter_mut().find(|slot| slot.is_none()) else {
+        return false;
+    };
+
+    *slot = Some(subscriber);
+    true
+}
+
+pub fn publish(event: Event) {
+    // Copy the table so subscribers run without the lock
+    let subscribers = *SUBSCRIBERS.lock();
+
+    for subscriber in subscribers.iter().flatten() {
+        subscriber(&event);
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Subscribers
+// ----------------------------------------------------------------------------
+
+// Everything that goes over the bus ends up in the debug log
+pub fn log_subscriber(event: &Event) {
+    log::debug!("event: {}", event);
+}
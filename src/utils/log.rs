@@ -0,0 +1,349 @@
+use super::locked::SpinLock;
+use super::rcu::Rcu;
+use super::theme::{self, Normal, Paint};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+// ============================================================================
+// 1. LOG LEVELS
+// ============================================================================
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Level> {
+        match text {
+            "error" | "ERROR" => Some(Level::Error),
+            "warn" | "WARN" => Some(Level::Warn),
+            "info" | "INFO" => Some(Level::Info),
+            "debug" | "DEBUG" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+}
+
+// ============================================================================
+// 2. RUNTIME CONFIG
+// Read on every log call, written almost never, so it lives behind an Rcu and
+// logging never has to take a lock just to check the level.
+//
+// A module override covers everything under that module: "fs" reaches
+// fs::fat32 and fs::vfs as well, and "fs::vfs" or "vfs" only the latter.
+// The innermost module with an override wins. Only the kernel's own modules
+// log; ddos-drivers never does, its errors are logged by whoever called it.
+// ============================================================================
+
+#[derive(Clone)]
+pub struct LogConfig {
+    pub level: Level,
+    // Per-module overrides, e.g. ("fs", Debug) while everything else stays at Info
+    pub modules: Vec<(String, Level)>,
+}
+
+static DEFAULT_CONFIG: LogConfig = LogConfig {
+    level: Level::Info,
+    modules: Vec::new(),
+};
+static CONFIG: Rcu<LogConfig> = Rcu::new(&DEFAULT_CONFIG);
+
+// Needs the heap, so only call this after memory::init()
+pub fn set_level(level: Level) {
+    CONFIG.modify(|config| config.level = level);
+}
+
+pub fn set_module_level(module: &str, level: Level) {
+    CONFIG.modify(
+        |config| match config.modules.iter_mut().find(|(name, _)| name == module) {
+            Some(entry) => entry.1 = level,
+            None => config.modules.push((String::from(module), level)),
+        },
+    );
+}
+
+pub fn clear_module_level(module: &str) {
+    CONFIG.modify(|config| config.modules.retain(|(name, _)| name != module));
+}
+
+pub fn level() -> Level {
+    CONFIG.read(|config| config.level)
+}
+
+// The level for a line logged from `path`, as given by module_path!()
+pub fn module_level(path: &str) -> Level {
+    CONFIG.read(|config| {
+        let find = |name: &str| {
+            config
+                .modules
+                .iter()
+                .find(|(module, _)| module == name)
+                .map(|(_, level)| *level)
+        };
+
+        // Innermost first, by its own name or its path below the crate root
+        let Some((_, mut module)) = path.split_once("::") else {
+            return find("kernel").unwrap_or(config.level);
+        };
+        loop {
+            let (parent, name) = match module.rsplit_once("::") {
+                Some((parent, name)) => (Some(parent), name),
+                None => (None, module),
+            };
+            if let Some(level) = find(name).or_else(|| find(module)) {
+                return level;
+            }
+            match parent {
+                Some(parent) => module = parent,
+                None => return config.level,
+            }
+        }
+    })
+}
+
+pub fn module_overrides() -> Vec<(String, Level)> {
+    CONFIG.read(|config| config.modules.clone())
+}
+
+pub fn enabled(level: Level, path: &str) -> bool {
+    level <= module_level(path)
+}
+
+// Accepts "debug" (global level), "fs=debug" (one module) or "fs=default"
+// (drop the override), as typed after `loglevel` in the shell
+pub fn apply_directive(directive: &str) -> Result<(), &'static str> {
+    match directive.split_once('=') {
+        Some((module, "default")) => {
+            clear_module_level(module);
+            Ok(())
+        }
+        Some((module, level)) => {
+            let level = Level::parse(level).ok_or("unknown log level")?;
+            set_module_level(module, level);
+            Ok(())
+        }
+        None => {
+            let level = Level::parse(directive).ok_or("unknown log level")?;
+            set_level(level);
+            Ok(())
+        }
+    }
+}
+
+// "ddos::fs::fat32" -> "fat32", the crate root itself -> "kernel"
+pub fn module_name(path: &'static str) -> &'static str {
+    match path.rsplit_once("::") {
+        Some((_, last)) => last,
+        None => "kernel",
+    }
+}
+
+// ============================================================================
+// 3. OUTPUT MULTIPLEXER
+// The serial console (either UART, see drivers::console) always gets
+// everything. Other outputs (a screen console later on) register a sink and
+// receive the same text.
+// ============================================================================
+
+const MAX_SINKS: usize = 4;
+
+pub type Sink = fn(&str);
+
+static SINKS: SpinLock<[Option<Sink>; MAX_SINKS]> = SpinLock::new([None; MAX_SINKS]);
+
+pub fn add_sink(sink: Sink) -> bool {
+    let mut sinks = SINKS.lock();
+
+    match sinks.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(sink);
+            true
+        }
+        None => false,
+    }
+}
+
+struct SinkWriter(Sink);
+
+impl Write for SinkWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        (self.0)(s);
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    crate::drivers::profile!("console", {
+        {
+            let mut console = crate::drivers::console::CONSOLE.lock();
+            let _ = console.write_fmt(args);
+        }
+
+        let sinks = *SINKS.lock();
+        for sink in sinks.iter().flatten() {
+            let _ = SinkWriter(*sink).write_fmt(args);
+        }
+    })
+}
+
+#[doc(hidden)]
+pub fn _log(level: Level, module_path: &'static str, args: fmt::Arguments) {
+    if !enabled(level, module_path) {
+        return;
+    }
+    let module = module_name(module_path);
+
+    let theme = theme::current();
+    let color = match level {
+        Level::Error => theme.error,
+        Level::Warn => theme.warn,
+        Level::Info => theme.info,
+        Level::Debug => theme.debug,
+    };
+
+    if color == theme::Color::Default {
+        _print(format_args!("[{}][{}] {}\n", level.name(), module, args));
+    } else {
+        _print(format_args!(
+            "[{}{}{}][{}] {}\n",
+            Paint(color),
+            level.name(),
+            Normal,
+            module,
+            args
+        ));
+    }
+}
+
+// ============================================================================
+// 4. MACROS
+// print!/println! are unconditional, the level macros go through the filter.
+// Use the level macros as log::info!(...) after `use crate::utils::log;`.
+// ============================================================================
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::utils::log::_print(format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::print!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::print!("{}\n", format_args!($($arg)*))
+    };
+}
+
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::utils::log::_log($crate::utils::log::Level::Error, module_path!(), format_args!($($arg)*))
+    };
+}
+
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        $crate::utils::log::_log($crate::utils::log::Level::Warn, module_path!(), format_args!($($arg)*))
+    };
+}
+
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::utils::log::_log($crate::utils::log::Level::Info, module_path!(), format_args!($($arg)*))
+    };
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        $crate::utils::log::_log($crate::utils::log::Level::Debug, module_path!(), format_args!($($arg)*))
+    };
+}
+
+// Defined under log_* names because a bare `warn` clashes with the builtin attribute
+#[allow(unused_imports)]
+pub(crate) use {log_debug as debug, log_error as error, log_info as info, log_warn as warn};
+
+#[cfg(test)]
+mod tests {
+    use super::{Level, add_sink, apply_directive, module_level, module_name};
+    use crate::utils::locked::SpinLock;
+    use crate::utils::testing::{Failure, check, check_eq};
+    use alloc::string::String;
+
+    #[test_case]
+    fn module_names() -> Result<(), Failure> {
+        check_eq!(module_name("ddos::fs::fat32"), "fat32");
+        check_eq!(module_name("ddos"), "kernel");
+        Ok(())
+    }
+
+    #[test_case]
+    fn module_directives() -> Result<(), Failure> {
+        check!(apply_directive("fs=debug").is_ok());
+        check!(module_level("ddos::fs") == Level::Debug);
+        check!(apply_directive("fs=default").is_ok());
+        check!(module_level("ddos::fs") == super::level());
+        check!(apply_directive("fs=loud").is_err());
+        Ok(())
+    }
+
+    #[test_case]
+    fn module_directives_reach_nested_modules() -> Result<(), Failure> {
+        check!(apply_directive("fs=debug").is_ok());
+        check!(module_level("ddos::fs::fat32") == Level::Debug);
+        check!(module_level("ddos::fs::vfs") == Level::Debug);
+        check!(module_level("ddos::memory::slab") == super::level());
+
+        // The innermost override wins, by name or by path
+        check!(apply_directive("fat32=error").is_ok());
+        check!(apply_directive("fs::vfs=warn").is_ok());
+        check!(module_level("ddos::fs::fat32") == Level::Error);
+        check!(module_level("ddos::fs::vfs") == Level::Warn);
+        check!(module_level("ddos::fs::cache") == Level::Debug);
+
+        for directive in ["fs=default", "fat32=default", "fs::vfs=default"] {
+            check!(apply_directive(directive).is_ok());
+        }
+        check!(module_level("ddos::fs::fat32") == super::level());
+        Ok(())
+    }
+
+    // Sinks cannot be removed, so this one only records while asked to
+    static CAPTURE: SpinLock<Option<String>> = SpinLock::new(None);
+
+    fn capture(text: &str) {
+        if let Some(captured) = CAPTURE.lock().as_mut() {
+            captured.push_str(text);
+        }
+    }
+
+    #[test_case]
+    fn sinks_get_console_output() -> Result<(), Failure> {
+        check!(add_sink(capture));
+
+        *CAPTURE.lock() = Some(String::new());
+        crate::println!("sink test {}", 42);
+        let captured = CAPTURE.lock().take();
+
+        check_eq!(captured.as_deref(), Some("sink test 42\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use core::arch::asm;
+
+// ============================================================================
+// MEMORY INTRINSICS
+// memcpy, memmove and memset for the whole kernel. compiler_builtins only
+// provides weak fallbacks, so these win at link time, and every copy the
+// compiler emits (struct moves, copy_from_slice, Vec growth, fills) ends up
+// here.
+//
+// The kernel is built softfloat, so there are no NEON registers to use; the
+// wide path moves 32 bytes per iteration through two ldp/stp pairs of
+// general purpose registers instead. With the MMU off all RAM is Device
+// memory, where an unaligned access faults, so the wide path only runs when
+// source and destination share their alignment within a word. Anything else
+// goes a byte at a time.
+//
+// The loops are inline assembly on purpose: written as plain Rust, LLVM
+// would recognise them as a copy and turn them into a call to memcpy.
+// ============================================================================
+
+const WORD: usize = 8;
+
+// Below this the alignment dance costs more than it saves
+const WIDE_THRESHOLD: usize = 32;
+
+// Copy `n` bytes upwards, one at a time. Returns the advanced pointers.
+#[inline(always)]
+unsafe fn bytes_forward(dest: *mut u8, src: *const u8, n: usize) -> (*mut u8, *const u8) {
+    let (mut dest, mut src) = (dest, src);
+    if n != 0 {
+        unsafe {
+            asm!(
+                "2:",
+                "ldrb {byte:w}, [{src}], #1",
+                "strb {byte:w}, [{dest}], #1",
+                "subs {n}, {n}, #1",
+                "b.ne 2b",
+                dest = inout(reg) dest,
+                src = inout(reg) src,
+                n = inout(reg) n => _,
+                byte = out(reg) _,
+                options(nostack),
+            );
+        }
+    }
+    (dest, src)
+}
+
+// Copy `n` bytes downwards from just below `dest_end`/`src_end`
+#[inline(always)]
+unsafe fn bytes_backward(dest_end: *mut u8, src_end: *const u8, n: usize) -> (*mut u8, *const u8) {
+    let (mut dest, mut src) = (dest_end, src_end);
+    if n != 0 {
+        unsafe {
+            asm!(
+                "2:",
+                "ldrb {byte:w}, [{src}, #-1]!",
+                "strb {byte:w}, [{dest}, #-1]!",
+                "subs {n}, {n}, #1",
+                "b.ne 2b",
+                dest = inout(reg) dest,
+                src = inout(reg) src,
+                n = inout(reg) n => _,
+                byte = out(reg) _,
+                options(nostack),
+            );
+        }
+    }
+    (dest, src)
+}
+
+// Copy `words` aligned 64-bit words upwards, four per iteration
+#[inline(always)]
+unsafe fn words_forward(dest: *mut u8, src: *const u8, words: usize) -> (*mut u8, *const u8) {
+    let (mut dest, mut src) = (dest, src);
+    unsafe {
+        asm!(
+            "2:",
+            "cmp {n}, #4",
+            "b.lo 3f",
+            "ldp {a}, {b}, [{src}], #16",
+            "ldp {c}, {d}, [{src}], #16",
+            "stp {a}, {b}, [{dest}], #16",
+            "stp {c}, {d}, [{dest}], #16",
+            "sub {n}, {n}, #4",
+            "b 2b",
+            "3:",
+            "cbz {n}, 4f",
+            "ldr {a}, [{src}], #8",
+            "str {a}, [{dest}], #8",
+            "sub {n}, {n}, #1",
+            "b 3b",
+            "4:",
+            dest = inout(reg) dest,
+            src = inout(reg) src,
+            n = inout(reg) words => _,
+            a = out(reg) _,
+            b = out(reg) _,
+            c = out(reg) _,
+            d = out(reg) _,
+            options(nostack),
+        );
+    }
+    (dest, src)
+}
+
+// Copy `words` aligned 64-bit words downwards from just below the ends
+#[inline(always)]
+unsafe fn words_backward(
+    dest_end: *mut u8,
+    src_end: *const u8,
+    words: usize,
+) -> (*mut u8, *const u8) {
+    let (mut dest, mut src) = (dest_end, src_end);
+    unsafe {
+        asm!(
+            "2:",
+            "cmp {n}, #4",
+            "b.lo 3f",
+            "ldp {a}, {b}, [{src}, #-16]!",
+            "ldp {c}, {d}, [{src}, #-16]!",
+            "stp {a}, {b}, [{dest}, #-16]!",
+            "stp {c}, {d}, [{dest}, #-16]!",
+            "sub {n}, {n}, #4",
+            "b 2b",
+            "3:",
+            "cbz {n}, 4f",
+            "ldr {a}, [{src}, #-8]!",
+            "str {a}, [{dest}, #-8]!",
+            "sub {n}, {n}, #1",
+            "b 3b",
+            "4:",
+            dest = inout(reg) dest,
+            src = inout(reg) src,
+            n = inout(reg) words => _,
+            a = out(reg) _,
+            b = out(reg) _,
+            c = out(reg) _,
+            d = out(reg) _,
+            options(nostack),
+        );
+    }
+    (dest, src)
+}
+
+// Whether both pointers can reach word alignment together
+fn co_aligned(dest: usize, src: usize, n: usize) -> bool {
+    n >= WIDE_THRESHOLD && (dest ^ src).is_multiple_of(WORD)
+}
+
+unsafe fn copy_forward(dest: *mut u8, src: *const u8, n: usize) {
+    if !co_aligned(dest as usize, src as usize, n) {
+        unsafe { bytes_forward(dest, src, n) };
+        return;
+    }
+
+    let head = (WORD - dest as usize % WORD) % WORD;
+    let words = (n - head) / WORD;
+    let tail = n - head - words * WORD;
+
+    unsafe {
+        let (dest, src) = bytes_forward(dest, src, head);
+        let (dest, src) = words_forward(dest, src, words);
+        bytes_forward(dest, src, tail);
+    }
+}
+
+unsafe fn copy_backward(dest: *mut u8, src: *const u8, n: usize) {
+    let (dest_end, src_end) = unsafe { (dest.add(n), src.add(n)) };
+    if !co_aligned(dest as usize, src as usize, n) {
+        unsafe { bytes_backward(dest_end, src_end, n) };
+        return;
+    }
+
+    let tail = dest_end as usize % WORD;
+    let words = (n - tail) / WORD;
+    let head = n - tail - words * WORD;
+
+    unsafe {
+        let (dest_end, src_end) = bytes_backward(dest_end, src_end, tail);
+        let (dest_end, src_end) = words_backward(dest_end, src_end, words);
+        bytes_backward(dest_end, src_end, head);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn memcpy(dest: *mut u8, src: *const u8, n: usize) -> *mut u8 {
+    unsafe { copy_forward(dest, src, n) };
+    dest
+}
+
+// Overlapping ranges are fine: copy away from the overlap
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn memmove(dest: *mut u8, src: *const u8, n: usize) -> *mut u8 {
+    if (dest as usize).wrapping_sub(src as usize) >= n {
+        unsafe { copy_forward(dest, src, n) };
+    } else {
+        unsafe { copy_backward(dest, src, n) };
+    }
+    dest
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn memset(dest: *mut u8, value: i32, n: usize) -> *mut u8 {
+    let byte = value as u8;
+    let head = if n >= WIDE_THRESHOLD {
+        (WORD - dest as usize % WORD) % WORD
+    } else {
+        n
+    };
+    let words = (n - head) / WORD;
+    let tail = n - head - words * WORD;
+    let pattern = byte as u64 * 0x0101_0101_0101_0101;
+
+    unsafe {
+        asm!(
+            // Bytes up to word alignment
+            "cbz {head}, 3f",
+            "2:",
+            "strb {pattern:w}, [{dest}], #1",
+            "subs {head}, {head}, #1",
+            "b.ne 2b",
+            "3:",
+            // Four words at a time, then single words
+            "cmp {words}, #4",
+            "b.lo 4f",
+            "stp {pattern}, {pattern}, [{dest}], #16",
+            "stp {pattern}, {pattern}, [{dest}], #16",
+            "sub {words}, {words}, #4",
+            "b 3b",
+            "4:",
+            "cbz {words}, 5f",
+            "str {pattern}, [{dest}], #8",
+            "sub {words}, {words}, #1",
+            "b 4b",
+            "5:",
+            // What is left of the last word
+            "cbz {tail}, 7f",
+            "6:",
+            "strb {pattern:w}, [{dest}], #1",
+            "subs {tail}, {tail}, #1",
+            "b.ne 6b",
+            "7:",
+            dest = inout(reg) dest => _,
+            head = inout(reg) head => _,
+            words = inout(reg) words => _,
+            tail = inout(reg) tail => _,
+            pattern = in(reg) pattern,
+            options(nostack),
+        );
+    }
+    dest
+}
@@ -1 +1,15 @@
-pub mod locked;
+pub mod crashlog;
+pub mod early;
+pub mod events;
+pub mod log;
+// aarch64 assembly; host builds keep the compiler_builtins versions
+#[cfg(target_arch = "aarch64")]
+pub mod mem;
+pub mod progress;
+pub mod rcu;
+pub mod testing;
+pub mod theme;
+pub mod tui;
+
+// The locks moved to the drivers library with the code that needs them
+pub use ddos_drivers::locked;
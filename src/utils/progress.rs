@@ -0,0 +1,135 @@
+use crate::drivers::timer;
+use crate::drivers::watchdog;
+use crate::print;
+use crate::utils::tui;
+use alloc::string::String;
+use core::fmt::Write;
+
+// ============================================================================
+// PROGRESS INDICATORS
+// One console line redrawn in place with '\r': a bar with percentage and
+// ETA for work whose amount is known up front. Redraws are limited to REDRAW_US apart so a tight
+// loop can call advance() as often as it likes without flooding the UART.
+// ============================================================================
+
+const BAR_WIDTH: usize = 24;
+const REDRAW_US: u64 = 100_000;
+
+pub struct Progress {
+    label: String,
+    total: u64,
+    done: u64,
+    started_us: u64,
+    last_draw_us: Option<u64>,
+    // Length of the last line drawn, so a shorter one can blank the rest
+    drawn: usize,
+    finished: bool,
+}
+
+impl Progress {
+    pub fn bar(label: &str, total: u64) -> Progress {
+        Progress {
+            label: String::from(label),
+            total,
+            done: 0,
+            started_us: timer::uptime_us(),
+            last_draw_us: None,
+            drawn: 0,
+            finished: false,
+        }
+    }
+
+    pub fn advance(&mut self, amount: u64) {
+        self.set(self.done + amount);
+    }
+
+    pub fn set(&mut self, done: u64) {
+        self.done = done;
+        // Anything slow enough for a progress bar is not a hang
+        watchdog::feed();
+
+        let now = timer::uptime_us();
+        if self
+            .last_draw_us
+            .is_none_or(|last| now.saturating_sub(last) >= REDRAW_US)
+        {
+            self.last_draw_us = Some(now);
+            self.draw("");
+        }
+    }
+
+    // Final redraw with `status` after it, and move to the next line
+    pub fn finish(mut self, status: &str) {
+        self.done = self.total;
+        self.draw(status);
+        print!("\n");
+        self.finished = true;
+    }
+
+    // Wipe the line, for callers that print their own summary instead
+    pub fn clear(mut self) {
+        print!("\r{:1$}\r", "", self.drawn);
+        self.finished = true;
+    }
+
+    fn eta_secs(&self, elapsed_us: u64) -> Option<u64> {
+        if self.done == 0 || self.done >= self.total {
+            return None;
+        }
+        let left = self.total - self.done;
+        Some((elapsed_us as u128 * left as u128 / self.done as u128 / 1_000_000) as u64)
+    }
+
+    fn draw(&mut self, status: &str) {
+        let elapsed_us = timer::uptime_us() - self.started_us;
+        let mut line = String::new();
+
+        let done = self.done.min(self.total);
+        // Nothing to do at all counts as done
+        let percent = match self.total {
+            0 => 100,
+            total => done as u128 * 100 / total as u128,
+        };
+
+        let _ = write!(
+            line,
+            "{} {} {:>3}%",
+            self.label,
+            tui::bar(done, self.total, BAR_WIDTH),
+            percent
+        );
+
+        match self.eta_secs(elapsed_us) {
+            Some(eta) => {
+                let _ = write!(line, " ETA {}:{:02}", eta / 60, eta % 60);
+            }
+            None if done == self.total => {
+                let _ = write!(
+                    line,
+                    " {}.{}s",
+                    elapsed_us / 1_000_000,
+                    elapsed_us / 100_000 % 10
+                );
+            }
+            None => {}
+        }
+
+        if !status.is_empty() {
+            line.push(' ');
+            line.push_str(status);
+        }
+
+        let padding = self.drawn.saturating_sub(line.len());
+        print!("\r{}{:2$}", line, "", padding);
+        self.drawn = line.len();
+    }
+}
+
+// Bailing out with ? should not leave the cursor at the end of a half bar
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if !self.finished && self.drawn > 0 {
+            print!("\n");
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use super::locked::SpinLock;
+use alloc::boxed::Box;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+// ============================================================================
+// READ-COPY-UPDATE STYLE SNAPSHOT
+// Readers load one pointer and never take a lock, so hot paths (log level,
+// colors, keymap) stay usable from interrupt context. Writers build a brand new
+// immutable snapshot on the heap and publish it with a single pointer store.
+//
+// Only plain load-acquire / store-release is used on the pointer (no swap or
+// CAS), so this works on RPi5 with the MMU off, same as the SpinLock workaround.
+//
+// Grace period: there is no reader tracking yet, so a replaced snapshot is kept
+// alive for one more update before it is freed. Readers must finish before two
+// updates happen, which holds for short reads of rarely written config data.
+// ============================================================================
+
+pub struct Rcu<T: 'static> {
+    current: AtomicPtr<T>,
+    initial: &'static T,
+    // The snapshot replaced by the last update, freed on the next one
+    retired: SpinLock<*mut T>,
+}
+
+unsafe impl<T: Sync> Sync for Rcu<T> {}
+unsafe impl<T: Send> Send for Rcu<T> {}
+
+impl<T> Rcu<T> {
+    // Starts out pointing at static data so it can be used before the heap exists
+    pub const fn new(initial: &'static T) -> Self {
+        Self {
+            current: AtomicPtr::new(initial as *const T as *mut T),
+            initial,
+            retired: SpinLock::new(null_mut()),
+        }
+    }
+
+    pub fn read<R>(&self, reader: impl FnOnce(&T) -> R) -> R {
+        let snapshot = self.current.load(Ordering::Acquire);
+        unsafe { reader(&*snapshot) }
+    }
+
+    pub fn update(&self, value: T) {
+        let mut retired = self.retired.lock();
+        self.publish(&mut retired, value);
+    }
+
+    // Copy the current snapshot, let the closure change it, then publish the copy.
+    // Holding the writer lock throughout keeps concurrent writers from losing edits.
+    pub fn modify(&self, change: impl FnOnce(&mut T))
+    where
+        T: Clone,
+    {
+        let mut retired = self.retired.lock();
+        let mut copy = self.read(|current| current.clone());
+        change(&mut copy);
+        self.publish(&mut retired, copy);
+    }
+
+    fn publish(&self, retired: &mut *mut T, value: T) {
+        let new_snapshot = Box::into_raw(Box::new(value));
+        let old_snapshot = self.current.load(Ordering::Relaxed);
+
+        self.current.store(new_snapshot, Ordering::Release);
+
+        // Readers have had a whole update cycle to move off the retired snapshot
+        self.free_snapshot(*retired);
+        *retired = old_snapshot;
+    }
+
+    fn free_snapshot(&self, snapshot: *mut T) {
+        if snapshot.is_null() || core::ptr::eq(snapshot, self.initial) {
+            return;
+        }
+
+        unsafe {
+            drop(Box::from_raw(snapshot));
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use super::theme::{Color, Normal, Paint};
+use crate::drivers::timer;
+use crate::println;
+use alloc::format;
+use alloc::string::String;
+
+// ============================================================================
+// TEST REPORTS
+// In-kernel tests run as plain functions returning Result<(), Failure>, with
+// check!/check_eq! bailing out on the first mismatch. Boot runs a few of them
+// itself, the rest are #[test_case]s for `cargo test` (see CARGO TEST below).
+// A Suite runs them one by one and prints a colored line for each, the
+// expected and actual values of a failure, and a summary with counts and the
+// time taken:
+//
+//     test heap::box ... ok
+//     test heap::vec ... FAILED
+//         at src/main.rs:104
+//         expected: [0, 1, 2]
+//           actual: [0, 1]
+//     test result: FAILED. 1 passed; 1 failed; 0.214 ms
+//
+// Every result is also printed as a MARKER line, uncolored and on its own,
+// which is what the host side greps for to decide its exit code:
+//
+//     ##ddos-test## pass heap::box
+//     ##ddos-test## fail heap::vec
+//     ##ddos-test## done passed=1 failed=1 us=214
+// ============================================================================
+
+pub const MARKER: &str = "##ddos-test##";
+
+pub struct Failure {
+    pub message: String,
+    pub file: &'static str,
+    pub line: u32,
+}
+
+// Anything a Suite can run: a plain test function, named after its path
+#[cfg(test)]
+pub trait Testable {
+    fn name(&self) -> &'static str;
+    fn run(&self) -> Result<(), Failure>;
+}
+
+#[cfg(test)]
+impl<T: Fn() -> Result<(), Failure>> Testable for T {
+    fn name(&self) -> &'static str {
+        core::any::type_name::<T>()
+    }
+
+    fn run(&self) -> Result<(), Failure> {
+        self()
+    }
+}
+
+pub struct Suite {
+    name: &'static str,
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+    start: u64,
+}
+
+impl Suite {
+    pub fn new(name: &'static str) -> Self {
+        println!("running {} tests", name);
+        Suite {
+            name,
+            passed: 0,
+            failed: 0,
+            skipped: 0,
+            start: timer::counter(),
+        }
+    }
+
+    pub fn run(&mut self, name: &str, test: impl FnOnce() -> Result<(), Failure>) {
+        match test() {
+            Ok(()) => {
+                self.passed += 1;
+                println!(
+                    "test {}::{} ... {}ok{}",
+                    self.name,
+                    name,
+                    Paint(Color::Green),
+                    Normal
+                );
+                println!("{} pass {}::{}", MARKER, self.name, name);
+            }
+            Err(failure) => {
+                self.failed += 1;
+                println!(
+                    "test {}::{} ... {}FAILED{}",
+                    self.name,
+                    name,
+                    Paint(Color::BrightRed),
+                    Normal
+                );
+                println!("    at {}:{}", failure.file, failure.line);
+                for line in failure.message.lines() {
+                    println!("    {}", line);
+                }
+                println!("{} fail {}::{}", MARKER, self.name, name);
+            }
+        }
+    }
+
+    // A test that cannot run here (no such hardware): neither passed nor
+    // failed. Only the self-test has any so far.
+    #[cfg(feature = "selftest")]
+    pub fn skip(&mut self, name: &str, reason: &str) {
+        self.skipped += 1;
+        println!(
+            "test {}::{} ... {}skipped{} ({})",
+            self.name,
+            name,
+            Paint(Color::Yellow),
+            Normal,
+            reason
+        );
+        println!("{} skip {}::{}", MARKER, self.name, name);
+    }
+
+    // Print the summary. True when every test that ran passed.
+    pub fn finish(self) -> bool {
+        let us = timer::ticks_to_us(timer::counter() - self.start);
+        let (color, result) = match self.failed {
+            0 => (Color::Green, "ok"),
+            _ => (Color::BrightRed, "FAILED"),
+        };
+
+        let skipped = match self.skipped {
+            0 => String::new(),
+            count => format!("{} skipped; ", count),
+        };
+        println!(
+            "test result: {}{}{}. {} passed; {} failed; {}{}.{:03} ms",
+            Paint(color),
+            result,
+            Normal,
+            self.passed,
+            self.failed,
+            skipped,
+            us / 1000,
+            us % 1000
+        );
+        println!(
+            "{} done passed={} failed={} us={}",
+            MARKER, self.passed, self.failed, us
+        );
+        self.failed == 0
+    }
+}
+
+// ============================================================================
+// CARGO TEST
+// `cargo test --features qemu` builds the kernel with every #[test_case]
+// function collected by the custom test framework (see main.rs) and boots it
+// in QEMU through the runner in .cargo/config.toml. Boot calls test_main()
+// once the heap and console are up, which ends up here: everything runs as
+// one Suite and QEMU exits with 0 when all passed, 1 otherwise.
+// ============================================================================
+
+#[cfg(test)]
+pub fn runner(tests: &[&dyn Testable]) {
+    let mut suite = Suite::new("ddos");
+    for test in tests {
+        let name = test.name();
+        suite.run(name.strip_prefix("ddos::").unwrap_or(name), || test.run());
+    }
+    let passed = suite.finish();
+
+    #[cfg(feature = "qemu")]
+    crate::qemu::exit(if passed { 0 } else { 1 });
+
+    #[cfg(not(feature = "qemu"))]
+    let _ = passed;
+}
+
+// ============================================================================
+// CHECK MACROS
+// Use them inside a Test after `use crate::utils::testing::{check, check_eq}`.
+// ============================================================================
+
+macro_rules! check {
+    ($condition:expr) => {
+        if !$condition {
+            return Err($crate::utils::testing::Failure {
+                message: alloc::format!("check failed: {}", stringify!($condition)),
+                file: file!(),
+                line: line!(),
+            });
+        }
+    };
+}
+
+macro_rules! check_eq {
+    ($actual:expr, $expected:expr) => {
+        match (&$actual, &$expected) {
+            (actual, expected) => {
+                if actual != expected {
+                    return Err($crate::utils::testing::Failure {
+                        message: alloc::format!("expected: {:?}\n  actual: {:?}", expected, actual),
+                        file: file!(),
+                        line: line!(),
+                    });
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use {check, check_eq};
@@ -0,0 +1,206 @@
+use super::rcu::Rcu;
+use core::fmt;
+
+// ============================================================================
+// CONSOLE THEME
+// Colors for the console: default text and background, one per log level
+// and the shell prompt. They are sent as ANSI SGR escapes, which every
+// serial terminal understands; Color::Default sends nothing at all, so the
+// "plain" theme leaves the output exactly as it was.
+//
+// The logger reads the theme on every line, so it sits behind an Rcu like
+// the log config. The shell loads overrides from a file (shell/theme.rs).
+// ============================================================================
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    // Whatever the terminal uses
+    Default,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+}
+
+const COLOR_NAMES: [(&str, Color); 17] = [
+    ("default", Color::Default),
+    ("black", Color::Black),
+    ("red", Color::Red),
+    ("green", Color::Green),
+    ("yellow", Color::Yellow),
+    ("blue", Color::Blue),
+    ("magenta", Color::Magenta),
+    ("cyan", Color::Cyan),
+    ("white", Color::White),
+    ("bright-black", Color::BrightBlack),
+    ("bright-red", Color::BrightRed),
+    ("bright-green", Color::BrightGreen),
+    ("bright-yellow", Color::BrightYellow),
+    ("bright-blue", Color::BrightBlue),
+    ("bright-magenta", Color::BrightMagenta),
+    ("bright-cyan", Color::BrightCyan),
+    ("bright-white", Color::BrightWhite),
+];
+
+impl Color {
+    pub fn parse(text: &str) -> Option<Color> {
+        COLOR_NAMES
+            .iter()
+            .find(|(name, _)| *name == text)
+            .map(|&(_, color)| color)
+    }
+
+    pub fn name(self) -> &'static str {
+        COLOR_NAMES
+            .iter()
+            .find(|(_, color)| *color == self)
+            .map_or("default", |&(name, _)| name)
+    }
+
+    // Foreground SGR code, add 10 for the background. None for Default.
+    fn sgr(self) -> Option<u8> {
+        let index = COLOR_NAMES.iter().position(|&(_, color)| color == self)? as u8;
+        match index {
+            0 => None,
+            1..=8 => Some(30 + index - 1),
+            _ => Some(90 + index - 9),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Theme {
+    pub name: &'static str,
+    pub foreground: Color,
+    pub background: Color,
+    pub error: Color,
+    pub warn: Color,
+    pub info: Color,
+    pub debug: Color,
+    pub prompt: Color,
+}
+
+pub static PLAIN: Theme = Theme {
+    name: "plain",
+    foreground: Color::Default,
+    background: Color::Default,
+    error: Color::Default,
+    warn: Color::Default,
+    info: Color::Default,
+    debug: Color::Default,
+    prompt: Color::Default,
+};
+
+pub static COLOR: Theme = Theme {
+    name: "color",
+    error: Color::BrightRed,
+    warn: Color::BrightYellow,
+    info: Color::Green,
+    debug: Color::BrightBlack,
+    prompt: Color::BrightCyan,
+    ..PLAIN
+};
+
+pub static LIGHT: Theme = Theme {
+    name: "light",
+    foreground: Color::Black,
+    background: Color::BrightWhite,
+    error: Color::Red,
+    warn: Color::Magenta,
+    info: Color::Blue,
+    debug: Color::BrightBlack,
+    prompt: Color::Blue,
+};
+
+// For terminals whose own default is not dark: sets the background too
+pub static DARK: Theme = Theme {
+    name: "dark",
+    foreground: Color::White,
+    background: Color::Black,
+    error: Color::BrightRed,
+    warn: Color::BrightYellow,
+    info: Color::BrightGreen,
+    debug: Color::BrightBlack,
+    prompt: Color::BrightBlue,
+};
+
+pub static BUILTIN: [&Theme; 4] = [&PLAIN, &COLOR, &LIGHT, &DARK];
+
+static CURRENT: Rcu<Theme> = Rcu::new(&PLAIN);
+
+pub fn builtin(name: &str) -> Option<&'static Theme> {
+    BUILTIN.iter().copied().find(|theme| theme.name == name)
+}
+
+pub fn current() -> Theme {
+    CURRENT.read(|theme| *theme)
+}
+
+// Needs the heap. Switches the terminal's default colors right away.
+pub fn set(theme: Theme) {
+    CURRENT.update(theme);
+    crate::print!("{}", Normal);
+}
+
+// Writes `color` as a foreground escape, or nothing for Color::Default
+pub struct Paint(pub Color);
+
+impl fmt::Display for Paint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.sgr() {
+            Some(code) => write!(f, "\x1b[{}m", code),
+            None => Ok(()),
+        }
+    }
+}
+
+// Back to the theme's text and background colors after a Paint
+pub struct Normal;
+
+impl fmt::Display for Normal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let theme = current();
+        write!(f, "\x1b[0m")?;
+        if let Some(code) = theme.foreground.sgr() {
+            write!(f, "\x1b[{}m", code)?;
+        }
+        if let Some(code) = theme.background.sgr() {
+            write!(f, "\x1b[{}m", code + 10)?;
+        }
+        Ok(())
+    }
+}
+
+// Apply one `key = value` setting from a theme file
+pub fn apply(theme: &mut Theme, key: &str, value: &str) -> Result<(), &'static str> {
+    if key == "base" {
+        *theme = *builtin(value).ok_or("unknown theme")?;
+        return Ok(());
+    }
+
+    let color = Color::parse(value).ok_or("unknown color")?;
+    let field = match key {
+        "foreground" | "fg" => &mut theme.foreground,
+        "background" | "bg" => &mut theme.background,
+        "error" => &mut theme.error,
+        "warn" => &mut theme.warn,
+        "info" => &mut theme.info,
+        "debug" => &mut theme.debug,
+        "prompt" => &mut theme.prompt,
+        _ => return Err("unknown setting"),
+    };
+    *field = color;
+    Ok(())
+}
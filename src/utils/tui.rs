@@ -0,0 +1,214 @@
+use crate::drivers::{console, timer, watchdog};
+use crate::input::{self, KeyCode};
+use crate::utils::theme::Normal;
+use crate::{print, println};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// ============================================================================
+// TEXT UI
+// Building blocks for screens on the serial terminal, drawn with ANSI
+// escapes and box-drawing characters (the UARTs pass UTF-8 through and the
+// terminal draws them):
+//
+//     panel(title, lines)      the lines in a box, the title in its top edge
+//     bar(done, total, width)  "[######      ]", for panels and progress lines
+//     status(text)             reverse video on the terminal's bottom line,
+//                              the cursor left where it was
+//     Menu                     a boxed list to pick from with Up/Down and
+//                              Enter, Escape to back out, redrawn in place
+//
+// Nothing knows the terminal's size, everything is as wide as its content.
+// ============================================================================
+
+const REVERSE: &str = "\x1b[7m";
+
+// `width` characters between the brackets, filled in proportion
+pub fn bar(done: u64, total: u64, width: usize) -> String {
+    let filled = match total {
+        0 => 0,
+        _ => (done.min(total) as u128 * width as u128 / total as u128) as usize,
+    };
+    format!("[{:#<filled$}{:<empty$}]", "", "", empty = width - filled)
+}
+
+// The rows of a box around `lines`, the one at `highlight` in reverse video
+fn frame(title: &str, lines: &[String], highlight: Option<usize>) -> Vec<String> {
+    let title_width = title.chars().count();
+    let mut width = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    if title_width > 0 {
+        width = width.max(title_width + 2);
+    }
+
+    let mut rows = Vec::with_capacity(lines.len() + 2);
+    rows.push(match title_width {
+        0 => format!("┌{}┐", "─".repeat(width + 2)),
+        _ => format!("┌─ {} {}┐", title, "─".repeat(width - title_width - 1)),
+    });
+    for (index, line) in lines.iter().enumerate() {
+        let padding = width - line.chars().count();
+        rows.push(if highlight == Some(index) {
+            format!("│ {}{}{:padding$}{} │", REVERSE, line, "", Normal)
+        } else {
+            format!("│ {}{:padding$} │", line, "")
+        });
+    }
+    rows.push(format!("└{}┘", "─".repeat(width + 2)));
+    rows
+}
+
+pub fn panel(title: &str, lines: &[String]) {
+    for row in frame(title, lines, None) {
+        println!("{}", row);
+    }
+}
+
+// Row 999 is past the bottom of any terminal, which puts the cursor on the
+// last row instead
+pub fn status(text: &str) {
+    print!("\x1b7\x1b[999;1H{}{}{}\x1b[K\x1b8", REVERSE, text, Normal);
+}
+
+pub fn clear_status() {
+    print!("\x1b7\x1b[999;1H\x1b[2K\x1b8");
+}
+
+pub struct Menu {
+    title: String,
+    items: Vec<String>,
+    selected: usize,
+    // Rows drawn last time, to go back up over them on a redraw
+    drawn: usize,
+}
+
+impl Menu {
+    // `items` must not be empty
+    pub fn new(title: &str, items: Vec<String>) -> Menu {
+        Menu {
+            title: String::from(title),
+            items,
+            selected: 0,
+            drawn: 0,
+        }
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.selected = index.min(self.items.len() - 1);
+    }
+
+    // New labels, e.g. after a setting changed; shown on the next draw
+    pub fn set_items(&mut self, items: Vec<String>) {
+        self.items = items;
+        self.select(self.selected);
+    }
+
+    pub fn draw(&mut self) {
+        if self.drawn > 0 {
+            print!("\x1b[{}A\r", self.drawn);
+        }
+        let rows = frame(&self.title, &self.items, Some(self.selected));
+        for row in &rows {
+            println!("{}\x1b[K", row);
+        }
+        self.drawn = rows.len();
+    }
+
+    // Wipe the menu off the screen, cursor where its top row was
+    pub fn erase(&mut self) {
+        if self.drawn > 0 {
+            print!("\x1b[{}A\r\x1b[J", self.drawn);
+            self.drawn = 0;
+        }
+    }
+
+    // Draw and wait: the index picked with Enter, None for Escape. With a
+    // timeout the status line counts it down and the selected item is
+    // picked when it runs out, unless a key came first.
+    pub fn choose(&mut self, timeout_ms: Option<u64>) -> Option<usize> {
+        self.draw();
+        let mut deadline = timeout_ms.map(|ms| timer::uptime_ms() + ms);
+        let mut shown = None;
+
+        loop {
+            watchdog::feed();
+            let Some(event) = input::poll() else {
+                console::drain();
+                let Some(deadline) = deadline else {
+                    continue;
+                };
+                let now = timer::uptime_ms();
+                if now >= deadline {
+                    clear_status();
+                    return Some(self.selected);
+                }
+                let seconds = (deadline - now).div_ceil(1000);
+                if shown != Some(seconds) {
+                    shown = Some(seconds);
+                    status(&format!(
+                        "Continuing in {} s, any key stops the countdown",
+                        seconds
+                    ));
+                }
+                continue;
+            };
+            if !event.pressed {
+                continue;
+            }
+            if deadline.take().is_some() {
+                clear_status();
+            }
+
+            match event.code {
+                KeyCode::UP if self.selected > 0 => self.selected -= 1,
+                KeyCode::DOWN if self.selected + 1 < self.items.len() => self.selected += 1,
+                KeyCode::HOME | KeyCode::PAGE_UP => self.selected = 0,
+                KeyCode::END | KeyCode::PAGE_DOWN => self.selected = self.items.len() - 1,
+                KeyCode::ENTER => return Some(self.selected),
+                KeyCode::ESCAPE => return None,
+                _ => continue,
+            }
+            self.draw();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bar, frame};
+    use crate::utils::testing::{Failure, check_eq};
+    use alloc::string::String;
+    use alloc::vec;
+
+    #[test_case]
+    fn bar_and_frame() -> Result<(), Failure> {
+        check_eq!(bar(0, 10, 4), "[    ]");
+        check_eq!(bar(5, 10, 4), "[##  ]");
+        check_eq!(bar(20, 10, 4), "[####]");
+        check_eq!(bar(1, 0, 2), "[  ]");
+
+        let lines = vec![String::from("heap"), String::from("sd0")];
+        check_eq!(
+            frame("dev", &lines, None),
+            ["┌─ dev ─┐", "│ heap  │", "│ sd0   │", "└───────┘"]
+        );
+        check_eq!(
+            frame("devices", &lines, None),
+            [
+                "┌─ devices ─┐",
+                "│ heap      │",
+                "│ sd0       │",
+                "└───────────┘"
+            ]
+        );
+        check_eq!(
+            frame("", &lines[1..], None),
+            ["┌─────┐", "│ sd0 │", "└─────┘"]
+        );
+        Ok(())
+    }
+}
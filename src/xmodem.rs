@@ -0,0 +1,240 @@
+use crate::drivers::console::{self, CONSOLE};
+use crate::drivers::timer;
+use crate::drivers::watchdog;
+use alloc::vec::Vec;
+use core::fmt;
+
+// ============================================================================
+// XMODEM RECEIVE
+// Takes a file over the serial console from the terminal on the other end,
+// e.g. `sx -k program.elf` on Linux (minicom, picocom --send-cmd), so a
+// program can get onto the board without an SD card. XMODEM-CRC with both
+// 128-byte (SOH) and 1 KiB (STX) blocks:
+//
+//     us    'C' every START_INTERVAL_MS until the first block comes
+//     them  SOH|STX, block, 255 - block, 128|1024 bytes, CRC-16 high, low
+//     us    ACK, or NAK to have it sent again
+//     them  EOT once done, we ACK
+//
+// The last block is padded with SUB (0x1A) and the padding is kept, the
+// protocol cannot tell it from data. Two CAN (Ctrl+X twice) from either
+// side cancel. YMODEM batches (sb) are not understood.
+//
+// While this runs the console carries the transfer, so nothing may print.
+// ============================================================================
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+// Asks for CRC-16 instead of the 8-bit checksum
+const CRC_MODE: u8 = b'C';
+
+const START_INTERVAL_MS: u64 = 3000;
+// About a minute to get the sender going
+const START_TRIES: usize = 20;
+// Between two bytes of a block
+const BYTE_TIMEOUT_MS: u64 = 1000;
+// NAKs in a row before giving up
+const MAX_RETRIES: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XmodemError {
+    // Nobody started sending
+    NoSender,
+    Cancelled,
+    // MAX_RETRIES bad blocks in a row
+    TooManyErrors,
+    // A block that is neither the next one nor the last one again
+    OutOfSequence,
+    TooLarge,
+}
+
+impl fmt::Display for XmodemError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XmodemError::NoSender => write!(f, "no transfer started"),
+            XmodemError::Cancelled => write!(f, "cancelled"),
+            XmodemError::TooManyErrors => write!(f, "too many bad blocks"),
+            XmodemError::OutOfSequence => write!(f, "block out of sequence"),
+            XmodemError::TooLarge => write!(f, "file too large"),
+        }
+    }
+}
+
+// CRC-16/XMODEM: polynomial 0x1021, starting from 0
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn send(byte: u8) {
+    let mut console = CONSOLE.lock();
+    let _ = console.send(byte as char);
+    let _ = console.flush();
+}
+
+fn read(timeout_ms: u64) -> Option<u8> {
+    let deadline = timer::uptime_ms() + timeout_ms;
+    loop {
+        if let Some(byte) = CONSOLE.lock().try_read_byte() {
+            return Some(byte);
+        }
+        if timer::uptime_ms() >= deadline {
+            return None;
+        }
+        watchdog::feed();
+    }
+}
+
+// Throw away the rest of a bad block, until the line goes quiet
+fn purge() {
+    while read(BYTE_TIMEOUT_MS).is_some() {}
+}
+
+fn cancel() {
+    for _ in 0..3 {
+        send(CAN);
+    }
+}
+
+enum Block {
+    // Block number and its data, CRC checked
+    Data(u8, usize),
+    End,
+    Cancelled,
+    // Timed out or garbled, worth a NAK
+    Bad,
+}
+
+// One block into `data` after its header byte
+fn read_block(header: u8, data: &mut [u8; 1024]) -> Block {
+    let size = match header {
+        SOH => 128,
+        STX => 1024,
+        EOT => return Block::End,
+        CAN if read(BYTE_TIMEOUT_MS) == Some(CAN) => return Block::Cancelled,
+        _ => return Block::Bad,
+    };
+
+    let (Some(number), Some(inverse)) = (read(BYTE_TIMEOUT_MS), read(BYTE_TIMEOUT_MS)) else {
+        return Block::Bad;
+    };
+    for byte in data[..size].iter_mut() {
+        match read(BYTE_TIMEOUT_MS) {
+            Some(value) => *byte = value,
+            None => return Block::Bad,
+        }
+    }
+    let (Some(high), Some(low)) = (read(BYTE_TIMEOUT_MS), read(BYTE_TIMEOUT_MS)) else {
+        return Block::Bad;
+    };
+
+    if number != !inverse || u16::from_be_bytes([high, low]) != crc16(&data[..size]) {
+        return Block::Bad;
+    }
+    Block::Data(number, size)
+}
+
+// Before the first block a NAK would talk the sender out of CRC mode
+fn retry(file: &[u8]) -> u8 {
+    if file.is_empty() { CRC_MODE } else { NAK }
+}
+
+// Receive one file of at most `limit` bytes, SUB padding included
+pub fn receive(limit: usize) -> Result<Vec<u8>, XmodemError> {
+    // Whatever was typed before has nothing to do with the transfer
+    console::flush();
+    while CONSOLE.lock().try_read_byte().is_some() {}
+
+    let mut header = None;
+    for _ in 0..START_TRIES {
+        send(CRC_MODE);
+        header = read(START_INTERVAL_MS);
+        if header.is_some() {
+            break;
+        }
+    }
+    let Some(mut header) = header else {
+        return Err(XmodemError::NoSender);
+    };
+
+    let mut file = Vec::new();
+    let mut block = [0u8; 1024];
+    let mut expected: u8 = 1;
+    let mut retries = 0;
+
+    loop {
+        match read_block(header, &mut block) {
+            Block::Data(number, size) if number == expected => {
+                if file.len() + size > limit {
+                    cancel();
+                    return Err(XmodemError::TooLarge);
+                }
+                file.extend_from_slice(&block[..size]);
+                expected = expected.wrapping_add(1);
+                retries = 0;
+                send(ACK);
+            }
+            // Our ACK got lost and the sender repeats itself
+            Block::Data(number, _) if number == expected.wrapping_sub(1) => send(ACK),
+            Block::Data(..) => {
+                cancel();
+                return Err(XmodemError::OutOfSequence);
+            }
+            Block::End => {
+                send(ACK);
+                return Ok(file);
+            }
+            Block::Cancelled => return Err(XmodemError::Cancelled),
+            Block::Bad => {
+                retries += 1;
+                if retries == MAX_RETRIES {
+                    cancel();
+                    return Err(XmodemError::TooManyErrors);
+                }
+                purge();
+                send(retry(&file));
+            }
+        }
+
+        header = loop {
+            match read(START_INTERVAL_MS) {
+                Some(byte) => break byte,
+                None => {
+                    retries += 1;
+                    if retries == MAX_RETRIES {
+                        cancel();
+                        return Err(XmodemError::TooManyErrors);
+                    }
+                    send(retry(&file));
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crc16;
+    use crate::utils::testing::{Failure, check_eq};
+
+    #[test_case]
+    fn crc16_check_value() -> Result<(), Failure> {
+        check_eq!(crc16(b"123456789"), 0x31C3);
+        check_eq!(crc16(&[]), 0);
+        Ok(())
+    }
+}